@@ -1,4 +1,4 @@
-use std::{fmt::Debug, marker::PhantomData};
+use std::{fmt::Debug, marker::PhantomData, sync::Arc, time::Duration};
 
 use axum::async_trait;
 use diesel::{
@@ -10,8 +10,19 @@ use tokio::sync::broadcast;
 use crate::{
     config::ConfigManager,
     entities::friends::repository::IFriendRepository,
-    helper::session::{ISession, ISessionManager},
-    persistence::connection_manager::IConnectionManager,
+    helper::{
+        cookie::CookieSettings,
+        fingerprint::ClientBinding,
+        keyring::Keyring,
+        rate_limit::{LoginThrottle, RateLimiter},
+        session::{ISession, ISessionManager},
+    },
+    persistence::{
+        connection_manager::IConnectionManager,
+        login_failure_repository::ILoginFailureRepository,
+        token_revocation_repository::ITokenRevocationRepository,
+        websocket_ticket_repository::IWebsocketTicketRepository,
+    },
 };
 
 #[async_trait]
@@ -21,6 +32,11 @@ pub trait IAppState<F: IFriendRepository, SM: ISessionManager<S, F>, S: ISession
     fn get_db_pool(&self) -> r2d2::PooledConnection<r2d2::ConnectionManager<PgConnection>>;
     fn get_session_manager(&self) -> &SM;
     fn get_config(&self) -> ConfigManager;
+    fn get_keyring(&self) -> &Keyring;
+    fn get_token_revocations(&self) -> &dyn ITokenRevocationRepository;
+    fn get_websocket_tickets(&self) -> &dyn IWebsocketTicketRepository;
+    fn get_client_binding(&self) -> ClientBinding;
+    fn get_cookie_settings(&self) -> &CookieSettings;
 }
 
 #[derive(Debug)]
@@ -36,6 +52,21 @@ where
     // Hashmap of currently logged in users
     pub current_user_connections: SM,
     pub config: ConfigManager,
+    // Keys tokens are signed and verified with
+    pub keyring: Keyring,
+    // Tokens rejected before they expire, e.g. after a logout
+    pub token_revocations: Arc<dyn ITokenRevocationRepository>,
+    // Single use tickets authenticating websocket handshakes
+    pub websocket_tickets: Arc<dyn IWebsocketTicketRepository>,
+    // Client attributes tokens are bound to
+    pub client_binding: ClientBinding,
+    // Attributes of the session cookies
+    pub cookie_settings: CookieSettings,
+    // Typing indicators per sender
+    pub typing_rate_limiter: RateLimiter,
+    // Failed logins per username and per ip address, shared by all instances
+    pub login_throttle_by_username: LoginThrottle,
+    pub login_throttle_by_ip: LoginThrottle,
     pub phantom1: PhantomData<S>,
     pub phantom2: PhantomData<F>,
 }
@@ -52,13 +83,48 @@ unsafe impl<SM: ISessionManager<S, F>, F: IFriendRepository, S: ISession<F>, C:
 impl<S: ISession<F>, SM: ISessionManager<S, F>, F: IFriendRepository, C: IConnectionManager>
     AppState<SM, S, C, F>
 {
-    pub fn new(cm: C, config: ConfigManager, session_manager: SM) -> Self {
+    pub fn new(
+        cm: C,
+        config: ConfigManager,
+        session_manager: SM,
+        token_revocations: Arc<dyn ITokenRevocationRepository>,
+        websocket_tickets: Arc<dyn IWebsocketTicketRepository>,
+        login_failures: Arc<dyn ILoginFailureRepository>,
+    ) -> Self {
         let (tx, _rx) = broadcast::channel(100);
+        let keyring = Keyring::from_config(&config.env).expect("Invalid JWT keys");
+        let client_binding =
+            ClientBinding::from_name(&config.env.CLIENT_BINDING).expect("Invalid CLIENT_BINDING");
+        let cookie_settings =
+            CookieSettings::from_config(&config.env).expect("Invalid cookie configuration");
         AppState {
             connection_manager: cm,
             broadcast: tx,
             config: config,
+            keyring,
+            token_revocations,
+            websocket_tickets,
+            client_binding,
+            cookie_settings,
             current_user_connections: session_manager,
+            typing_rate_limiter: RateLimiter::new(10, Duration::from_secs(5)),
+            login_throttle_by_username: LoginThrottle::new(
+                "username",
+                login_failures.clone(),
+                5,
+                Duration::from_secs(30),
+                Duration::from_secs(15 * 60),
+                Duration::from_secs(60 * 60),
+            ),
+            // A single ip trying many usernames is credential stuffing, so it gets more attempts but longer lockouts
+            login_throttle_by_ip: LoginThrottle::new(
+                "ip",
+                login_failures,
+                20,
+                Duration::from_secs(60),
+                Duration::from_secs(60 * 60),
+                Duration::from_secs(60 * 60),
+            ),
             phantom1: PhantomData,
             phantom2: PhantomData,
         }
@@ -72,6 +138,21 @@ impl<SM: ISessionManager<S, F>, F: IFriendRepository, S: ISession<F>, C: IConnec
     fn get_config(&self) -> ConfigManager {
         self.config.clone()
     }
+    fn get_keyring(&self) -> &Keyring {
+        &self.keyring
+    }
+    fn get_token_revocations(&self) -> &dyn ITokenRevocationRepository {
+        self.token_revocations.as_ref()
+    }
+    fn get_websocket_tickets(&self) -> &dyn IWebsocketTicketRepository {
+        self.websocket_tickets.as_ref()
+    }
+    fn get_client_binding(&self) -> ClientBinding {
+        self.client_binding
+    }
+    fn get_cookie_settings(&self) -> &CookieSettings {
+        &self.cookie_settings
+    }
     fn get_session_manager(&self) -> &SM {
         &self.current_user_connections
     }
@@ -7,6 +7,32 @@ pub struct EnvConfig {
     pub HASHING_KEY: String,
     pub APP_VERSION: String,
     pub CORS_ORIGIN: Option<String>,
+    // Either "memory" (default) or "postgres"
+    pub SESSION_STORE: String,
+    // Either "in-process" (default, single instance) or "postgres"
+    pub BACKPLANE: String,
+    // Comma separated kid:algorithm:key entries, see Keyring::from_config
+    #[serde(skip_serializing)]
+    pub JWT_KEYS: String,
+    // Key new tokens are signed with, defaults to the first one of JWT_KEYS
+    pub JWT_SIGNING_KEY_ID: Option<String>,
+    // Root of the secrets which are not token keys (client fingerprints, key challenges), independent of JWT_KEYS
+    #[serde(skip_serializing)]
+    pub SERVER_SECRET: String,
+    pub JWT_ISSUER: String,
+    pub JWT_AUDIENCE: String,
+    // Client attributes tokens are bound to: "off", "user-agent" (default) or "strict", which includes the network
+    pub CLIENT_BINDING: String,
+    // Attributes of the session cookies. Secure unless COOKIE_SECURE is "false", SameSite defaults to "Lax"
+    pub COOKIE_SECURE: bool,
+    pub COOKIE_SAMESITE: String,
+    pub COOKIE_DOMAIN: Option<String>,
+    // Whether websocket handshakes may pass the access token as query parameter, true unless "false". Tickets are always accepted
+    pub WS_QUERY_TOKEN: bool,
+    // Whether registrations may let the service generate their keypair, false unless "true". The private key is returned once
+    pub SERVER_KEY_GENERATION: bool,
+    // Whether the signatures of incoming messages are checked against the key of the sender, false unless "true"
+    pub VERIFY_MESSAGE_SIGNATURES: bool,
 }
 
 impl EnvConfig {
@@ -20,6 +46,20 @@ impl EnvConfig {
                 Ok(r) => Some(r),
                 Err(_) => None,
             },
+            SESSION_STORE: env::var("SESSION_STORE").unwrap_or_else(|_| String::from("memory")),
+            BACKPLANE: env::var("BACKPLANE").unwrap_or_else(|_| String::from("in-process")),
+            JWT_KEYS: env::var("JWT_KEYS").expect("missing env JWT_KEYS"),
+            JWT_SIGNING_KEY_ID: env::var("JWT_SIGNING_KEY_ID").ok(),
+            SERVER_SECRET: env::var("SERVER_SECRET").expect("missing env SERVER_SECRET"),
+            JWT_ISSUER: env::var("JWT_ISSUER").unwrap_or_else(|_| String::from("sanctumchat-service")),
+            JWT_AUDIENCE: env::var("JWT_AUDIENCE").unwrap_or_else(|_| String::from("sanctumchat")),
+            CLIENT_BINDING: env::var("CLIENT_BINDING").unwrap_or_else(|_| String::from("user-agent")),
+            COOKIE_SECURE: env::var("COOKIE_SECURE").map_or(true, |secure| secure != "false"),
+            COOKIE_SAMESITE: env::var("COOKIE_SAMESITE").unwrap_or_else(|_| String::from("Lax")),
+            COOKIE_DOMAIN: env::var("COOKIE_DOMAIN").ok(),
+            WS_QUERY_TOKEN: env::var("WS_QUERY_TOKEN").map_or(true, |enabled| enabled != "false"),
+            SERVER_KEY_GENERATION: env::var("SERVER_KEY_GENERATION").map_or(false, |enabled| enabled == "true"),
+            VERIFY_MESSAGE_SIGNATURES: env::var("VERIFY_MESSAGE_SIGNATURES").map_or(false, |enabled| enabled == "true"),
         }
     }
 }
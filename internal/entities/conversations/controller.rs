@@ -0,0 +1,52 @@
+use std::sync::Arc;
+
+use axum::http::StatusCode;
+use axum::Extension;
+use axum::{extract::State, response::IntoResponse};
+
+use crate::appstate::{AppState, IAppState};
+use crate::entities::friends::repository::IFriendRepository;
+use crate::helper::errors::HTTPResponse;
+use crate::helper::jwt::Token;
+use crate::helper::session::{ISession, ISessionManager};
+use crate::persistence::connection_manager::IConnectionManager;
+
+use super::conversations::{ConversationDTO, ConversationDomain};
+use super::repository::ConversationRepository;
+
+pub async fn get_conversations<
+    SM: ISessionManager<S, F>,
+    S: ISession<F>,
+    F: IFriendRepository,
+    C: IConnectionManager,
+>(
+    State(app_state): State<Arc<AppState<SM, S, C, F>>>,
+    token: Extension<Token>,
+) -> impl IntoResponse {
+    let repo = ConversationRepository {
+        pg_pool: app_state.get_db_pool(),
+    };
+    let mut domain = ConversationDomain::new(repo);
+
+    let mut conversations = match domain.get_conversations(&token.sub) {
+        Err(err) => return err.into_response(),
+        Ok(res) => res,
+    };
+
+    let session_manager = app_state.get_session_manager();
+    for conversation in conversations.iter_mut() {
+        match &conversation.peer {
+            Some(peer) if conversation.is_friend => {
+                conversation.is_online = Some(session_manager.is_user_connected(peer).await);
+            }
+            _ => {}
+        }
+    }
+
+    HTTPResponse::<Vec<ConversationDTO>> {
+        status: StatusCode::OK,
+        data: Some(conversations),
+        message: None,
+    }
+    .into_response()
+}
@@ -0,0 +1,77 @@
+use uuid::Uuid;
+
+use crate::{entities::messages::repository::MessageDTO, helper::errors::HTTPResponse};
+
+use super::repository::{ConversationRepositoryInterface, ConversationRow};
+
+/// Entry of the inbox. Either a direct conversation with `peer` or a room with `room_id`.
+/// Read state is only tracked for direct messages, so rooms never have unread messages
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq)]
+pub struct ConversationDTO {
+    pub peer: Option<String>,
+    pub room_id: Option<Uuid>,
+    pub name: String,
+    pub last_message: Option<MessageDTO>,
+    pub unread_message_count: i64,
+    /// Whether the peer is currently a friend of the user, always `false` for rooms
+    pub is_friend: bool,
+    /// Online state of the peer, only set for friends
+    pub is_online: Option<bool>,
+}
+
+impl From<ConversationRow> for ConversationDTO {
+    fn from(row: ConversationRow) -> Self {
+        let last_message = match (row.message_id, row.message_sender, row.message_sent_at) {
+            (Some(id), Some(sender), Some(sent_at)) => Some(MessageDTO {
+                id,
+                sender,
+                recipient: row.message_recipient,
+                room_id: row.room_id,
+                sent_at,
+                content: row.message_content.unwrap_or_default(),
+                content_signature: row.message_content_signature.unwrap_or_default(),
+                is_read: row.message_is_read.unwrap_or(false),
+                delivered_at: row.message_delivered_at,
+                edited_at: row.message_edited_at,
+                deleted_at: row.message_deleted_at,
+                key_id: row.message_key_id,
+                sender_key_id: row.message_sender_key_id,
+            }),
+            _ => None,
+        };
+
+        ConversationDTO {
+            peer: row.peer,
+            room_id: row.room_id,
+            name: row.name,
+            last_message,
+            unread_message_count: row.unread_message_count,
+            is_friend: row.is_friend,
+            is_online: None,
+        }
+    }
+}
+
+pub struct ConversationDomain<I: ConversationRepositoryInterface> {
+    conversation_repository: I,
+}
+
+impl<I: ConversationRepositoryInterface> ConversationDomain<I> {
+    pub fn new(conversation_repository: I) -> Self {
+        return Self {
+            conversation_repository,
+        };
+    }
+
+    /// The online state is left to the caller, since it is only known to the session manager.
+    /// It must only be reported for friends, like the online users pushed on connect
+    pub fn get_conversations(
+        &mut self,
+        username: &String,
+    ) -> Result<Vec<ConversationDTO>, HTTPResponse<()>> {
+        match self.conversation_repository.get_conversations(username) {
+            Err(err) => Err(HTTPResponse::new_internal_error(err)),
+            Ok(rows) => Ok(rows.into_iter().map(ConversationDTO::from).collect()),
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use std::time::SystemTime;
+
+use uuid::Uuid;
+
+use super::repository::{ConversationRepositoryInterface, ConversationRow};
+
+struct ConversationRepositoryMock {}
+
+fn empty_row(name: &str) -> ConversationRow {
+    ConversationRow {
+        peer: None,
+        room_id: None,
+        name: String::from(name),
+        is_friend: false,
+        unread_message_count: 0,
+        message_id: None,
+        message_sender: None,
+        message_recipient: None,
+        message_sent_at: None,
+        message_content: None,
+        message_content_signature: None,
+        message_is_read: None,
+        message_delivered_at: None,
+        message_edited_at: None,
+        message_deleted_at: None,
+        message_key_id: None,
+        message_sender_key_id: None,
+    }
+}
+
+impl ConversationRepositoryInterface for ConversationRepositoryMock {
+    fn get_conversations(&mut self, username: &String) -> Result<Vec<ConversationRow>, String> {
+        let direct = ConversationRow {
+            peer: Some(String::from("Friend")),
+            is_friend: true,
+            unread_message_count: 2,
+            message_id: Some(Uuid::new_v4()),
+            message_sender: Some(String::from("Friend")),
+            message_recipient: Some(username.clone()),
+            message_sent_at: Some(SystemTime::now()),
+            message_content: Some(String::from("Content")),
+            message_content_signature: Some(String::from("Signature")),
+            message_is_read: Some(false),
+            ..empty_row("Friend")
+        };
+        let room = ConversationRow {
+            room_id: Some(Uuid::new_v4()),
+            ..empty_row("Room")
+        };
+        let silent_friend = ConversationRow {
+            peer: Some(String::from("Silent")),
+            is_friend: true,
+            ..empty_row("Silent")
+        };
+        // Someone the user exchanged messages with but who is no friend (anymore)
+        let stranger = ConversationRow {
+            peer: Some(String::from("Stranger")),
+            ..empty_row("Stranger")
+        };
+        return Ok(vec![direct, room, silent_friend, stranger]);
+    }
+}
+
+pub mod conversation_tests {
+    use crate::entities::conversations::conversations::ConversationDomain;
+
+    use super::ConversationRepositoryMock;
+
+    #[test]
+    fn test_get_conversations() {
+        let mut domain = ConversationDomain::new(ConversationRepositoryMock {});
+
+        let result = domain.get_conversations(&String::from("User")).unwrap();
+        assert_eq!(result.len(), 4);
+
+        let direct = &result[0];
+        assert_eq!(direct.peer, Some(String::from("Friend")));
+        assert!(direct.is_friend);
+        assert_eq!(direct.unread_message_count, 2);
+        let last_message = direct.last_message.as_ref().unwrap();
+        assert_eq!(last_message.sender, String::from("Friend"));
+        assert_eq!(last_message.recipient, Some(String::from("User")));
+        assert_eq!(last_message.content, String::from("Content"));
+
+        let room = &result[1];
+        assert!(room.room_id.is_some());
+        assert_eq!(room.last_message, None);
+        assert_eq!(room.is_online, None);
+        assert!(!room.is_friend);
+
+        assert_eq!(result[2].peer, Some(String::from("Silent")));
+        assert_eq!(result[2].last_message, None);
+
+        assert_eq!(result[3].peer, Some(String::from("Stranger")));
+        assert!(!result[3].is_friend);
+    }
+}
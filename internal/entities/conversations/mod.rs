@@ -0,0 +1,4 @@
+pub mod controller;
+pub mod conversations;
+pub mod conversations_test;
+pub mod repository;
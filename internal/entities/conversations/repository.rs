@@ -0,0 +1,158 @@
+use std::time::SystemTime;
+
+use diesel::prelude::*;
+use diesel::{
+    r2d2::{ConnectionManager, PooledConnection},
+    sql_types::{BigInt, Bool, Nullable, Text, Timestamp},
+    PgConnection,
+};
+use uuid::Uuid;
+
+/// A conversation of the user with its latest message, as returned by the database.
+/// Direct conversations have `peer` set, rooms have `room_id` set. The `message_` columns are null if nothing was sent yet
+#[derive(Debug, QueryableByName, Clone, PartialEq)]
+pub struct ConversationRow {
+    #[diesel(sql_type = Nullable<Text>)]
+    pub peer: Option<String>,
+    #[diesel(sql_type = Nullable<diesel::sql_types::Uuid>)]
+    pub room_id: Option<Uuid>,
+    #[diesel(sql_type = Text)]
+    pub name: String,
+    #[diesel(sql_type = Bool)]
+    pub is_friend: bool,
+    #[diesel(sql_type = BigInt)]
+    pub unread_message_count: i64,
+    #[diesel(sql_type = Nullable<diesel::sql_types::Uuid>)]
+    pub message_id: Option<Uuid>,
+    #[diesel(sql_type = Nullable<Text>)]
+    pub message_sender: Option<String>,
+    #[diesel(sql_type = Nullable<Text>)]
+    pub message_recipient: Option<String>,
+    #[diesel(sql_type = Nullable<Timestamp>)]
+    pub message_sent_at: Option<SystemTime>,
+    #[diesel(sql_type = Nullable<Text>)]
+    pub message_content: Option<String>,
+    #[diesel(sql_type = Nullable<Text>)]
+    pub message_content_signature: Option<String>,
+    #[diesel(sql_type = Nullable<Bool>)]
+    pub message_is_read: Option<bool>,
+    #[diesel(sql_type = Nullable<Timestamp>)]
+    pub message_delivered_at: Option<SystemTime>,
+    #[diesel(sql_type = Nullable<Timestamp>)]
+    pub message_edited_at: Option<SystemTime>,
+    #[diesel(sql_type = Nullable<Timestamp>)]
+    pub message_deleted_at: Option<SystemTime>,
+    #[diesel(sql_type = Nullable<diesel::sql_types::Uuid>)]
+    pub message_key_id: Option<Uuid>,
+    #[diesel(sql_type = Nullable<diesel::sql_types::Uuid>)]
+    pub message_sender_key_id: Option<Uuid>,
+}
+
+pub trait ConversationRepositoryInterface {
+    /// Every direct conversation (friends and anyone the user exchanged messages with) and every room of the user,
+    /// most recent activity first
+    fn get_conversations(&mut self, username: &String) -> Result<Vec<ConversationRow>, String>;
+}
+
+pub struct ConversationRepository {
+    pub pg_pool: PooledConnection<ConnectionManager<PgConnection>>,
+}
+
+impl ConversationRepositoryInterface for ConversationRepository {
+    fn get_conversations(&mut self, username: &String) -> Result<Vec<ConversationRow>, String> {
+        // Only the copies addressed to the user are joined, so the latest message is always one the user can decrypt
+        let query = diesel::sql_query(
+            "
+            WITH peers AS (
+                SELECT f.befriended_user_id AS peer FROM friends AS f WHERE f.user_id = $1
+                UNION
+                SELECT CASE WHEN m.sender = $1 THEN m.recipient ELSE m.sender END AS peer
+                FROM messages AS m
+                WHERE m.room_id IS NULL AND (m.sender = $1 OR m.recipient = $1)
+            )
+            SELECT * FROM (
+                SELECT
+                    p.peer AS peer,
+                    NULL::uuid AS room_id,
+                    p.peer AS name,
+                    EXISTS (
+                        SELECT 1 FROM friends AS f WHERE f.user_id = $1 AND f.befriended_user_id = p.peer
+                    ) AS is_friend,
+                    (
+                        SELECT COUNT(*) FROM messages AS m
+                        WHERE m.room_id IS NULL AND m.sender = p.peer AND m.recipient = $1
+                        AND m.is_read = 'f' AND m.deleted_at IS NULL
+                    ) AS unread_message_count,
+                    latest.*,
+                    latest.message_sent_at AS last_activity
+                FROM peers AS p
+                LEFT JOIN LATERAL (
+                    SELECT
+                        m.id AS message_id,
+                        m.sender AS message_sender,
+                        m.recipient AS message_recipient,
+                        m.sent_at AS message_sent_at,
+                        mp.content AS message_content,
+                        mp.content_signature AS message_content_signature,
+                        m.is_read AS message_is_read,
+                        m.delivered_at AS message_delivered_at,
+                        m.edited_at AS message_edited_at,
+                        m.deleted_at AS message_deleted_at,
+                        mp.key_id AS message_key_id,
+                        mp.sender_key_id AS message_sender_key_id
+                    FROM messages AS m
+                    INNER JOIN message_payloads AS mp ON mp.message_id = m.id AND mp.recipient = $1
+                    WHERE m.room_id IS NULL
+                    AND ((m.sender = $1 AND m.recipient = p.peer) OR (m.sender = p.peer AND m.recipient = $1))
+                    ORDER BY m.sent_at DESC, m.id DESC
+                    LIMIT 1
+                ) AS latest ON true
+                WHERE p.peer IS NOT NULL
+
+                UNION ALL
+
+                SELECT
+                    NULL AS peer,
+                    r.id AS room_id,
+                    r.name AS name,
+                    false AS is_friend,
+                    0::bigint AS unread_message_count,
+                    latest.*,
+                    COALESCE(latest.message_sent_at, rm.joined_at) AS last_activity
+                FROM rooms AS r
+                INNER JOIN room_members AS rm ON rm.room_id = r.id AND rm.user_id = $1
+                LEFT JOIN LATERAL (
+                    SELECT
+                        m.id AS message_id,
+                        m.sender AS message_sender,
+                        m.recipient AS message_recipient,
+                        m.sent_at AS message_sent_at,
+                        mp.content AS message_content,
+                        mp.content_signature AS message_content_signature,
+                        m.is_read AS message_is_read,
+                        m.delivered_at AS message_delivered_at,
+                        m.edited_at AS message_edited_at,
+                        m.deleted_at AS message_deleted_at,
+                        mp.key_id AS message_key_id,
+                        mp.sender_key_id AS message_sender_key_id
+                    FROM messages AS m
+                    INNER JOIN message_payloads AS mp ON mp.message_id = m.id AND mp.recipient = $1
+                    WHERE m.room_id = r.id
+                    ORDER BY m.sent_at DESC, m.id DESC
+                    LIMIT 1
+                ) AS latest ON true
+            ) AS conversations
+            ORDER BY last_activity DESC NULLS LAST, name ASC
+            ",
+        )
+        .bind::<Text, _>(username);
+
+        match query.load::<ConversationRow>(&mut self.pg_pool) {
+            Err(err) => Err(format!(
+                "Could not get conversations of {}: {}",
+                username, err
+            )),
+            Ok(res) => Ok(res),
+        }
+    }
+}
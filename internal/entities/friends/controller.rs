@@ -85,21 +85,15 @@ pub async fn create_friend_request<
         Err(err) => return err.into_response(),
     };
 
-    let receiver_session_manager = app_state
+    let friend_request_message =
+        SocketMessageFriendRequest::new(friend_request.id, token.sub.clone());
+    app_state
         .get_session_manager()
-        .get_current_user_connections()
-        .lock()
+        .send_to_user(
+            &recipient,
+            SocketMessage::SocketMessageFriendRequest(friend_request_message),
+        )
         .await;
-    let receiver_session_manager = receiver_session_manager.get(&recipient.clone());
-    if let Some(sm) = receiver_session_manager {
-        let sm = sm.lock().await;
-        let friend_request_message =
-            SocketMessageFriendRequest::new(friend_request.id, token.sub.clone());
-        sm.send_direct_message(SocketMessage::SocketMessageFriendRequest(
-            friend_request_message,
-        ))
-        .await;
-    };
 
     HTTPResponse::<FriendRequest> {
         status: StatusCode::CREATED,
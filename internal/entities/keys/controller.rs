@@ -0,0 +1,154 @@
+use std::sync::Arc;
+
+use axum::extract::Path;
+use axum::http::StatusCode;
+use axum::{
+    extract::State,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+
+use crate::appstate::{AppState, IAppState};
+use crate::entities::friends::repository::{FriendRepository, IFriendRepository};
+use crate::entities::friends::service::FriendDomain;
+use crate::helper::errors::HTTPResponse;
+use crate::helper::jwt::Token;
+use crate::helper::keys::KEY_CHALLENGE_SECRET;
+use crate::helper::session::{ISession, ISessionManager};
+use crate::interfaces::websockets::socket_messages::{SocketMessage, SocketMessageKeyChange};
+use crate::persistence::connection_manager::IConnectionManager;
+
+use super::keys::KeyDomain;
+use super::repository::{KeyRepository, UserKeyDTO};
+
+/// `signature` is the base64 encoded signature of `challenge` from `POST /users/key-challenge` with the new private key
+#[derive(serde::Deserialize)]
+pub struct KeyPOSTRequestDTO {
+    /// Base64 encoded PEM, like on registration
+    pub public_key: String,
+    pub challenge: Option<String>,
+    pub signature: Option<String>,
+}
+
+/// Replaces the public key of the user. Friends which are online and the other devices of the user are told about the new key
+pub async fn rotate_key<
+    SM: ISessionManager<S, F>,
+    S: ISession<F>,
+    F: IFriendRepository,
+    C: IConnectionManager,
+>(
+    State(app_state): State<Arc<AppState<SM, S, C, F>>>,
+    token: Extension<Token>,
+    Json(body): Json<KeyPOSTRequestDTO>,
+) -> impl IntoResponse {
+    let mut key_domain = KeyDomain::new(KeyRepository {
+        pg_pool: app_state.get_db_pool(),
+    });
+
+    let key = match key_domain.rotate_key(
+        &token.sub,
+        &body.public_key,
+        &body.challenge,
+        &body.signature,
+        &app_state.get_keyring().derive_secret(KEY_CHALLENGE_SECRET),
+    ) {
+        Err(err) => return err.into_response(),
+        Ok(key) => UserKeyDTO::from(key),
+    };
+
+    let mut recipients: Vec<String> = app_state
+        .get_session_manager()
+        .get_friends_in_current_user_connections(&token.sub)
+        .await
+        .into_keys()
+        .collect();
+    recipients.push(token.sub.clone());
+    for recipient in recipients {
+        app_state
+            .get_session_manager()
+            .send_to_user(
+                &recipient,
+                SocketMessage::SocketMessageKeyChange(SocketMessageKeyChange::new(
+                    token.sub.clone(),
+                    key.id,
+                    key.public_key.clone(),
+                )),
+            )
+            .await;
+    }
+
+    HTTPResponse::<UserKeyDTO> {
+        status: StatusCode::CREATED,
+        data: Some(key),
+        message: Some(String::from("Public key replaced")),
+    }
+    .into_response()
+}
+
+/// Key history of the user or one of their friends, the current key first
+pub async fn get_keys<
+    SM: ISessionManager<S, F>,
+    S: ISession<F>,
+    F: IFriendRepository,
+    C: IConnectionManager,
+>(
+    State(app_state): State<Arc<AppState<SM, S, C, F>>>,
+    token: Extension<Token>,
+    Path(username): Path<String>,
+) -> impl IntoResponse {
+    if username != token.sub {
+        let friend_domain = FriendDomain::new(FriendRepository {
+            pg_pool: C::new(app_state.get_config().env),
+        });
+        match friend_domain.check_if_user_has_friend(&token.sub, &username) {
+            Err(err) => return HTTPResponse::<()>::new_internal_error(err).into_response(),
+            Ok(false) => {
+                return HTTPResponse::<()> {
+                    status: StatusCode::FORBIDDEN,
+                    data: None,
+                    message: Some(format!("You are not befriended with {}", username)),
+                }
+                .into_response()
+            }
+            Ok(true) => {}
+        }
+    }
+
+    key_history_response(&app_state, &username)
+}
+
+/// Key history of the user, the current key first
+pub async fn get_own_keys<
+    SM: ISessionManager<S, F>,
+    S: ISession<F>,
+    F: IFriendRepository,
+    C: IConnectionManager,
+>(
+    State(app_state): State<Arc<AppState<SM, S, C, F>>>,
+    token: Extension<Token>,
+) -> impl IntoResponse {
+    key_history_response(&app_state, &token.sub)
+}
+
+fn key_history_response<
+    SM: ISessionManager<S, F>,
+    S: ISession<F>,
+    F: IFriendRepository,
+    C: IConnectionManager,
+>(
+    app_state: &Arc<AppState<SM, S, C, F>>,
+    username: &String,
+) -> Response {
+    let mut key_domain = KeyDomain::new(KeyRepository {
+        pg_pool: app_state.get_db_pool(),
+    });
+    match key_domain.get_keys(username) {
+        Err(err) => err.into_response(),
+        Ok(keys) => HTTPResponse::<Vec<UserKeyDTO>> {
+            status: StatusCode::OK,
+            data: Some(keys.into_iter().map(UserKeyDTO::from).collect()),
+            message: None,
+        }
+        .into_response(),
+    }
+}
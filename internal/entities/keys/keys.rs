@@ -0,0 +1,97 @@
+use std::time::SystemTime;
+
+use axum::http::StatusCode;
+use base64::Engine;
+use uuid::Uuid;
+
+use crate::{
+    helper::{
+        errors::HTTPResponse,
+        keys::{check_proof_of_possession, validate_public_key},
+    },
+    models::UserKey,
+};
+
+use super::repository::KeyRepositoryInterface;
+
+pub struct KeyDomain<I: KeyRepositoryInterface> {
+    key_repository: I,
+}
+
+impl<I: KeyRepositoryInterface> KeyDomain<I> {
+    pub fn new(key_repository: I) -> Self {
+        return Self { key_repository };
+    }
+
+    /// Every key of the user, the current one first
+    pub fn get_keys(&mut self, username: &String) -> Result<Vec<UserKey>, HTTPResponse<()>> {
+        match self.key_repository.get_keys(username) {
+            Err(err) => Err(HTTPResponse::new_internal_error(err)),
+            Ok(keys) => Ok(keys),
+        }
+    }
+
+    /// Replaces the current key of the user. `public_key` is the base64 encoded PEM, like on registration.
+    /// Like on registration, the client has to sign a key challenge with the new key. `challenge_secret` is the
+    /// `key-challenge` secret of the keyring. The previous keys are kept, so messages encrypted for or signed with them can still be verified
+    pub fn rotate_key(
+        &mut self,
+        username: &String,
+        public_key: &String,
+        challenge: &Option<String>,
+        signature: &Option<String>,
+        challenge_secret: &[u8],
+    ) -> Result<UserKey, HTTPResponse<()>> {
+        let bad_request = |message: &str| HTTPResponse {
+            status: StatusCode::BAD_REQUEST,
+            data: None,
+            message: Some(String::from(message)),
+        };
+
+        let decoded = match base64::engine::general_purpose::STANDARD.decode(public_key) {
+            Err(_) => return Err(bad_request("Public key is not base64 encoded")),
+            Ok(decoded) => decoded,
+        };
+        if validate_public_key(&decoded).is_err() {
+            return Err(bad_request(
+                "Could not validate public key. Ensure that its using .PEM PKCS#8 format",
+            ));
+        }
+        if let Err(err) =
+            check_proof_of_possession(challenge_secret, username, &decoded, challenge, signature)
+        {
+            tracing::info!(target: "audit", "[rotate_key] rejected key of {}: {}", username, err);
+            return Err(HTTPResponse {
+                status: StatusCode::BAD_REQUEST,
+                data: None,
+                message: Some(format!("Proof of possession failed: {}", err)),
+            });
+        }
+
+        let keys = self.get_keys(username)?;
+        if keys
+            .iter()
+            .any(|key| key.public_key == public_key.as_bytes())
+        {
+            return Err(HTTPResponse {
+                status: StatusCode::CONFLICT,
+                data: None,
+                message: Some(String::from("This key was already used")),
+            });
+        }
+
+        let key = UserKey {
+            id: Uuid::new_v4(),
+            username: username.clone(),
+            public_key: public_key.as_bytes().to_vec(),
+            created_at: SystemTime::now(),
+            replaced_at: None,
+        };
+        if let Err(err) = self.key_repository.save_current_key(&key) {
+            return Err(HTTPResponse::new_internal_error(err));
+        }
+
+        tracing::info!(target: "audit", "[rotate_key] user {} rotated their public key to {}", username, key.id);
+        Ok(key)
+    }
+}
@@ -0,0 +1,170 @@
+use crate::models::UserKey;
+
+use super::repository::KeyRepositoryInterface;
+
+#[derive(Default)]
+pub struct KeyRepositoryMock {
+    pub keys: Vec<UserKey>,
+}
+
+impl KeyRepositoryInterface for KeyRepositoryMock {
+    fn get_keys(&mut self, username: &String) -> Result<Vec<UserKey>, String> {
+        let mut keys: Vec<UserKey> = self
+            .keys
+            .iter()
+            .filter(|k| &k.username == username)
+            .cloned()
+            .collect();
+        keys.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        return Ok(keys);
+    }
+
+    fn save_current_key(&mut self, key: &UserKey) -> Result<(), String> {
+        for current in self
+            .keys
+            .iter_mut()
+            .filter(|k| k.username == key.username && k.replaced_at.is_none())
+        {
+            current.replaced_at = Some(key.created_at);
+        }
+        self.keys.push(key.clone());
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod integration_tests {
+    use std::time::{Duration, SystemTime};
+
+    use axum::http::StatusCode;
+    use base64::Engine;
+    use openssl::{hash::MessageDigest, pkey::PKey, rsa::Rsa, sign::Signer};
+    use uuid::Uuid;
+
+    use crate::{
+        entities::keys::{keys::KeyDomain, keys_test::KeyRepositoryMock},
+        helper::keys::{generate_rsa_key_pair, issue_key_challenge},
+        models::UserKey,
+    };
+
+    const SECRET: &[u8] = b"secret";
+
+    fn username() -> String {
+        String::from("TestUser")
+    }
+
+    /// A base64 encoded public key along with a challenge and the base64 encoded signature of it with the private key
+    struct NewKey {
+        public_key: String,
+        challenge: Option<String>,
+        signature: Option<String>,
+    }
+
+    fn new_key() -> NewKey {
+        let (private_key, public_key) = generate_rsa_key_pair().unwrap();
+        let challenge = issue_key_challenge(SECRET, &username(), SystemTime::now()).unwrap();
+        let pkey = PKey::from_rsa(Rsa::private_key_from_pem(&private_key).unwrap()).unwrap();
+        let mut signer = Signer::new(MessageDigest::sha256(), &pkey).unwrap();
+        let signature = signer.sign_oneshot_to_vec(challenge.as_bytes()).unwrap();
+        NewKey {
+            public_key: base64::engine::general_purpose::STANDARD.encode(public_key),
+            challenge: Some(challenge),
+            signature: Some(base64::engine::general_purpose::STANDARD.encode(signature)),
+        }
+    }
+
+    fn new_public_key() -> String {
+        new_key().public_key
+    }
+
+    fn rotate(domain: &mut KeyDomain<KeyRepositoryMock>, key: &NewKey) -> Result<UserKey, StatusCode> {
+        domain
+            .rotate_key(&username(), &key.public_key, &key.challenge, &key.signature, SECRET)
+            .map_err(|err| err.status)
+    }
+
+    /// Domain of a user which registered with `public_key`
+    fn registered_domain(public_key: &String) -> KeyDomain<KeyRepositoryMock> {
+        KeyDomain::new(KeyRepositoryMock {
+            keys: vec![UserKey {
+                id: Uuid::new_v4(),
+                username: username(),
+                public_key: public_key.as_bytes().to_vec(),
+                created_at: SystemTime::now() - Duration::from_secs(60),
+                replaced_at: None,
+            }],
+        })
+    }
+
+    #[test]
+    fn test_rotate_key_keeps_history() {
+        let first_key = new_public_key();
+        let mut domain = registered_domain(&first_key);
+        let registered = domain.get_keys(&username()).unwrap();
+
+        let second_key = new_key();
+        let rotated = rotate(&mut domain, &second_key).unwrap();
+        assert_eq!(rotated.public_key, second_key.public_key.as_bytes());
+        assert_eq!(rotated.replaced_at, None);
+
+        let keys = domain.get_keys(&username()).unwrap();
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0], rotated);
+        assert_eq!(keys[1].id, registered[0].id);
+        assert_eq!(keys[1].public_key, first_key.as_bytes());
+        assert_eq!(keys[1].replaced_at, Some(rotated.created_at));
+    }
+
+    #[test]
+    fn test_rotate_key_rejects_invalid_keys() {
+        let mut domain = registered_domain(&new_public_key());
+
+        let mut not_base64 = new_key();
+        not_base64.public_key = String::from("not base64!");
+        assert_eq!(rotate(&mut domain, &not_base64), Err(StatusCode::BAD_REQUEST));
+
+        let mut not_pem = new_key();
+        not_pem.public_key = base64::engine::general_purpose::STANDARD.encode("not a key");
+        assert_eq!(rotate(&mut domain, &not_pem), Err(StatusCode::BAD_REQUEST));
+
+        assert_eq!(domain.get_keys(&username()).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_rotate_key_requires_proof_of_possession() {
+        let mut domain = registered_domain(&new_public_key());
+
+        let mut without_proof = new_key();
+        without_proof.challenge = None;
+        without_proof.signature = None;
+        assert_eq!(rotate(&mut domain, &without_proof), Err(StatusCode::BAD_REQUEST));
+
+        // A key the user does not hold, signed with another one
+        let mut foreign = new_key();
+        foreign.public_key = new_public_key();
+        assert_eq!(rotate(&mut domain, &foreign), Err(StatusCode::BAD_REQUEST));
+
+        // A challenge issued for someone else
+        let mut other_user = new_key();
+        other_user.challenge =
+            Some(issue_key_challenge(SECRET, &String::from("Other"), SystemTime::now()).unwrap());
+        assert_eq!(rotate(&mut domain, &other_user), Err(StatusCode::BAD_REQUEST));
+
+        let key = new_key();
+        let result = domain.rotate_key(&username(), &key.public_key, &key.challenge, &key.signature, b"other");
+        assert_eq!(result.unwrap_err().status, StatusCode::BAD_REQUEST);
+
+        assert_eq!(domain.get_keys(&username()).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_rotate_key_rejects_previous_keys() {
+        let first_key = new_key();
+        let mut domain = registered_domain(&first_key.public_key);
+
+        assert_eq!(rotate(&mut domain, &first_key), Err(StatusCode::CONFLICT));
+
+        rotate(&mut domain, &new_key()).unwrap();
+        assert_eq!(rotate(&mut domain, &first_key), Err(StatusCode::CONFLICT));
+    }
+}
@@ -0,0 +1,4 @@
+pub mod controller;
+pub mod keys;
+pub mod keys_test;
+pub mod repository;
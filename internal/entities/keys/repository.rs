@@ -0,0 +1,88 @@
+use std::time::SystemTime;
+
+use diesel::prelude::*;
+use diesel::{
+    r2d2::{ConnectionManager, PooledConnection},
+    PgConnection,
+};
+use uuid::Uuid;
+
+use crate::{
+    models::UserKey,
+    schema::{user_keys, users},
+};
+
+/// Public key as handed out to clients, `public_key` is the base64 encoded PEM
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq)]
+pub struct UserKeyDTO {
+    pub id: Uuid,
+    pub public_key: String,
+    pub created_at: SystemTime,
+    pub replaced_at: Option<SystemTime>,
+}
+
+impl From<UserKey> for UserKeyDTO {
+    fn from(key: UserKey) -> Self {
+        UserKeyDTO {
+            id: key.id,
+            public_key: String::from_utf8_lossy(&key.public_key).into_owned(),
+            created_at: key.created_at,
+            replaced_at: key.replaced_at,
+        }
+    }
+}
+
+pub trait KeyRepositoryInterface {
+    /// Every key the user ever had, the current one first
+    fn get_keys(&mut self, username: &String) -> Result<Vec<UserKey>, String>;
+    /// Stores the key as the current one of its user and marks the previous one as replaced
+    fn save_current_key(&mut self, key: &UserKey) -> Result<(), String>;
+}
+
+pub struct KeyRepository {
+    pub pg_pool: PooledConnection<ConnectionManager<PgConnection>>,
+}
+
+impl KeyRepositoryInterface for KeyRepository {
+    fn get_keys(&mut self, username: &String) -> Result<Vec<UserKey>, String> {
+        let result = user_keys::table
+            .select(UserKey::as_select())
+            .filter(user_keys::username.eq(username))
+            .order_by(user_keys::created_at.desc())
+            .load::<UserKey>(&mut self.pg_pool);
+
+        match result {
+            Err(err) => Err(format!("Could not get keys of {}: {}", username, err)),
+            Ok(res) => Ok(res),
+        }
+    }
+
+    fn save_current_key(&mut self, key: &UserKey) -> Result<(), String> {
+        let result = self
+            .pg_pool
+            .transaction::<_, diesel::result::Error, _>(|conn| {
+                diesel::update(
+                    user_keys::table
+                        .filter(user_keys::username.eq(&key.username))
+                        .filter(user_keys::replaced_at.is_null()),
+                )
+                .set(user_keys::replaced_at.eq(key.created_at))
+                .execute(conn)?;
+                diesel::insert_into(user_keys::table)
+                    .values(key)
+                    .execute(conn)?;
+                // Kept in sync for everything which only needs the current key
+                diesel::update(users::table.find(&key.username))
+                    .set(users::public_key.eq(&key.public_key))
+                    .execute(conn)
+            });
+
+        match result {
+            Err(err) => Err(format!(
+                "Could not save key of {}: {}",
+                key.username, err
+            )),
+            Ok(_) => Ok(()),
+        }
+    }
+}
@@ -3,26 +3,39 @@ use std::sync::Arc;
 
 use crate::appstate::{AppState, IAppState};
 use crate::entities::friends::repository::IFriendRepository;
+use crate::entities::rooms::{repository::RoomRepository, rooms::RoomDomain};
 use crate::helper::errors::HTTPResponse;
 use crate::helper::jwt::Token;
 use crate::helper::pagination::Pagination;
 use crate::helper::session::{ISession, ISessionManager};
-use crate::models::Message;
+use crate::interfaces::websockets::messages::SocketMessageDelete::{
+    send_message_delete, SocketMessageDelete,
+};
+use crate::interfaces::websockets::messages::SocketMessageEdit::{
+    send_message_edit, SocketMessageEdit,
+};
+use crate::interfaces::websockets::messages::SocketMessageReadReceipt::send_read_receipts;
+use crate::models::MessageEdit;
 use crate::persistence::connection_manager::IConnectionManager;
-use axum::extract::Query;
+use axum::extract::{Path, Query};
 use axum::http::StatusCode;
 use axum::{extract::State, response::IntoResponse};
 use axum::{Extension, Json};
 use serde::{Deserialize, Serialize};
 
 use super::messages::MessageDomain;
-use super::repository::MessageRepository;
+use super::repository::{MessagePage, MessageRepository};
 
 #[derive(serde::Deserialize, Debug, Clone)]
 pub struct GetMessageDTO {
-    pub origin: String,
+    pub origin: Option<String>,
+    pub room_id: Option<uuid::Uuid>,
     pub size: Option<u8>,
-    pub index: Option<u8>,
+    /// Cursor of the `before` or `after` field of a previous page
+    pub before: Option<String>,
+    pub after: Option<String>,
+    /// Id of a message to load together with its surrounding messages
+    pub around: Option<uuid::Uuid>,
 }
 
 pub async fn get_messages<
@@ -40,11 +53,50 @@ pub async fn get_messages<
     };
     let mut domain = MessageDomain::new(repo);
 
-    let pagination = Pagination::new(query.size, query.index);
-    let messages = domain.get_messages(&token.sub, &query.origin, pagination);
+    let pagination = match Pagination::new(query.size, query.before, query.after, query.around)
+    {
+        Err(err) => {
+            return HTTPResponse::<()> {
+                data: None,
+                status: StatusCode::BAD_REQUEST,
+                message: Some(err),
+            }
+            .into_response()
+        }
+        Ok(res) => res,
+    };
+    let messages = match (&query.origin, &query.room_id) {
+        (Some(origin), None) => domain.get_messages(&token.sub, origin, pagination),
+        (None, Some(room_id)) => {
+            let mut room_domain = RoomDomain::new(RoomRepository {
+                pg_pool: app_state.get_db_pool(),
+            });
+            match room_domain.check_if_user_is_room_member(room_id, &token.sub) {
+                Err(err) => return HTTPResponse::<()>::new_internal_error(err).into_response(),
+                Ok(false) => {
+                    return HTTPResponse::<()> {
+                        data: None,
+                        status: StatusCode::FORBIDDEN,
+                        message: Some(String::from("You are not a member of this room")),
+                    }
+                    .into_response()
+                }
+                Ok(true) => {}
+            }
+            domain.get_room_messages(&token.sub, room_id, pagination)
+        }
+        _ => {
+            return HTTPResponse::<()> {
+                data: None,
+                status: StatusCode::BAD_REQUEST,
+                message: Some(String::from("Either origin or room_id has to be specified")),
+            }
+            .into_response()
+        }
+    };
 
     match messages {
-        Ok(res) => HTTPResponse::<Vec<Message>> {
+        Ok(res) => HTTPResponse::<MessagePage> {
             data: Some(res),
             status: StatusCode::OK,
             message: None,
@@ -92,12 +144,155 @@ pub async fn set_messages_read<
     let result = domain.set_message_read(&uuids, &true, &token.sub);
 
     match result {
-        Ok(_) => HTTPResponse::<()> {
-            message: Some(String::from("Successfully edited messages")),
+        Ok(read_by_sender) => {
+            send_read_receipts(app_state.get_session_manager(), &token.sub, read_by_sender).await;
+            HTTPResponse::<()> {
+                message: Some(String::from("Successfully edited messages")),
+                data: None,
+                status: StatusCode::OK,
+            }
+            .into_response()
+        }
+        Err(err) => HTTPResponse::<()>::new_internal_error(String::from(err)).into_response(),
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct MessagePATCHRequestDTO {
+    pub message: String,
+    pub message_signature: String,
+    pub message_self_encrypted: String,
+    pub message_self_encrypted_signature: String,
+    pub key_id: Option<uuid::Uuid>,
+    pub sender_key_id: Option<uuid::Uuid>,
+}
+
+fn parse_message_id(uuid: &String) -> Result<uuid::Uuid, HTTPResponse<()>> {
+    match uuid::Uuid::from_str(uuid) {
+        Err(_) => Err(HTTPResponse {
+            status: StatusCode::BAD_REQUEST,
             data: None,
+            message: Some(format!("Could not parse {} as uuid", uuid)),
+        }),
+        Ok(id) => Ok(id),
+    }
+}
+
+pub async fn edit_message<
+    SM: ISessionManager<S, F>,
+    S: ISession<F>,
+    F: IFriendRepository,
+    C: IConnectionManager,
+>(
+    State(app_state): State<Arc<AppState<SM, S, C, F>>>,
+    token: Extension<Token>,
+    Path(uuid): Path<String>,
+    Json(body): Json<MessagePATCHRequestDTO>,
+) -> impl IntoResponse {
+    let message_id = match parse_message_id(&uuid) {
+        Err(err) => return err.into_response(),
+        Ok(id) => id,
+    };
+
+    let repo = MessageRepository {
+        pg_pool: app_state.get_db_pool(),
+    };
+    let mut domain = MessageDomain::new(repo)
+        .with_signature_verification(app_state.get_config().env.VERIFY_MESSAGE_SIGNATURES);
+
+    let edit = SocketMessageEdit::new(
+        message_id,
+        None,
+        None,
+        body.message,
+        body.message_signature,
+        body.message_self_encrypted,
+        body.message_self_encrypted_signature,
+        body.key_id,
+        body.sender_key_id,
+        None,
+    );
+
+    match domain.edit_message(&token.sub, &edit) {
+        Err(err) => err.into_response(),
+        Ok((_, edit)) => {
+            send_message_edit(app_state.get_session_manager(), edit.clone()).await;
+            HTTPResponse::<SocketMessageEdit> {
+                status: StatusCode::OK,
+                data: Some(edit),
+                message: Some(String::from("Successfully edited message")),
+            }
+            .into_response()
+        }
+    }
+}
+
+pub async fn delete_message<
+    SM: ISessionManager<S, F>,
+    S: ISession<F>,
+    F: IFriendRepository,
+    C: IConnectionManager,
+>(
+    State(app_state): State<Arc<AppState<SM, S, C, F>>>,
+    token: Extension<Token>,
+    Path(uuid): Path<String>,
+) -> impl IntoResponse {
+    let message_id = match parse_message_id(&uuid) {
+        Err(err) => return err.into_response(),
+        Ok(id) => id,
+    };
+
+    let repo = MessageRepository {
+        pg_pool: app_state.get_db_pool(),
+    };
+    let mut domain = MessageDomain::new(repo);
+
+    match domain.delete_message(&token.sub, &message_id) {
+        Err(err) => err.into_response(),
+        Ok(recipients) => {
+            send_message_delete(
+                app_state.get_session_manager(),
+                SocketMessageDelete::new(message_id, Some(token.sub.clone())),
+                recipients,
+            )
+            .await;
+            HTTPResponse::<()> {
+                status: StatusCode::OK,
+                data: None,
+                message: Some(String::from("Successfully deleted message")),
+            }
+            .into_response()
+        }
+    }
+}
+
+pub async fn get_message_edits<
+    SM: ISessionManager<S, F>,
+    S: ISession<F>,
+    F: IFriendRepository,
+    C: IConnectionManager,
+>(
+    State(app_state): State<Arc<AppState<SM, S, C, F>>>,
+    token: Extension<Token>,
+    Path(uuid): Path<String>,
+) -> impl IntoResponse {
+    let message_id = match parse_message_id(&uuid) {
+        Err(err) => return err.into_response(),
+        Ok(id) => id,
+    };
+
+    let repo = MessageRepository {
+        pg_pool: app_state.get_db_pool(),
+    };
+    let mut domain = MessageDomain::new(repo);
+
+    match domain.get_message_edits(&token.sub, &message_id) {
+        Err(err) => err.into_response(),
+        Ok(edits) => HTTPResponse::<Vec<MessageEdit>> {
             status: StatusCode::OK,
+            data: Some(edits),
+            message: None,
         }
         .into_response(),
-        Err(err) => HTTPResponse::<()>::new_internal_error(String::from(err)).into_response(),
     }
 }
@@ -1,24 +1,39 @@
-use std::time::SystemTime;
+use std::{collections::HashMap, time::SystemTime};
 
+use axum::http::StatusCode;
 use uuid::Uuid;
 
 use crate::{
     helper::{errors::HTTPResponse, pagination::Pagination},
-    interfaces::websockets::{
-        messages::SocketMessageDirect::SocketMessageDirect, socket_messages::SocketMessageError,
+    interfaces::websockets::messages::{
+        SocketMessageDirect::SocketMessageDirect, SocketMessageEdit::SocketMessageEdit,
+        SocketMessageRoom::SocketMessageRoom,
     },
-    models::Message,
+    models::{Message, MessageEdit, MessagePayload},
 };
 
-use super::repository::MessageRepositoryInterface;
+use super::{
+    repository::{MessageDTO, MessagePage, MessageRepositoryInterface},
+    signatures::verify_signatures,
+};
 
 pub struct MessageDomain<I: MessageRepositoryInterface> {
     message_repository: I,
+    verify_signatures: bool,
 }
 
 impl<I: MessageRepositoryInterface> MessageDomain<I> {
     pub fn new(message_repository: I) -> Self {
-        return Self { message_repository };
+        return Self {
+            message_repository,
+            verify_signatures: false,
+        };
+    }
+
+    /// Whether saving and editing reject copies which are not signed by the sender, see VERIFY_MESSAGE_SIGNATURES
+    pub fn with_signature_verification(mut self, enabled: bool) -> Self {
+        self.verify_signatures = enabled;
+        self
     }
 
     pub fn get_messages(
@@ -26,7 +41,7 @@ impl<I: MessageRepositoryInterface> MessageDomain<I> {
         username: &String,
         origin: &String,
         pagination: Pagination,
-    ) -> Result<Vec<Message>, HTTPResponse<()>> {
+    ) -> Result<MessagePage, HTTPResponse<()>> {
         match self
             .message_repository
             .get_messages(username, origin, pagination)
@@ -36,10 +51,26 @@ impl<I: MessageRepositoryInterface> MessageDomain<I> {
         }
     }
 
+    pub fn get_room_messages(
+        &mut self,
+        username: &String,
+        room_id: &Uuid,
+        pagination: Pagination,
+    ) -> Result<MessagePage, HTTPResponse<()>> {
+        match self
+            .message_repository
+            .get_room_messages(username, room_id, pagination)
+        {
+            Ok(res) => Ok(res),
+            Err(err) => Err(HTTPResponse::new_internal_error(err)),
+        }
+    }
+
+    /// Splits a direct message into its envelope and the two encrypted copies (recipient and sender)
     pub fn direct_message_to_message_entity(
         &self,
         direct_message: &SocketMessageDirect,
-    ) -> Result<Message, String> {
+    ) -> Result<(Message, Vec<MessagePayload>), String> {
         let direct_message = direct_message.clone();
 
         let sender = match direct_message.sender {
@@ -53,40 +84,379 @@ impl<I: MessageRepositoryInterface> MessageDomain<I> {
         };
 
         let message_db = Message {
-            content: direct_message.message,
-            content_signature: direct_message.message_signature,
-            content_self_encrypted: direct_message.message_self_encrypted,
-            content_self_encrypted_signature: direct_message.message_self_encrypted_signature,
             id: direct_message.id.unwrap_or(Uuid::new_v4()),
-            recipient: recipient,
+            recipient: Some(recipient.clone()),
+            room_id: None,
             is_read: false,
-            sender: sender,
+            sender: sender.clone(),
             sent_at: SystemTime::now(),
+            delivered_at: None,
+            edited_at: None,
+            deleted_at: None,
         };
 
-        return Ok(message_db);
+        let payloads = vec![
+            MessagePayload {
+                message_id: message_db.id,
+                recipient: recipient,
+                content: direct_message.message,
+                content_signature: direct_message.message_signature,
+                delivered_at: None,
+                key_id: direct_message.key_id,
+                sender_key_id: direct_message.sender_key_id,
+            },
+            // The sender already has the message, so the own copy never has to be delivered
+            MessagePayload {
+                message_id: message_db.id,
+                recipient: sender,
+                content: direct_message.message_self_encrypted,
+                content_signature: direct_message.message_self_encrypted_signature,
+                delivered_at: Some(message_db.sent_at),
+                key_id: direct_message.sender_key_id,
+                sender_key_id: direct_message.sender_key_id,
+            },
+        ];
+
+        return Ok((message_db, payloads));
     }
 
-    pub fn save_message(&mut self, message: &Message) -> Result<(), String> {
-        let result = self.message_repository.save_message(message);
-        match result {
+    /// Checks the signature of every copy against the key of the sender it names, or the current key if it names none.
+    /// Does nothing unless signature verification is enabled
+    pub fn check_signatures(
+        &mut self,
+        sender: &String,
+        payloads: &Vec<MessagePayload>,
+    ) -> Result<(), String> {
+        if !self.verify_signatures {
+            return Ok(());
+        }
+
+        let key_ids: Vec<Uuid> = payloads.iter().filter_map(|p| p.sender_key_id).collect();
+        let mut keys: HashMap<Option<Uuid>, Vec<u8>> = match self.message_repository.get_keys(&key_ids) {
             Err(err) => return Err(err),
+            Ok(keys) => keys
+                .into_iter()
+                .filter(|key| &key.username == sender)
+                .map(|key| (Some(key.id), key.public_key))
+                .collect(),
+        };
+        if payloads.iter().any(|p| p.sender_key_id.is_none()) {
+            match self.message_repository.get_current_key(sender) {
+                Err(err) => return Err(err),
+                Ok(None) => return Err(format!("{} has no key to verify the message with", sender)),
+                Ok(Some(key)) => keys.insert(None, key.public_key),
+            };
+        }
+
+        for payload in payloads.iter() {
+            let result = match keys.get(&payload.sender_key_id) {
+                None => Err(format!("{} has no key to verify the message with", sender)),
+                Some(key) => verify_signatures(
+                    key,
+                    &vec![(&payload.content, &payload.content_signature)],
+                ),
+            };
+            if let Err(err) = result {
+                tracing::info!(target: "audit", "[check_signatures] rejected message of {}: {}", sender, err);
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Splits a room message into its envelope and one encrypted copy per room member.
+    /// Every member (including the sender) has to be addressed exactly once.
+    pub fn room_message_to_message_entity(
+        &self,
+        room_message: &SocketMessageRoom,
+        members: &Vec<String>,
+    ) -> Result<(Message, Vec<MessagePayload>), String> {
+        let sender = match &room_message.sender {
+            None => return Err(String::from("A sender has to be specified")),
+            Some(sender) => sender.clone(),
+        };
+
+        let message_db = Message {
+            id: room_message.id.unwrap_or(Uuid::new_v4()),
+            recipient: None,
+            room_id: Some(room_message.room_id),
+            is_read: false,
+            sender,
+            sent_at: SystemTime::now(),
+            delivered_at: None,
+            edited_at: None,
+            deleted_at: None,
+        };
+
+        let mut payloads: Vec<MessagePayload> = vec![];
+        for payload in room_message.payloads.iter() {
+            if !members.contains(&payload.recipient) {
+                return Err(format!("{} is not a member of this room", payload.recipient));
+            }
+            if payloads.iter().any(|p| p.recipient == payload.recipient) {
+                return Err(format!("{} is addressed more than once", payload.recipient));
+            }
+            let delivered_at = match payload.recipient == message_db.sender {
+                true => Some(message_db.sent_at),
+                false => None,
+            };
+            payloads.push(MessagePayload {
+                message_id: message_db.id,
+                recipient: payload.recipient.clone(),
+                content: payload.message.clone(),
+                content_signature: payload.message_signature.clone(),
+                delivered_at,
+                key_id: payload.key_id,
+                sender_key_id: room_message.sender_key_id,
+            });
+        }
+
+        if payloads.len() != members.len() {
+            return Err(String::from(
+                "The message has to be encrypted for every member of the room",
+            ));
+        }
+
+        return Ok((message_db, payloads));
+    }
+
+    /// Rejects key ids which are not in the key history of the user they are given for.
+    /// Copies without key ids are stored as encrypted for and signed with unknown keys
+    pub fn check_message_keys(
+        &mut self,
+        sender: &String,
+        payloads: &Vec<MessagePayload>,
+    ) -> Result<(), String> {
+        let mut key_ids: Vec<Uuid> = vec![];
+        for payload in payloads.iter() {
+            key_ids.extend(payload.key_id);
+            key_ids.extend(payload.sender_key_id);
+        }
+        if key_ids.len() == 0 {
+            return Ok(());
+        }
+
+        let owners: HashMap<Uuid, String> = match self.message_repository.get_keys(&key_ids) {
+            Err(err) => return Err(err),
+            Ok(keys) => keys.into_iter().map(|key| (key.id, key.username)).collect(),
+        };
+        for payload in payloads.iter() {
+            let checks = [
+                (payload.key_id, &payload.recipient),
+                (payload.sender_key_id, sender),
+            ];
+            for (key_id, owner) in checks.iter() {
+                match key_id {
+                    Some(key_id) if owners.get(key_id) != Some(*owner) => {
+                        return Err(format!("{} is not a key of {}", key_id, owner))
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Rejects copies naming keys of someone else or carrying a signature which does not match the sender
+    pub fn save_message(
+        &mut self,
+        message: &Message,
+        payloads: &Vec<MessagePayload>,
+    ) -> Result<(), HTTPResponse<()>> {
+        if let Err(err) = self
+            .check_message_keys(&message.sender, payloads)
+            .and_then(|_| self.check_signatures(&message.sender, payloads))
+        {
+            return Err(HTTPResponse {
+                status: StatusCode::BAD_REQUEST,
+                data: None,
+                message: Some(err),
+            });
+        }
+
+        let result = self.message_repository.save_message(message, payloads);
+        match result {
+            Err(err) => return Err(HTTPResponse::new_internal_error(err)),
             Ok(res) => Ok(res),
         }
     }
 
+    /// Returns the updated message ids grouped by their sender, so the senders can be notified
     pub fn set_message_read(
         &mut self,
         message_ids: &Vec<Uuid>,
         is_read: &bool,
         issuer: &String,
-    ) -> Result<(), String> {
+    ) -> Result<HashMap<String, Vec<Uuid>>, String> {
         let result = self
             .message_repository
             .set_message_read(message_ids, is_read, issuer);
-        match result {
+        let updated = match result {
             Err(err) => return Err(err),
+            Ok(res) => res,
+        };
+
+        let mut by_sender: HashMap<String, Vec<Uuid>> = HashMap::new();
+        for message in updated {
+            by_sender.entry(message.sender).or_default().push(message.id);
+        }
+        Ok(by_sender)
+    }
+
+    /// All copies addressed to the user which did not reach any of the user's devices yet, oldest first
+    pub fn get_undelivered_messages(
+        &mut self,
+        username: &String,
+    ) -> Result<Vec<MessageDTO>, String> {
+        self.message_repository.get_undelivered_messages(username)
+    }
+
+    /// Records that the copies addressed to the issuer reached one of the issuer's devices
+    pub fn acknowledge_messages(
+        &mut self,
+        message_ids: &Vec<Uuid>,
+        issuer: &String,
+    ) -> Result<(), String> {
+        if message_ids.len() == 0 {
+            return Err(String::from("No message ids specified"));
+        }
+        self.message_repository
+            .set_messages_delivered(message_ids, issuer)
+    }
+
+    /// Replaces both encrypted copies of a direct message. Returns the envelope and the edit to push to both parties
+    pub fn edit_message(
+        &mut self,
+        issuer: &String,
+        edit: &SocketMessageEdit,
+    ) -> Result<(Message, SocketMessageEdit), HTTPResponse<()>> {
+        let message = self.get_own_message(&edit.message_id, issuer)?;
+        let recipient = match &message.recipient {
+            None => {
+                return Err(HTTPResponse {
+                    status: StatusCode::BAD_REQUEST,
+                    data: None,
+                    message: Some(String::from("Only direct messages can be edited")),
+                })
+            }
+            Some(recipient) => recipient.clone(),
+        };
+
+        let payloads = vec![
+            MessagePayload {
+                message_id: message.id,
+                recipient: recipient.clone(),
+                content: edit.message.clone(),
+                content_signature: edit.message_signature.clone(),
+                delivered_at: None,
+                key_id: edit.key_id,
+                sender_key_id: edit.sender_key_id,
+            },
+            MessagePayload {
+                message_id: message.id,
+                recipient: message.sender.clone(),
+                content: edit.message_self_encrypted.clone(),
+                content_signature: edit.message_self_encrypted_signature.clone(),
+                delivered_at: None,
+                key_id: edit.sender_key_id,
+                sender_key_id: edit.sender_key_id,
+            },
+        ];
+
+        if let Err(err) = self
+            .check_message_keys(&message.sender, &payloads)
+            .and_then(|_| self.check_signatures(&message.sender, &payloads))
+        {
+            return Err(HTTPResponse {
+                status: StatusCode::BAD_REQUEST,
+                data: None,
+                message: Some(err),
+            });
+        }
+
+        let edited_at = SystemTime::now();
+        if let Err(err) = self
+            .message_repository
+            .edit_message(&message.id, &payloads, &edited_at)
+        {
+            return Err(HTTPResponse::new_internal_error(err));
+        }
+
+        let edit = SocketMessageEdit::new(
+            message.id,
+            Some(message.sender.clone()),
+            Some(recipient),
+            edit.message.clone(),
+            edit.message_signature.clone(),
+            edit.message_self_encrypted.clone(),
+            edit.message_self_encrypted_signature.clone(),
+            edit.key_id,
+            edit.sender_key_id,
+            Some(edited_at),
+        );
+        Ok((message, edit))
+    }
+
+    /// Turns the message into a tombstone. Returns everyone who had a copy of it
+    pub fn delete_message(
+        &mut self,
+        issuer: &String,
+        message_id: &Uuid,
+    ) -> Result<Vec<String>, HTTPResponse<()>> {
+        let message = self.get_own_message(message_id, issuer)?;
+        match self
+            .message_repository
+            .delete_message(&message.id, &SystemTime::now())
+        {
+            Err(err) => Err(HTTPResponse::new_internal_error(err)),
+            Ok(recipients) => Ok(recipients),
+        }
+    }
+
+    /// Previous versions of the copy addressed to the issuer, oldest first
+    pub fn get_message_edits(
+        &mut self,
+        issuer: &String,
+        message_id: &Uuid,
+    ) -> Result<Vec<MessageEdit>, HTTPResponse<()>> {
+        match self.message_repository.get_message_edits(message_id, issuer) {
+            Err(err) => Err(HTTPResponse::new_internal_error(err)),
             Ok(res) => Ok(res),
         }
     }
+
+    fn get_own_message(
+        &mut self,
+        message_id: &Uuid,
+        issuer: &String,
+    ) -> Result<Message, HTTPResponse<()>> {
+        let message = match self.message_repository.get_message(message_id) {
+            Err(err) => return Err(HTTPResponse::new_internal_error(err)),
+            Ok(None) => {
+                return Err(HTTPResponse {
+                    status: StatusCode::NOT_FOUND,
+                    data: None,
+                    message: Some(String::from("Message not found")),
+                })
+            }
+            Ok(Some(message)) => message,
+        };
+
+        if &message.sender != issuer {
+            return Err(HTTPResponse {
+                status: StatusCode::FORBIDDEN,
+                data: None,
+                message: Some(String::from("You can only change your own messages")),
+            });
+        }
+
+        if message.deleted_at.is_some() {
+            return Err(HTTPResponse {
+                status: StatusCode::BAD_REQUEST,
+                data: None,
+                message: Some(String::from("Message was deleted")),
+            });
+        }
+        Ok(message)
+    }
 }
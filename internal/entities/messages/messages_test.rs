@@ -1,19 +1,118 @@
-use crate::repositories::message_repository::MessageRepositoryInterface;
+use std::{str::FromStr, sync::OnceLock, time::SystemTime};
+
+use base64::Engine;
+use uuid::Uuid;
+
+use crate::{
+    entities::messages::repository::{MessageDTO, MessagePage, MessageRepositoryInterface},
+    helper::{keys::generate_rsa_key_pair, pagination::Pagination},
+    models::{Message, MessageEdit, MessagePayload, MessageSenderDTO, UserKey},
+};
 
 struct MessageRepositoryMock {}
 
+const DELETED_MESSAGE_ID: &str = "7c1e5a4e-6d5e-4a4b-9d54-0a2cbd7a5d11";
+// The sender has a replaced and a current key, the recipient only a current one
+const SENDER_OLD_KEY_ID: &str = "2f0c33c1-8b7e-4d6e-9a4b-5b1f3c7d2e10";
+const SENDER_KEY_ID: &str = "2f0c33c1-8b7e-4d6e-9a4b-5b1f3c7d2e11";
+const RECIPIENT_KEY_ID: &str = "2f0c33c1-8b7e-4d6e-9a4b-5b1f3c7d2e12";
+
+/// Key pair of every key in the mock, generated once as it takes a while
+fn key_pair() -> &'static (Vec<u8>, Vec<u8>) {
+    static KEY_PAIR: OnceLock<(Vec<u8>, Vec<u8>)> = OnceLock::new();
+    KEY_PAIR.get_or_init(|| generate_rsa_key_pair().unwrap())
+}
+
+fn mock_key(id: &str, username: &str, replaced_at: Option<SystemTime>) -> UserKey {
+    UserKey {
+        id: Uuid::from_str(id).unwrap(),
+        username: String::from(username),
+        // Stored the same way as on registration
+        public_key: base64::engine::general_purpose::STANDARD.encode(&key_pair().1).into_bytes(),
+        created_at: SystemTime::now(),
+        replaced_at,
+    }
+}
+
 impl MessageRepositoryInterface for MessageRepositoryMock {
-    fn get_messages(&mut self, _: &String, _: &String, _: crate::helper::pagination::Pagination) -> Result<Vec<crate::models::Message>, String> {
+    fn get_messages(&mut self, _: &String, _: &String, _: Pagination) -> Result<MessagePage, String> {
+        return Ok(MessagePage::new(vec![], false, vec![], false))
+    }
+
+    fn get_room_messages(&mut self, _: &String, _: &Uuid, _: Pagination) -> Result<MessagePage, String> {
+        return Ok(MessagePage::new(vec![], false, vec![], false))
+    }
+
+    fn save_message(&mut self, _: &Message, _: &Vec<MessagePayload>) -> Result<(), String> {
+        return Ok(())
+    }
+
+    fn set_message_read(&mut self, ids: &Vec<Uuid>, _: &bool, _: &String) -> Result<Vec<MessageSenderDTO>, String> {
+        // Every even message was sent by Sender1, every odd one by Sender2
+        return Ok(ids
+            .iter()
+            .enumerate()
+            .map(|(index, id)| MessageSenderDTO {
+                id: id.clone(),
+                sender: format!("Sender{}", index % 2 + 1),
+            })
+            .collect())
+    }
+
+    fn get_undelivered_messages(&mut self, _: &String) -> Result<Vec<MessageDTO>, String> {
         return Ok(vec![])
     }
 
-    fn save_message(&mut self, _: &crate::models::Message) -> Result<(), String> {
+    fn set_messages_delivered(&mut self, _: &Vec<Uuid>, _: &String) -> Result<(), String> {
         return Ok(())
     }
 
-    fn set_message_read(&mut self, ids: &Vec<uuid::Uuid>, is_read: &bool, issuer: &String) -> Result<(), String> {
+    fn get_message(&mut self, id: &Uuid) -> Result<Option<Message>, String> {
+        let deleted_at = match id == &Uuid::from_str(DELETED_MESSAGE_ID).unwrap() {
+            true => Some(SystemTime::now()),
+            false => None,
+        };
+        return Ok(Some(Message {
+            id: id.clone(),
+            sender: String::from("Sender"),
+            recipient: Some(String::from("Recipient")),
+            sent_at: SystemTime::now(),
+            is_read: false,
+            room_id: None,
+            delivered_at: None,
+            edited_at: None,
+            deleted_at,
+        }))
+    }
+
+    fn edit_message(&mut self, _: &Uuid, _: &Vec<MessagePayload>, _: &SystemTime) -> Result<(), String> {
         return Ok(())
     }
+
+    fn delete_message(&mut self, _: &Uuid, _: &SystemTime) -> Result<Vec<String>, String> {
+        return Ok(vec![String::from("Recipient"), String::from("Sender")])
+    }
+
+    fn get_message_edits(&mut self, _: &Uuid, _: &String) -> Result<Vec<MessageEdit>, String> {
+        return Ok(vec![])
+    }
+
+    fn get_keys(&mut self, ids: &Vec<Uuid>) -> Result<Vec<UserKey>, String> {
+        let keys = vec![
+            mock_key(SENDER_OLD_KEY_ID, "Sender", Some(SystemTime::now())),
+            mock_key(SENDER_KEY_ID, "Sender", None),
+            mock_key(RECIPIENT_KEY_ID, "Recipient", None),
+        ];
+        return Ok(keys.into_iter().filter(|key| ids.contains(&key.id)).collect())
+    }
+
+    fn get_current_key(&mut self, username: &String) -> Result<Option<UserKey>, String> {
+        return Ok(match username.as_str() {
+            "Sender" => Some(mock_key(SENDER_KEY_ID, "Sender", None)),
+            "Recipient" => Some(mock_key(RECIPIENT_KEY_ID, "Recipient", None)),
+            _ => None,
+        })
+    }
 }
 
 
@@ -22,11 +121,28 @@ impl MessageRepositoryInterface for MessageRepositoryMock {
 pub mod message_integration_tests {
     use std::str::FromStr;
 
+    use axum::http::StatusCode;
+    use base64::Engine;
+    use openssl::{hash::MessageDigest, pkey::PKey, rsa::Rsa, sign::Signer};
     use uuid::Uuid;
 
-    use crate::{domain::message_domain::MessageDomain, handler::socket_handler::ws_handle_direct::SocketMessageDirect};
+    use crate::{
+        entities::messages::{messages::MessageDomain, signatures::verify_signatures},
+        helper::keys::generate_rsa_key_pair,
+        models::MessagePayload,
+        interfaces::websockets::{
+            messages::{
+                SocketMessageDirect::SocketMessageDirect, SocketMessageEdit::SocketMessageEdit,
+                SocketMessageRoom::SocketMessageRoom,
+            },
+            socket_messages::SocketMessagePayload,
+        },
+    };
 
-    use super::MessageRepositoryMock;
+    use super::{
+        key_pair, MessageRepositoryMock, DELETED_MESSAGE_ID, RECIPIENT_KEY_ID, SENDER_KEY_ID,
+        SENDER_OLD_KEY_ID,
+    };
 
     #[test]
     fn test_direct_message_to_message_entity() {
@@ -40,17 +156,32 @@ pub mod message_integration_tests {
             message_self_encrypted_signature: String::from("Message_self encrypted signature"),
             message_signature: String::from("Message signature"),
             recipient: Some(String::from("Recipient")),
-            sender: Some(String::from("Sender"))
+            sender: Some(String::from("Sender")),
+            key_id: Some(Uuid::from_str(RECIPIENT_KEY_ID).unwrap()),
+            sender_key_id: Some(Uuid::from_str(SENDER_KEY_ID).unwrap()),
         };
 
         
-        let result = domain.direct_message_to_message_entity(&direct_message).unwrap();
-        assert_eq!(result.content, direct_message.message);
-        assert_eq!(result.content_signature, direct_message.message_signature);
-        assert_eq!(result.content_self_encrypted, direct_message.message_self_encrypted);
-        assert_eq!(result.content_self_encrypted_signature, direct_message.message_self_encrypted_signature);
-        assert_eq!(result.recipient, direct_message.clone().recipient.unwrap());
+        let (result, payloads) = domain.direct_message_to_message_entity(&direct_message).unwrap();
+        assert_eq!(result.id, direct_message.id.unwrap());
+        assert_eq!(result.recipient, direct_message.clone().recipient);
         assert_eq!(result.sender, direct_message.clone().sender.unwrap());
+        assert_eq!(result.room_id, None);
+
+        assert_eq!(payloads.len(), 2);
+        assert_eq!(payloads[0].recipient, direct_message.clone().recipient.unwrap());
+        assert_eq!(payloads[0].content, direct_message.message);
+        assert_eq!(payloads[0].content_signature, direct_message.message_signature);
+        assert_eq!(payloads[1].recipient, direct_message.clone().sender.unwrap());
+        assert_eq!(payloads[1].content, direct_message.message_self_encrypted);
+        assert_eq!(payloads[1].content_signature, direct_message.message_self_encrypted_signature);
+        // Only the recipient copy is pending delivery
+        assert_eq!(payloads[0].delivered_at, None);
+        assert_eq!(payloads[1].delivered_at, Some(result.sent_at));
+        // The own copy is encrypted for the key the sender signs with
+        assert_eq!(payloads[0].key_id, direct_message.key_id);
+        assert_eq!(payloads[1].key_id, direct_message.sender_key_id);
+        assert_eq!(payloads[1].sender_key_id, direct_message.sender_key_id);
 
 
         direct_message.sender = None;
@@ -64,4 +195,248 @@ pub mod message_integration_tests {
 
 
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_room_message_to_message_entity() {
+        let domain = MessageDomain::new(MessageRepositoryMock {});
+        let members = vec![String::from("Sender"), String::from("Member")];
+        let payload = |recipient: &str| SocketMessagePayload {
+            recipient: String::from(recipient),
+            message: format!("Message for {}", recipient),
+            message_signature: format!("Signature for {}", recipient),
+            key_id: None,
+        };
+
+        let room_id = Uuid::from_str("18cb8735-b226-49d5-a726-e6937bd6e841").unwrap();
+        let mut room_message = SocketMessageRoom::new(
+            room_id,
+            Some(String::from("Sender")),
+            vec![payload("Sender"), payload("Member")],
+            None,
+        );
+
+        let (result, payloads) = domain.room_message_to_message_entity(&room_message, &members).unwrap();
+        assert_eq!(result.room_id, Some(room_id));
+        assert_eq!(result.recipient, None);
+        assert_eq!(payloads.len(), 2);
+        assert_eq!(payloads[1].recipient, String::from("Member"));
+        assert_eq!(payloads[1].content, String::from("Message for Member"));
+        assert_eq!(payloads[0].delivered_at, Some(result.sent_at));
+        assert_eq!(payloads[1].delivered_at, None);
+
+        let copy = room_message.for_recipient(&String::from("Member"));
+        assert_eq!(copy.payloads, vec![payload("Member")]);
+
+        room_message.payloads = vec![payload("Sender")];
+        let result = domain.room_message_to_message_entity(&room_message, &members).unwrap_err();
+        assert_eq!(result, String::from("The message has to be encrypted for every member of the room"));
+
+        room_message.payloads = vec![payload("Sender"), payload("Member"), payload("Stranger")];
+        let result = domain.room_message_to_message_entity(&room_message, &members).unwrap_err();
+        assert_eq!(result, String::from("Stranger is not a member of this room"));
+
+        room_message.payloads = vec![payload("Sender"), payload("Sender")];
+        let result = domain.room_message_to_message_entity(&room_message, &members).unwrap_err();
+        assert_eq!(result, String::from("Sender is addressed more than once"));
+    }
+
+    #[test]
+    fn test_acknowledge_messages() {
+        let mut domain = MessageDomain::new(MessageRepositoryMock {});
+        let issuer = String::from("Recipient");
+
+        let result = domain.acknowledge_messages(&vec![], &issuer).unwrap_err();
+        assert_eq!(result, String::from("No message ids specified"));
+
+        let message_id = Uuid::from_str("18cb8735-b226-49d5-a726-e6937bd6e841").unwrap();
+        assert!(domain.acknowledge_messages(&vec![message_id], &issuer).is_ok());
+    }
+
+    #[test]
+    fn test_set_message_read_groups_by_sender() {
+        let mut domain = MessageDomain::new(MessageRepositoryMock {});
+        let ids = vec![Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4()];
+
+        let result = domain
+            .set_message_read(&ids, &true, &String::from("Recipient"))
+            .unwrap();
+        assert_eq!(result.len(), 2);
+        assert_eq!(result.get("Sender1").unwrap(), &vec![ids[0], ids[2]]);
+        assert_eq!(result.get("Sender2").unwrap(), &vec![ids[1]]);
+    }
+
+    #[test]
+    fn test_edit_and_delete_message() {
+        let mut domain = MessageDomain::new(MessageRepositoryMock {});
+        let message_id = Uuid::new_v4();
+        let edit = SocketMessageEdit::new(
+            message_id,
+            None,
+            None,
+            String::from("Edited"),
+            String::from("Edited signature"),
+            String::from("Edited self encrypted"),
+            String::from("Edited self encrypted signature"),
+            Some(Uuid::from_str(RECIPIENT_KEY_ID).unwrap()),
+            Some(Uuid::from_str(SENDER_KEY_ID).unwrap()),
+            None,
+        );
+
+        let result = domain.edit_message(&String::from("Recipient"), &edit).unwrap_err();
+        assert_eq!(result.status, StatusCode::FORBIDDEN);
+
+        let (message, edit) = domain.edit_message(&String::from("Sender"), &edit).unwrap();
+        assert_eq!(message.id, message_id);
+        assert_eq!(edit.sender, Some(String::from("Sender")));
+        assert_eq!(edit.recipient, Some(String::from("Recipient")));
+        assert!(edit.edited_at.is_some());
+
+        let mut forged = edit.clone();
+        forged.key_id = forged.sender_key_id;
+        let result = domain.edit_message(&String::from("Sender"), &forged).unwrap_err();
+        assert_eq!(result.status, StatusCode::BAD_REQUEST);
+
+        let result = domain.delete_message(&String::from("Recipient"), &message_id).unwrap_err();
+        assert_eq!(result.status, StatusCode::FORBIDDEN);
+
+        let recipients = domain.delete_message(&String::from("Sender"), &message_id).unwrap();
+        assert_eq!(recipients.len(), 2);
+
+        let deleted_id = Uuid::from_str(DELETED_MESSAGE_ID).unwrap();
+        let result = domain.delete_message(&String::from("Sender"), &deleted_id).unwrap_err();
+        assert_eq!(result.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_message_keys_are_checked_against_the_key_history() {
+        let mut domain = MessageDomain::new(MessageRepositoryMock {});
+        let sender = String::from("Sender");
+        let payload = |recipient: &str, key_id: &str, sender_key_id: &str| MessagePayload {
+            message_id: Uuid::new_v4(),
+            recipient: String::from(recipient),
+            content: String::from("Message"),
+            content_signature: String::from("Signature"),
+            delivered_at: None,
+            key_id: Some(Uuid::from_str(key_id).unwrap()),
+            sender_key_id: Some(Uuid::from_str(sender_key_id).unwrap()),
+        };
+
+        let valid = vec![
+            payload("Recipient", RECIPIENT_KEY_ID, SENDER_KEY_ID),
+            payload("Sender", SENDER_KEY_ID, SENDER_KEY_ID),
+        ];
+        assert_eq!(domain.check_message_keys(&sender, &valid), Ok(()));
+
+        // Messages sent while the sender rotated their key still reference a known key
+        let replaced = vec![payload("Recipient", RECIPIENT_KEY_ID, SENDER_OLD_KEY_ID)];
+        assert_eq!(domain.check_message_keys(&sender, &replaced), Ok(()));
+
+        let unknown_id = Uuid::new_v4().to_string();
+        let unknown = vec![payload("Recipient", &unknown_id, SENDER_KEY_ID)];
+        assert_eq!(
+            domain.check_message_keys(&sender, &unknown),
+            Err(format!("{} is not a key of Recipient", unknown_id))
+        );
+
+        let foreign = vec![payload("Recipient", SENDER_KEY_ID, SENDER_KEY_ID)];
+        assert!(domain.check_message_keys(&sender, &foreign).is_err());
+
+        let foreign_sender_key = vec![payload("Recipient", RECIPIENT_KEY_ID, RECIPIENT_KEY_ID)];
+        assert!(domain.check_message_keys(&sender, &foreign_sender_key).is_err());
+
+        let mut without_keys = payload("Recipient", RECIPIENT_KEY_ID, SENDER_KEY_ID);
+        without_keys.key_id = None;
+        without_keys.sender_key_id = None;
+        assert_eq!(domain.check_message_keys(&sender, &vec![without_keys]), Ok(()));
+    }
+
+    fn sign(private_key: &Vec<u8>, content: &String) -> String {
+        let pkey = PKey::from_rsa(Rsa::private_key_from_pem(private_key).unwrap()).unwrap();
+        let mut signer = Signer::new(MessageDigest::sha256(), &pkey).unwrap();
+        base64::engine::general_purpose::STANDARD.encode(signer.sign_oneshot_to_vec(content.as_bytes()).unwrap())
+    }
+
+    #[test]
+    fn test_verify_signatures() {
+        let (private_key, public_key) = generate_rsa_key_pair().unwrap();
+        let (other_private_key, _) = generate_rsa_key_pair().unwrap();
+        // Stored the same way as on registration
+        let public_key = base64::engine::general_purpose::STANDARD.encode(public_key).into_bytes();
+
+        let message = String::from("Encrypted for the recipient");
+        let message_self_encrypted = String::from("Encrypted for the sender");
+        let signature = sign(&private_key, &message);
+        let self_encrypted_signature = sign(&private_key, &message_self_encrypted);
+
+        let result = verify_signatures(&public_key, &vec![(&message, &signature), (&message_self_encrypted, &self_encrypted_signature)]);
+        assert_eq!(result, Ok(()));
+
+        // Signature of another content
+        let result = verify_signatures(&public_key, &vec![(&message, &signature), (&message_self_encrypted, &signature)]).unwrap_err();
+        assert_eq!(result, String::from("The message signature does not match the key of the sender"));
+
+        let forged = sign(&other_private_key, &message);
+        let result = verify_signatures(&public_key, &vec![(&message, &forged)]).unwrap_err();
+        assert_eq!(result, String::from("The message signature does not match the key of the sender"));
+
+        let result = verify_signatures(&public_key, &vec![(&message, &String::from("not base64!"))]).unwrap_err();
+        assert_eq!(result, String::from("The message signature is malformed"));
+    }
+
+    #[test]
+    fn test_saving_and_editing_checks_signatures() {
+        let mut domain = MessageDomain::new(MessageRepositoryMock {}).with_signature_verification(true);
+        let private_key = &key_pair().0;
+        let sender_key_id = Uuid::from_str(SENDER_KEY_ID).unwrap();
+        let message = String::from("Encrypted for the recipient");
+        let message_self_encrypted = String::from("Encrypted for the sender");
+
+        let mut direct_message = SocketMessageDirect::new(
+            Some(String::from("Sender")),
+            Some(String::from("Recipient")),
+            message.clone(),
+            sign(private_key, &message),
+            message_self_encrypted.clone(),
+            sign(private_key, &message_self_encrypted),
+            Some(Uuid::from_str(RECIPIENT_KEY_ID).unwrap()),
+            Some(sender_key_id),
+        );
+        let (entity, payloads) = domain.direct_message_to_message_entity(&direct_message).unwrap();
+        assert!(domain.save_message(&entity, &payloads).is_ok());
+
+        // Without key ids the current key of the sender is used
+        direct_message.key_id = None;
+        direct_message.sender_key_id = None;
+        let (entity, payloads) = domain.direct_message_to_message_entity(&direct_message).unwrap();
+        assert!(domain.save_message(&entity, &payloads).is_ok());
+
+        direct_message.message_self_encrypted_signature = direct_message.message_signature.clone();
+        let (entity, payloads) = domain.direct_message_to_message_entity(&direct_message).unwrap();
+        let result = domain.save_message(&entity, &payloads).unwrap_err();
+        assert_eq!(result.status, StatusCode::BAD_REQUEST);
+        assert_eq!(result.message, Some(String::from("The message signature does not match the key of the sender")));
+
+        // Edits go through the same check, whichever interface they come from
+        let mut edit = SocketMessageEdit::new(
+            Uuid::new_v4(),
+            None,
+            None,
+            message.clone(),
+            sign(private_key, &message),
+            message_self_encrypted.clone(),
+            sign(private_key, &message_self_encrypted),
+            None,
+            Some(sender_key_id),
+            None,
+        );
+        assert!(domain.edit_message(&String::from("Sender"), &edit).is_ok());
+
+        edit.message_signature = String::from("not base64!");
+        let result = domain.edit_message(&String::from("Sender"), &edit).unwrap_err();
+        assert_eq!(result.status, StatusCode::BAD_REQUEST);
+
+        // Disabled by default
+        let mut domain = MessageDomain::new(MessageRepositoryMock {});
+        assert!(domain.edit_message(&String::from("Sender"), &edit).is_ok());
+    }
+}
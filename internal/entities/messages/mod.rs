@@ -1,3 +1,5 @@
 pub mod controller;
 pub mod messages;
+pub mod messages_test;
 pub mod repository;
+pub mod signatures;
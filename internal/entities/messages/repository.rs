@@ -1,85 +1,365 @@
 use crate::{
-    helper::pagination::Pagination,
-    models::Message,
-    schema::messages::{self, all_columns, recipient, sender, sent_at},
+    helper::pagination::{MessageCursor, PageCursor, Pagination},
+    models::{Message, MessageEdit, MessagePayload, MessageSenderDTO, UserKey},
+    schema::{
+        message_edits, message_payloads, user_keys,
+        messages::{self, recipient, room_id, sender, sent_at},
+    },
 };
 use diesel::prelude::*;
 use diesel::prelude::*;
 use diesel::query_dsl::*;
 use diesel::query_dsl::*;
 use diesel::{
+    pg::Pg,
     r2d2::{ConnectionManager, PooledConnection},
     sql_types::Array,
     PgConnection,
 };
+use std::time::SystemTime;
 use uuid::Uuid;
 
+/// A message envelope together with the encrypted copy addressed to the requesting user.
+/// The delivery state is sent (`delivered_at` not set), delivered (`delivered_at` set) or read (`is_read`).
+/// Deleted messages are kept as tombstones with `deleted_at` set and empty content
+#[derive(Debug, serde::Deserialize, serde::Serialize, Queryable, Clone, PartialEq)]
+pub struct MessageDTO {
+    pub id: Uuid,
+    pub sender: String,
+    pub recipient: Option<String>,
+    pub room_id: Option<Uuid>,
+    pub sent_at: SystemTime,
+    pub content: String,
+    pub content_signature: String,
+    pub is_read: bool,
+    pub delivered_at: Option<SystemTime>,
+    pub edited_at: Option<SystemTime>,
+    pub deleted_at: Option<SystemTime>,
+    /// Key of the user the content was encrypted for and key of the sender the signature was made with
+    pub key_id: Option<Uuid>,
+    pub sender_key_id: Option<Uuid>,
+}
+
+impl MessageDTO {
+    pub fn cursor(&self) -> MessageCursor {
+        MessageCursor::new(self.sent_at, self.id)
+    }
+}
+
+/// A slice of a conversation in chronological order.
+/// `before` and `after` are only set if there are older or newer messages to load
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq)]
+pub struct MessagePage {
+    pub messages: Vec<MessageDTO>,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+impl MessagePage {
+    pub fn new(
+        older: Vec<MessageDTO>,
+        has_older: bool,
+        newer: Vec<MessageDTO>,
+        has_newer: bool,
+    ) -> Self {
+        let mut messages = older;
+        messages.extend(newer);
+
+        let before = match (has_older, messages.first()) {
+            (true, Some(first)) => Some(first.cursor().to_string()),
+            _ => None,
+        };
+        let after = match (has_newer, messages.last()) {
+            (true, Some(last)) => Some(last.cursor().to_string()),
+            _ => None,
+        };
+
+        MessagePage {
+            messages,
+            before,
+            after,
+        }
+    }
+}
+
 pub trait MessageRepositoryInterface {
     fn get_messages(
         &mut self,
         username: &String,
         origin: &String,
         pagination: Pagination,
-    ) -> Result<Vec<Message>, String>;
-    fn save_message(&mut self, message: &Message) -> Result<(), String>;
+    ) -> Result<MessagePage, String>;
+    fn get_room_messages(
+        &mut self,
+        username: &String,
+        room: &Uuid,
+        pagination: Pagination,
+    ) -> Result<MessagePage, String>;
+    fn save_message(
+        &mut self,
+        message: &Message,
+        payloads: &Vec<MessagePayload>,
+    ) -> Result<(), String>;
     fn set_message_read(
         &mut self,
         ids: &Vec<Uuid>,
         is_read: &bool,
         issuer: &String,
+    ) -> Result<Vec<MessageSenderDTO>, String>;
+    fn get_undelivered_messages(&mut self, username: &String) -> Result<Vec<MessageDTO>, String>;
+    /// Marks the copies addressed to the issuer as delivered. Acknowledging a message twice is not an error
+    fn set_messages_delivered(&mut self, ids: &Vec<Uuid>, issuer: &String) -> Result<(), String>;
+    fn get_message(&mut self, id: &Uuid) -> Result<Option<Message>, String>;
+    /// Replaces the given copies and keeps the previous ones in the edit history
+    fn edit_message(
+        &mut self,
+        id: &Uuid,
+        payloads: &Vec<MessagePayload>,
+        edited_at: &SystemTime,
     ) -> Result<(), String>;
+    /// Wipes all copies and the edit history, leaving a tombstone. Returns the recipients of the copies
+    fn delete_message(&mut self, id: &Uuid, deleted_at: &SystemTime) -> Result<Vec<String>, String>;
+    fn get_message_edits(&mut self, id: &Uuid, recipient: &String) -> Result<Vec<MessageEdit>, String>;
+    /// The keys with the given ids, current or replaced. Unknown ids are left out
+    fn get_keys(&mut self, ids: &Vec<Uuid>) -> Result<Vec<UserKey>, String>;
+    fn get_current_key(&mut self, username: &String) -> Result<Option<UserKey>, String>;
 }
 
+type MessageDTOColumns = (
+    messages::id,
+    messages::sender,
+    messages::recipient,
+    messages::room_id,
+    messages::sent_at,
+    message_payloads::content,
+    message_payloads::content_signature,
+    messages::is_read,
+    messages::delivered_at,
+    messages::edited_at,
+    messages::deleted_at,
+    message_payloads::key_id,
+    message_payloads::sender_key_id,
+);
+
+const MESSAGE_DTO_COLUMNS: MessageDTOColumns = (
+    messages::id,
+    messages::sender,
+    messages::recipient,
+    messages::room_id,
+    messages::sent_at,
+    message_payloads::content,
+    message_payloads::content_signature,
+    messages::is_read,
+    messages::delivered_at,
+    messages::edited_at,
+    messages::deleted_at,
+    message_payloads::key_id,
+    message_payloads::sender_key_id,
+);
+
 pub struct MessageRepository {
     pub pg_pool: PooledConnection<ConnectionManager<PgConnection>>,
 }
 
+type MessageDTOQuery<'a> = diesel::dsl::IntoBoxed<
+    'a,
+    diesel::dsl::Select<
+        diesel::dsl::InnerJoin<messages::table, message_payloads::table>,
+        MessageDTOColumns,
+    >,
+    Pg,
+>;
+
+#[derive(Clone, Copy)]
+enum Direction {
+    Older,
+    Newer,
+}
+
+impl MessageRepository {
+    /// Loads up to `limit` messages of the conversation next to the cursor, in chronological order.
+    /// With `inclusive` the message at the cursor is part of the result.
+    /// The second value tells if there are more messages beyond the result
+    fn load_messages<'a>(
+        &mut self,
+        conversation: impl Fn() -> MessageDTOQuery<'a>,
+        cursor: Option<MessageCursor>,
+        inclusive: bool,
+        direction: Direction,
+        limit: i64,
+    ) -> Result<(Vec<MessageDTO>, bool), diesel::result::Error> {
+        let mut query = conversation();
+
+        if let Some(cursor) = cursor {
+            query = match (direction, inclusive) {
+                (Direction::Older, false) => query.filter(
+                    sent_at
+                        .lt(cursor.sent_at)
+                        .or(sent_at.eq(cursor.sent_at).and(messages::id.lt(cursor.id))),
+                ),
+                (Direction::Older, true) => query.filter(
+                    sent_at
+                        .lt(cursor.sent_at)
+                        .or(sent_at.eq(cursor.sent_at).and(messages::id.le(cursor.id))),
+                ),
+                (Direction::Newer, false) => query.filter(
+                    sent_at
+                        .gt(cursor.sent_at)
+                        .or(sent_at.eq(cursor.sent_at).and(messages::id.gt(cursor.id))),
+                ),
+                (Direction::Newer, true) => query.filter(
+                    sent_at
+                        .gt(cursor.sent_at)
+                        .or(sent_at.eq(cursor.sent_at).and(messages::id.ge(cursor.id))),
+                ),
+            };
+        }
+
+        query = match direction {
+            Direction::Older => query.order_by((sent_at.desc(), messages::id.desc())),
+            Direction::Newer => query.order_by((sent_at.asc(), messages::id.asc())),
+        };
+
+        // One more than requested, to know if there is another page
+        let mut db_messages = query.limit(limit + 1).load::<MessageDTO>(&mut self.pg_pool)?;
+
+        let has_more = db_messages.len() as i64 > limit;
+        db_messages.truncate(limit as usize);
+
+        if let Direction::Older = direction {
+            db_messages.reverse();
+        }
+
+        Ok((db_messages, has_more))
+    }
+
+    fn load_page<'a>(
+        &mut self,
+        conversation: impl Fn() -> MessageDTOQuery<'a>,
+        pagination: Pagination,
+    ) -> Result<MessagePage, diesel::result::Error> {
+        let size: i64 = pagination.size.into();
+
+        match pagination.cursor {
+            PageCursor::Latest => {
+                let (older, has_older) =
+                    self.load_messages(conversation, None, false, Direction::Older, size)?;
+                Ok(MessagePage::new(older, has_older, vec![], false))
+            }
+            PageCursor::Before(cursor) => {
+                let (older, has_older) =
+                    self.load_messages(conversation, Some(cursor), false, Direction::Older, size)?;
+                // The cursor itself is newer than the page
+                Ok(MessagePage::new(older, has_older, vec![], true))
+            }
+            PageCursor::After(cursor) => {
+                let (newer, has_newer) =
+                    self.load_messages(conversation, Some(cursor), false, Direction::Newer, size)?;
+                Ok(MessagePage::new(vec![], true, newer, has_newer))
+            }
+            PageCursor::Around(id) => {
+                // Looked up within the conversation, so ids of other conversations reveal nothing
+                let target = conversation()
+                    .filter(messages::id.eq(id))
+                    .first::<MessageDTO>(&mut self.pg_pool)
+                    .optional()?;
+                let cursor = match target {
+                    None => return Ok(MessagePage::new(vec![], false, vec![], false)),
+                    Some(target) => MessageCursor::new(target.sent_at, id),
+                };
+
+                // The older half includes the message itself
+                let (older, has_older) = self.load_messages(
+                    &conversation,
+                    Some(cursor),
+                    true,
+                    Direction::Older,
+                    size - size / 2,
+                )?;
+                let (newer, has_newer) = self.load_messages(
+                    &conversation,
+                    Some(cursor),
+                    false,
+                    Direction::Newer,
+                    size / 2,
+                )?;
+                Ok(MessagePage::new(older, has_older, newer, has_newer))
+            }
+        }
+    }
+}
+
 impl MessageRepositoryInterface for MessageRepository {
     fn get_messages(
         &mut self,
         username: &String,
         origin: &String,
         pagination: Pagination,
-    ) -> Result<Vec<Message>, String> {
-        let client_sent_or_received = sender
-            .eq(username.clone())
-            .or(recipient.eq(username.clone()));
-        let recipient_sent_or_received = sender.eq(origin.clone()).or(recipient.eq(origin));
-
-        let offset: i64 = (pagination.index * pagination.size).into();
-        let limit: i64 = pagination.size.into();
-
-        let sql_query = messages::table
-            .select(all_columns)
-            .order_by(sent_at.desc())
-            .offset(offset);
-
-        let db_messages = sql_query
-            .limit(limit)
-            .filter(client_sent_or_received)
-            .filter(recipient_sent_or_received)
-            .load(&mut self.pg_pool);
-
-        let mut db_messages: Vec<Message> = match db_messages {
-            Err(err) => return Err(format!("Could not get messages from db: {}", err)),
-            Ok(res) => res,
+    ) -> Result<MessagePage, String> {
+        let conversation = || {
+            let client_sent_or_received = sender
+                .eq(username.clone())
+                .or(recipient.eq(username.clone()));
+            let recipient_sent_or_received =
+                sender.eq(origin.clone()).or(recipient.eq(origin.clone()));
+
+            messages::table
+                .inner_join(message_payloads::table)
+                .select(MESSAGE_DTO_COLUMNS)
+                .filter(message_payloads::recipient.eq(username.clone()))
+                .filter(room_id.is_null())
+                .filter(client_sent_or_received)
+                .filter(recipient_sent_or_received)
+                .into_boxed()
         };
 
-        db_messages.reverse();
+        match self.load_page(conversation, pagination) {
+            Err(err) => Err(format!("Could not get messages from db: {}", err)),
+            Ok(res) => Ok(res),
+        }
+    }
 
-        Ok(db_messages)
+    fn get_room_messages(
+        &mut self,
+        username: &String,
+        room: &Uuid,
+        pagination: Pagination,
+    ) -> Result<MessagePage, String> {
+        let conversation = || {
+            messages::table
+                .inner_join(message_payloads::table)
+                .select(MESSAGE_DTO_COLUMNS)
+                .filter(room_id.eq(room.clone()))
+                .filter(message_payloads::recipient.eq(username.clone()))
+                .into_boxed()
+        };
+
+        match self.load_page(conversation, pagination) {
+            Err(err) => Err(format!("Could not get room messages from db: {}", err)),
+            Ok(res) => Ok(res),
+        }
     }
-    fn save_message(&mut self, message: &Message) -> Result<(), String> {
-        let result = diesel::insert_into(messages::table)
-            .values(message)
-            .execute(&mut self.pg_pool);
+
+    fn save_message(
+        &mut self,
+        message: &Message,
+        payloads: &Vec<MessagePayload>,
+    ) -> Result<(), String> {
+        let result = self.pg_pool.transaction::<_, diesel::result::Error, _>(|conn| {
+            diesel::insert_into(messages::table)
+                .values(message)
+                .execute(conn)?;
+            diesel::insert_into(message_payloads::table)
+                .values(payloads)
+                .execute(conn)
+        });
         let result = match result {
             Err(err) => return Err(format!("Could not save message {:?}: {}", message, err)),
             Ok(res) => res,
         };
 
-        if result == 0 {
-            return Err(String::from("Inserted 0 items, maybe mistake??"));
+        if result != payloads.len() {
+            return Err(String::from("Not every payload was inserted, maybe mistake??"));
         };
         return Ok(());
     }
@@ -89,28 +369,207 @@ impl MessageRepositoryInterface for MessageRepository {
         ids: &Vec<Uuid>,
         is_read: &bool,
         issuer: &String,
-    ) -> Result<(), String> {
+    ) -> Result<Vec<MessageSenderDTO>, String> {
         let result = diesel::sql_query(
             "
             UPDATE messages
             SET is_read = $1
             WHERE id = ANY($2)
             AND recipient = $3
+            RETURNING id, sender
             ",
         )
         .bind::<diesel::sql_types::Bool, _>(is_read)
         .bind::<Array<diesel::sql_types::Uuid>, _>(ids)
         .bind::<diesel::sql_types::Text, _>(issuer)
-        .execute(&mut self.pg_pool);
+        .load::<MessageSenderDTO>(&mut self.pg_pool);
 
         let result = match result {
             Err(err) => return Err(format!("Could not update messages: {}", err)),
             Ok(res) => res,
         };
 
-        if result == 0 {
+        if result.len() == 0 {
             return Err(String::from("Inserted 0 items, maybe mistake??"));
         };
-        return Ok(());
+        return Ok(result);
+    }
+
+    fn get_undelivered_messages(&mut self, username: &String) -> Result<Vec<MessageDTO>, String> {
+        let db_messages = messages::table
+            .inner_join(message_payloads::table)
+            .select(MESSAGE_DTO_COLUMNS)
+            .filter(message_payloads::recipient.eq(username))
+            .filter(message_payloads::delivered_at.is_null())
+            .order_by((sent_at.asc(), messages::id.asc()))
+            .load::<MessageDTO>(&mut self.pg_pool);
+
+        match db_messages {
+            Err(err) => Err(format!("Could not get undelivered messages from db: {}", err)),
+            Ok(res) => Ok(res),
+        }
+    }
+
+    fn set_messages_delivered(&mut self, ids: &Vec<Uuid>, issuer: &String) -> Result<(), String> {
+        let now = SystemTime::now();
+        let result = self.pg_pool.transaction::<_, diesel::result::Error, _>(|conn| {
+            diesel::update(
+                message_payloads::table
+                    .filter(message_payloads::message_id.eq_any(ids))
+                    .filter(message_payloads::recipient.eq(issuer))
+                    .filter(message_payloads::delivered_at.is_null()),
+            )
+            .set(message_payloads::delivered_at.eq(now))
+            .execute(conn)?;
+
+            // The message as a whole is delivered once no copy is pending anymore
+            diesel::sql_query(
+                "
+                UPDATE messages
+                SET delivered_at = $1
+                WHERE id = ANY($2)
+                AND delivered_at IS NULL
+                AND NOT EXISTS (
+                    SELECT 1 FROM message_payloads
+                    WHERE message_payloads.message_id = messages.id
+                    AND message_payloads.delivered_at IS NULL
+                )
+                ",
+            )
+            .bind::<diesel::sql_types::Timestamp, _>(now)
+            .bind::<Array<diesel::sql_types::Uuid>, _>(ids)
+            .execute(conn)
+        });
+
+        match result {
+            Err(err) => Err(format!("Could not mark messages as delivered: {}", err)),
+            Ok(_) => Ok(()),
+        }
+    }
+
+    fn get_message(&mut self, id: &Uuid) -> Result<Option<Message>, String> {
+        let result = messages::table
+            .find(id)
+            .select(Message::as_select())
+            .first::<Message>(&mut self.pg_pool)
+            .optional();
+
+        match result {
+            Err(err) => Err(format!("Could not get message {}: {}", id, err)),
+            Ok(res) => Ok(res),
+        }
+    }
+
+    fn edit_message(
+        &mut self,
+        id: &Uuid,
+        payloads: &Vec<MessagePayload>,
+        edited_at: &SystemTime,
+    ) -> Result<(), String> {
+        let result = self.pg_pool.transaction::<_, diesel::result::Error, _>(|conn| {
+            diesel::sql_query(
+                "
+                INSERT INTO message_edits(message_id, recipient, content, content_signature, edited_at, key_id, sender_key_id)
+                SELECT message_id, recipient, content, content_signature, $2, key_id, sender_key_id
+                FROM message_payloads
+                WHERE message_id = $1
+                ",
+            )
+            .bind::<diesel::sql_types::Uuid, _>(id)
+            .bind::<diesel::sql_types::Timestamp, _>(edited_at)
+            .execute(conn)?;
+
+            for payload in payloads.iter() {
+                diesel::update(message_payloads::table.find((id, &payload.recipient)))
+                    .set((
+                        message_payloads::content.eq(&payload.content),
+                        message_payloads::content_signature.eq(&payload.content_signature),
+                        message_payloads::key_id.eq(&payload.key_id),
+                        message_payloads::sender_key_id.eq(&payload.sender_key_id),
+                    ))
+                    .execute(conn)?;
+            }
+
+            diesel::update(messages::table.find(id))
+                .set(messages::edited_at.eq(edited_at))
+                .execute(conn)
+        });
+
+        match result {
+            Err(err) => Err(format!("Could not edit message {}: {}", id, err)),
+            Ok(_) => Ok(()),
+        }
+    }
+
+    fn delete_message(&mut self, id: &Uuid, deleted_at: &SystemTime) -> Result<Vec<String>, String> {
+        let result = self.pg_pool.transaction::<_, diesel::result::Error, _>(|conn| {
+            diesel::delete(message_edits::table.filter(message_edits::message_id.eq(id)))
+                .execute(conn)?;
+
+            diesel::update(message_payloads::table.filter(message_payloads::message_id.eq(id)))
+                .set((
+                    message_payloads::content.eq(""),
+                    message_payloads::content_signature.eq(""),
+                ))
+                .execute(conn)?;
+
+            diesel::update(messages::table.find(id))
+                .set(messages::deleted_at.eq(deleted_at))
+                .execute(conn)?;
+
+            message_payloads::table
+                .filter(message_payloads::message_id.eq(id))
+                .select(message_payloads::recipient)
+                .load::<String>(conn)
+        });
+
+        match result {
+            Err(err) => Err(format!("Could not delete message {}: {}", id, err)),
+            Ok(res) => Ok(res),
+        }
+    }
+
+    fn get_message_edits(
+        &mut self,
+        id: &Uuid,
+        edit_recipient: &String,
+    ) -> Result<Vec<MessageEdit>, String> {
+        let result = message_edits::table
+            .filter(message_edits::message_id.eq(id))
+            .filter(message_edits::recipient.eq(edit_recipient))
+            .order_by(message_edits::edited_at.asc())
+            .select(MessageEdit::as_select())
+            .load::<MessageEdit>(&mut self.pg_pool);
+
+        match result {
+            Err(err) => Err(format!("Could not get edits of message {}: {}", id, err)),
+            Ok(res) => Ok(res),
+        }
+    }
+
+    fn get_keys(&mut self, ids: &Vec<Uuid>) -> Result<Vec<UserKey>, String> {
+        let result = user_keys::table
+            .filter(user_keys::id.eq_any(ids))
+            .select(UserKey::as_select())
+            .load::<UserKey>(&mut self.pg_pool);
+
+        match result {
+            Err(err) => Err(format!("Could not get keys {:?}: {}", ids, err)),
+            Ok(res) => Ok(res),
+        }
+    }
+
+    fn get_current_key(&mut self, username: &String) -> Result<Option<UserKey>, String> {
+        let result = user_keys::table
+            .filter(user_keys::username.eq(username))
+            .filter(user_keys::replaced_at.is_null())
+            .select(UserKey::as_select())
+            .first::<UserKey>(&mut self.pg_pool)
+            .optional();
+
+        match result {
+            Err(err) => Err(format!("Could not get the current key of {}: {}", username, err)),
+            Ok(res) => Ok(res),
+        }
     }
 }
@@ -0,0 +1,28 @@
+use base64::Engine;
+
+use crate::helper::keys::verify_signature;
+
+/// Checks every `(content, signature)` pair against the public key of the sender, which is the base64 encoded PEM
+/// as stored on the user. Signatures are the base64 encoded RSA PKCS#1 v1.5 SHA-256 signatures of the encrypted content
+pub fn verify_signatures(
+    sender_public_key: &Vec<u8>,
+    signed: &Vec<(&String, &String)>,
+) -> Result<(), String> {
+    let public_key = match base64::engine::general_purpose::STANDARD.decode(sender_public_key) {
+        Err(_) => return Err(String::from("The key of the sender is not base64 encoded")),
+        Ok(key) => key,
+    };
+
+    for (content, signature) in signed.iter() {
+        let signature = match base64::engine::general_purpose::STANDARD.decode(signature) {
+            Err(_) => return Err(String::from("The message signature is malformed")),
+            Ok(signature) => signature,
+        };
+        if verify_signature(&public_key, content.as_bytes(), &signature).is_err() {
+            return Err(String::from(
+                "The message signature does not match the key of the sender",
+            ));
+        }
+    }
+    Ok(())
+}
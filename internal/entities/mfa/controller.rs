@@ -0,0 +1,144 @@
+use crate::appstate::{AppState, IAppState};
+use crate::entities::friends::repository::IFriendRepository;
+use crate::entities::users::controller::with_login_throttle;
+use crate::helper::errors::HTTPResponse;
+use crate::helper::jwt::Token;
+use crate::helper::session::{ISession, ISessionManager};
+use crate::persistence::connection_manager::IConnectionManager;
+use axum::http::StatusCode;
+use axum::{
+    extract::{ConnectInfo, State},
+    response::IntoResponse,
+    Extension, Json,
+};
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use super::mfa::{MfaDomain, TotpEnrollment};
+use super::repository::MfaRepository;
+
+/// TOTP code of the authenticator app or a recovery code.
+/// Wrong codes count as failed logins, so they cannot be guessed with a stolen access token
+#[derive(serde::Deserialize)]
+pub struct MfaCodeDTO {
+    pub code: String,
+}
+
+/// Recovery codes are only shown once, the service only keeps their hashes
+#[derive(serde::Serialize)]
+pub struct RecoveryCodesDTO {
+    pub recovery_codes: Vec<String>,
+}
+
+pub async fn enroll_totp<
+    SM: ISessionManager<S, F>,
+    S: ISession<F>,
+    F: IFriendRepository,
+    C: IConnectionManager,
+>(
+    State(app_state): State<Arc<AppState<SM, S, C, F>>>,
+    token: Extension<Token>,
+) -> impl IntoResponse {
+    let mut mfa_domain = MfaDomain::new(MfaRepository {
+        pg_pool: app_state.get_db_pool(),
+    });
+
+    match mfa_domain.start_enrollment(&token.sub) {
+        Err(err) => err.into_response(),
+        Ok(enrollment) => HTTPResponse::<TotpEnrollment> {
+            data: Some(enrollment),
+            message: Some(String::from(
+                "Confirm the enrollment with a code of the authenticator app",
+            )),
+            status: StatusCode::OK,
+        }
+        .into_response(),
+    }
+}
+
+pub async fn confirm_totp<
+    SM: ISessionManager<S, F>,
+    S: ISession<F>,
+    F: IFriendRepository,
+    C: IConnectionManager,
+>(
+    State(app_state): State<Arc<AppState<SM, S, C, F>>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    token: Extension<Token>,
+    Json(body): Json<MfaCodeDTO>,
+) -> impl IntoResponse {
+    let mut mfa_domain = MfaDomain::new(MfaRepository {
+        pg_pool: app_state.get_db_pool(),
+    });
+
+    let result = with_login_throttle(&app_state, &token.sub, &addr, || {
+        mfa_domain.confirm_enrollment(&token.sub, &body.code)
+    });
+    match result {
+        Err(response) => response,
+        Ok(recovery_codes) => HTTPResponse::<RecoveryCodesDTO> {
+            data: Some(RecoveryCodesDTO { recovery_codes }),
+            message: Some(String::from("Two-factor authentication enabled")),
+            status: StatusCode::OK,
+        }
+        .into_response(),
+    }
+}
+
+pub async fn regenerate_recovery_codes<
+    SM: ISessionManager<S, F>,
+    S: ISession<F>,
+    F: IFriendRepository,
+    C: IConnectionManager,
+>(
+    State(app_state): State<Arc<AppState<SM, S, C, F>>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    token: Extension<Token>,
+    Json(body): Json<MfaCodeDTO>,
+) -> impl IntoResponse {
+    let mut mfa_domain = MfaDomain::new(MfaRepository {
+        pg_pool: app_state.get_db_pool(),
+    });
+
+    let result = with_login_throttle(&app_state, &token.sub, &addr, || {
+        mfa_domain.regenerate_recovery_codes(&token.sub, &body.code)
+    });
+    match result {
+        Err(response) => response,
+        Ok(recovery_codes) => HTTPResponse::<RecoveryCodesDTO> {
+            data: Some(RecoveryCodesDTO { recovery_codes }),
+            message: None,
+            status: StatusCode::OK,
+        }
+        .into_response(),
+    }
+}
+
+pub async fn disable_mfa<
+    SM: ISessionManager<S, F>,
+    S: ISession<F>,
+    F: IFriendRepository,
+    C: IConnectionManager,
+>(
+    State(app_state): State<Arc<AppState<SM, S, C, F>>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    token: Extension<Token>,
+    Json(body): Json<MfaCodeDTO>,
+) -> impl IntoResponse {
+    let mut mfa_domain = MfaDomain::new(MfaRepository {
+        pg_pool: app_state.get_db_pool(),
+    });
+
+    let result = with_login_throttle(&app_state, &token.sub, &addr, || {
+        mfa_domain.disable(&token.sub, &body.code)
+    });
+    match result {
+        Err(response) => response,
+        Ok(_) => HTTPResponse::<()> {
+            data: None,
+            message: Some(String::from("Two-factor authentication disabled")),
+            status: StatusCode::OK,
+        }
+        .into_response(),
+    }
+}
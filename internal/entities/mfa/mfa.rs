@@ -0,0 +1,335 @@
+use std::time::{Duration, SystemTime};
+
+use axum::http::StatusCode;
+use base64::Engine;
+use sha2::{Digest, Sha256};
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::{
+    helper::{
+        errors::HTTPResponse,
+        fingerprint::{check_client_binding, ClientBinding, ClientFingerprint},
+        totp::{encode_totp_secret, generate_totp_secret, otpauth_uri, verify_totp},
+    },
+    models::{MfaChallenge, RecoveryCode, UserTotp},
+};
+
+use super::repository::MfaRepositoryInterface;
+
+/// Time the user has to enter the second factor after the password was accepted
+pub const MFA_CHALLENGE_LIFETIME: Duration = Duration::from_secs(5 * 60);
+const RECOVERY_CODE_COUNT: usize = 10;
+/// 80 random bits, shown as four groups of four base32 characters
+const RECOVERY_CODE_LENGTH: usize = 10;
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct TotpEnrollment {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+pub struct MfaDomain<I: MfaRepositoryInterface> {
+    mfa_repository: I,
+    clock: fn() -> SystemTime,
+}
+
+impl<I: MfaRepositoryInterface> MfaDomain<I> {
+    pub fn new(mfa_repository: I) -> Self {
+        return Self::with_clock(mfa_repository, SystemTime::now);
+    }
+
+    /// TOTP codes depend on the time, tests pass a fixed clock
+    pub fn with_clock(mfa_repository: I, clock: fn() -> SystemTime) -> Self {
+        return Self {
+            mfa_repository,
+            clock,
+        };
+    }
+
+    /// Whether logins of the user need a second factor
+    pub fn is_enabled(&mut self, username: &String) -> Result<bool, HTTPResponse<()>> {
+        match self.mfa_repository.get_totp(username) {
+            Err(err) => Err(HTTPResponse::new_internal_error(err)),
+            Ok(totp) => Ok(totp.is_some_and(|totp| totp.confirmed_at.is_some())),
+        }
+    }
+
+    /// Generates a new secret, which is only required on login once a code of it was confirmed
+    pub fn start_enrollment(
+        &mut self,
+        username: &String,
+    ) -> Result<TotpEnrollment, HTTPResponse<()>> {
+        if self.is_enabled(username)? {
+            return Err(HTTPResponse {
+                status: StatusCode::CONFLICT,
+                data: None,
+                message: Some(String::from("Two-factor authentication is already enabled")),
+            });
+        }
+
+        let secret = generate_totp_secret().map_err(HTTPResponse::new_internal_error)?;
+        let totp = UserTotp {
+            username: username.clone(),
+            secret: secret.clone(),
+            confirmed_at: None,
+            last_used_step: None,
+            created_at: (self.clock)(),
+        };
+        if let Err(err) = self.mfa_repository.save_pending_totp(&totp) {
+            return Err(HTTPResponse::new_internal_error(err));
+        }
+
+        debug!(target: "application", "[start_enrollment] started TOTP enrollment of {}", username);
+        Ok(TotpEnrollment {
+            secret: encode_totp_secret(&secret),
+            otpauth_uri: otpauth_uri(username, &secret),
+        })
+    }
+
+    /// Enables the second factor once the user proved the authenticator app has the secret.
+    /// Returns the recovery codes, which are not shown again
+    pub fn confirm_enrollment(
+        &mut self,
+        username: &String,
+        code: &String,
+    ) -> Result<Vec<String>, HTTPResponse<()>> {
+        let totp = match self.mfa_repository.get_totp(username) {
+            Err(err) => return Err(HTTPResponse::new_internal_error(err)),
+            Ok(Some(totp)) if totp.confirmed_at.is_none() => totp,
+            Ok(_) => {
+                return Err(HTTPResponse {
+                    status: StatusCode::BAD_REQUEST,
+                    data: None,
+                    message: Some(String::from("No pending two-factor enrollment")),
+                })
+            }
+        };
+
+        let step = match verify_totp(&totp.secret, code, (self.clock)(), None) {
+            None => return Err(invalid_code()),
+            Some(step) => step,
+        };
+        match self.mfa_repository.confirm_totp(username, step as i64) {
+            Err(err) => return Err(HTTPResponse::new_internal_error(err)),
+            Ok(false) => return Err(invalid_code()),
+            Ok(true) => {}
+        }
+
+        tracing::info!(target: "audit", "[confirm_enrollment] enabled two-factor authentication of {}", username);
+        self.replace_recovery_codes(username)
+    }
+
+    /// Accepts a TOTP code or an unused recovery code. Either can only be used once
+    pub fn verify_second_factor(
+        &mut self,
+        username: &String,
+        code: &String,
+    ) -> Result<(), HTTPResponse<()>> {
+        let totp = match self.mfa_repository.get_totp(username) {
+            Err(err) => return Err(HTTPResponse::new_internal_error(err)),
+            Ok(Some(totp)) if totp.confirmed_at.is_some() => totp,
+            Ok(_) => {
+                return Err(HTTPResponse {
+                    status: StatusCode::BAD_REQUEST,
+                    data: None,
+                    message: Some(String::from("Two-factor authentication is not enabled")),
+                })
+            }
+        };
+
+        let last_used_step = totp.last_used_step.map(|step| step as u64);
+        if let Some(step) = verify_totp(&totp.secret, code, (self.clock)(), last_used_step) {
+            return match self.mfa_repository.claim_totp_step(username, step as i64) {
+                Err(err) => Err(HTTPResponse::new_internal_error(err)),
+                Ok(false) => Err(invalid_code()),
+                Ok(true) => Ok(()),
+            };
+        }
+
+        match self
+            .mfa_repository
+            .use_recovery_code(username, &hash_recovery_code(code))
+        {
+            Err(err) => Err(HTTPResponse::new_internal_error(err)),
+            Ok(false) => Err(invalid_code()),
+            Ok(true) => {
+                tracing::warn!(target: "audit", "[verify_second_factor] {} used a recovery code", username);
+                Ok(())
+            }
+        }
+    }
+
+    /// Replaces all recovery codes, e.g. after they were used up or leaked
+    pub fn regenerate_recovery_codes(
+        &mut self,
+        username: &String,
+        code: &String,
+    ) -> Result<Vec<String>, HTTPResponse<()>> {
+        self.verify_second_factor(username, code)?;
+        self.replace_recovery_codes(username)
+    }
+
+    pub fn disable(&mut self, username: &String, code: &String) -> Result<(), HTTPResponse<()>> {
+        self.verify_second_factor(username, code)?;
+        if let Err(err) = self.mfa_repository.delete_totp(username) {
+            return Err(HTTPResponse::new_internal_error(err));
+        }
+        tracing::info!(target: "audit", "[disable] disabled two-factor authentication of {}", username);
+        Ok(())
+    }
+
+    /// Starts the second login step of a user whose password was accepted. The device session
+    /// is only created once the challenge was completed by the same client
+    pub fn create_challenge(
+        &mut self,
+        username: &String,
+        session_id: &String,
+        fingerprint: &ClientFingerprint,
+    ) -> Result<String, HTTPResponse<()>> {
+        let challenge = random_token(32).map_err(HTTPResponse::new_internal_error)?;
+        let now = (self.clock)();
+        let record = MfaChallenge {
+            challenge_hash: hash_mfa_token(&challenge),
+            username: username.clone(),
+            session_id: session_id.clone(),
+            client_fingerprint: Some(
+                serde_json::to_string(fingerprint).expect("Could not serialize fingerprint"),
+            ),
+            expires_at: now + MFA_CHALLENGE_LIFETIME,
+            created_at: now,
+        };
+
+        match self.mfa_repository.save_mfa_challenge(&record) {
+            Err(err) => Err(HTTPResponse::new_internal_error(err)),
+            Ok(_) => Ok(challenge),
+        }
+    }
+
+    /// Looks up a pending challenge. It stays valid until `complete_challenge`, so a mistyped code can be corrected
+    pub fn get_challenge(
+        &mut self,
+        challenge: &String,
+        fingerprint: &ClientFingerprint,
+        binding: ClientBinding,
+    ) -> Result<MfaChallenge, HTTPResponse<()>> {
+        let invalid_challenge = || HTTPResponse {
+            status: StatusCode::UNAUTHORIZED,
+            data: None,
+            message: Some(String::from(
+                "Invalid or expired MFA token, please log in again",
+            )),
+        };
+
+        let stored = match self
+            .mfa_repository
+            .get_mfa_challenge(&hash_mfa_token(challenge))
+        {
+            Err(err) => return Err(HTTPResponse::new_internal_error(err)),
+            Ok(None) => return Err(invalid_challenge()),
+            Ok(Some(stored)) => stored,
+        };
+        if stored.expires_at <= (self.clock)() {
+            return Err(invalid_challenge());
+        }
+
+        let stored_fingerprint = stored
+            .client_fingerprint
+            .as_ref()
+            .and_then(|f| serde_json::from_str::<ClientFingerprint>(f).ok());
+        if let Err(err) = check_client_binding(&stored_fingerprint, fingerprint, binding) {
+            tracing::warn!(target: "audit", "[get_challenge] MFA challenge of {} presented by another client: {}", stored.username, err);
+            return Err(invalid_challenge());
+        }
+        Ok(stored)
+    }
+
+    /// Consumes the challenge, a login can only be completed once
+    pub fn complete_challenge(&mut self, challenge: &MfaChallenge) -> Result<(), HTTPResponse<()>> {
+        match self
+            .mfa_repository
+            .delete_mfa_challenge(&challenge.challenge_hash)
+        {
+            Err(err) => Err(HTTPResponse::new_internal_error(err)),
+            Ok(false) => Err(HTTPResponse {
+                status: StatusCode::UNAUTHORIZED,
+                data: None,
+                message: Some(String::from(
+                    "Invalid or expired MFA token, please log in again",
+                )),
+            }),
+            Ok(true) => Ok(()),
+        }
+    }
+
+    fn replace_recovery_codes(
+        &mut self,
+        username: &String,
+    ) -> Result<Vec<String>, HTTPResponse<()>> {
+        let now = (self.clock)();
+        let mut codes = Vec::with_capacity(RECOVERY_CODE_COUNT);
+        let mut records = Vec::with_capacity(RECOVERY_CODE_COUNT);
+        for _ in 0..RECOVERY_CODE_COUNT {
+            let code = generate_recovery_code().map_err(HTTPResponse::new_internal_error)?;
+            records.push(RecoveryCode {
+                id: Uuid::new_v4(),
+                username: username.clone(),
+                code_hash: hash_recovery_code(&code),
+                used_at: None,
+                created_at: now,
+            });
+            codes.push(code);
+        }
+
+        match self
+            .mfa_repository
+            .replace_recovery_codes(username, &records)
+        {
+            Err(err) => Err(HTTPResponse::new_internal_error(err)),
+            Ok(_) => Ok(codes),
+        }
+    }
+}
+
+fn invalid_code() -> HTTPResponse<()> {
+    HTTPResponse {
+        status: StatusCode::UNAUTHORIZED,
+        data: None,
+        message: Some(String::from("Invalid code")),
+    }
+}
+
+fn random_token(length: usize) -> Result<String, String> {
+    let mut secret = vec![0u8; length];
+    openssl::rand::rand_bytes(&mut secret)
+        .map_err(|err| format!("Could not generate MFA token: {}", err))?;
+    Ok(base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(secret))
+}
+
+/// MFA tokens are 256 random bits, like refresh tokens a plain SHA-256 is enough
+pub fn hash_mfa_token(token: &String) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+fn generate_recovery_code() -> Result<String, String> {
+    let mut secret = [0u8; RECOVERY_CODE_LENGTH];
+    openssl::rand::rand_bytes(&mut secret)
+        .map_err(|err| format!("Could not generate recovery code: {}", err))?;
+    let code = encode_totp_secret(&secret).to_lowercase();
+    Ok(code
+        .as_bytes()
+        .chunks(4)
+        .map(|chunk| String::from_utf8_lossy(chunk).into_owned())
+        .collect::<Vec<String>>()
+        .join("-"))
+}
+
+/// Recovery codes are 80 random bits, a plain SHA-256 is enough. Case and separators do not matter
+pub fn hash_recovery_code(code: &String) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    hash_mfa_token(&normalized)
+}
@@ -0,0 +1,346 @@
+use std::time::SystemTime;
+
+use crate::models::{MfaChallenge, RecoveryCode, UserTotp};
+
+use super::repository::MfaRepositoryInterface;
+
+#[derive(Default)]
+pub struct MfaRepositoryMock {
+    pub totps: Vec<UserTotp>,
+    pub recovery_codes: Vec<RecoveryCode>,
+    pub challenges: Vec<MfaChallenge>,
+}
+
+impl MfaRepositoryInterface for MfaRepositoryMock {
+    fn get_totp(&mut self, username: &String) -> Result<Option<UserTotp>, String> {
+        return Ok(self.totps.iter().find(|t| &t.username == username).cloned());
+    }
+
+    fn save_pending_totp(&mut self, totp: &UserTotp) -> Result<(), String> {
+        self.totps
+            .retain(|t| t.username != totp.username || t.confirmed_at.is_some());
+        if self.totps.iter().all(|t| t.username != totp.username) {
+            self.totps.push(totp.clone());
+        }
+        return Ok(());
+    }
+
+    fn confirm_totp(&mut self, username: &String, step: i64) -> Result<bool, String> {
+        let totp = self
+            .totps
+            .iter_mut()
+            .find(|t| &t.username == username && t.confirmed_at.is_none());
+        match totp {
+            None => return Ok(false),
+            Some(totp) => {
+                totp.confirmed_at = Some(SystemTime::now());
+                totp.last_used_step = Some(step);
+            }
+        }
+        return Ok(true);
+    }
+
+    fn claim_totp_step(&mut self, username: &String, step: i64) -> Result<bool, String> {
+        let totp = self
+            .totps
+            .iter_mut()
+            .find(|t| &t.username == username && t.last_used_step.map_or(true, |last| last < step));
+        match totp {
+            None => return Ok(false),
+            Some(totp) => totp.last_used_step = Some(step),
+        }
+        return Ok(true);
+    }
+
+    fn delete_totp(&mut self, username: &String) -> Result<usize, String> {
+        self.recovery_codes.retain(|c| &c.username != username);
+        let count = self.totps.len();
+        self.totps.retain(|t| &t.username != username);
+        return Ok(count - self.totps.len());
+    }
+
+    fn replace_recovery_codes(
+        &mut self,
+        username: &String,
+        codes: &Vec<RecoveryCode>,
+    ) -> Result<(), String> {
+        self.recovery_codes.retain(|c| &c.username != username);
+        self.recovery_codes.extend(codes.iter().cloned());
+        return Ok(());
+    }
+
+    fn use_recovery_code(&mut self, username: &String, code_hash: &String) -> Result<bool, String> {
+        let code = self
+            .recovery_codes
+            .iter_mut()
+            .find(|c| &c.username == username && &c.code_hash == code_hash && c.used_at.is_none());
+        match code {
+            None => return Ok(false),
+            Some(code) => code.used_at = Some(SystemTime::now()),
+        }
+        return Ok(true);
+    }
+
+    fn save_mfa_challenge(&mut self, challenge: &MfaChallenge) -> Result<(), String> {
+        self.challenges.push(challenge.clone());
+        return Ok(());
+    }
+
+    fn get_mfa_challenge(
+        &mut self,
+        challenge_hash: &String,
+    ) -> Result<Option<MfaChallenge>, String> {
+        return Ok(self
+            .challenges
+            .iter()
+            .find(|c| &c.challenge_hash == challenge_hash)
+            .cloned());
+    }
+
+    fn delete_mfa_challenge(&mut self, challenge_hash: &String) -> Result<bool, String> {
+        let count = self.challenges.len();
+        self.challenges
+            .retain(|c| &c.challenge_hash != challenge_hash);
+        return Ok(count != self.challenges.len());
+    }
+}
+
+#[cfg(test)]
+mod integration_tests {
+    use std::{
+        net::IpAddr,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    use axum::http::StatusCode;
+
+    use crate::{
+        entities::mfa::{
+            mfa::{hash_mfa_token, MfaDomain, MFA_CHALLENGE_LIFETIME},
+            mfa_test::MfaRepositoryMock,
+        },
+        helper::{
+            fingerprint::{ClientBinding, ClientFingerprint},
+            totp::{hotp, totp_step, TOTP_STEP},
+        },
+        models::{MfaChallenge, UserTotp},
+    };
+
+    const SECRET: &[u8] = b"12345678901234567890";
+
+    fn fixed_clock() -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1234567890)
+    }
+
+    fn username() -> String {
+        String::from("TestUser")
+    }
+
+    /// Code of the authenticator app `steps` time steps from the fixed clock
+    fn code(steps: i64) -> String {
+        hotp(SECRET, (totp_step(fixed_clock()) as i64 + steps) as u64)
+    }
+
+    fn fingerprint() -> ClientFingerprint {
+        ClientFingerprint::new(b"secret", Some("Agent"), &IpAddr::from([10, 0, 0, 1]), None)
+    }
+
+    /// Domain of a user with confirmed TOTP, which used no code yet
+    fn enabled_domain() -> MfaDomain<MfaRepositoryMock> {
+        MfaDomain::with_clock(
+            MfaRepositoryMock {
+                totps: vec![UserTotp {
+                    username: username(),
+                    secret: SECRET.to_vec(),
+                    confirmed_at: Some(fixed_clock()),
+                    last_used_step: None,
+                    created_at: fixed_clock(),
+                }],
+                ..Default::default()
+            },
+            fixed_clock,
+        )
+    }
+
+    #[test]
+    fn test_enrollment() {
+        let mut domain = MfaDomain::with_clock(MfaRepositoryMock::default(), fixed_clock);
+
+        let enrollment = domain.start_enrollment(&username()).unwrap();
+        assert!(enrollment
+            .otpauth_uri
+            .starts_with("otpauth://totp/SanctumChat:TestUser?secret="));
+        assert!(enrollment.otpauth_uri.contains(&enrollment.secret));
+
+        // Not required before the enrollment was confirmed
+        assert_eq!(domain.is_enabled(&username()), Ok(false));
+
+        let secret = base32::decode(
+            base32::Alphabet::RFC4648 { padding: false },
+            &enrollment.secret,
+        )
+        .unwrap();
+        let wrong = domain
+            .confirm_enrollment(&username(), &String::from("000000"))
+            .unwrap_err();
+        assert_eq!(wrong.status, StatusCode::UNAUTHORIZED);
+
+        let valid_code = hotp(&secret, totp_step(fixed_clock()));
+        let recovery_codes = domain.confirm_enrollment(&username(), &valid_code).unwrap();
+        assert_eq!(recovery_codes.len(), 10);
+        assert_eq!(domain.is_enabled(&username()), Ok(true));
+
+        // The code which confirmed the enrollment cannot be used to log in
+        let replay = domain
+            .verify_second_factor(&username(), &valid_code)
+            .unwrap_err();
+        assert_eq!(replay.status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn test_enrollment_cannot_replace_enabled_secret() {
+        let mut domain = enabled_domain();
+
+        let result = domain.start_enrollment(&username()).unwrap_err();
+        assert_eq!(result.status, StatusCode::CONFLICT);
+
+        let result = domain
+            .confirm_enrollment(&username(), &code(0))
+            .unwrap_err();
+        assert_eq!(result.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_totp_code_is_single_use() {
+        let mut domain = enabled_domain();
+
+        assert_eq!(domain.verify_second_factor(&username(), &code(0)), Ok(()));
+        assert!(domain.verify_second_factor(&username(), &code(0)).is_err());
+
+        // Codes of earlier steps are not accepted after a later one was used
+        assert!(domain.verify_second_factor(&username(), &code(-1)).is_err());
+        assert_eq!(domain.verify_second_factor(&username(), &code(1)), Ok(()));
+        assert!(domain.verify_second_factor(&username(), &code(2)).is_err());
+    }
+
+    #[test]
+    fn test_recovery_codes() {
+        let mut domain = enabled_domain();
+        let codes = domain
+            .regenerate_recovery_codes(&username(), &code(0))
+            .unwrap();
+        assert_eq!(codes.len(), 10);
+        assert_eq!(codes[0].len(), 19);
+        assert_ne!(codes[0], codes[1]);
+
+        // Case and separators do not matter, but every code only works once
+        let typed = codes[0].to_uppercase().replace('-', " ");
+        assert_eq!(domain.verify_second_factor(&username(), &typed), Ok(()));
+        assert!(domain.verify_second_factor(&username(), &codes[0]).is_err());
+        assert_eq!(domain.verify_second_factor(&username(), &codes[1]), Ok(()));
+
+        // Regenerating invalidates the remaining codes
+        let new_codes = domain
+            .regenerate_recovery_codes(&username(), &code(1))
+            .unwrap();
+        assert!(domain.verify_second_factor(&username(), &codes[2]).is_err());
+        assert_eq!(
+            domain.verify_second_factor(&username(), &new_codes[0]),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_regenerate_recovery_codes_requires_second_factor() {
+        let mut domain = enabled_domain();
+
+        let result = domain
+            .regenerate_recovery_codes(&username(), &String::from("000000"))
+            .unwrap_err();
+        assert_eq!(result.status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn test_disable() {
+        let mut domain = enabled_domain();
+        let codes = domain
+            .regenerate_recovery_codes(&username(), &code(0))
+            .unwrap();
+
+        let result = domain
+            .disable(&username(), &String::from("000000"))
+            .unwrap_err();
+        assert_eq!(result.status, StatusCode::UNAUTHORIZED);
+        assert_eq!(domain.is_enabled(&username()), Ok(true));
+
+        assert_eq!(domain.disable(&username(), &codes[0]), Ok(()));
+        assert_eq!(domain.is_enabled(&username()), Ok(false));
+        assert!(domain.verify_second_factor(&username(), &codes[1]).is_err());
+    }
+
+    #[test]
+    fn test_challenge() {
+        let mut domain = enabled_domain();
+        let token = domain
+            .create_challenge(&username(), &String::from("sid"), &fingerprint())
+            .unwrap();
+
+        let challenge = domain
+            .get_challenge(&token, &fingerprint(), ClientBinding::UserAgent)
+            .unwrap();
+        assert_eq!(challenge.username, username());
+        assert_eq!(challenge.session_id, "sid");
+        assert_eq!(challenge.challenge_hash, hash_mfa_token(&token));
+        assert_eq!(challenge.expires_at, fixed_clock() + MFA_CHALLENGE_LIFETIME);
+
+        assert_eq!(domain.complete_challenge(&challenge), Ok(()));
+        let result = domain
+            .get_challenge(&token, &fingerprint(), ClientBinding::UserAgent)
+            .unwrap_err();
+        assert_eq!(result.status, StatusCode::UNAUTHORIZED);
+        assert!(domain.complete_challenge(&challenge).is_err());
+    }
+
+    #[test]
+    fn test_challenge_is_bound_to_client() {
+        let mut domain = enabled_domain();
+        let token = domain
+            .create_challenge(&username(), &String::from("sid"), &fingerprint())
+            .unwrap();
+        let other_client =
+            ClientFingerprint::new(b"secret", Some("Other"), &IpAddr::from([10, 0, 0, 1]), None);
+
+        let result = domain
+            .get_challenge(&token, &other_client, ClientBinding::UserAgent)
+            .unwrap_err();
+        assert_eq!(result.status, StatusCode::UNAUTHORIZED);
+        assert!(domain
+            .get_challenge(&token, &fingerprint(), ClientBinding::UserAgent)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_expired_challenge_is_rejected() {
+        let token = String::from("expired");
+        let created_at = fixed_clock() - MFA_CHALLENGE_LIFETIME - Duration::from_secs(TOTP_STEP);
+        let mut domain = MfaDomain::with_clock(
+            MfaRepositoryMock {
+                challenges: vec![MfaChallenge {
+                    challenge_hash: hash_mfa_token(&token),
+                    username: username(),
+                    session_id: String::from("sid"),
+                    client_fingerprint: None,
+                    expires_at: created_at + MFA_CHALLENGE_LIFETIME,
+                    created_at,
+                }],
+                ..Default::default()
+            },
+            fixed_clock,
+        );
+
+        let result = domain
+            .get_challenge(&token, &fingerprint(), ClientBinding::Off)
+            .unwrap_err();
+        assert_eq!(result.status, StatusCode::UNAUTHORIZED);
+    }
+}
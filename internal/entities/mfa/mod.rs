@@ -0,0 +1,4 @@
+pub mod controller;
+pub mod mfa;
+pub mod mfa_test;
+pub mod repository;
@@ -0,0 +1,231 @@
+use std::time::SystemTime;
+
+use diesel::prelude::*;
+use diesel::{
+    r2d2::{ConnectionManager, PooledConnection},
+    PgConnection,
+};
+
+use crate::{
+    models::{MfaChallenge, RecoveryCode, UserTotp},
+    schema::{mfa_challenges, recovery_codes, user_totp},
+};
+
+pub trait MfaRepositoryInterface {
+    fn get_totp(&mut self, username: &String) -> Result<Option<UserTotp>, String>;
+    /// Replaces an unconfirmed secret, confirmed ones are kept
+    fn save_pending_totp(&mut self, totp: &UserTotp) -> Result<(), String>;
+    /// Returns false if the enrollment was confirmed before
+    fn confirm_totp(&mut self, username: &String, step: i64) -> Result<bool, String>;
+    /// Records the time step of an accepted code. Returns false if the step or a later one was used before
+    fn claim_totp_step(&mut self, username: &String, step: i64) -> Result<bool, String>;
+    /// Removes the secret and the recovery codes
+    fn delete_totp(&mut self, username: &String) -> Result<usize, String>;
+    fn replace_recovery_codes(
+        &mut self,
+        username: &String,
+        codes: &Vec<RecoveryCode>,
+    ) -> Result<(), String>;
+    /// Marks the code used. Returns false if it does not exist or was used before
+    fn use_recovery_code(&mut self, username: &String, code_hash: &String) -> Result<bool, String>;
+    /// Expired challenges of the user are deleted along the way
+    fn save_mfa_challenge(&mut self, challenge: &MfaChallenge) -> Result<(), String>;
+    fn get_mfa_challenge(
+        &mut self,
+        challenge_hash: &String,
+    ) -> Result<Option<MfaChallenge>, String>;
+    /// Returns false if another request deleted it first
+    fn delete_mfa_challenge(&mut self, challenge_hash: &String) -> Result<bool, String>;
+}
+
+pub struct MfaRepository {
+    pub pg_pool: PooledConnection<ConnectionManager<PgConnection>>,
+}
+
+impl MfaRepositoryInterface for MfaRepository {
+    fn get_totp(&mut self, username: &String) -> Result<Option<UserTotp>, String> {
+        let result = user_totp::table
+            .select(UserTotp::as_select())
+            .filter(user_totp::username.eq(username))
+            .first::<UserTotp>(&mut self.pg_pool)
+            .optional();
+
+        match result {
+            Err(err) => Err(format!("Could not get TOTP of {}: {}", username, err)),
+            Ok(res) => Ok(res),
+        }
+    }
+
+    fn save_pending_totp(&mut self, totp: &UserTotp) -> Result<(), String> {
+        let result = self
+            .pg_pool
+            .transaction::<_, diesel::result::Error, _>(|conn| {
+                diesel::delete(
+                    user_totp::table
+                        .filter(user_totp::username.eq(&totp.username))
+                        .filter(user_totp::confirmed_at.is_null()),
+                )
+                .execute(conn)?;
+                diesel::insert_into(user_totp::table)
+                    .values(totp)
+                    .on_conflict_do_nothing()
+                    .execute(conn)
+            });
+
+        match result {
+            Err(err) => Err(format!("Could not save TOTP of {}: {}", totp.username, err)),
+            Ok(_) => Ok(()),
+        }
+    }
+
+    fn confirm_totp(&mut self, username: &String, step: i64) -> Result<bool, String> {
+        let result = diesel::update(
+            user_totp::table
+                .filter(user_totp::username.eq(username))
+                .filter(user_totp::confirmed_at.is_null()),
+        )
+        .set((
+            user_totp::confirmed_at.eq(SystemTime::now()),
+            user_totp::last_used_step.eq(step),
+        ))
+        .execute(&mut self.pg_pool);
+
+        match result {
+            Err(err) => Err(format!("Could not confirm TOTP of {}: {}", username, err)),
+            Ok(updated) => Ok(updated == 1),
+        }
+    }
+
+    fn claim_totp_step(&mut self, username: &String, step: i64) -> Result<bool, String> {
+        let result = diesel::update(
+            user_totp::table
+                .filter(user_totp::username.eq(username))
+                .filter(
+                    user_totp::last_used_step
+                        .is_null()
+                        .or(user_totp::last_used_step.lt(step)),
+                ),
+        )
+        .set(user_totp::last_used_step.eq(step))
+        .execute(&mut self.pg_pool);
+
+        match result {
+            Err(err) => Err(format!(
+                "Could not claim TOTP step of {}: {}",
+                username, err
+            )),
+            Ok(updated) => Ok(updated == 1),
+        }
+    }
+
+    fn delete_totp(&mut self, username: &String) -> Result<usize, String> {
+        let result = self
+            .pg_pool
+            .transaction::<_, diesel::result::Error, _>(|conn| {
+                diesel::delete(recovery_codes::table.filter(recovery_codes::username.eq(username)))
+                    .execute(conn)?;
+                diesel::delete(user_totp::table.filter(user_totp::username.eq(username)))
+                    .execute(conn)
+            });
+
+        match result {
+            Err(err) => Err(format!("Could not delete TOTP of {}: {}", username, err)),
+            Ok(res) => Ok(res),
+        }
+    }
+
+    fn replace_recovery_codes(
+        &mut self,
+        username: &String,
+        codes: &Vec<RecoveryCode>,
+    ) -> Result<(), String> {
+        let result = self
+            .pg_pool
+            .transaction::<_, diesel::result::Error, _>(|conn| {
+                diesel::delete(recovery_codes::table.filter(recovery_codes::username.eq(username)))
+                    .execute(conn)?;
+                diesel::insert_into(recovery_codes::table)
+                    .values(codes)
+                    .execute(conn)
+            });
+
+        match result {
+            Err(err) => Err(format!(
+                "Could not replace recovery codes of {}: {}",
+                username, err
+            )),
+            Ok(_) => Ok(()),
+        }
+    }
+
+    fn use_recovery_code(&mut self, username: &String, code_hash: &String) -> Result<bool, String> {
+        let result = diesel::update(
+            recovery_codes::table
+                .filter(recovery_codes::username.eq(username))
+                .filter(recovery_codes::code_hash.eq(code_hash))
+                .filter(recovery_codes::used_at.is_null()),
+        )
+        .set(recovery_codes::used_at.eq(SystemTime::now()))
+        .execute(&mut self.pg_pool);
+
+        match result {
+            Err(err) => Err(format!(
+                "Could not use recovery code of {}: {}",
+                username, err
+            )),
+            Ok(updated) => Ok(updated > 0),
+        }
+    }
+
+    fn save_mfa_challenge(&mut self, challenge: &MfaChallenge) -> Result<(), String> {
+        let result = self
+            .pg_pool
+            .transaction::<_, diesel::result::Error, _>(|conn| {
+                diesel::delete(
+                    mfa_challenges::table
+                        .filter(mfa_challenges::username.eq(&challenge.username))
+                        .filter(mfa_challenges::expires_at.le(SystemTime::now())),
+                )
+                .execute(conn)?;
+                diesel::insert_into(mfa_challenges::table)
+                    .values(challenge)
+                    .execute(conn)
+            });
+
+        match result {
+            Err(err) => Err(format!(
+                "Could not save MFA challenge of {}: {}",
+                challenge.username, err
+            )),
+            Ok(_) => Ok(()),
+        }
+    }
+
+    fn get_mfa_challenge(
+        &mut self,
+        challenge_hash: &String,
+    ) -> Result<Option<MfaChallenge>, String> {
+        let result = mfa_challenges::table
+            .select(MfaChallenge::as_select())
+            .filter(mfa_challenges::challenge_hash.eq(challenge_hash))
+            .first::<MfaChallenge>(&mut self.pg_pool)
+            .optional();
+
+        match result {
+            Err(err) => Err(format!("Could not get MFA challenge: {}", err)),
+            Ok(res) => Ok(res),
+        }
+    }
+
+    fn delete_mfa_challenge(&mut self, challenge_hash: &String) -> Result<bool, String> {
+        let result = diesel::delete(
+            mfa_challenges::table.filter(mfa_challenges::challenge_hash.eq(challenge_hash)),
+        )
+        .execute(&mut self.pg_pool);
+
+        match result {
+            Err(err) => Err(format!("Could not delete MFA challenge: {}", err)),
+            Ok(deleted) => Ok(deleted == 1),
+        }
+    }
+}
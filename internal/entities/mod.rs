@@ -1,4 +1,9 @@
+pub mod conversations;
 pub mod friend_requests;
 pub mod friends;
+pub mod keys;
 pub mod messages;
+pub mod mfa;
+pub mod refresh_tokens;
+pub mod rooms;
 pub mod users;
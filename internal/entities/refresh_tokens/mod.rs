@@ -0,0 +1,3 @@
+pub mod refresh_tokens;
+pub mod refresh_tokens_test;
+pub mod repository;
@@ -0,0 +1,222 @@
+use std::time::{Duration, SystemTime};
+
+use axum::http::StatusCode;
+use base64::Engine;
+use sha2::{Digest, Sha256};
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::{
+    helper::{
+        errors::HTTPResponse,
+        fingerprint::{check_client_binding, ClientBinding, ClientFingerprint},
+    },
+    models::RefreshToken,
+};
+
+use super::repository::RefreshTokenRepositoryInterface;
+
+/// Every rotation issues a token valid for this long, so sessions stay alive as long as they are used
+pub const REFRESH_TOKEN_LIFETIME: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+pub struct RefreshTokenDomain<I: RefreshTokenRepositoryInterface> {
+    refresh_token_repository: I,
+}
+
+impl<I: RefreshTokenRepositoryInterface> RefreshTokenDomain<I> {
+    pub fn new(refresh_token_repository: I) -> Self {
+        return Self {
+            refresh_token_repository,
+        };
+    }
+
+    /// Starts a new token family for the device session.
+    /// The returned token is only ever handed to the client, the database only knows its hash
+    pub fn issue_refresh_token(
+        &mut self,
+        username: &String,
+        session_id: &String,
+        fingerprint: &ClientFingerprint,
+    ) -> Result<String, HTTPResponse<()>> {
+        self.save_refresh_token(Uuid::new_v4(), username, session_id, fingerprint)
+    }
+
+    /// Exchanges the refresh token for a new one of the same family.
+    /// Presenting a token that was already exchanged means it leaked, so the whole family is revoked
+    /// and neither the thief nor the legitimate client can refresh the session anymore.
+    /// The token has to be presented by the client it was issued to, as far as `binding` requires
+    pub fn rotate_refresh_token(
+        &mut self,
+        refresh_token: &String,
+        fingerprint: &ClientFingerprint,
+        binding: ClientBinding,
+    ) -> Result<(RefreshToken, String), HTTPResponse<()>> {
+        let invalid_token = || HTTPResponse {
+            status: StatusCode::UNAUTHORIZED,
+            data: None,
+            message: Some(String::from("Invalid refresh token")),
+        };
+
+        let stored = match self
+            .refresh_token_repository
+            .get_refresh_token(&hash_refresh_token(refresh_token))
+        {
+            Err(err) => return Err(HTTPResponse::new_internal_error(err)),
+            Ok(None) => return Err(invalid_token()),
+            Ok(Some(stored)) => stored,
+        };
+
+        if stored.revoked_at.is_some() || stored.expires_at <= SystemTime::now() {
+            return Err(invalid_token());
+        }
+
+        // Checked before the client binding, so a replayed token revokes its family whichever client presents it
+        if stored.used_at.is_some() {
+            self.revoke_reused_token_family(&stored)?;
+            return Err(invalid_token());
+        }
+
+        let stored_fingerprint = stored
+            .client_fingerprint
+            .as_ref()
+            .and_then(|f| serde_json::from_str::<ClientFingerprint>(f).ok());
+        if let Err(err) = check_client_binding(&stored_fingerprint, fingerprint, binding) {
+            tracing::warn!(target: "audit", "[rotate_refresh_token] refresh token of {} session {} presented by another client: {}", stored.username, stored.session_id, err);
+            return Err(HTTPResponse {
+                status: StatusCode::UNAUTHORIZED,
+                data: None,
+                message: Some(String::from("Client changed, please log in again")),
+            });
+        }
+
+        // Another rotation may have used the token since it was loaded
+        match self
+            .refresh_token_repository
+            .mark_refresh_token_used(&stored.id)
+        {
+            Err(err) => return Err(HTTPResponse::new_internal_error(err)),
+            Ok(false) => {
+                self.revoke_reused_token_family(&stored)?;
+                return Err(invalid_token());
+            }
+            Ok(true) => {}
+        }
+
+        let new_token = self.save_refresh_token(
+            stored.family_id,
+            &stored.username,
+            &stored.session_id,
+            fingerprint,
+        )?;
+        debug!(target: "application", "[rotate_refresh_token] rotated refresh token of {} session {}", stored.username, stored.session_id);
+        Ok((stored, new_token))
+    }
+
+    fn revoke_reused_token_family(
+        &mut self,
+        stored: &RefreshToken,
+    ) -> Result<(), HTTPResponse<()>> {
+        tracing::warn!(target: "audit", "[rotate_refresh_token] refresh token of {} session {} was reused, revoking token family {}", stored.username, stored.session_id, stored.family_id);
+        match self
+            .refresh_token_repository
+            .revoke_refresh_token_family(&stored.family_id)
+        {
+            Err(err) => Err(HTTPResponse::new_internal_error(err)),
+            Ok(_) => Ok(()),
+        }
+    }
+
+    pub fn revoke_session_refresh_tokens(
+        &mut self,
+        session_id: &String,
+    ) -> Result<(), HTTPResponse<()>> {
+        match self
+            .refresh_token_repository
+            .revoke_session_refresh_tokens(session_id)
+        {
+            Err(err) => Err(HTTPResponse::new_internal_error(err)),
+            Ok(revoked) => {
+                debug!(target: "application", "[revoke_session_refresh_tokens] revoked {} refresh tokens of session {}", revoked, session_id);
+                Ok(())
+            }
+        }
+    }
+
+    /// Used to log out everywhere, every device session of the user has to log in again
+    pub fn revoke_user_refresh_tokens(
+        &mut self,
+        username: &String,
+    ) -> Result<(), HTTPResponse<()>> {
+        match self
+            .refresh_token_repository
+            .revoke_user_refresh_tokens(username)
+        {
+            Err(err) => Err(HTTPResponse::new_internal_error(err)),
+            Ok(revoked) => {
+                debug!(target: "application", "[revoke_user_refresh_tokens] revoked {} refresh tokens of {}", revoked, username);
+                Ok(())
+            }
+        }
+    }
+
+    /// Used after a password change, only the device session it was changed with stays logged in
+    pub fn revoke_other_refresh_tokens(
+        &mut self,
+        username: &String,
+        kept_session_id: &String,
+    ) -> Result<(), HTTPResponse<()>> {
+        match self
+            .refresh_token_repository
+            .revoke_other_refresh_tokens(username, kept_session_id)
+        {
+            Err(err) => Err(HTTPResponse::new_internal_error(err)),
+            Ok(revoked) => {
+                debug!(target: "application", "[revoke_other_refresh_tokens] revoked {} refresh tokens of other sessions of {}", revoked, username);
+                Ok(())
+            }
+        }
+    }
+
+    fn save_refresh_token(
+        &mut self,
+        family_id: Uuid,
+        username: &String,
+        session_id: &String,
+        fingerprint: &ClientFingerprint,
+    ) -> Result<String, HTTPResponse<()>> {
+        let mut secret = [0u8; 32];
+        if let Err(err) = openssl::rand::rand_bytes(&mut secret) {
+            return Err(HTTPResponse::new_internal_error(format!(
+                "Could not generate refresh token: {}",
+                err
+            )));
+        }
+        let token = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(secret);
+
+        let now = SystemTime::now();
+        let record = RefreshToken {
+            id: Uuid::new_v4(),
+            family_id,
+            session_id: session_id.clone(),
+            username: username.clone(),
+            token_hash: hash_refresh_token(&token),
+            expires_at: now + REFRESH_TOKEN_LIFETIME,
+            used_at: None,
+            revoked_at: None,
+            created_at: now,
+            client_fingerprint: Some(
+                serde_json::to_string(fingerprint).expect("Could not serialize fingerprint"),
+            ),
+        };
+
+        match self.refresh_token_repository.save_refresh_token(&record) {
+            Err(err) => Err(HTTPResponse::new_internal_error(err)),
+            Ok(_) => Ok(token),
+        }
+    }
+}
+
+/// Refresh tokens are 256 random bits, a plain SHA-256 is enough to keep a database leak from exposing them
+pub fn hash_refresh_token(token: &String) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
@@ -0,0 +1,380 @@
+use std::time::SystemTime;
+
+use uuid::Uuid;
+
+use crate::models::RefreshToken;
+
+use super::repository::RefreshTokenRepositoryInterface;
+
+pub struct RefreshTokenRepositoryMock {
+    pub tokens: Vec<RefreshToken>,
+}
+
+impl RefreshTokenRepositoryInterface for RefreshTokenRepositoryMock {
+    fn save_refresh_token(&mut self, token: &RefreshToken) -> Result<(), String> {
+        self.tokens.push(token.clone());
+        return Ok(());
+    }
+
+    fn get_refresh_token(&mut self, token_hash: &String) -> Result<Option<RefreshToken>, String> {
+        return Ok(self
+            .tokens
+            .iter()
+            .find(|t| &t.token_hash == token_hash)
+            .cloned());
+    }
+
+    fn mark_refresh_token_used(&mut self, id: &Uuid) -> Result<bool, String> {
+        let token = self
+            .tokens
+            .iter_mut()
+            .find(|t| &t.id == id && t.used_at.is_none() && t.revoked_at.is_none());
+        match token {
+            None => return Ok(false),
+            Some(token) => token.used_at = Some(SystemTime::now()),
+        }
+        return Ok(true);
+    }
+
+    fn revoke_refresh_token_family(&mut self, family_id: &Uuid) -> Result<usize, String> {
+        let mut revoked = 0;
+        for token in self.tokens.iter_mut().filter(|t| &t.family_id == family_id) {
+            token.revoked_at = Some(SystemTime::now());
+            revoked += 1;
+        }
+        return Ok(revoked);
+    }
+
+    fn revoke_session_refresh_tokens(&mut self, session_id: &String) -> Result<usize, String> {
+        let mut revoked = 0;
+        for token in self
+            .tokens
+            .iter_mut()
+            .filter(|t| &t.session_id == session_id)
+        {
+            token.revoked_at = Some(SystemTime::now());
+            revoked += 1;
+        }
+        return Ok(revoked);
+    }
+
+    fn revoke_user_refresh_tokens(&mut self, username: &String) -> Result<usize, String> {
+        let mut revoked = 0;
+        for token in self.tokens.iter_mut().filter(|t| &t.username == username) {
+            token.revoked_at = Some(SystemTime::now());
+            revoked += 1;
+        }
+        return Ok(revoked);
+    }
+
+    fn revoke_other_refresh_tokens(
+        &mut self,
+        username: &String,
+        kept_session_id: &String,
+    ) -> Result<usize, String> {
+        let mut revoked = 0;
+        for token in self
+            .tokens
+            .iter_mut()
+            .filter(|t| &t.username == username && &t.session_id != kept_session_id)
+        {
+            token.revoked_at = Some(SystemTime::now());
+            revoked += 1;
+        }
+        return Ok(revoked);
+    }
+}
+
+#[cfg(test)]
+mod integration_tests {
+    use std::time::{Duration, SystemTime};
+
+    use std::net::IpAddr;
+
+    use axum::http::StatusCode;
+    use uuid::Uuid;
+
+    use crate::{
+        entities::refresh_tokens::{
+            refresh_tokens::{hash_refresh_token, RefreshTokenDomain, REFRESH_TOKEN_LIFETIME},
+            refresh_tokens_test::RefreshTokenRepositoryMock,
+        },
+        helper::fingerprint::{ClientBinding, ClientFingerprint},
+        models::RefreshToken,
+    };
+
+    fn fingerprint() -> ClientFingerprint {
+        ClientFingerprint::new(b"secret", Some("Agent"), &IpAddr::from([10, 0, 0, 1]), None)
+    }
+
+    fn new_domain() -> RefreshTokenDomain<RefreshTokenRepositoryMock> {
+        RefreshTokenDomain::new(RefreshTokenRepositoryMock { tokens: vec![] })
+    }
+
+    #[test]
+    fn test_issue_refresh_token_stores_only_the_hash() {
+        let mut domain = new_domain();
+        let token = domain
+            .issue_refresh_token(
+                &String::from("TestUser"),
+                &String::from("sid"),
+                &fingerprint(),
+            )
+            .unwrap();
+        let (stored, _) = domain
+            .rotate_refresh_token(&token, &fingerprint(), ClientBinding::UserAgent)
+            .unwrap();
+
+        assert_eq!(stored.token_hash, hash_refresh_token(&token));
+        assert_ne!(stored.token_hash, token);
+        assert_eq!(stored.username, "TestUser");
+        assert_eq!(stored.session_id, "sid");
+        assert!(stored.expires_at > SystemTime::now() + Duration::from_secs(29 * 24 * 60 * 60));
+
+        let result = domain
+            .rotate_refresh_token(
+                &String::from("unknown"),
+                &fingerprint(),
+                ClientBinding::UserAgent,
+            )
+            .unwrap_err();
+        assert_eq!(result.status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn test_expired_refresh_token_is_rejected() {
+        let token = String::from("expired");
+        let created_at = SystemTime::now() - Duration::from_secs(31 * 24 * 60 * 60);
+        let mut domain = RefreshTokenDomain::new(RefreshTokenRepositoryMock {
+            tokens: vec![RefreshToken {
+                id: Uuid::new_v4(),
+                family_id: Uuid::new_v4(),
+                session_id: String::from("sid"),
+                username: String::from("TestUser"),
+                token_hash: hash_refresh_token(&token),
+                expires_at: created_at + REFRESH_TOKEN_LIFETIME,
+                used_at: None,
+                revoked_at: None,
+                created_at,
+                client_fingerprint: None,
+            }],
+        });
+
+        let result = domain
+            .rotate_refresh_token(&token, &fingerprint(), ClientBinding::UserAgent)
+            .unwrap_err();
+        assert_eq!(result.status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn test_rotate_refresh_token() {
+        let mut domain = new_domain();
+        let first = domain
+            .issue_refresh_token(
+                &String::from("TestUser"),
+                &String::from("sid"),
+                &fingerprint(),
+            )
+            .unwrap();
+
+        let (first_stored, second) = domain
+            .rotate_refresh_token(&first, &fingerprint(), ClientBinding::UserAgent)
+            .unwrap();
+        assert_ne!(first, second);
+
+        let (second_stored, third) = domain
+            .rotate_refresh_token(&second, &fingerprint(), ClientBinding::UserAgent)
+            .unwrap();
+        assert_ne!(second, third);
+        assert_eq!(first_stored.family_id, second_stored.family_id);
+        assert_eq!(second_stored.session_id, "sid");
+    }
+
+    #[test]
+    fn test_reused_refresh_token_revokes_family() {
+        let mut domain = new_domain();
+        let first = domain
+            .issue_refresh_token(
+                &String::from("TestUser"),
+                &String::from("sid"),
+                &fingerprint(),
+            )
+            .unwrap();
+        let other_session = domain
+            .issue_refresh_token(
+                &String::from("TestUser"),
+                &String::from("other"),
+                &fingerprint(),
+            )
+            .unwrap();
+
+        let (_, second) = domain
+            .rotate_refresh_token(&first, &fingerprint(), ClientBinding::UserAgent)
+            .unwrap();
+
+        // The old token is replayed, e.g. by someone who stole it
+        let result = domain
+            .rotate_refresh_token(&first, &fingerprint(), ClientBinding::UserAgent)
+            .unwrap_err();
+        assert_eq!(result.status, StatusCode::UNAUTHORIZED);
+
+        // The newest token of the family is revoked as well
+        let result = domain
+            .rotate_refresh_token(&second, &fingerprint(), ClientBinding::UserAgent)
+            .unwrap_err();
+        assert_eq!(result.status, StatusCode::UNAUTHORIZED);
+
+        // Other device sessions are not affected
+        assert!(domain
+            .rotate_refresh_token(&other_session, &fingerprint(), ClientBinding::UserAgent)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_revoke_session_refresh_tokens() {
+        let mut domain = new_domain();
+        let token = domain
+            .issue_refresh_token(
+                &String::from("TestUser"),
+                &String::from("sid"),
+                &fingerprint(),
+            )
+            .unwrap();
+
+        domain
+            .revoke_session_refresh_tokens(&String::from("sid"))
+            .unwrap();
+
+        let result = domain
+            .rotate_refresh_token(&token, &fingerprint(), ClientBinding::UserAgent)
+            .unwrap_err();
+        assert_eq!(result.status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn test_revoke_user_refresh_tokens() {
+        let mut domain = new_domain();
+        let first_device = domain
+            .issue_refresh_token(
+                &String::from("TestUser"),
+                &String::from("sid"),
+                &fingerprint(),
+            )
+            .unwrap();
+        let second_device = domain
+            .issue_refresh_token(
+                &String::from("TestUser"),
+                &String::from("other"),
+                &fingerprint(),
+            )
+            .unwrap();
+        let other_user = domain
+            .issue_refresh_token(
+                &String::from("OtherUser"),
+                &String::from("third"),
+                &fingerprint(),
+            )
+            .unwrap();
+
+        domain
+            .revoke_user_refresh_tokens(&String::from("TestUser"))
+            .unwrap();
+
+        assert!(domain
+            .rotate_refresh_token(&first_device, &fingerprint(), ClientBinding::UserAgent)
+            .is_err());
+        assert!(domain
+            .rotate_refresh_token(&second_device, &fingerprint(), ClientBinding::UserAgent)
+            .is_err());
+        assert!(domain
+            .rotate_refresh_token(&other_user, &fingerprint(), ClientBinding::UserAgent)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_revoke_other_refresh_tokens() {
+        let mut domain = new_domain();
+        let current_device = domain
+            .issue_refresh_token(
+                &String::from("TestUser"),
+                &String::from("sid"),
+                &fingerprint(),
+            )
+            .unwrap();
+        let other_device = domain
+            .issue_refresh_token(
+                &String::from("TestUser"),
+                &String::from("other"),
+                &fingerprint(),
+            )
+            .unwrap();
+
+        domain
+            .revoke_other_refresh_tokens(&String::from("TestUser"), &String::from("sid"))
+            .unwrap();
+
+        assert!(domain
+            .rotate_refresh_token(&current_device, &fingerprint(), ClientBinding::UserAgent)
+            .is_ok());
+        assert!(domain
+            .rotate_refresh_token(&other_device, &fingerprint(), ClientBinding::UserAgent)
+            .is_err());
+    }
+
+    #[test]
+    fn test_refresh_token_is_bound_to_client() {
+        let mut domain = new_domain();
+        let token = domain
+            .issue_refresh_token(
+                &String::from("TestUser"),
+                &String::from("sid"),
+                &fingerprint(),
+            )
+            .unwrap();
+
+        let other_client =
+            ClientFingerprint::new(b"secret", Some("Other"), &IpAddr::from([10, 0, 0, 1]), None);
+        let result = domain
+            .rotate_refresh_token(&token, &other_client, ClientBinding::UserAgent)
+            .unwrap_err();
+        assert_eq!(result.status, StatusCode::UNAUTHORIZED);
+
+        // Rejected clients do not use up the token
+        let other_network =
+            ClientFingerprint::new(b"secret", Some("Agent"), &IpAddr::from([10, 0, 1, 1]), None);
+        assert!(domain
+            .rotate_refresh_token(&token, &other_network, ClientBinding::Strict)
+            .is_err());
+        assert!(domain
+            .rotate_refresh_token(&token, &other_network, ClientBinding::UserAgent)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_reused_refresh_token_from_another_client_revokes_family() {
+        let mut domain = new_domain();
+        let first = domain
+            .issue_refresh_token(
+                &String::from("TestUser"),
+                &String::from("sid"),
+                &fingerprint(),
+            )
+            .unwrap();
+        let (_, second) = domain
+            .rotate_refresh_token(&first, &fingerprint(), ClientBinding::UserAgent)
+            .unwrap();
+
+        // The stolen token is replayed from another client, which must not hide the reuse
+        let other_client =
+            ClientFingerprint::new(b"secret", Some("Other"), &IpAddr::from([10, 0, 0, 1]), None);
+        let result = domain
+            .rotate_refresh_token(&first, &other_client, ClientBinding::UserAgent)
+            .unwrap_err();
+        assert_eq!(result.status, StatusCode::UNAUTHORIZED);
+
+        let result = domain
+            .rotate_refresh_token(&second, &fingerprint(), ClientBinding::UserAgent)
+            .unwrap_err();
+        assert_eq!(result.status, StatusCode::UNAUTHORIZED);
+    }
+}
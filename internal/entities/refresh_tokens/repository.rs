@@ -0,0 +1,153 @@
+use std::time::SystemTime;
+
+use diesel::prelude::*;
+use diesel::{
+    r2d2::{ConnectionManager, PooledConnection},
+    PgConnection,
+};
+use uuid::Uuid;
+
+use crate::{models::RefreshToken, schema::refresh_tokens};
+
+pub trait RefreshTokenRepositoryInterface {
+    fn save_refresh_token(&mut self, token: &RefreshToken) -> Result<(), String>;
+    fn get_refresh_token(&mut self, token_hash: &String) -> Result<Option<RefreshToken>, String>;
+    /// Marks the token as used unless it was used or revoked before.
+    /// Returns false if another request used it first
+    fn mark_refresh_token_used(&mut self, id: &Uuid) -> Result<bool, String>;
+    fn revoke_refresh_token_family(&mut self, family_id: &Uuid) -> Result<usize, String>;
+    fn revoke_session_refresh_tokens(&mut self, session_id: &String) -> Result<usize, String>;
+    fn revoke_user_refresh_tokens(&mut self, username: &String) -> Result<usize, String>;
+    /// Revokes the tokens of every device session of the user except `kept_session_id`
+    fn revoke_other_refresh_tokens(
+        &mut self,
+        username: &String,
+        kept_session_id: &String,
+    ) -> Result<usize, String>;
+}
+
+pub struct RefreshTokenRepository {
+    pub pg_pool: PooledConnection<ConnectionManager<PgConnection>>,
+}
+
+impl RefreshTokenRepositoryInterface for RefreshTokenRepository {
+    fn save_refresh_token(&mut self, token: &RefreshToken) -> Result<(), String> {
+        let result = diesel::insert_into(refresh_tokens::table)
+            .values(token)
+            .execute(&mut self.pg_pool);
+
+        match result {
+            Err(err) => Err(format!(
+                "Could not save refresh token of {}: {}",
+                token.username, err
+            )),
+            Ok(_) => Ok(()),
+        }
+    }
+
+    fn get_refresh_token(&mut self, token_hash: &String) -> Result<Option<RefreshToken>, String> {
+        let result = refresh_tokens::table
+            .select(RefreshToken::as_select())
+            .filter(refresh_tokens::token_hash.eq(token_hash))
+            .first::<RefreshToken>(&mut self.pg_pool)
+            .optional();
+
+        match result {
+            Err(err) => Err(format!("Could not get refresh token: {}", err)),
+            Ok(res) => Ok(res),
+        }
+    }
+
+    fn mark_refresh_token_used(&mut self, id: &Uuid) -> Result<bool, String> {
+        let result = diesel::update(
+            refresh_tokens::table
+                .filter(refresh_tokens::id.eq(id))
+                .filter(refresh_tokens::used_at.is_null())
+                .filter(refresh_tokens::revoked_at.is_null()),
+        )
+        .set(refresh_tokens::used_at.eq(SystemTime::now()))
+        .execute(&mut self.pg_pool);
+
+        match result {
+            Err(err) => Err(format!("Could not mark refresh token {} used: {}", id, err)),
+            Ok(updated) => Ok(updated == 1),
+        }
+    }
+
+    fn revoke_refresh_token_family(&mut self, family_id: &Uuid) -> Result<usize, String> {
+        let result = diesel::update(
+            refresh_tokens::table
+                .filter(refresh_tokens::family_id.eq(family_id))
+                .filter(refresh_tokens::revoked_at.is_null()),
+        )
+        .set(refresh_tokens::revoked_at.eq(SystemTime::now()))
+        .execute(&mut self.pg_pool);
+
+        match result {
+            Err(err) => Err(format!(
+                "Could not revoke refresh token family {}: {}",
+                family_id, err
+            )),
+            Ok(revoked) => Ok(revoked),
+        }
+    }
+
+    fn revoke_session_refresh_tokens(&mut self, session_id: &String) -> Result<usize, String> {
+        let result = diesel::update(
+            refresh_tokens::table
+                .filter(refresh_tokens::session_id.eq(session_id))
+                .filter(refresh_tokens::revoked_at.is_null()),
+        )
+        .set(refresh_tokens::revoked_at.eq(SystemTime::now()))
+        .execute(&mut self.pg_pool);
+
+        match result {
+            Err(err) => Err(format!(
+                "Could not revoke refresh tokens of session {}: {}",
+                session_id, err
+            )),
+            Ok(revoked) => Ok(revoked),
+        }
+    }
+
+    fn revoke_user_refresh_tokens(&mut self, username: &String) -> Result<usize, String> {
+        let result = diesel::update(
+            refresh_tokens::table
+                .filter(refresh_tokens::username.eq(username))
+                .filter(refresh_tokens::revoked_at.is_null()),
+        )
+        .set(refresh_tokens::revoked_at.eq(SystemTime::now()))
+        .execute(&mut self.pg_pool);
+
+        match result {
+            Err(err) => Err(format!(
+                "Could not revoke refresh tokens of {}: {}",
+                username, err
+            )),
+            Ok(revoked) => Ok(revoked),
+        }
+    }
+
+    fn revoke_other_refresh_tokens(
+        &mut self,
+        username: &String,
+        kept_session_id: &String,
+    ) -> Result<usize, String> {
+        let result = diesel::update(
+            refresh_tokens::table
+                .filter(refresh_tokens::username.eq(username))
+                .filter(refresh_tokens::session_id.ne(kept_session_id))
+                .filter(refresh_tokens::revoked_at.is_null()),
+        )
+        .set(refresh_tokens::revoked_at.eq(SystemTime::now()))
+        .execute(&mut self.pg_pool);
+
+        match result {
+            Err(err) => Err(format!(
+                "Could not revoke refresh tokens of other sessions of {}: {}",
+                username, err
+            )),
+            Ok(revoked) => Ok(revoked),
+        }
+    }
+}
@@ -0,0 +1,188 @@
+use std::sync::Arc;
+
+use axum::extract::Path;
+use axum::http::StatusCode;
+use axum::{extract::State, response::IntoResponse, Extension, Json};
+
+use crate::appstate::{AppState, IAppState};
+use crate::entities::friends::repository::{FriendRepository, IFriendRepository};
+use crate::entities::friends::service::FriendDomain;
+use crate::helper::errors::HTTPResponse;
+use crate::helper::jwt::Token;
+use crate::helper::session::{ISession, ISessionManager};
+use crate::models::{Room, RoomMember};
+use crate::persistence::connection_manager::IConnectionManager;
+use crate::validation::string_validate::{UuidValidator, DEFAULT_INPUT_FIELD_STRING_VALIDATOR};
+
+use super::repository::{RoomDTO, RoomRepository};
+use super::rooms::RoomDomain;
+
+#[derive(serde::Deserialize, Debug)]
+pub struct RoomPOSTRequestDTO {
+    pub name: String,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct RoomMemberPOSTRequestDTO {
+    pub username: String,
+}
+
+fn parse_room_id(uuid: &String) -> Result<uuid::Uuid, HTTPResponse<()>> {
+    if let Err(err) = UuidValidator::new().validate(uuid.as_str()) {
+        return Err(HTTPResponse {
+            status: StatusCode::BAD_REQUEST,
+            data: None,
+            message: Some(err),
+        });
+    }
+
+    match uuid::Uuid::parse_str(uuid.as_str()) {
+        Err(_) => Err(HTTPResponse {
+            status: StatusCode::BAD_REQUEST,
+            data: None,
+            message: Some(String::from("Failed validating room id")),
+        }),
+        Ok(id) => Ok(id),
+    }
+}
+
+pub async fn create_room<
+    SM: ISessionManager<S, F>,
+    S: ISession<F>,
+    F: IFriendRepository,
+    C: IConnectionManager,
+>(
+    State(app_state): State<Arc<AppState<SM, S, C, F>>>,
+    token: Extension<Token>,
+    Json(body): Json<RoomPOSTRequestDTO>,
+) -> impl IntoResponse {
+    if let Err(err) = DEFAULT_INPUT_FIELD_STRING_VALIDATOR.validate(&body.name) {
+        return HTTPResponse::<()> {
+            status: StatusCode::BAD_REQUEST,
+            data: None,
+            message: Some(format!("Room name validation failed: {}", err)),
+        }
+        .into_response();
+    }
+
+    let room_repository = RoomRepository {
+        pg_pool: app_state.get_db_pool(),
+    };
+    let mut room_domain = RoomDomain::new(room_repository);
+
+    match room_domain.create_room(&token.sub, &body.name) {
+        Ok(room) => HTTPResponse::<Room> {
+            status: StatusCode::CREATED,
+            data: Some(room),
+            message: Some(String::from("Successfully created room")),
+        }
+        .into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+pub async fn get_rooms<
+    SM: ISessionManager<S, F>,
+    S: ISession<F>,
+    F: IFriendRepository,
+    C: IConnectionManager,
+>(
+    State(app_state): State<Arc<AppState<SM, S, C, F>>>,
+    token: Extension<Token>,
+) -> impl IntoResponse {
+    let room_repository = RoomRepository {
+        pg_pool: app_state.get_db_pool(),
+    };
+    let mut room_domain = RoomDomain::new(room_repository);
+
+    match room_domain.get_rooms(&token.sub) {
+        Ok(rooms) => HTTPResponse::<Vec<RoomDTO>> {
+            status: StatusCode::OK,
+            data: Some(rooms),
+            message: None,
+        }
+        .into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+pub async fn invite_to_room<
+    SM: ISessionManager<S, F>,
+    S: ISession<F>,
+    F: IFriendRepository,
+    C: IConnectionManager,
+>(
+    State(app_state): State<Arc<AppState<SM, S, C, F>>>,
+    token: Extension<Token>,
+    Path(uuid): Path<String>,
+    Json(body): Json<RoomMemberPOSTRequestDTO>,
+) -> impl IntoResponse {
+    let room_id = match parse_room_id(&uuid) {
+        Err(err) => return err.into_response(),
+        Ok(id) => id,
+    };
+
+    let friend_repository = FriendRepository {
+        pg_pool: C::new(app_state.get_config().env),
+    };
+    let friend_domain = FriendDomain::new(friend_repository);
+
+    match friend_domain.check_if_user_has_friend(&token.sub, &body.username) {
+        Err(err) => return HTTPResponse::<()>::new_internal_error(err).into_response(),
+        Ok(false) => {
+            return HTTPResponse::<()> {
+                status: StatusCode::BAD_REQUEST,
+                data: None,
+                message: Some(format!("You are not befriended with {}", body.username)),
+            }
+            .into_response()
+        }
+        Ok(true) => {}
+    }
+
+    let room_repository = RoomRepository {
+        pg_pool: app_state.get_db_pool(),
+    };
+    let mut room_domain = RoomDomain::new(room_repository);
+
+    match room_domain.invite_user(&room_id, &token.sub, &body.username) {
+        Ok(member) => HTTPResponse::<RoomMember> {
+            status: StatusCode::CREATED,
+            data: Some(member),
+            message: Some(format!("Successfully invited {}", body.username)),
+        }
+        .into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+pub async fn leave_room<
+    SM: ISessionManager<S, F>,
+    S: ISession<F>,
+    F: IFriendRepository,
+    C: IConnectionManager,
+>(
+    State(app_state): State<Arc<AppState<SM, S, C, F>>>,
+    token: Extension<Token>,
+    Path(uuid): Path<String>,
+) -> impl IntoResponse {
+    let room_id = match parse_room_id(&uuid) {
+        Err(err) => return err.into_response(),
+        Ok(id) => id,
+    };
+
+    let room_repository = RoomRepository {
+        pg_pool: app_state.get_db_pool(),
+    };
+    let mut room_domain = RoomDomain::new(room_repository);
+
+    match room_domain.leave_room(&room_id, &token.sub) {
+        Ok(_) => HTTPResponse::<()> {
+            status: StatusCode::OK,
+            data: None,
+            message: Some(String::from("Successfully left room")),
+        }
+        .into_response(),
+        Err(err) => err.into_response(),
+    }
+}
@@ -0,0 +1,4 @@
+pub mod controller;
+pub mod repository;
+pub mod rooms;
+pub mod rooms_test;
@@ -0,0 +1,164 @@
+use crate::{
+    helper::sql::Count,
+    models::{Room, RoomMember},
+    schema::{room_members, rooms},
+};
+use diesel::prelude::*;
+use diesel::{
+    r2d2::{ConnectionManager, PooledConnection},
+    sql_types::{Array, Text},
+    PgConnection,
+};
+use uuid::Uuid;
+
+#[derive(Debug, serde::Deserialize, serde::Serialize, QueryableByName, Clone, PartialEq)]
+pub struct RoomDTO {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    pub id: Uuid,
+    #[diesel(sql_type = Text)]
+    pub name: String,
+    #[diesel(sql_type = Text)]
+    pub owner: String,
+    #[diesel(sql_type = Array<Text>)]
+    pub members: Vec<String>,
+}
+
+pub trait RoomRepositoryInterface {
+    fn save_room(&mut self, room: &Room, owner_membership: &RoomMember) -> Result<(), String>;
+    fn get_rooms_for_user(&mut self, username: &String) -> Result<Vec<RoomDTO>, String>;
+    fn get_room_members(&mut self, room_id: &Uuid) -> Result<Vec<String>, String>;
+    fn check_if_user_is_room_member(
+        &mut self,
+        room_id: &Uuid,
+        username: &String,
+    ) -> Result<bool, String>;
+    fn save_room_member(&mut self, member: &RoomMember) -> Result<(), String>;
+    fn delete_room_member(&mut self, room_id: &Uuid, username: &String) -> Result<(), String>;
+    fn delete_room(&mut self, room_id: &Uuid) -> Result<(), String>;
+}
+
+pub struct RoomRepository {
+    pub pg_pool: PooledConnection<ConnectionManager<PgConnection>>,
+}
+
+impl RoomRepositoryInterface for RoomRepository {
+    fn save_room(&mut self, room: &Room, owner_membership: &RoomMember) -> Result<(), String> {
+        let result = self.pg_pool.transaction::<_, diesel::result::Error, _>(|conn| {
+            diesel::insert_into(rooms::table)
+                .values(room)
+                .execute(conn)?;
+            diesel::insert_into(room_members::table)
+                .values(owner_membership)
+                .execute(conn)?;
+            Ok(())
+        });
+
+        match result {
+            Err(err) => Err(format!("Could not save room {:?}: {}", room, err)),
+            Ok(_) => Ok(()),
+        }
+    }
+
+    fn get_rooms_for_user(&mut self, username: &String) -> Result<Vec<RoomDTO>, String> {
+        let query = diesel::sql_query(
+            "SELECT
+                r.id as id,
+                r.name as name,
+                r.owner as owner,
+                array_agg(m.user_id ORDER BY m.joined_at) as members
+            FROM rooms as r
+            INNER JOIN room_members as m
+            ON m.room_id = r.id
+            WHERE r.id IN (SELECT room_id FROM room_members WHERE user_id = $1)
+            GROUP BY r.id
+            ORDER BY r.created_at",
+        )
+        .bind::<Text, _>(username);
+
+        match query.load(&mut self.pg_pool) {
+            Ok(res) => Ok(res),
+            Err(err) => Err(format!("Could not get rooms: {}", err)),
+        }
+    }
+
+    fn get_room_members(&mut self, room_id: &Uuid) -> Result<Vec<String>, String> {
+        let members = room_members::table
+            .select(room_members::user_id)
+            .filter(room_members::room_id.eq(room_id))
+            .load::<String>(&mut self.pg_pool);
+
+        match members {
+            Ok(res) => Ok(res),
+            Err(err) => Err(format!("Could not get room members: {}", err)),
+        }
+    }
+
+    fn check_if_user_is_room_member(
+        &mut self,
+        room_id: &Uuid,
+        username: &String,
+    ) -> Result<bool, String> {
+        let count = diesel::sql_query(
+            "SELECT COUNT(*) FROM room_members WHERE room_id = $1 AND user_id = $2",
+        )
+        .bind::<diesel::sql_types::Uuid, _>(room_id)
+        .bind::<Text, _>(username)
+        .load::<Count>(&mut self.pg_pool);
+
+        let mut count = match count {
+            Ok(c) => c,
+            Err(err) => return Err(format!("Could not get room membership: {}", err)),
+        };
+
+        match count.pop() {
+            Some(c) => Ok(c.count > 0),
+            None => Ok(false),
+        }
+    }
+
+    fn save_room_member(&mut self, member: &RoomMember) -> Result<(), String> {
+        let inserted_rows = match diesel::insert_into(room_members::table)
+            .values(member)
+            .execute(&mut self.pg_pool)
+        {
+            Ok(t) => t,
+            Err(err) => return Err(format!("Could not insert room member: {:?}", err)),
+        };
+
+        if inserted_rows == 0 {
+            return Err(String::from("0 rows inserted ... maybe a problem?"));
+        }
+
+        return Ok(());
+    }
+
+    fn delete_room_member(&mut self, room_id: &Uuid, username: &String) -> Result<(), String> {
+        let deleted_rows = diesel::delete(
+            room_members::table
+                .filter(room_members::room_id.eq(room_id))
+                .filter(room_members::user_id.eq(username)),
+        )
+        .execute(&mut self.pg_pool);
+
+        let deleted_rows = match deleted_rows {
+            Err(err) => return Err(format!("Could not delete room member: {}", err)),
+            Ok(res) => res,
+        };
+
+        if deleted_rows == 0 {
+            return Err(String::from("0 rows deleted ... maybe a problem?"));
+        }
+
+        return Ok(());
+    }
+
+    fn delete_room(&mut self, room_id: &Uuid) -> Result<(), String> {
+        let result = diesel::delete(rooms::table.filter(rooms::id.eq(room_id)))
+            .execute(&mut self.pg_pool);
+
+        match result {
+            Err(err) => Err(format!("Could not delete room: {}", err)),
+            Ok(_) => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use std::time::SystemTime;
+
+use axum::http::StatusCode;
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::{
+    helper::errors::HTTPResponse,
+    models::{Room, RoomMember},
+};
+
+use super::repository::{RoomDTO, RoomRepositoryInterface};
+
+pub struct RoomDomain<I: RoomRepositoryInterface> {
+    room_repository: I,
+}
+
+impl<I: RoomRepositoryInterface> RoomDomain<I> {
+    pub fn new(room_repository: I) -> Self {
+        return Self { room_repository };
+    }
+
+    pub fn create_room(&mut self, owner: &String, name: &String) -> Result<Room, HTTPResponse<()>> {
+        let room = Room {
+            id: Uuid::new_v4(),
+            name: name.clone(),
+            owner: owner.clone(),
+            created_at: SystemTime::now(),
+        };
+
+        let owner_membership = RoomMember {
+            id: Uuid::new_v4(),
+            room_id: room.id,
+            user_id: owner.clone(),
+            joined_at: room.created_at,
+        };
+
+        match self.room_repository.save_room(&room, &owner_membership) {
+            Err(err) => Err(HTTPResponse::new_internal_error(err)),
+            Ok(_) => {
+                debug!(target: "application", "[create_room] {} created room {}", owner, room.id);
+                Ok(room)
+            }
+        }
+    }
+
+    pub fn get_rooms(&mut self, username: &String) -> Result<Vec<RoomDTO>, HTTPResponse<()>> {
+        match self.room_repository.get_rooms_for_user(username) {
+            Err(err) => Err(HTTPResponse::new_internal_error(err)),
+            Ok(res) => Ok(res),
+        }
+    }
+
+    pub fn check_if_user_is_room_member(
+        &mut self,
+        room_id: &Uuid,
+        username: &String,
+    ) -> Result<bool, String> {
+        self.room_repository
+            .check_if_user_is_room_member(room_id, username)
+    }
+
+    /// Returns all members of the room, but only if the issuer is a member of it
+    pub fn get_room_members(
+        &mut self,
+        room_id: &Uuid,
+        issuer: &String,
+    ) -> Result<Vec<String>, HTTPResponse<()>> {
+        self.ensure_membership(room_id, issuer)?;
+        match self.room_repository.get_room_members(room_id) {
+            Err(err) => Err(HTTPResponse::new_internal_error(err)),
+            Ok(res) => Ok(res),
+        }
+    }
+
+    /// Adds the invitee to the room. Checking the friendship between inviter and invitee is up to the caller
+    pub fn invite_user(
+        &mut self,
+        room_id: &Uuid,
+        inviter: &String,
+        invitee: &String,
+    ) -> Result<RoomMember, HTTPResponse<()>> {
+        self.ensure_membership(room_id, inviter)?;
+
+        let invitee_is_member = match self
+            .room_repository
+            .check_if_user_is_room_member(room_id, invitee)
+        {
+            Err(err) => return Err(HTTPResponse::new_internal_error(err)),
+            Ok(res) => res,
+        };
+
+        if invitee_is_member {
+            return Err(HTTPResponse {
+                status: StatusCode::BAD_REQUEST,
+                data: None,
+                message: Some(format!("{} is already a member of this room", invitee)),
+            });
+        }
+
+        let member = RoomMember {
+            id: Uuid::new_v4(),
+            room_id: room_id.clone(),
+            user_id: invitee.clone(),
+            joined_at: SystemTime::now(),
+        };
+
+        match self.room_repository.save_room_member(&member) {
+            Err(err) => Err(HTTPResponse::new_internal_error(err)),
+            Ok(_) => {
+                debug!(target: "application", "[invite_user] {} invited {} into room {}", inviter, invitee, room_id);
+                Ok(member)
+            }
+        }
+    }
+
+    /// Removes the user from the room. The room itself is deleted once the last member left
+    pub fn leave_room(&mut self, room_id: &Uuid, username: &String) -> Result<(), HTTPResponse<()>> {
+        self.ensure_membership(room_id, username)?;
+
+        if let Err(err) = self.room_repository.delete_room_member(room_id, username) {
+            return Err(HTTPResponse::new_internal_error(err));
+        }
+        debug!(target: "application", "[leave_room] {} left room {}", username, room_id);
+
+        let remaining_members = match self.room_repository.get_room_members(room_id) {
+            Err(err) => return Err(HTTPResponse::new_internal_error(err)),
+            Ok(res) => res,
+        };
+
+        if remaining_members.len() > 0 {
+            return Ok(());
+        }
+
+        match self.room_repository.delete_room(room_id) {
+            Err(err) => Err(HTTPResponse::new_internal_error(err)),
+            Ok(_) => {
+                debug!(target: "application", "[leave_room] deleted empty room {}", room_id);
+                Ok(())
+            }
+        }
+    }
+
+    fn ensure_membership(&mut self, room_id: &Uuid, username: &String) -> Result<(), HTTPResponse<()>> {
+        let is_member = match self
+            .room_repository
+            .check_if_user_is_room_member(room_id, username)
+        {
+            Err(err) => return Err(HTTPResponse::new_internal_error(err)),
+            Ok(res) => res,
+        };
+
+        if is_member == false {
+            return Err(HTTPResponse {
+                status: StatusCode::FORBIDDEN,
+                data: None,
+                message: Some(String::from("You are not a member of this room")),
+            });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,121 @@
+use std::str::FromStr;
+
+use uuid::Uuid;
+
+use crate::models::{Room, RoomMember};
+
+use super::repository::{RoomDTO, RoomRepositoryInterface};
+
+pub const ROOM_ID: &str = "18cb8735-b226-49d5-a726-e6937bd6e841";
+
+struct RoomRepositoryMock {
+    pub members: Vec<String>,
+    pub room_deleted: bool,
+}
+
+impl RoomRepositoryInterface for RoomRepositoryMock {
+    fn save_room(&mut self, _: &Room, owner_membership: &RoomMember) -> Result<(), String> {
+        self.members.push(owner_membership.user_id.clone());
+        return Ok(());
+    }
+
+    fn get_rooms_for_user(&mut self, _: &String) -> Result<Vec<RoomDTO>, String> {
+        return Ok(vec![]);
+    }
+
+    fn get_room_members(&mut self, _: &Uuid) -> Result<Vec<String>, String> {
+        return Ok(self.members.clone());
+    }
+
+    fn check_if_user_is_room_member(
+        &mut self,
+        room_id: &Uuid,
+        username: &String,
+    ) -> Result<bool, String> {
+        if room_id != &Uuid::from_str(ROOM_ID).unwrap() {
+            return Ok(false);
+        }
+        return Ok(self.members.contains(username));
+    }
+
+    fn save_room_member(&mut self, member: &RoomMember) -> Result<(), String> {
+        self.members.push(member.user_id.clone());
+        return Ok(());
+    }
+
+    fn delete_room_member(&mut self, _: &Uuid, username: &String) -> Result<(), String> {
+        self.members.retain(|m| m != username);
+        return Ok(());
+    }
+
+    fn delete_room(&mut self, _: &Uuid) -> Result<(), String> {
+        self.room_deleted = true;
+        return Ok(());
+    }
+}
+
+pub mod room_integration_tests {
+    use std::str::FromStr;
+
+    use axum::http::StatusCode;
+    use uuid::Uuid;
+
+    use crate::entities::rooms::rooms::RoomDomain;
+
+    use super::{RoomRepositoryMock, ROOM_ID};
+
+    #[test]
+    fn test_invite_user() {
+        let repo = RoomRepositoryMock {
+            members: vec![String::from("Owner")],
+            room_deleted: false,
+        };
+        let mut domain = RoomDomain::new(repo);
+        let room_id = Uuid::from_str(ROOM_ID).unwrap();
+
+        let result = domain
+            .invite_user(&room_id, &String::from("Stranger"), &String::from("Friend"))
+            .unwrap_err();
+        assert_eq!(result.status, StatusCode::FORBIDDEN);
+
+        let member = domain
+            .invite_user(&room_id, &String::from("Owner"), &String::from("Friend"))
+            .unwrap();
+        assert_eq!(member.user_id, String::from("Friend"));
+        assert_eq!(member.room_id, room_id);
+
+        let result = domain
+            .invite_user(&room_id, &String::from("Owner"), &String::from("Friend"))
+            .unwrap_err();
+        assert_eq!(result.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_leave_room_deletes_empty_room() {
+        let repo = RoomRepositoryMock {
+            members: vec![String::from("Owner"), String::from("Friend")],
+            room_deleted: false,
+        };
+        let mut domain = RoomDomain::new(repo);
+        let room_id = Uuid::from_str(ROOM_ID).unwrap();
+
+        domain
+            .leave_room(&room_id, &String::from("Friend"))
+            .unwrap();
+        let members = domain
+            .get_room_members(&room_id, &String::from("Owner"))
+            .unwrap();
+        assert_eq!(members, vec![String::from("Owner")]);
+
+        let result = domain
+            .leave_room(&room_id, &String::from("Friend"))
+            .unwrap_err();
+        assert_eq!(result.status, StatusCode::FORBIDDEN);
+
+        domain.leave_room(&room_id, &String::from("Owner")).unwrap();
+        let result = domain
+            .get_room_members(&room_id, &String::from("Owner"))
+            .unwrap_err();
+        assert_eq!(result.status, StatusCode::FORBIDDEN);
+    }
+}
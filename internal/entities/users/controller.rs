@@ -1,38 +1,75 @@
 use crate::appstate::{AppState, IAppState};
-use crate::entities::friends::repository::IFriendRepository;
+use crate::entities::friends::repository::{FriendRepository, IFriendRepository};
+use crate::entities::friends::service::FriendDomain;
+use crate::helper::cookie::{
+    check_csrf, generate_csrf_token, get_cookie, CookieSettings, REFRESH_TOKEN_COOKIE,
+};
 use crate::helper::errors::HTTPResponse;
-use crate::helper::session::{ISession, ISessionManager};
+use crate::helper::fingerprint::{ClientFingerprint, CLIENT_FINGERPRINT_SECRET};
+use crate::helper::session::{ClientInfo, ISession, ISessionManager};
+use crate::interfaces::websockets::socket_messages::{
+    EEvent, SocketMessage, SocketMessageNotification, SocketMessageStatusChange,
+};
 use crate::persistence::connection_manager::IConnectionManager;
 use crate::validation::string_validate::DEFAULT_INPUT_FIELD_STRING_VALIDATOR;
 use crate::{
     helper::{
-        jwt::{hash_string, Token},
-        keys::{generate_rsa_key_pair, validate_public_key},
+        jwt::Token,
+        keys::{
+            check_proof_of_possession, generate_rsa_key_pair, issue_key_challenge,
+            validate_public_key, KEY_CHALLENGE_LIFETIME, KEY_CHALLENGE_SECRET,
+        },
     },
     models::UserDTO,
 };
 use axum::{
-    extract::{Json, State},
+    extract::{ConnectInfo, Json, State},
     http::{
         header::{self, SET_COOKIE},
-        HeaderMap, StatusCode,
+        HeaderMap, HeaderValue, StatusCode,
     },
-    response::IntoResponse,
+    response::{IntoResponse, Response},
     Extension,
 };
 use base64;
 use base64::Engine;
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
 use super::repository::UserRepository;
-use super::users::UserDomain;
+use super::users::{UserDomain, ACCESS_TOKEN_LIFETIME};
+use crate::entities::mfa::{
+    mfa::{MfaDomain, MFA_CHALLENGE_LIFETIME},
+    repository::MfaRepository,
+};
+use crate::entities::refresh_tokens::{
+    refresh_tokens::{RefreshTokenDomain, REFRESH_TOKEN_LIFETIME},
+    repository::RefreshTokenRepository,
+};
 
+/// Without `generate_key`, `signature` is the base64 encoded signature of `challenge` with the private key of `public_key`
 #[derive(serde::Deserialize)]
 pub struct UserCreateDTO {
     pub username: String,
     password: String,
     pub public_key: String,
+    #[serde(default)]
     pub generate_key: bool,
+    pub challenge: Option<String>,
+    pub signature: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct KeyChallengeRequestDTO {
+    pub username: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct KeyChallengeDTO {
+    pub challenge: String,
+    /// Seconds left to register with the challenge
+    pub expires_in: u64,
 }
 
 #[derive(serde::Deserialize)]
@@ -112,6 +149,19 @@ pub async fn create_user<
         })
     }; */
 
+    if body.generate_key == true && !state.get_config().env.SERVER_KEY_GENERATION {
+        return (
+            headers,
+            HTTPResponse {
+                data: None,
+                message: Some(String::from(
+                    "Server side key generation is disabled, submit a public key along with a signed key challenge",
+                )),
+                status: StatusCode::BAD_REQUEST,
+            },
+        );
+    }
+
     if body.generate_key == false {
         match validate_public_key(&pub_key_decoded) {
             Err(_) => return (
@@ -126,6 +176,24 @@ pub async fn create_user<
             ),
             Ok(_) => {}
         }
+
+        if let Err(err) = check_proof_of_possession(
+            &state.get_keyring().derive_secret(KEY_CHALLENGE_SECRET),
+            &body.username,
+            &pub_key_decoded,
+            &body.challenge,
+            &body.signature,
+        ) {
+            tracing::info!(target: "audit", "[create_user] rejected key of {}: {}", body.username, err);
+            return (
+                headers,
+                HTTPResponse {
+                    data: None,
+                    message: Some(format!("Proof of possession failed: {}", err)),
+                    status: StatusCode::BAD_REQUEST,
+                },
+            );
+        }
     }
 
     if body.generate_key == true {
@@ -155,25 +223,153 @@ pub async fn create_user<
         public_key: pub_key,
     };
 
-    let result = user_domain.create_user(&new_user, &state.get_config().env.HASHING_KEY.as_bytes());
+    let result = user_domain.create_user(&new_user);
 
+    // Taken usernames get the same answer, only without the generated key which was never stored
     match result {
-        Ok(_) => (
+        Ok(created) => (
             headers,
             HTTPResponse {
-                message: Some(format!("User created successfully")),
-                data: private_key,
-                status: StatusCode::CREATED,
+                message: Some(String::from(
+                    "If the username was available, the account was created",
+                )),
+                data: created.and(private_key),
+                status: StatusCode::ACCEPTED,
             },
         ),
         Err(err) => (headers, err),
     }
 }
 
+/// Challenge the client signs to prove it holds the private key of the public key it registers or rotates to
+pub async fn key_challenge<
+    SM: ISessionManager<S, F>,
+    S: ISession<F>,
+    F: IFriendRepository,
+    C: IConnectionManager,
+>(
+    State(state): State<Arc<AppState<SM, S, C, F>>>,
+    Json(body): Json<KeyChallengeRequestDTO>,
+) -> impl IntoResponse {
+    let challenge = issue_key_challenge(
+        &state.get_keyring().derive_secret(KEY_CHALLENGE_SECRET),
+        &body.username,
+        SystemTime::now(),
+    );
+
+    match challenge {
+        Err(err) => HTTPResponse::<()>::new_internal_error(err).into_response(),
+        Ok(challenge) => HTTPResponse::<KeyChallengeDTO> {
+            data: Some(KeyChallengeDTO {
+                challenge,
+                expires_in: KEY_CHALLENGE_LIFETIME.as_secs(),
+            }),
+            message: None,
+            status: StatusCode::OK,
+        }
+        .into_response(),
+    }
+}
+
 #[derive(serde::Deserialize)]
 pub struct LoginDTO {
     pub username: String,
     pub password: String,
+    /// Keep the tokens in HttpOnly cookies instead of returning them
+    #[serde(default)]
+    pub cookie: bool,
+}
+
+/// Second login step, `mfa_token` is returned by the login of users with two-factor authentication
+#[derive(serde::Deserialize)]
+pub struct MfaLoginDTO {
+    pub mfa_token: String,
+    /// TOTP code or recovery code
+    pub code: String,
+    #[serde(default)]
+    pub cookie: bool,
+}
+
+#[derive(serde::Serialize)]
+pub struct MfaChallengeDTO {
+    pub mfa_token: String,
+    /// Seconds left to complete the login
+    pub expires_in: u64,
+}
+
+#[derive(serde::Deserialize)]
+pub struct RefreshTokenDTO {
+    pub refresh_token: String,
+}
+
+/// The refresh token is only returned once, clients have to replace theirs on every refresh.
+/// Cookie authenticated clients only get the CSRF token, which they have to send in the `X-CSRF-Token` header
+#[derive(serde::Serialize)]
+pub struct TokenResponseDTO {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub csrf_token: Option<String>,
+    /// Seconds until the access token expires
+    pub expires_in: u64,
+}
+
+/// Hands out the tokens in the body, or as cookies if the client authenticates with cookies, which is when there is a CSRF token
+fn token_response(
+    cookie_settings: &CookieSettings,
+    access_token: String,
+    refresh_token: String,
+    csrf_token: Option<String>,
+) -> (HeaderMap, TokenResponseDTO) {
+    let mut headers = HeaderMap::new();
+    let expires_in = ACCESS_TOKEN_LIFETIME.as_secs();
+
+    let csrf_token = match csrf_token {
+        None => {
+            return (
+                headers,
+                TokenResponseDTO {
+                    access_token: Some(access_token),
+                    refresh_token: Some(refresh_token),
+                    csrf_token: None,
+                    expires_in,
+                },
+            )
+        }
+        Some(csrf_token) => csrf_token,
+    };
+
+    headers.append(
+        SET_COOKIE,
+        cookie_settings.session_cookie(&access_token, ACCESS_TOKEN_LIFETIME),
+    );
+    headers.append(
+        SET_COOKIE,
+        cookie_settings.refresh_token_cookie(&refresh_token, REFRESH_TOKEN_LIFETIME),
+    );
+    headers.append(
+        SET_COOKIE,
+        cookie_settings.csrf_cookie(&csrf_token, REFRESH_TOKEN_LIFETIME),
+    );
+    (
+        headers,
+        TokenResponseDTO {
+            access_token: None,
+            refresh_token: None,
+            csrf_token: Some(csrf_token),
+            expires_in,
+        },
+    )
+}
+
+fn removal_cookie_headers(cookie_settings: &CookieSettings) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for cookie in cookie_settings.removal_cookies() {
+        headers.append(SET_COOKIE, cookie);
+    }
+    headers
 }
 
 pub async fn logout<
@@ -185,9 +381,20 @@ pub async fn logout<
     State(state): State<Arc<AppState<SM, S, C, F>>>,
     token: Extension<Token>,
 ) -> impl IntoResponse {
+    // Revoked first, the session might already be gone from memory but must not be refreshable
+    let mut refresh_token_domain = RefreshTokenDomain::new(RefreshTokenRepository {
+        pg_pool: state.get_db_pool(),
+    });
+    if let Err(err) = refresh_token_domain.revoke_session_refresh_tokens(&token.sid) {
+        return err.into_response();
+    }
+    if let Err(err) = state.get_token_revocations().revoke_token(&token) {
+        return HTTPResponse::<()>::new_internal_error(err).into_response();
+    }
+
     let session = match state
         .get_session_manager()
-        .remove_from_current_user_connections(&token.sub)
+        .remove_from_current_user_connections(&token.sub, &token.sid)
         .await
     {
         Ok(sm) => sm,
@@ -197,6 +404,7 @@ pub async fn logout<
                 data: None,
                 status: StatusCode::INTERNAL_SERVER_ERROR,
             }
+            .into_response()
         }
     };
 
@@ -207,11 +415,244 @@ pub async fn logout<
         .await;
 
     tracing::debug!(target: "application", "[logout] user {} manually logged out", token.sub);
+    (
+        removal_cookie_headers(state.get_cookie_settings()),
+        HTTPResponse::<()> {
+            message: Some(String::from("Successfully logged out")),
+            data: None,
+            status: StatusCode::OK,
+        },
+    )
+        .into_response()
+}
+
+/// Revokes every token of the user and closes all their sessions
+pub async fn logout_all<
+    SM: ISessionManager<S, F>,
+    S: ISession<F>,
+    F: IFriendRepository,
+    C: IConnectionManager,
+>(
+    State(state): State<Arc<AppState<SM, S, C, F>>>,
+    token: Extension<Token>,
+) -> impl IntoResponse {
+    let now = SystemTime::now();
+    if let Err(err) = state.get_token_revocations().revoke_user_tokens(
+        &token.sub,
+        now,
+        now + ACCESS_TOKEN_LIFETIME,
+        None,
+    ) {
+        return HTTPResponse::<()>::new_internal_error(err).into_response();
+    }
+
+    let mut refresh_token_domain = RefreshTokenDomain::new(RefreshTokenRepository {
+        pg_pool: state.get_db_pool(),
+    });
+    if let Err(err) = refresh_token_domain.revoke_user_refresh_tokens(&token.sub) {
+        return err.into_response();
+    }
+
+    close_user_sessions(
+        &state,
+        &token.sub,
+        None,
+        "You were logged out on all devices",
+    )
+    .await;
+
+    tracing::info!(target: "audit", "[logout_all] user {} logged out on all devices", token.sub);
+    (
+        removal_cookie_headers(state.get_cookie_settings()),
+        HTTPResponse::<()> {
+            message: Some(String::from("Successfully logged out on all devices")),
+            data: None,
+            status: StatusCode::OK,
+        },
+    )
+        .into_response()
+}
+
+#[derive(serde::Deserialize)]
+pub struct ChangePasswordDTO {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+/// Changes the password of the user. Every other device session is logged out, the one it was changed with is kept
+pub async fn change_password<
+    SM: ISessionManager<S, F>,
+    S: ISession<F>,
+    F: IFriendRepository,
+    C: IConnectionManager,
+>(
+    State(state): State<Arc<AppState<SM, S, C, F>>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    token: Extension<Token>,
+    Json(body): Json<ChangePasswordDTO>,
+) -> impl IntoResponse {
+    if let Err(err) = DEFAULT_INPUT_FIELD_STRING_VALIDATOR.validate(&body.new_password) {
+        return HTTPResponse::<()> {
+            message: Some(format!("Password validation failed: {}", err)),
+            data: None,
+            status: StatusCode::BAD_REQUEST,
+        }
+        .into_response();
+    }
+
+    let mut user_domain = UserDomain::new(UserRepository {
+        pg_pool: state.get_db_pool(),
+    });
+    let result = with_login_throttle(&state, &token.sub, &addr, || {
+        user_domain.change_password(
+            &token.sub,
+            &body.current_password,
+            &body.new_password,
+            state.get_config().env.HASHING_KEY.as_bytes(),
+        )
+    });
+    if let Err(response) = result {
+        return response;
+    }
+
+    let now = SystemTime::now();
+    if let Err(err) = state.get_token_revocations().revoke_user_tokens(
+        &token.sub,
+        now,
+        now + ACCESS_TOKEN_LIFETIME,
+        Some(&token.sid),
+    ) {
+        return HTTPResponse::<()>::new_internal_error(err).into_response();
+    }
+
+    let mut refresh_token_domain = RefreshTokenDomain::new(RefreshTokenRepository {
+        pg_pool: state.get_db_pool(),
+    });
+    if let Err(err) = refresh_token_domain.revoke_other_refresh_tokens(&token.sub, &token.sid) {
+        return err.into_response();
+    }
+
+    close_user_sessions(
+        &state,
+        &token.sub,
+        Some(&token.sid),
+        "Your password was changed, please log in again",
+    )
+    .await;
+
     HTTPResponse::<()> {
-        message: Some(String::from("Successfully logged out")),
+        message: Some(String::from("Password changed")),
         data: None,
         status: StatusCode::OK,
     }
+    .into_response()
+}
+
+#[derive(serde::Deserialize)]
+pub struct DeleteUserDTO {
+    pub password: String,
+}
+
+/// Deletes the account with its friendships, friend requests and messages. Its sockets are closed and its friends are told
+/// about it, on every instance
+pub async fn delete_user<
+    SM: ISessionManager<S, F>,
+    S: ISession<F>,
+    F: IFriendRepository,
+    C: IConnectionManager,
+>(
+    State(state): State<Arc<AppState<SM, S, C, F>>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    token: Extension<Token>,
+    Json(body): Json<DeleteUserDTO>,
+) -> impl IntoResponse {
+    let mut user_domain = UserDomain::new(UserRepository {
+        pg_pool: state.get_db_pool(),
+    });
+    let result = with_login_throttle(&state, &token.sub, &addr, || {
+        user_domain.confirm_password(
+            &token.sub,
+            &body.password,
+            state.get_config().env.HASHING_KEY.as_bytes(),
+        )
+    });
+    if let Err(response) = result {
+        return response;
+    }
+
+    // Looked up before the friendships are deleted with the user. All of them, as friends may be connected to other instances
+    let friend_domain = FriendDomain::new(FriendRepository {
+        pg_pool: C::new(state.get_config().env),
+    });
+    let friends = match friend_domain.get_friends(&token.sub) {
+        Err(err) => return HTTPResponse::<()>::new_internal_error(err).into_response(),
+        Ok(friends) => friends,
+    };
+
+    // Revoked before the user is gone, the revocation outlives the user so the tokens cannot be used if the name is registered again
+    let now = SystemTime::now();
+    if let Err(err) = state.get_token_revocations().revoke_user_tokens(
+        &token.sub,
+        now,
+        now + ACCESS_TOKEN_LIFETIME,
+        None,
+    ) {
+        return HTTPResponse::<()>::new_internal_error(err).into_response();
+    }
+
+    if let Err(err) = user_domain.delete_user(&token.sub) {
+        return err.into_response();
+    }
+
+    close_user_sessions(&state, &token.sub, None, "Your account was deleted").await;
+    for friend in friends {
+        state
+            .get_session_manager()
+            .send_to_user(
+                &friend.username,
+                SocketMessage::SocketMessageStatusChange(SocketMessageStatusChange::new(
+                    EEvent::DELETED,
+                    token.sub.clone(),
+                )),
+            )
+            .await;
+    }
+
+    (
+        removal_cookie_headers(state.get_cookie_settings()),
+        HTTPResponse::<()> {
+            message: Some(String::from("Account deleted")),
+            data: None,
+            status: StatusCode::OK,
+        },
+    )
+        .into_response()
+}
+
+/// Closes the sessions of the user on every instance, except `kept_session_id`, and tells their devices why
+async fn close_user_sessions<
+    SM: ISessionManager<S, F>,
+    S: ISession<F>,
+    F: IFriendRepository,
+    C: IConnectionManager,
+>(
+    state: &Arc<AppState<SM, S, C, F>>,
+    username: &String,
+    kept_session_id: Option<&String>,
+    notice: &str,
+) {
+    state
+        .get_session_manager()
+        .close_user_sessions(
+            username,
+            kept_session_id,
+            SocketMessage::SocketMessageNotification(SocketMessageNotification::new(
+                String::from("error"),
+                String::from("Important"),
+                String::from(notice),
+            )),
+        )
+        .await;
 }
 
 pub async fn login<
@@ -221,11 +662,14 @@ pub async fn login<
     C: IConnectionManager,
 >(
     State(state): State<Arc<AppState<SM, S, C, F>>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    request_headers: HeaderMap,
     Json(body): Json<LoginDTO>,
 ) -> impl IntoResponse {
     let LoginDTO {
         password: pw,
         username: username_id,
+        cookie,
     } = body;
     let user_repository = UserRepository {
         pg_pool: state.get_db_pool(),
@@ -235,76 +679,291 @@ pub async fn login<
     let mut headers = HeaderMap::new();
     headers.insert(header::CONTENT_TYPE, "application/json".parse().unwrap());
 
-    match DEFAULT_INPUT_FIELD_STRING_VALIDATOR.validate(&username_id) {
-        Err(err) => {
-            return (
-                headers,
-                HTTPResponse::<()> {
-                    data: None,
-                    message: Some(String::from(format!("Username validation failed: {}", err))),
-                    status: StatusCode::UNAUTHORIZED,
-                }
-                .into_response(),
-            )
-        }
-        Ok(_) => {}
+    let ip_address = addr.ip().to_string();
+    let locked = state
+        .login_throttle_by_username
+        .check(&username_id)
+        .and(state.login_throttle_by_ip.check(&ip_address));
+    if let Err(retry_after) = locked {
+        return (headers, too_many_login_attempts(retry_after));
     }
 
-    match DEFAULT_INPUT_FIELD_STRING_VALIDATOR.validate(&pw) {
-        Err(err) => {
-            return (
-                headers,
-                HTTPResponse::<()> {
-                    data: None,
-                    message: Some(String::from(format!("Password validation failed: {}", err))),
-                    status: StatusCode::UNAUTHORIZED,
-                }
-                .into_response(),
-            )
-        }
-        Ok(_) => {}
+    // Invalid input gets the same response as wrong credentials
+    let is_valid = DEFAULT_INPUT_FIELD_STRING_VALIDATOR
+        .validate(&username_id)
+        .and(DEFAULT_INPUT_FIELD_STRING_VALIDATOR.validate(&pw));
+    if let Err(_) = is_valid {
+        return (
+            headers,
+            HTTPResponse::<()> {
+                data: None,
+                message: Some(String::from("Username or password wrong")),
+                status: StatusCode::UNAUTHORIZED,
+            }
+            .into_response(),
+        );
     }
 
-    let pw = hash_string(&pw, state.get_config().env.HASHING_KEY.as_bytes());
+    let fingerprint = ClientFingerprint::from_request(
+        &state.get_keyring().derive_secret(CLIENT_FINGERPRINT_SECRET),
+        &request_headers,
+        &addr,
+    );
     let token = user_domain.login_user_and_prepare_token(
         &username_id,
         &pw,
         state.get_config().env.HASHING_KEY.as_bytes(),
+        state.get_keyring(),
+        &fingerprint,
     );
 
     let (user, token, session_token) = match token {
         Ok(result) => result,
+        Err(err) => {
+            if err.status == StatusCode::UNAUTHORIZED {
+                record_failed_login(&state, &username_id, &ip_address);
+            }
+            return (headers, err.into_response());
+        }
+    };
+
+    // The password alone does not start a session if a second factor is enabled. The lockout
+    // of the username is only lifted once the second factor was accepted as well
+    let mut mfa_domain = MfaDomain::new(MfaRepository {
+        pg_pool: state.get_db_pool(),
+    });
+    let mfa_enabled = match mfa_domain.is_enabled(&user.username) {
         Err(err) => return (headers, err.into_response()),
+        Ok(mfa_enabled) => mfa_enabled,
+    };
+    if mfa_enabled {
+        let mfa_token = match mfa_domain.create_challenge(&user.username, &token.sid, &fingerprint)
+        {
+            Err(err) => return (headers, err.into_response()),
+            Ok(mfa_token) => mfa_token,
+        };
+        return (
+            headers,
+            HTTPResponse::<MfaChallengeDTO> {
+                data: Some(MfaChallengeDTO {
+                    mfa_token,
+                    expires_in: MFA_CHALLENGE_LIFETIME.as_secs(),
+                }),
+                message: Some(String::from("Second factor required")),
+                status: StatusCode::OK,
+            }
+            .into_response(),
+        );
+    }
+    state.login_throttle_by_username.reset(&username_id);
+
+    (
+        headers,
+        start_session(
+            &state,
+            user,
+            token,
+            session_token,
+            &fingerprint,
+            cookie,
+            &request_headers,
+            &addr,
+        )
+        .await,
+    )
+}
+
+/// Second login step of users with two-factor authentication, completes the login the MFA token was issued for
+pub async fn login_mfa<
+    SM: ISessionManager<S, F>,
+    S: ISession<F>,
+    F: IFriendRepository,
+    C: IConnectionManager,
+>(
+    State(state): State<Arc<AppState<SM, S, C, F>>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    request_headers: HeaderMap,
+    Json(body): Json<MfaLoginDTO>,
+) -> impl IntoResponse {
+    let mut mfa_domain = MfaDomain::new(MfaRepository {
+        pg_pool: state.get_db_pool(),
+    });
+    let fingerprint = ClientFingerprint::from_request(
+        &state.get_keyring().derive_secret(CLIENT_FINGERPRINT_SECRET),
+        &request_headers,
+        &addr,
+    );
+
+    let challenge = match mfa_domain.get_challenge(
+        &body.mfa_token,
+        &fingerprint,
+        state.get_client_binding(),
+    ) {
+        Err(err) => return err.into_response(),
+        Ok(challenge) => challenge,
+    };
+
+    let verified = with_login_throttle(&state, &challenge.username, &addr, || {
+        mfa_domain.verify_second_factor(&challenge.username, &body.code)?;
+        mfa_domain.complete_challenge(&challenge)
+    });
+    if let Err(response) = verified {
+        return response;
+    }
+    state.login_throttle_by_username.reset(&challenge.username);
+
+    let mut user_domain = UserDomain::new(UserRepository {
+        pg_pool: state.get_db_pool(),
+    });
+    let (user, token, session_token) = match user_domain.renew_token(
+        &challenge.username,
+        &challenge.session_id,
+        state.get_keyring(),
+        &fingerprint,
+    ) {
+        Err(err) => return HTTPResponse::<()>::new_internal_error(err).into_response(),
+        Ok(result) => result,
+    };
+    tracing::debug!(target: "application", "[login_mfa] user {} completed the second login step", user.username);
+
+    start_session(
+        &state,
+        user,
+        token,
+        session_token,
+        &fingerprint,
+        body.cookie,
+        &request_headers,
+        &addr,
+    )
+    .await
+}
+
+/// Registers the device session of a completed login and hands out its tokens
+async fn start_session<
+    SM: ISessionManager<S, F>,
+    S: ISession<F>,
+    F: IFriendRepository,
+    C: IConnectionManager,
+>(
+    state: &Arc<AppState<SM, S, C, F>>,
+    user: UserDTO,
+    token: Token,
+    session_token: String,
+    fingerprint: &ClientFingerprint,
+    cookie: bool,
+    request_headers: &HeaderMap,
+    addr: &SocketAddr,
+) -> Response {
+    let mut refresh_token_domain = RefreshTokenDomain::new(RefreshTokenRepository {
+        pg_pool: state.get_db_pool(),
+    });
+    let refresh_token =
+        match refresh_token_domain.issue_refresh_token(&user.username, &token.sid, fingerprint) {
+            Err(err) => return err.into_response(),
+            Ok(refresh_token) => refresh_token,
+        };
+
+    let csrf_token = match cookie {
+        false => None,
+        true => match generate_csrf_token() {
+            Err(err) => return HTTPResponse::<()>::new_internal_error(err).into_response(),
+            Ok(csrf_token) => Some(csrf_token),
+        },
     };
 
-    let session = S::new(user.clone(), token);
+    let mut session = S::new(user.clone(), token);
+    session.set_client_info(ClientInfo::new(request_headers, addr));
     session.notify_online(state.get_session_manager()).await;
     state
         .get_session_manager()
         .insert_into_current_user_connections(session)
         .await;
 
-    headers.insert(
-        SET_COOKIE,
-        format!(
-            "session={}; Max-Age=2592000; Path=/; SameSite=None",
-            session_token
-        )
-        .parse()
-        .unwrap(),
+    let (token_headers, token_response) = token_response(
+        state.get_cookie_settings(),
+        session_token,
+        refresh_token,
+        csrf_token,
     );
 
     (
-        headers,
-        HTTPResponse::<String> {
-            data: Some(session_token),
+        token_headers,
+        HTTPResponse::<TokenResponseDTO> {
+            data: Some(token_response),
             message: Some(String::from("Login successful")),
             status: StatusCode::OK,
-        }
-        .into_response(),
+        },
     )
+        .into_response()
+}
+
+fn too_many_login_attempts(retry_after: Duration) -> Response {
+    // Rounded up, so clients retrying right on time are not rejected again
+    let retry_after = retry_after.as_secs() + (retry_after.subsec_nanos() > 0) as u64;
+    let mut response = HTTPResponse::<()> {
+        data: None,
+        message: Some(String::from("Too many failed login attempts, try again later")),
+        status: StatusCode::TOO_MANY_REQUESTS,
+    }
+    .into_response();
+    response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+    response
+}
+
+/// Runs a check of user supplied credentials, e.g. a second factor, unless the user or the ip address is locked.
+/// Rejected credentials count as failed logins
+pub fn with_login_throttle<
+    SM: ISessionManager<S, F>,
+    S: ISession<F>,
+    F: IFriendRepository,
+    C: IConnectionManager,
+    T,
+>(
+    state: &Arc<AppState<SM, S, C, F>>,
+    username_id: &String,
+    addr: &SocketAddr,
+    check: impl FnOnce() -> Result<T, HTTPResponse<()>>,
+) -> Result<T, Response> {
+    let ip_address = addr.ip().to_string();
+    let locked = state
+        .login_throttle_by_username
+        .check(username_id)
+        .and(state.login_throttle_by_ip.check(&ip_address));
+    if let Err(retry_after) = locked {
+        return Err(too_many_login_attempts(retry_after));
+    }
+
+    check().map_err(|err| {
+        if err.status == StatusCode::UNAUTHORIZED {
+            record_failed_login(state, username_id, &ip_address);
+        }
+        err.into_response()
+    })
+}
+
+fn record_failed_login<
+    SM: ISessionManager<S, F>,
+    S: ISession<F>,
+    F: IFriendRepository,
+    C: IConnectionManager,
+>(
+    state: &Arc<AppState<SM, S, C, F>>,
+    username_id: &String,
+    ip_address: &String,
+) {
+    if let Some(lockout) = state.login_throttle_by_username.record_failure(username_id) {
+        tracing::warn!(target: "audit", "[login] locked user {} for {}s after failed login attempts, last one from {}", username_id, lockout.as_secs(), ip_address);
+    }
+    if let Some(lockout) = state.login_throttle_by_ip.record_failure(ip_address) {
+        tracing::warn!(target: "audit", "[login] locked ip {} for {}s after failed login attempts, last one for user {}", ip_address, lockout.as_secs(), username_id);
+    }
 }
 
+/// Exchanges a refresh token for a new access token and a new refresh token of the same device session.
+/// Cookie authenticated clients send no body, their refresh token cookie is used after the CSRF check
 pub async fn token<
     'a,
     SM: ISessionManager<S, F>,
@@ -313,15 +972,54 @@ pub async fn token<
     T: IAppState<F, SM, S>,
 >(
     State(app_state): State<Arc<T>>,
-    Extension(token): Extension<Token>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    body: Option<Json<RefreshTokenDTO>>,
 ) -> impl IntoResponse {
-    let pool = app_state.get_db_pool();
-    let repository = UserRepository { pg_pool: pool };
+    let (presented_token, csrf_token) = match body {
+        Some(Json(body)) => (body.refresh_token, None),
+        None => {
+            let refresh_token = match get_cookie(&headers, REFRESH_TOKEN_COOKIE) {
+                None => return StatusCode::UNAUTHORIZED.into_response(),
+                Some(refresh_token) => refresh_token,
+            };
+            match check_csrf(&headers) {
+                Err(err) => {
+                    tracing::warn!(target: "audit", "[token] rejected cookie refresh from {}: {}", addr.ip(), err);
+                    return StatusCode::FORBIDDEN.into_response();
+                }
+                Ok(csrf_token) => (refresh_token, Some(csrf_token)),
+            }
+        }
+    };
+
+    let mut refresh_token_domain = RefreshTokenDomain::new(RefreshTokenRepository {
+        pg_pool: app_state.get_db_pool(),
+    });
+    let fingerprint = ClientFingerprint::from_request(
+        &app_state.get_keyring().derive_secret(CLIENT_FINGERPRINT_SECRET),
+        &headers,
+        &addr,
+    );
+    let (used_token, refresh_token) = match refresh_token_domain.rotate_refresh_token(
+        &presented_token,
+        &fingerprint,
+        app_state.get_client_binding(),
+    ) {
+        Err(err) => return err.into_response(),
+        Ok(result) => result,
+    };
+
+    let repository = UserRepository {
+        pg_pool: app_state.get_db_pool(),
+    };
     let mut domain = UserDomain::new(repository);
 
     let result = domain.renew_token(
-        &token.sub,
-        app_state.get_config().env.HASHING_KEY.as_bytes(),
+        &used_token.username,
+        &used_token.session_id,
+        app_state.get_keyring(),
+        &fingerprint,
     );
 
     let (user, token, token_str) = match result {
@@ -336,33 +1034,44 @@ pub async fn token<
         }
     };
 
-    let user_id = &token.sub;
-
-    let available_session = app_state.get_session_manager().get_current_user_connections().lock().await;
-    let session = available_session.get(user_id);
+    let session = app_state
+        .get_session_manager()
+        .get_session(&token.sub, &token.sid)
+        .await;
 
-    if let Some(_) = session {
-        return     HTTPResponse::<String> {
-            data: Some(token_str),
-            message: None,
-            status: StatusCode::OK,
+    // The session expires with its token, so it gets the new one
+    let session = match session {
+        Some(session) => {
+            let mut session = session.lock().await.clone();
+            session.set_token(token);
+            session
         }
-        .into_response()
-    }
-
-    drop(available_session);
-
-    let session = S::new(user.clone(), token);
-    session.notify_online(app_state.get_session_manager()).await;
+        None => {
+            let mut session = S::new(user.clone(), token);
+            session.set_client_info(ClientInfo::new(&headers, &addr));
+            session.notify_online(app_state.get_session_manager()).await;
+            session
+        }
+    };
     app_state
         .get_session_manager()
         .insert_into_current_user_connections(session)
         .await;
 
-    HTTPResponse::<String> {
-        data: Some(token_str),
-        message: None,
-        status: StatusCode::OK,
-    }
-    .into_response()
+    let (response_headers, token_response) = token_response(
+        app_state.get_cookie_settings(),
+        token_str,
+        refresh_token,
+        csrf_token,
+    );
+
+    (
+        response_headers,
+        HTTPResponse::<TokenResponseDTO> {
+            data: Some(token_response),
+            message: None,
+            status: StatusCode::OK,
+        },
+    )
+        .into_response()
 }
@@ -4,13 +4,22 @@ use diesel::query_dsl::*;
 use crate::{schema::users::dsl::*};
 
 
-use crate::{helper::sql::Count, schema, models::UserDTO};
+use std::time::SystemTime;
+use uuid::Uuid;
+
+use crate::{helper::sql::Count, schema, models::{UserDTO, UserKey}};
 
 pub trait UserRepositoryInterface {
     fn check_if_user_already_exists(&mut self, usern: &String) -> Result<bool, String>;
     fn get_user_by_username(&mut self, usern: &String) -> Result<UserDTO, String>;
-    fn save_user(&mut self, user: &UserDTO) -> Result<(), String>;
-    fn get_user_by_username_and_password(&mut self, usern: &String, passw: &String) -> Result<UserDTO, String>;
+    /// Returns false if the username is taken
+    fn save_user(&mut self, user: &UserDTO) -> Result<bool, String>;
+    fn update_password(&mut self, usern: &String, password_hash: &String) -> Result<(), String>;
+    /// Hands every room owned by the user to the member who joined it first after the owner.
+    /// Rooms without other members are left to be removed along with the user
+    fn transfer_owned_rooms(&mut self, usern: &String) -> Result<(), String>;
+    /// Everything of the user is removed along with it by the foreign keys. Returns false if the user did not exist
+    fn delete_user(&mut self, usern: &String) -> Result<bool, String>;
 }
 
 pub struct UserRepository {
@@ -47,31 +56,71 @@ impl UserRepositoryInterface for UserRepository {
         return Ok(user)
     }
 
-    fn save_user(&mut self, user: &UserDTO) -> Result<(), String> {
+    fn save_user(&mut self, user: &UserDTO) -> Result<bool, String> {
 
-        let values = vec![user];
-        let result = diesel::insert_into(schema::users::table).values(values).execute(&mut self.pg_pool);
+        let key = UserKey {
+            id: Uuid::new_v4(),
+            username: user.username.clone(),
+            public_key: user.public_key.clone(),
+            created_at: SystemTime::now(),
+            replaced_at: None
+        };
+        let result = self.pg_pool.transaction::<_, diesel::result::Error, _>(|conn| {
+            diesel::insert_into(schema::users::table).values(vec![user]).execute(conn)?;
+            diesel::insert_into(schema::user_keys::table).values(&key).execute(conn)
+        });
 
 
         match result {
+            // Registered in between by someone else
+            Err(diesel::result::Error::DatabaseError(diesel::result::DatabaseErrorKind::UniqueViolation, _)) => Ok(false),
             Err(err) => Err(format!("Could not save user {:?}", err)),
+            Ok(_) => Ok(true)
+        }
+    }
+
+    fn update_password(&mut self, usern: &String, password_hash: &String) -> Result<(), String> {
+        let result = diesel::update(users.filter(username.eq(usern)))
+            .set(password.eq(password_hash))
+            .execute(&mut self.pg_pool);
+
+        match result {
+            Err(err) => Err(format!("Could not update password of {}: {}", usern, err)),
             Ok(_) => Ok(())
         }
     }
 
-    fn get_user_by_username_and_password(&mut self, usern: &String, passw: &String) -> Result<UserDTO, String> {
-        let user: Result<UserDTO, _> = schema::users::table.select(schema::users::all_columns)
-        .filter(username
-            .eq(usern)
-            .and(password.eq(passw)))
-        .first::<UserDTO>(&mut self.pg_pool);
+    fn transfer_owned_rooms(&mut self, usern: &String) -> Result<(), String> {
+        let result = diesel::sql_query(
+            "
+            UPDATE rooms AS r
+            SET owner = successor.user_id
+            FROM (
+                SELECT DISTINCT ON (rm.room_id) rm.room_id, rm.user_id
+                FROM room_members AS rm
+                WHERE rm.user_id <> $1
+                ORDER BY rm.room_id, rm.joined_at ASC, rm.id ASC
+            ) AS successor
+            WHERE r.owner = $1 AND successor.room_id = r.id
+            ",
+        )
+        .bind::<Text, _>(usern)
+        .execute(&mut self.pg_pool);
 
-        let user = match user {
-            Err(err) => return Err(format!("Could not get user, {}", err)),
-            Ok(result) => result
-        };
+        match result {
+            Err(err) => Err(format!("Could not transfer the rooms of {}: {}", usern, err)),
+            Ok(_) => Ok(())
+        }
+    }
 
-        Ok(user)
+    fn delete_user(&mut self, usern: &String) -> Result<bool, String> {
+        let result = diesel::delete(users.filter(username.eq(usern)))
+            .execute(&mut self.pg_pool);
+
+        match result {
+            Err(err) => Err(format!("Could not delete user {}: {}", usern, err)),
+            Ok(deleted) => Ok(deleted == 1)
+        }
     }
 
 }
\ No newline at end of file
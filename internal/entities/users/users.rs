@@ -3,7 +3,10 @@ use std::time::Duration;
 use crate::{
     helper::{
         errors::HTTPResponse,
-        jwt::{create_user_token, generate_token_expiration, hash_string, Token},
+        fingerprint::ClientFingerprint,
+        jwt::{create_user_token, generate_token_expiration, Token},
+        keyring::Keyring,
+        password::{hash_password, verify_dummy_password, verify_password, PasswordVerification},
     },
     models::UserDTO,
 };
@@ -12,6 +15,9 @@ use tracing::debug;
 
 use super::repository::UserRepositoryInterface;
 
+/// Access tokens are short-lived, clients keep their session alive with a refresh token
+pub const ACCESS_TOKEN_LIFETIME: Duration = Duration::from_secs(15 * 60);
+
 pub struct UserDomain<I: UserRepositoryInterface> {
     user_repository: I,
 }
@@ -21,12 +27,14 @@ impl<I: UserRepositoryInterface> UserDomain<I> {
         return UserDomain { user_repository };
     }
 
-    pub fn create_user(
-        &mut self,
-        user: &UserDTO,
-        hashing_key: &[u8],
-    ) -> Result<UserDTO, HTTPResponse<Vec<u8>>> {
+    /// Returns the user if it was registered and `None` if the username is taken. Callers answer both the same way,
+    /// so registrations cannot be used to find registered users. The password is hashed either way, so both take the same time
+    pub fn create_user(&mut self, user: &UserDTO) -> Result<Option<UserDTO>, HTTPResponse<Vec<u8>>> {
         let mut user = user.clone();
+        user.password = match hash_password(&user.password) {
+            Err(err) => return Err(HTTPResponse::new_internal_error(err)),
+            Ok(res) => res,
+        };
 
         let user_exists = self
             .user_repository
@@ -36,49 +44,64 @@ impl<I: UserRepositoryInterface> UserDomain<I> {
             Ok(exists) => exists,
         };
 
-        if user_exists {
-            return Err(HTTPResponse {
-                message: Some(String::from("User with this username already exists")),
-                status: StatusCode::BAD_REQUEST,
-                data: None,
-            });
-        }
-
-        let encrypted_password = hash_string(&user.password, hashing_key);
-        user.password = encrypted_password;
-
-        match self.user_repository.save_user(&user) {
-            Ok(_) => {
+        let saved = match user_exists {
+            true => Ok(false),
+            false => self.user_repository.save_user(&user),
+        };
+        match saved {
+            Ok(true) => {
                 debug!(target: "application", "[create_user] user registered {}", &user.username);
-                Ok(user)
+                Ok(Some(user))
+            }
+            Ok(false) => {
+                tracing::info!(target: "audit", "[create_user] registration for taken username {} ignored", &user.username);
+                Ok(None)
             }
             Err(err) => Err(HTTPResponse::new_internal_error(err)),
         }
     }
 
+    /// Legacy HMAC hashes (keyed with `hashing_key`) are replaced by an Argon2id hash once the password matched.
+    /// Unknown users and wrong passwords take the same time and get the same error, so logins cannot be used to find registered users
     pub fn login_user_and_prepare_token(
         &mut self,
         usern: &String,
         passw: &String,
         hashing_key: &[u8],
+        keyring: &Keyring,
+        fingerprint: &ClientFingerprint,
     ) -> Result<(UserDTO, Token, String), HTTPResponse<Token>> {
-        let user = self
-            .user_repository
-            .get_user_by_username_and_password(usern, passw);
-        let user = match user {
+        let wrong_credentials = || HTTPResponse {
+            status: StatusCode::UNAUTHORIZED,
+            data: None,
+            message: Some(String::from("Username or password wrong")),
+        };
+
+        let mut user = match self.user_repository.get_user_by_username(usern) {
             Ok(user) => user,
             Err(_) => {
-                return Err(HTTPResponse {
-                    status: StatusCode::UNAUTHORIZED,
-                    data: None,
-                    message: Some(String::from("Username or password wrong")),
-                })
+                verify_dummy_password(passw);
+                return Err(wrong_credentials());
             }
         };
 
-        let (valid_for, _) = generate_token_expiration(Duration::new(15 * 60, 0));
+        match verify_password(passw, &user.password, hashing_key) {
+            PasswordVerification::Invalid => return Err(wrong_credentials()),
+            PasswordVerification::Valid => {}
+            PasswordVerification::ValidNeedsRehash => self.rehash_password(&mut user, passw),
+        }
+
+        let (valid_for, _) = generate_token_expiration(ACCESS_TOKEN_LIFETIME);
 
-        let (token, token_str) = create_user_token(user.clone(), hashing_key, valid_for);
+        // Every login is a new device session
+        let session_id = uuid::Uuid::new_v4().to_string();
+        let (token, token_str) = create_user_token(
+            user.clone(),
+            keyring,
+            valid_for,
+            session_id,
+            fingerprint.clone(),
+        );
         debug!(
             target: "application", "[login_user_and_prepare_token] user {} logged in",
             user.clone().username
@@ -86,10 +109,103 @@ impl<I: UserRepositoryInterface> UserDomain<I> {
         Ok((user, token, token_str))
     }
 
-    pub fn renew_token(
+    /// Replaces the password once the current one was confirmed. Callers revoke the other sessions of the user
+    pub fn change_password(
         &mut self,
         usern: &String,
+        current_password: &String,
+        new_password: &String,
         hashing_key: &[u8],
+    ) -> Result<(), HTTPResponse<()>> {
+        self.confirm_password(usern, current_password, hashing_key)?;
+
+        let password_hash = match hash_password(new_password) {
+            Err(err) => return Err(HTTPResponse::new_internal_error(err)),
+            Ok(res) => res,
+        };
+        if let Err(err) = self
+            .user_repository
+            .update_password(usern, &password_hash)
+        {
+            return Err(HTTPResponse::new_internal_error(err));
+        }
+
+        tracing::info!(target: "audit", "[change_password] user {} changed their password", usern);
+        Ok(())
+    }
+
+    /// Deletes the account along with everything stored about it, callers confirm the password first.
+    /// Rooms the user owns are handed to another member first, so they do not vanish for everyone else
+    pub fn delete_user(&mut self, usern: &String) -> Result<(), HTTPResponse<()>> {
+        if let Err(err) = self.user_repository.transfer_owned_rooms(usern) {
+            return Err(HTTPResponse::new_internal_error(err));
+        }
+
+        match self.user_repository.delete_user(usern) {
+            Err(err) => Err(HTTPResponse::new_internal_error(err)),
+            Ok(false) => Err(HTTPResponse {
+                status: StatusCode::NOT_FOUND,
+                data: None,
+                message: Some(String::from("User does not exist")),
+            }),
+            Ok(true) => {
+                tracing::info!(target: "audit", "[delete_user] user {} deleted their account", usern);
+                Ok(())
+            }
+        }
+    }
+
+    /// Sensitive changes of an authenticated user need the password again, a stolen access token is not enough
+    pub fn confirm_password(
+        &mut self,
+        usern: &String,
+        passw: &String,
+        hashing_key: &[u8],
+    ) -> Result<(), HTTPResponse<()>> {
+        let user = match self.user_repository.get_user_by_username(usern) {
+            Err(err) => return Err(HTTPResponse::new_internal_error(err)),
+            Ok(user) => user,
+        };
+
+        match verify_password(passw, &user.password, hashing_key) {
+            PasswordVerification::Invalid => Err(HTTPResponse {
+                status: StatusCode::UNAUTHORIZED,
+                data: None,
+                message: Some(String::from("Password wrong")),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// A failed rehash does not fail the login, it is tried again on the next one
+    fn rehash_password(&mut self, user: &mut UserDTO, passw: &String) {
+        let password_hash = match hash_password(passw) {
+            Err(err) => {
+                tracing::error!(target: "application", "[rehash_password] {}", err);
+                return;
+            }
+            Ok(res) => res,
+        };
+
+        match self
+            .user_repository
+            .update_password(&user.username, &password_hash)
+        {
+            Err(err) => tracing::error!(target: "application", "[rehash_password] {}", err),
+            Ok(_) => {
+                debug!(target: "application", "[rehash_password] rehashed password of {}", user.username);
+                user.password = password_hash;
+            }
+        }
+    }
+
+    /// Issues a fresh access token for the same device session, callers have to check the session is still valid
+    pub fn renew_token(
+        &mut self,
+        usern: &String,
+        session_id: &String,
+        keyring: &Keyring,
+        fingerprint: &ClientFingerprint,
     ) -> Result<(UserDTO, Token, String), String> {
         let user = self.user_repository.get_user_by_username(usern);
         let user = match user {
@@ -97,9 +213,15 @@ impl<I: UserRepositoryInterface> UserDomain<I> {
             Ok(user) => user,
         };
 
-        let (valid_for, _) = generate_token_expiration(Duration::new(15 * 60, 0));
+        let (valid_for, _) = generate_token_expiration(ACCESS_TOKEN_LIFETIME);
 
-        let (token, token_str) = create_user_token(user.clone(), hashing_key, valid_for);
+        let (token, token_str) = create_user_token(
+            user.clone(),
+            keyring,
+            valid_for,
+            session_id.clone(),
+            fingerprint.clone(),
+        );
         debug!(target: "application", "[renew_token] renewed token for: {}", user.clone().username);
         return Ok((user, token, token_str));
     }
@@ -1,10 +1,20 @@
-use crate::models::UserDTO;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::{
+    helper::{jwt::hash_string, password::hash_password},
+    models::{Room, UserDTO},
+};
 
 use super::repository::UserRepositoryInterface;
 
-pub struct UserRepositoryMock {}
+#[derive(Default)]
+pub struct UserRepositoryMock {
+    // Rooms with their members in the order they joined, shared so tests can look at them after a deletion
+    pub rooms: Arc<Mutex<Vec<(Room, Vec<String>)>>>,
+}
 
 impl UserRepositoryInterface for UserRepositoryMock {
+    /// `raced` is registered by someone else between the check and the insert
     fn check_if_user_already_exists(&mut self, usern: &String) -> Result<bool, String> {
         if usern == "exists" {
             return Ok(true);
@@ -14,25 +24,52 @@ impl UserRepositoryInterface for UserRepositoryMock {
         return Ok(false);
     }
 
-    fn get_user_by_username_and_password(
-        &mut self,
-        usern: &String,
-        passw: &String,
-    ) -> Result<UserDTO, String> {
-        return Ok(UserDTO {
-            username: usern.to_owned(),
-            password: passw.to_owned(),
-            public_key: vec![],
-        });
+    fn update_password(&mut self, _: &String, _: &String) -> Result<(), String> {
+        return Ok(());
+    }
+
+    fn save_user(&mut self, user: &UserDTO) -> Result<bool, String> {
+        // Like the unique constraint of the database
+        return Ok(user.username != "exists" && user.username != "raced");
     }
-    fn save_user(&mut self, _: &UserDTO) -> Result<(), String> {
+
+    fn transfer_owned_rooms(&mut self, usern: &String) -> Result<(), String> {
+        for (room, members) in self.rooms.lock().unwrap().iter_mut() {
+            if &room.owner != usern {
+                continue;
+            }
+            if let Some(successor) = members.iter().find(|member| *member != usern) {
+                room.owner = successor.clone();
+            }
+        }
         return Ok(());
     }
 
+    fn delete_user(&mut self, usern: &String) -> Result<bool, String> {
+        // Like the foreign keys, which remove the rooms of the user and their memberships
+        let mut rooms = self.rooms.lock().unwrap();
+        rooms.retain(|(room, _)| &room.owner != usern);
+        for (_, members) in rooms.iter_mut() {
+            members.retain(|member| member != usern);
+        }
+        return Ok(usern != "missing");
+    }
+
+    /// Every user has the password TestPassword, Legacy still has a HMAC hash of it
     fn get_user_by_username(&mut self, usern: &String) -> Result<UserDTO, String> {
+        let password = match usern.as_str() {
+            "missing" => return Err(String::from("Could not get user")),
+            "Legacy" => hash_string("TestPassword", b"abc"),
+            _ => {
+                static PASSWORD_HASH: OnceLock<String> = OnceLock::new();
+                PASSWORD_HASH
+                    .get_or_init(|| hash_password("TestPassword").unwrap())
+                    .clone()
+            }
+        };
         return Ok(UserDTO {
             username: usern.to_owned(),
-            password: String::from("TestPassword"),
+            password,
             public_key: vec![],
         });
     }
@@ -40,17 +77,25 @@ impl UserRepositoryInterface for UserRepositoryMock {
 
 #[cfg(test)]
 mod integration_tests {
-    use std::time::Duration;
+    use std::time::{Duration, SystemTime};
+
+    use axum::http::StatusCode;
+    use uuid::Uuid;
 
     use crate::{
         entities::users::{users::UserDomain, users_test::UserRepositoryMock},
-        helper::jwt::{create_user_token, generate_token_expiration, hash_string},
-        models::UserDTO,
+        helper::{
+            jwt::{create_user_token, generate_token_expiration},
+            fingerprint_test::test_fingerprint,
+            keyring_test::test_keyring,
+            password::{argon2_verifications, verify_password, PasswordVerification},
+        },
+        models::{Room, UserDTO},
     };
 
     #[test]
     fn test_user_creation() {
-        let repo = UserRepositoryMock {};
+        let repo = UserRepositoryMock::default();
         let mut domain = UserDomain::new(repo);
         let user_input = UserDTO {
             username: String::from("Test1"),
@@ -58,31 +103,27 @@ mod integration_tests {
             public_key: vec![],
         };
 
-        let hashing_key = String::from("abc");
-        let hashing_key = hashing_key.as_bytes();
-
-        let x = domain.create_user(&user_input, &hashing_key);
-        let hashed_password = hash_string(&user_input.password, &hashing_key);
-
-        let user_expect = UserDTO {
-            password: hashed_password,
-            public_key: vec![],
-            username: String::from("Test1"),
-        };
+        let x = domain.create_user(&user_input);
 
         let user_output = match x {
-            Ok(usr) => usr,
-            Err(_) => return assert!(false),
+            Ok(Some(usr)) => usr,
+            _ => return assert!(false),
         };
 
-        assert_eq!(user_expect, user_output)
+        assert_eq!(user_output.username, String::from("Test1"));
+        assert_ne!(user_output.password, user_input.password);
+        assert_eq!(
+            verify_password(&user_input.password, &user_output.password, b"abc"),
+            PasswordVerification::Valid
+        );
     }
 
     #[test]
     fn test_login_user_and_prepare_token() {
-        let repo = UserRepositoryMock {};
+        let repo = UserRepositoryMock::default();
         let mut domain = UserDomain::new(repo);
 
+        let keyring = test_keyring();
         let hashing_key = String::from("abc");
         let hashing_key = hashing_key.as_bytes();
 
@@ -95,25 +136,62 @@ mod integration_tests {
         };
 
         let (valid_for, _) = generate_token_expiration(Duration::new(15 * 60, 0));
-        let (token_expect, _) = create_user_token(user_expect.clone(), hashing_key, valid_for);
-        let result = domain.login_user_and_prepare_token(&username, &password, hashing_key);
+        let (token_expect, _) = create_user_token(
+            user_expect.clone(),
+            &keyring,
+            valid_for,
+            String::from("sid"),
+            test_fingerprint(),
+        );
+        let result = domain.login_user_and_prepare_token(&username, &password, hashing_key, &keyring, &test_fingerprint());
 
         let (user_output, token, _) = match result {
             Ok(res) => res,
             Err(_) => return assert!(false),
         };
 
-        assert_eq!(user_expect, user_output);
+        assert_eq!(user_expect.username, user_output.username);
         assert_eq!(token_expect.sub, token.sub);
+
+        let result = domain
+            .login_user_and_prepare_token(&username, &String::from("WrongPassword"), hashing_key, &keyring, &test_fingerprint())
+            .unwrap_err();
+        assert_eq!(result.status, StatusCode::UNAUTHORIZED);
+
+        let result = domain
+            .login_user_and_prepare_token(&String::from("missing"), &password, hashing_key, &keyring, &test_fingerprint())
+            .unwrap_err();
+        assert_eq!(result.status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn test_login_rehashes_legacy_password() {
+        let mut domain = UserDomain::new(UserRepositoryMock::default());
+        let keyring = test_keyring();
+        let password = String::from("TestPassword");
+
+        let (user_output, _, _) = domain
+            .login_user_and_prepare_token(&String::from("Legacy"), &password, b"abc", &keyring, &test_fingerprint())
+            .unwrap();
+        assert!(user_output.password.starts_with("$argon2id$"));
+        assert_eq!(
+            verify_password(&password, &user_output.password, b"abc"),
+            PasswordVerification::Valid
+        );
+
+        // The legacy hash is bound to the hashing key
+        let result = domain
+            .login_user_and_prepare_token(&String::from("Legacy"), &password, b"other", &keyring, &test_fingerprint())
+            .unwrap_err();
+        assert_eq!(result.status, StatusCode::UNAUTHORIZED);
     }
 
     #[test]
     fn test_renew_token() {
-        let repo = UserRepositoryMock {};
+        let repo = UserRepositoryMock::default();
         let mut domain = UserDomain::new(repo);
 
-        let hashing_key = String::from("abc");
-        let hashing_key = hashing_key.as_bytes();
+        let keyring = test_keyring();
 
         let username = String::from("TestUser");
         let password = String::from("TestPassword");
@@ -125,15 +203,132 @@ mod integration_tests {
 
         let (valid_for, _) = generate_token_expiration(Duration::new(15 * 60, 0));
 
-        let (token_expect, _) = create_user_token(user_expect.clone(), hashing_key, valid_for);
-        let result = domain.renew_token(&username, hashing_key);
+        let (token_expect, _) = create_user_token(
+            user_expect.clone(),
+            &keyring,
+            valid_for,
+            String::from("sid"),
+            test_fingerprint(),
+        );
+        let result = domain.renew_token(&username, &String::from("sid"), &keyring, &test_fingerprint());
 
         let (user_output, token, _) = match result {
             Ok(res) => res,
             Err(_) => return assert!(false),
         };
 
-        assert_eq!(user_expect, user_output);
+        assert_eq!(user_expect.username, user_output.username);
         assert_eq!(token_expect.sub, token.sub);
+        assert_eq!(token_expect.sid, token.sid);
+    }
+
+    #[test]
+    fn test_change_password() {
+        let mut domain = UserDomain::new(UserRepositoryMock::default());
+        let new_password = String::from("NewPassword");
+
+        let result = domain
+            .change_password(&String::from("TestUser"), &String::from("WrongPassword"), &new_password, b"abc")
+            .unwrap_err();
+        assert_eq!(result.status, StatusCode::UNAUTHORIZED);
+
+        assert_eq!(
+            domain.change_password(&String::from("TestUser"), &String::from("TestPassword"), &new_password, b"abc"),
+            Ok(())
+        );
+        // Legacy hashes are accepted as the current password as well
+        assert_eq!(
+            domain.change_password(&String::from("Legacy"), &String::from("TestPassword"), &new_password, b"abc"),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_delete_user() {
+        let mut domain = UserDomain::new(UserRepositoryMock::default());
+
+        let result = domain
+            .confirm_password(&String::from("TestUser"), &String::from("WrongPassword"), b"abc")
+            .unwrap_err();
+        assert_eq!(result.status, StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            domain.confirm_password(&String::from("TestUser"), &String::from("TestPassword"), b"abc"),
+            Ok(())
+        );
+
+        assert_eq!(domain.delete_user(&String::from("TestUser")), Ok(()));
+        let result = domain.delete_user(&String::from("missing")).unwrap_err();
+        assert_eq!(result.status, StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_deleting_the_owner_keeps_rooms_of_other_members() {
+        let room = |name: &str| Room {
+            id: Uuid::new_v4(),
+            name: String::from(name),
+            owner: String::from("Owner"),
+            created_at: SystemTime::now(),
+        };
+        let repo = UserRepositoryMock::default();
+        let rooms = repo.rooms.clone();
+        rooms.lock().unwrap().extend([
+            (room("Shared"), vec![String::from("Owner"), String::from("First"), String::from("Second")]),
+            (room("Alone"), vec![String::from("Owner")]),
+        ]);
+        let mut domain = UserDomain::new(repo);
+
+        assert_eq!(domain.delete_user(&String::from("Owner")), Ok(()));
+
+        // The room of the owner alone is gone, the shared one belongs to the member who joined next
+        let rooms = rooms.lock().unwrap();
+        assert_eq!(rooms.len(), 1);
+        let (shared, members) = &rooms[0];
+        assert_eq!(shared.name, String::from("Shared"));
+        assert_eq!(shared.owner, String::from("First"));
+        assert_eq!(members, &vec![String::from("First"), String::from("Second")]);
+    }
+
+    #[test]
+    fn test_login_does_not_reveal_registered_users() {
+        let mut domain = UserDomain::new(UserRepositoryMock::default());
+        let keyring = test_keyring();
+        let hashing_key = b"abc";
+        let password = String::from("WrongPassword");
+
+        // Every path verifies exactly one Argon2id hash, which is what the time of a login is made of
+        let mut login = |username: &str| {
+            let before = argon2_verifications();
+            let result = domain
+                .login_user_and_prepare_token(&String::from(username), &password, hashing_key, &keyring, &test_fingerprint())
+                .unwrap_err();
+            (result, argon2_verifications() - before)
+        };
+        let (unknown_user, unknown_user_verifications) = login("missing");
+        let (wrong_password, wrong_password_verifications) = login("TestUser");
+        let (legacy_wrong_password, legacy_verifications) = login("Legacy");
+
+        assert_eq!(unknown_user, wrong_password);
+        assert_eq!(unknown_user, legacy_wrong_password);
+        assert_eq!(unknown_user.status, StatusCode::UNAUTHORIZED);
+
+        assert_eq!(unknown_user_verifications, 1);
+        assert_eq!(wrong_password_verifications, 1);
+        assert_eq!(legacy_verifications, 1);
+    }
+
+    #[test]
+    fn test_registration_does_not_reveal_registered_users() {
+        let mut domain = UserDomain::new(UserRepositoryMock::default());
+        let user = |username: &str| UserDTO {
+            username: String::from(username),
+            password: String::from("TestPassword"),
+            public_key: vec![],
+        };
+
+        // Both the check and the unique constraint of a concurrent registration end up the same way
+        assert!(matches!(domain.create_user(&user("exists")), Ok(None)));
+        assert!(matches!(domain.create_user(&user("raced")), Ok(None)));
+        let registered = domain.create_user(&user("new")).unwrap().unwrap();
+        assert_ne!(registered.password, String::from("TestPassword"));
     }
 }
@@ -0,0 +1,164 @@
+use crate::interfaces::websockets::socket_messages::SocketMessage;
+use axum::async_trait;
+use futures::StreamExt;
+use std::fmt::Debug;
+use tokio::sync::broadcast;
+use tokio_postgres::{AsyncMessage, NoTls};
+
+/// Postgres channel all instances LISTEN on
+pub const BACKPLANE_CHANNEL: &str = "sanctumchat_backplane";
+
+/// A socket message addressed to a single user, which may be connected to any instance
+#[derive(Clone, serde::Deserialize, serde::Serialize, Debug)]
+pub struct BackplaneMessage {
+    pub recipient: String,
+    pub message: SocketMessage,
+    /// Closes the sessions of the recipient instead, `message` is the last one they receive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub close_sessions: Option<CloseSessions>,
+}
+
+#[derive(Clone, serde::Deserialize, serde::Serialize, Debug, PartialEq)]
+pub struct CloseSessions {
+    pub kept_session_id: Option<String>,
+}
+
+impl BackplaneMessage {
+    pub fn new(recipient: String, message: SocketMessage) -> BackplaneMessage {
+        BackplaneMessage {
+            recipient,
+            message,
+            close_sessions: None,
+        }
+    }
+
+    pub fn close_sessions(
+        recipient: String,
+        kept_session_id: Option<String>,
+        notice: SocketMessage,
+    ) -> BackplaneMessage {
+        BackplaneMessage {
+            recipient,
+            message: notice,
+            close_sessions: Some(CloseSessions { kept_session_id }),
+        }
+    }
+}
+
+/// Pub/sub used to fan out socket messages across all running instances.
+/// Every published message is handed to every subscriber of every instance, which then delivers it to its local sessions
+#[async_trait]
+pub trait IBackplane: Debug + Send + Sync + 'static {
+    async fn publish(&self, message: BackplaneMessage) -> Result<(), String>;
+    fn subscribe(&self) -> broadcast::Receiver<BackplaneMessage>;
+}
+
+/// Backplane which never leaves the process. Used when running a single instance and in tests
+#[derive(Debug)]
+pub struct InProcessBackplane {
+    sender: broadcast::Sender<BackplaneMessage>,
+}
+
+impl InProcessBackplane {
+    pub fn new() -> Self {
+        return Self {
+            sender: broadcast::channel(100).0,
+        };
+    }
+}
+
+#[async_trait]
+impl IBackplane for InProcessBackplane {
+    async fn publish(&self, message: BackplaneMessage) -> Result<(), String> {
+        // Having no subscribers is not an error, there is just nobody to deliver to
+        let _ = self.sender.send(message);
+        Ok(())
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<BackplaneMessage> {
+        self.sender.subscribe()
+    }
+}
+
+/// Backplane based on postgres LISTEN/NOTIFY.
+/// Keeps one dedicated connection outside of the diesel pool, since a LISTEN is bound to its connection
+pub struct PostgresBackplane {
+    client: tokio_postgres::Client,
+    sender: broadcast::Sender<BackplaneMessage>,
+}
+
+impl Debug for PostgresBackplane {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PostgresBackplane").finish()
+    }
+}
+
+impl PostgresBackplane {
+    pub async fn connect(database_url: &String) -> Result<Self, String> {
+        let (client, mut connection) = match tokio_postgres::connect(database_url, NoTls).await {
+            Err(err) => return Err(format!("Could not connect backplane: {}", err)),
+            Ok(res) => res,
+        };
+
+        let sender = broadcast::channel(100).0;
+        let notification_sender = sender.clone();
+
+        // The connection has to be polled manually, otherwise notifications are never received
+        tokio::spawn(async move {
+            let mut messages = futures::stream::poll_fn(move |cx| connection.poll_message(cx));
+            while let Some(message) = messages.next().await {
+                let notification = match message {
+                    Ok(AsyncMessage::Notification(notification)) => notification,
+                    Ok(_) => continue,
+                    Err(err) => {
+                        tracing::error!(target: "application", "[PostgresBackplane] Connection closed: {}", err);
+                        break;
+                    }
+                };
+                match serde_json::from_str::<BackplaneMessage>(notification.payload()) {
+                    Ok(message) => {
+                        let _ = notification_sender.send(message);
+                    }
+                    Err(err) => {
+                        tracing::error!(target: "application", "[PostgresBackplane] Could not deserialize notification: {}", err)
+                    }
+                }
+            }
+        });
+
+        if let Err(err) = client
+            .batch_execute(&format!("LISTEN {}", BACKPLANE_CHANNEL))
+            .await
+        {
+            return Err(format!("Could not listen on {}: {}", BACKPLANE_CHANNEL, err));
+        }
+
+        Ok(Self { client, sender })
+    }
+}
+
+#[async_trait]
+impl IBackplane for PostgresBackplane {
+    /// NOTIFY payloads are limited to 8000 bytes, larger messages are rejected by postgres
+    async fn publish(&self, message: BackplaneMessage) -> Result<(), String> {
+        let payload = match serde_json::to_string(&message) {
+            Err(err) => return Err(format!("Could not serialize backplane message: {}", err)),
+            Ok(res) => res,
+        };
+        match self
+            .client
+            .execute("SELECT pg_notify($1, $2)", &[&BACKPLANE_CHANNEL, &payload])
+            .await
+        {
+            Err(err) => Err(format!(
+                "Could not publish message for {}: {}",
+                message.recipient, err
+            )),
+            Ok(_) => Ok(()),
+        }
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<BackplaneMessage> {
+        self.sender.subscribe()
+    }
+}
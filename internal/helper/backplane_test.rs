@@ -0,0 +1,137 @@
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use uuid::Uuid;
+
+    use crate::{
+        entities::friends::repository::FriendRepository,
+        helper::{
+            backplane::{BackplaneMessage, IBackplane, InProcessBackplane},
+            jwt::Token,
+            session::{ISession, Session},
+        },
+        interfaces::websockets::{
+            messages::{
+                SocketMessageDirect::SocketMessageDirect, SocketMessageRoom::SocketMessageRoom,
+            },
+            socket_messages::{
+                EEvent, SocketMessage, SocketMessageFriendRequest, SocketMessageNotification,
+                SocketMessagePayload, SocketMessageStatusChange,
+            },
+        },
+        models::UserDTO,
+        persistence::connection_manager::ConnectionManager,
+    };
+
+    type TestFriendRepository = FriendRepository<ConnectionManager>;
+
+    fn new_session(username: &str) -> Session {
+        <Session as ISession<TestFriendRepository>>::new(
+            UserDTO {
+                username: String::from(username),
+                password: String::from("Pass"),
+                public_key: Vec::<u8>::new(),
+            },
+            Token {
+                exp: Duration::from_secs(10000),
+                iat: Duration::from_secs(0),
+                sub: String::from(username),
+                jti: String::from("jti"),
+                sid: String::from("sid"),
+                cfp: None,
+            },
+        )
+    }
+
+    fn roundtrip(message: SocketMessage) -> SocketMessage {
+        let serialized =
+            serde_json::to_string(&BackplaneMessage::new(String::from("Test"), message)).unwrap();
+        serde_json::from_str::<BackplaneMessage>(&serialized)
+            .unwrap()
+            .message
+    }
+
+    #[test]
+    fn test_socket_messages_survive_the_backplane() {
+        let direct = roundtrip(SocketMessage::SocketMessageDirect(SocketMessageDirect::new(
+            Some(String::from("Sender")),
+            Some(String::from("Recipient")),
+            String::from("msg"),
+            String::from("sig"),
+            String::from("self"),
+            String::from("self_sig"),
+            Some(Uuid::new_v4()),
+            Some(Uuid::new_v4()),
+        )));
+        assert!(matches!(direct, SocketMessage::SocketMessageDirect(m) if m.message_self_encrypted == "self"));
+
+        let notification = roundtrip(SocketMessage::SocketMessageNotification(
+            SocketMessageNotification::new(
+                String::from("error"),
+                String::from("Important"),
+                String::from("Your session expired"),
+            ),
+        ));
+        assert!(matches!(notification, SocketMessage::SocketMessageNotification(_)));
+
+        let status = roundtrip(SocketMessage::SocketMessageStatusChange(
+            SocketMessageStatusChange::new(EEvent::OFFLINE, String::from("Friend")),
+        ));
+        assert!(matches!(status, SocketMessage::SocketMessageStatusChange(m) if m.user_id == "Friend"));
+
+        let friend_request = roundtrip(SocketMessage::SocketMessageFriendRequest(
+            SocketMessageFriendRequest::new(Uuid::new_v4(), String::from("Sender")),
+        ));
+        assert!(matches!(friend_request, SocketMessage::SocketMessageFriendRequest(_)));
+
+        let room = roundtrip(SocketMessage::SocketMessageRoom(SocketMessageRoom::new(
+            Uuid::new_v4(),
+            Some(String::from("Sender")),
+            vec![SocketMessagePayload {
+                recipient: String::from("Recipient"),
+                message: String::from("msg"),
+                message_signature: String::from("sig"),
+                key_id: Some(Uuid::new_v4()),
+            }],
+            Some(Uuid::new_v4()),
+        )));
+        assert!(matches!(room, SocketMessage::SocketMessageRoom(m) if m.payloads.len() == 1));
+    }
+
+    #[tokio::test]
+    async fn test_session_publishes_through_backplane() {
+        let backplane = Arc::new(InProcessBackplane::new());
+        let mut backplane_receiver = backplane.subscribe();
+
+        let mut session = new_session("Test");
+        let mut socket_receiver = session.user_socket.subscribe();
+
+        // Without a backplane the message goes straight to the socket
+        <Session as ISession<TestFriendRepository>>::send_direct_message(
+            &session,
+            SocketMessage::SocketMessageStatusChange(SocketMessageStatusChange::new(
+                EEvent::ONLINE,
+                String::from("Friend"),
+            )),
+        )
+        .await;
+        assert!(socket_receiver.try_recv().is_ok());
+
+        <Session as ISession<TestFriendRepository>>::set_backplane(&mut session, backplane.clone());
+        <Session as ISession<TestFriendRepository>>::send_direct_message(
+            &session,
+            SocketMessage::SocketMessageStatusChange(SocketMessageStatusChange::new(
+                EEvent::OFFLINE,
+                String::from("Friend"),
+            )),
+        )
+        .await;
+
+        // Delivery to the socket is up to the backplane listener now
+        assert!(socket_receiver.try_recv().is_err());
+        let published = backplane_receiver.try_recv().unwrap();
+        assert_eq!(published.recipient, String::from("Test"));
+        assert!(matches!(published.message, SocketMessage::SocketMessageStatusChange(_)));
+    }
+}
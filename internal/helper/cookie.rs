@@ -0,0 +1,147 @@
+use std::time::Duration;
+
+use axum::http::{header::COOKIE, HeaderMap, HeaderValue, Method};
+use base64::Engine;
+use cookie::{Cookie, SameSite};
+
+use crate::config::EnvConfig;
+
+use super::password::constant_time_eq;
+
+/// Access token of cookie authenticated clients
+pub const SESSION_COOKIE: &str = "session";
+/// Refresh token of cookie authenticated clients, only sent to the refresh endpoint
+pub const REFRESH_TOKEN_COOKIE: &str = "refresh_token";
+/// Readable by the client, which echoes it in `CSRF_HEADER` to prove the request was made by its own scripts
+pub const CSRF_COOKIE: &str = "csrf_token";
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+const REFRESH_TOKEN_PATH: &str = "/api/token";
+
+/// Attributes of the cookies set by the service
+#[derive(Debug, Clone, PartialEq)]
+pub struct CookieSettings {
+    pub secure: bool,
+    pub same_site: SameSite,
+    pub domain: Option<String>,
+}
+
+impl CookieSettings {
+    pub fn from_config(env: &EnvConfig) -> Result<Self, String> {
+        let same_site = match env.COOKIE_SAMESITE.as_str() {
+            "Strict" => SameSite::Strict,
+            "Lax" => SameSite::Lax,
+            "None" => SameSite::None,
+            other => {
+                return Err(format!(
+                    "Unknown COOKIE_SAMESITE {}, use Strict, Lax or None",
+                    other
+                ))
+            }
+        };
+        // Browsers drop SameSite=None cookies which are not Secure
+        if same_site == SameSite::None && !env.COOKIE_SECURE {
+            return Err(String::from("COOKIE_SAMESITE=None requires COOKIE_SECURE"));
+        }
+
+        Ok(Self {
+            secure: env.COOKIE_SECURE,
+            same_site,
+            domain: env.COOKIE_DOMAIN.clone(),
+        })
+    }
+
+    pub fn session_cookie(&self, access_token: &str, max_age: Duration) -> HeaderValue {
+        self.build(SESSION_COOKIE, access_token, "/", true, max_age)
+    }
+
+    pub fn refresh_token_cookie(&self, refresh_token: &str, max_age: Duration) -> HeaderValue {
+        self.build(
+            REFRESH_TOKEN_COOKIE,
+            refresh_token,
+            REFRESH_TOKEN_PATH,
+            true,
+            max_age,
+        )
+    }
+
+    pub fn csrf_cookie(&self, csrf_token: &str, max_age: Duration) -> HeaderValue {
+        self.build(CSRF_COOKIE, csrf_token, "/", false, max_age)
+    }
+
+    /// Cookies which make the browser drop all cookies of the session
+    pub fn removal_cookies(&self) -> Vec<HeaderValue> {
+        vec![
+            self.build(SESSION_COOKIE, "", "/", true, Duration::ZERO),
+            self.build(
+                REFRESH_TOKEN_COOKIE,
+                "",
+                REFRESH_TOKEN_PATH,
+                true,
+                Duration::ZERO,
+            ),
+            self.build(CSRF_COOKIE, "", "/", false, Duration::ZERO),
+        ]
+    }
+
+    fn build(
+        &self,
+        name: &str,
+        value: &str,
+        path: &str,
+        http_only: bool,
+        max_age: Duration,
+    ) -> HeaderValue {
+        let mut cookie = Cookie::build((name.to_owned(), value.to_owned()))
+            .path(path.to_owned())
+            .http_only(http_only)
+            .secure(self.secure)
+            .same_site(self.same_site)
+            .max_age(cookie::time::Duration::seconds(max_age.as_secs() as i64));
+        if let Some(domain) = &self.domain {
+            cookie = cookie.domain(domain.clone());
+        }
+        cookie
+            .build()
+            .to_string()
+            .parse()
+            .expect("Cookies only contain valid header characters")
+    }
+}
+
+pub fn get_cookie(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|header| header.to_str().ok())
+        .flat_map(|header| header.split(';'))
+        .filter_map(|cookie| Cookie::parse(cookie.trim()).ok())
+        .find(|cookie| cookie.name() == name)
+        .map(|cookie| cookie.value().to_owned())
+}
+
+pub fn generate_csrf_token() -> Result<String, String> {
+    let mut secret = [0u8; 32];
+    openssl::rand::rand_bytes(&mut secret)
+        .map_err(|err| format!("Could not generate CSRF token: {}", err))?;
+    Ok(base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(secret))
+}
+
+/// Reading requests cannot change state, so only the others of cookie authenticated clients have to prove their origin
+pub fn requires_csrf_check(method: &Method) -> bool {
+    !matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+/// Double submit check, the header has to repeat the CSRF cookie. Returns the CSRF token
+pub fn check_csrf(headers: &HeaderMap) -> Result<String, String> {
+    let cookie = get_cookie(headers, CSRF_COOKIE).ok_or("CSRF cookie missing")?;
+    let header = headers
+        .get(CSRF_HEADER)
+        .and_then(|header| header.to_str().ok())
+        .ok_or("CSRF header missing")?;
+
+    if cookie.is_empty() || !constant_time_eq(cookie.as_bytes(), header.as_bytes()) {
+        return Err(String::from("CSRF token mismatch"));
+    }
+    Ok(cookie)
+}
@@ -0,0 +1,181 @@
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::http::{header::COOKIE, HeaderMap, Method};
+    use cookie::SameSite;
+
+    use crate::{
+        config::EnvConfig,
+        helper::cookie::{
+            check_csrf, generate_csrf_token, get_cookie, requires_csrf_check, CookieSettings,
+            CSRF_HEADER,
+        },
+    };
+
+    fn env(secure: bool, same_site: &str, domain: Option<&str>) -> EnvConfig {
+        EnvConfig {
+            DATABASE_URL: String::new(),
+            HASHING_KEY: String::new(),
+            APP_VERSION: String::new(),
+            CORS_ORIGIN: None,
+            SESSION_STORE: String::from("memory"),
+            BACKPLANE: String::from("in-process"),
+            JWT_KEYS: String::new(),
+            JWT_SIGNING_KEY_ID: None,
+            SERVER_SECRET: String::new(),
+            JWT_ISSUER: String::new(),
+            JWT_AUDIENCE: String::new(),
+            CLIENT_BINDING: String::from("user-agent"),
+            COOKIE_SECURE: secure,
+            COOKIE_SAMESITE: String::from(same_site),
+            COOKIE_DOMAIN: domain.map(String::from),
+            WS_QUERY_TOKEN: true,
+            SERVER_KEY_GENERATION: false,
+            VERIFY_MESSAGE_SIGNATURES: false,
+        }
+    }
+
+    fn settings() -> CookieSettings {
+        CookieSettings::from_config(&env(true, "Lax", None)).unwrap()
+    }
+
+    fn cookie_headers(cookies: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(COOKIE, cookies.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_from_config() {
+        let result = CookieSettings::from_config(&env(false, "Strict", Some("example.com")));
+        assert_eq!(
+            result,
+            Ok(CookieSettings {
+                secure: false,
+                same_site: SameSite::Strict,
+                domain: Some(String::from("example.com")),
+            })
+        );
+
+        assert!(CookieSettings::from_config(&env(true, "None", None)).is_ok());
+        assert!(CookieSettings::from_config(&env(false, "None", None)).is_err());
+        assert!(CookieSettings::from_config(&env(true, "lax", None)).is_err());
+    }
+
+    #[test]
+    fn test_session_cookie() {
+        let cookie = settings().session_cookie("token", Duration::from_secs(900));
+        let cookie = cookie.to_str().unwrap();
+
+        assert!(cookie.starts_with("session=token"));
+        assert!(cookie.contains("HttpOnly"));
+        assert!(cookie.contains("Secure"));
+        assert!(cookie.contains("SameSite=Lax"));
+        assert!(cookie.contains("Path=/"));
+        assert!(cookie.contains("Max-Age=900"));
+        assert!(!cookie.contains("Domain"));
+    }
+
+    #[test]
+    fn test_refresh_token_cookie_is_limited_to_refresh_endpoint() {
+        let cookie = settings().refresh_token_cookie("refresh", Duration::from_secs(60));
+        let cookie = cookie.to_str().unwrap();
+
+        assert!(cookie.starts_with("refresh_token=refresh"));
+        assert!(cookie.contains("HttpOnly"));
+        assert!(cookie.contains("Path=/api/token"));
+    }
+
+    #[test]
+    fn test_csrf_cookie_is_readable_by_scripts() {
+        let cookie = settings().csrf_cookie("csrf", Duration::from_secs(60));
+        let cookie = cookie.to_str().unwrap();
+
+        assert!(cookie.starts_with("csrf_token=csrf"));
+        assert!(!cookie.contains("HttpOnly"));
+    }
+
+    #[test]
+    fn test_cookie_domain() {
+        let settings =
+            CookieSettings::from_config(&env(false, "Lax", Some("example.com"))).unwrap();
+        let cookie = settings.session_cookie("token", Duration::from_secs(60));
+        let cookie = cookie.to_str().unwrap();
+
+        assert!(cookie.contains("Domain=example.com"));
+        assert!(!cookie.contains("Secure"));
+    }
+
+    #[test]
+    fn test_removal_cookies() {
+        let cookies = settings().removal_cookies();
+
+        assert_eq!(cookies.len(), 3);
+        for cookie in cookies {
+            assert!(cookie.to_str().unwrap().contains("Max-Age=0"));
+        }
+    }
+
+    #[test]
+    fn test_get_cookie() {
+        let headers = cookie_headers("theme=dark; session=token; csrf_token=csrf");
+
+        assert_eq!(get_cookie(&headers, "session"), Some(String::from("token")));
+        assert_eq!(
+            get_cookie(&headers, "csrf_token"),
+            Some(String::from("csrf"))
+        );
+        assert_eq!(get_cookie(&headers, "refresh_token"), None);
+        assert_eq!(get_cookie(&HeaderMap::new(), "session"), None);
+    }
+
+    #[test]
+    fn test_generate_csrf_token() {
+        let first = generate_csrf_token().unwrap();
+        let second = generate_csrf_token().unwrap();
+
+        assert_eq!(first.len(), 43);
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn test_check_csrf() {
+        let mut headers = cookie_headers("session=token; csrf_token=csrf");
+        headers.insert(CSRF_HEADER, "csrf".parse().unwrap());
+
+        assert_eq!(check_csrf(&headers), Ok(String::from("csrf")));
+    }
+
+    #[test]
+    fn test_check_csrf_rejects_mismatch() {
+        let mut headers = cookie_headers("session=token; csrf_token=csrf");
+        headers.insert(CSRF_HEADER, "other".parse().unwrap());
+
+        assert!(check_csrf(&headers).is_err());
+    }
+
+    #[test]
+    fn test_check_csrf_rejects_missing_values() {
+        let headers = cookie_headers("session=token; csrf_token=csrf");
+        assert!(check_csrf(&headers).is_err());
+
+        let mut headers = cookie_headers("session=token");
+        headers.insert(CSRF_HEADER, "csrf".parse().unwrap());
+        assert!(check_csrf(&headers).is_err());
+
+        let mut headers = cookie_headers("session=token; csrf_token=");
+        headers.insert(CSRF_HEADER, "".parse().unwrap());
+        assert!(check_csrf(&headers).is_err());
+    }
+
+    #[test]
+    fn test_requires_csrf_check() {
+        assert!(!requires_csrf_check(&Method::GET));
+        assert!(!requires_csrf_check(&Method::HEAD));
+        assert!(!requires_csrf_check(&Method::OPTIONS));
+        assert!(requires_csrf_check(&Method::POST));
+        assert!(requires_csrf_check(&Method::PATCH));
+        assert!(requires_csrf_check(&Method::DELETE));
+    }
+}
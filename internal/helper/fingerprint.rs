@@ -0,0 +1,124 @@
+use std::net::{IpAddr, SocketAddr};
+
+use axum::http::{header::USER_AGENT, HeaderMap};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// Purpose of the keyring secret the attributes are hashed with
+pub const CLIENT_FINGERPRINT_SECRET: &str = "client-fingerprint";
+
+/// Header with a random key the client generated once and keeps for the device
+pub const DEVICE_KEY_HEADER: &str = "x-device-key";
+
+/// Which client attributes tokens are bound to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClientBinding {
+    Off,
+    /// User agent and device key. Survives network changes of mobile clients
+    UserAgent,
+    /// User agent, device key and the network the client connects from
+    Strict,
+}
+
+impl ClientBinding {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "off" => Ok(ClientBinding::Off),
+            "user-agent" => Ok(ClientBinding::UserAgent),
+            "strict" => Ok(ClientBinding::Strict),
+            _ => Err(format!(
+                "Unknown CLIENT_BINDING {}, use off, user-agent or strict",
+                name
+            )),
+        }
+    }
+}
+
+/// Keyed hashes of the attributes of the client a token was issued to. Tokens are readable,
+/// so without the key the /24 prefix could be brute forced from them
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ClientFingerprint {
+    pub ua: String,
+    pub ip: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dk: Option<String>,
+}
+
+impl ClientFingerprint {
+    /// `secret` is the `client-fingerprint` secret of the keyring
+    pub fn new(secret: &[u8], user_agent: Option<&str>, ip: &IpAddr, device_key: Option<&str>) -> Self {
+        let hash = |name: &str, value: &str| hash_attribute(secret, name, value);
+        return Self {
+            ua: hash("ua", user_agent.unwrap_or_default()),
+            ip: hash("ip", &ip_prefix(ip)),
+            dk: device_key.map(|device_key| hash("dk", device_key)),
+        };
+    }
+
+    pub fn from_request(secret: &[u8], headers: &HeaderMap, addr: &SocketAddr) -> Self {
+        let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+        Self::new(
+            secret,
+            header(USER_AGENT.as_str()),
+            &addr.ip(),
+            header(DEVICE_KEY_HEADER),
+        )
+    }
+
+    /// Checks that `current` is the client the fingerprint was taken from.
+    /// A device key is only compared if the token was bound to one
+    pub fn check(&self, current: &ClientFingerprint, binding: ClientBinding) -> Result<(), String> {
+        if binding == ClientBinding::Off {
+            return Ok(());
+        }
+        if self.dk.is_some() && self.dk != current.dk {
+            return Err(String::from("Device key changed"));
+        }
+        if self.ua != current.ua {
+            return Err(String::from("User agent changed"));
+        }
+        if binding == ClientBinding::Strict && self.ip != current.ip {
+            return Err(String::from("Network changed"));
+        }
+        Ok(())
+    }
+}
+
+/// Addresses within the same /24 (IPv4) or /64 (IPv6) are treated as one network
+fn ip_prefix(ip: &IpAddr) -> String {
+    match ip.to_canonical() {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            format!("{}.{}.{}.0/24", a, b, c)
+        }
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            format!(
+                "{:x}:{:x}:{:x}:{:x}::/64",
+                segments[0], segments[1], segments[2], segments[3]
+            )
+        }
+    }
+}
+
+fn hash_attribute(secret: &[u8], name: &str, value: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC takes keys of any size");
+    mac.update(name.as_bytes());
+    mac.update(b":");
+    mac.update(value.as_bytes());
+    // Truncated to 128 bits to keep tokens small, collisions would still need to be found on purpose
+    hex::encode(&mac.finalize().into_bytes()[..16])
+}
+
+/// Tokens without fingerprint, e.g. ones restored from a session record, only pass if the binding is off
+pub fn check_client_binding(
+    fingerprint: &Option<ClientFingerprint>,
+    current: &ClientFingerprint,
+    binding: ClientBinding,
+) -> Result<(), String> {
+    match (binding, fingerprint) {
+        (ClientBinding::Off, _) => Ok(()),
+        (_, None) => Err(String::from("Token is not bound to a client")),
+        (_, Some(fingerprint)) => fingerprint.check(current, binding),
+    }
+}
@@ -0,0 +1,133 @@
+use std::net::IpAddr;
+
+use super::fingerprint::ClientFingerprint;
+
+pub fn test_fingerprint() -> ClientFingerprint {
+    ClientFingerprint::new(b"secret", Some("TestAgent"), &IpAddr::from([127, 0, 0, 1]), None)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, SocketAddr};
+
+    use axum::http::{header::USER_AGENT, HeaderMap};
+
+    use crate::helper::fingerprint::{
+        check_client_binding, ClientBinding, ClientFingerprint, DEVICE_KEY_HEADER,
+    };
+
+    fn fingerprint(user_agent: &str, ip: &str, device_key: Option<&str>) -> ClientFingerprint {
+        ClientFingerprint::new(b"secret", Some(user_agent), &ip.parse::<IpAddr>().unwrap(), device_key)
+    }
+
+    #[test]
+    fn test_from_request() {
+        let mut headers = HeaderMap::new();
+        headers.insert(USER_AGENT, "Agent".parse().unwrap());
+        headers.insert(DEVICE_KEY_HEADER, "device".parse().unwrap());
+        let addr: SocketAddr = "10.0.0.1:4000".parse().unwrap();
+
+        let result = ClientFingerprint::from_request(b"secret", &headers, &addr);
+        assert_eq!(result, fingerprint("Agent", "10.0.0.1", Some("device")));
+
+        // Only hashes end up in the token
+        assert_ne!(result.ua, "Agent");
+        assert_ne!(result.dk, Some(String::from("device")));
+    }
+
+    #[test]
+    fn test_hashes_are_keyed() {
+        let ip = "10.0.0.1".parse::<IpAddr>().unwrap();
+        let result = ClientFingerprint::new(b"secret", Some("Agent"), &ip, Some("device"));
+        let other = ClientFingerprint::new(b"other", Some("Agent"), &ip, Some("device"));
+
+        assert_ne!(result.ua, other.ua);
+        assert_ne!(result.ip, other.ip);
+        assert_ne!(result.dk, other.dk);
+    }
+
+    #[test]
+    fn test_user_agent_binding() {
+        let issued = fingerprint("Agent", "10.0.0.1", None);
+        let binding = ClientBinding::UserAgent;
+
+        assert!(issued
+            .check(&fingerprint("Agent", "192.168.0.1", None), binding)
+            .is_ok());
+        // A device key is ignored if the token was not bound to one
+        assert!(issued
+            .check(&fingerprint("Agent", "10.0.0.1", Some("device")), binding)
+            .is_ok());
+        assert!(issued
+            .check(&fingerprint("Other", "10.0.0.1", None), binding)
+            .is_err());
+    }
+
+    #[test]
+    fn test_device_key_binding() {
+        let issued = fingerprint("Agent", "10.0.0.1", Some("device"));
+        let binding = ClientBinding::UserAgent;
+
+        assert!(issued
+            .check(&fingerprint("Agent", "10.0.0.1", Some("device")), binding)
+            .is_ok());
+        assert!(issued
+            .check(&fingerprint("Agent", "10.0.0.1", Some("other")), binding)
+            .is_err());
+        assert!(issued
+            .check(&fingerprint("Agent", "10.0.0.1", None), binding)
+            .is_err());
+    }
+
+    #[test]
+    fn test_strict_binding_compares_networks() {
+        let binding = ClientBinding::Strict;
+
+        let issued = fingerprint("Agent", "10.0.0.1", None);
+        assert!(issued
+            .check(&fingerprint("Agent", "10.0.0.200", None), binding)
+            .is_ok());
+        assert!(issued
+            .check(&fingerprint("Agent", "10.0.1.1", None), binding)
+            .is_err());
+        // IPv4 clients connecting through an IPv6 socket are the same network
+        assert!(issued
+            .check(&fingerprint("Agent", "::ffff:10.0.0.2", None), binding)
+            .is_ok());
+
+        let issued = fingerprint("Agent", "2001:db8:1:2::1", None);
+        assert!(issued
+            .check(&fingerprint("Agent", "2001:db8:1:2:ffff::1", None), binding)
+            .is_ok());
+        assert!(issued
+            .check(&fingerprint("Agent", "2001:db8:1:3::1", None), binding)
+            .is_err());
+    }
+
+    #[test]
+    fn test_check_client_binding() {
+        let issued = fingerprint("Agent", "10.0.0.1", None);
+        let other = fingerprint("Other", "10.0.1.1", None);
+
+        assert!(check_client_binding(&Some(issued.clone()), &other, ClientBinding::Off).is_ok());
+        assert!(check_client_binding(&None, &other, ClientBinding::Off).is_ok());
+        assert!(check_client_binding(&None, &issued, ClientBinding::UserAgent).is_err());
+        assert!(
+            check_client_binding(&Some(issued.clone()), &issued, ClientBinding::Strict).is_ok()
+        );
+    }
+
+    #[test]
+    fn test_client_binding_from_name() {
+        assert_eq!(ClientBinding::from_name("off"), Ok(ClientBinding::Off));
+        assert_eq!(
+            ClientBinding::from_name("user-agent"),
+            Ok(ClientBinding::UserAgent)
+        );
+        assert_eq!(
+            ClientBinding::from_name("strict"),
+            Ok(ClientBinding::Strict)
+        );
+        assert!(ClientBinding::from_name("lax").is_err());
+    }
+}
@@ -1,20 +1,30 @@
+use base64::Engine;
 use hmac::{Hmac, Mac};
-use jwt::{SignWithKey, VerifyWithKey};
 use serde::{Deserialize, Serialize};
 use sha2::Sha256;
 use std::{
-    collections::BTreeMap,
     ops::Add,
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-use crate::models::UserDTO;
+use crate::{
+    models::UserDTO, persistence::token_revocation_repository::ITokenRevocationRepository,
+};
+
+use super::{fingerprint::ClientFingerprint, keyring::Keyring};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Token {
     pub sub: String,
-    pub public_key: String,
     pub exp: Duration,
+    // Issue time in whole seconds, tokens issued before a user wide revocation are rejected
+    pub iat: Duration,
+    // Unique token id, used to reference the token in the persisted session store
+    pub jti: String,
+    // Id of the device session the token belongs to. Stays the same when the token is renewed
+    pub sid: String,
+    // Client the token was issued to
+    pub cfp: Option<ClientFingerprint>,
 }
 
 pub fn get_time_since_epoch() -> Duration {
@@ -33,40 +43,135 @@ pub fn generate_token_expiration(token_duration: Duration) -> (Duration, String)
     (jwt_expires, jwt_expires.as_secs_f32().to_string())
 }
 
-pub fn create_user_token(user: UserDTO, secret_key: &[u8], expires: Duration) -> (Token, String) {
-    let key: Hmac<Sha256> = Hmac::new_from_slice(secret_key).unwrap();
-    let mut claims: BTreeMap<&str, String> = BTreeMap::new();
+/// Tokens issued a little in the future by an instance with a skewed clock are still accepted
+const CLOCK_SKEW_LEEWAY: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    alg: String,
+    typ: String,
+    kid: String,
+}
+
+/// `aud` may be a single audience or a list of them
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum Audience {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+impl Audience {
+    fn contains(&self, audience: &String) -> bool {
+        match self {
+            Audience::Single(aud) => aud == audience,
+            Audience::Multiple(auds) => auds.contains(audience),
+        }
+    }
+}
+
+/// Registered claims of RFC 7519 plus the private `sid` and `cfp` claims. Times are seconds since epoch,
+/// `iat` has microseconds, so tokens of a login right after a revocation are told apart from the revoked ones
+/// Public keys are versioned and fetched from `/users/:username/keys`, they are not part of the token
+#[derive(Debug, Serialize, Deserialize)]
+struct Claims {
+    iss: String,
+    aud: Audience,
+    sub: String,
+    iat: f64,
+    nbf: u64,
+    exp: u64,
+    jti: String,
+    sid: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cfp: Option<ClientFingerprint>,
+}
 
-    let public_key_base64 = String::from_utf8(user.public_key).expect("Could not parse public_key"); // Converting to string
+fn base64_encode(input: &[u8]) -> String {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(input)
+}
+
+fn base64_decode(input: &str) -> Result<Vec<u8>, String> {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(input)
+        .map_err(|err| format!("Invalid token encoding - {}", err))
+}
 
+/// Signs a token for the user with the signing key of the keyring. `exp` is truncated to whole seconds, `iat` to microseconds
+pub fn create_user_token(
+    user: UserDTO,
+    keyring: &Keyring,
+    expires: Duration,
+    session_id: String,
+    fingerprint: ClientFingerprint,
+) -> (Token, String) {
+    let issued_at = Duration::from_micros(get_time_since_epoch().as_micros() as u64);
     let token = Token {
-        exp: expires,
-        public_key: public_key_base64,
+        exp: Duration::from_secs(expires.as_secs()),
+        iat: issued_at,
         sub: user.username.to_string(),
+        jti: uuid::Uuid::new_v4().to_string(),
+        sid: session_id,
+        cfp: Some(fingerprint),
+    };
+
+    let signing_key = keyring.signing_key();
+    let header = Header {
+        alg: signing_key.algorithm.name().to_owned(),
+        typ: String::from("JWT"),
+        kid: signing_key.kid.clone(),
+    };
+
+    let claims = Claims {
+        iss: keyring.issuer.clone(),
+        aud: Audience::Single(keyring.audience.clone()),
+        sub: token.sub.clone(),
+        iat: issued_at.as_secs_f64(),
+        nbf: issued_at.as_secs(),
+        exp: token.exp.as_secs(),
+        jti: token.jti.clone(),
+        sid: token.sid.clone(),
+        cfp: token.cfp.clone(),
     };
 
-    claims.insert("sub", token.sub.clone());
-    claims.insert("exp", token.exp.as_nanos().to_string().clone());
-    claims.insert("public_key", token.public_key.clone());
+    let signing_input = format!(
+        "{}.{}",
+        base64_encode(&serde_json::to_vec(&header).expect("Could not serialize header")),
+        base64_encode(&serde_json::to_vec(&claims).expect("Could not serialize claims"))
+    );
+    let signature = signing_key
+        .sign(signing_input.as_bytes())
+        .expect("Could not sign token");
 
-    let token_str = claims.clone().sign_with_key(&key).unwrap();
+    let token_str = format!("{}.{}", signing_input, base64_encode(&signature));
 
     (token, token_str)
 }
 
-pub fn validate_user_token(token: String, secret_key: &[u8]) -> Result<bool, String> {
-
+/// Verifies the token, its expiration and that it was not revoked.
+/// A revocation list which cannot be checked rejects the token
+pub fn validate_user_token(
+    token: &String,
+    keyring: &Keyring,
+    revocations: &dyn ITokenRevocationRepository,
+) -> Result<Token, String> {
     // the function token_into_typed validates the token
-    let token = match token_into_typed(&token, secret_key) {
+    let token = match token_into_typed(token, keyring) {
         Err(err) => return Err(err),
         Ok(t) => t,
     };
 
-    match check_token_expiration(token) {
+    match check_token_expiration(token.clone()) {
         Err(err) => return Err(err),
         Ok(_) => {}
     }
-    return Ok(true);
+
+    match revocations.is_revoked(&token) {
+        Err(err) => return Err(err),
+        Ok(true) => return Err(String::from("Token was revoked")),
+        Ok(false) => {}
+    }
+    return Ok(token);
 }
 
 pub fn check_token_expiration(token: Token) -> Result<(), String> {
@@ -77,24 +182,59 @@ pub fn check_token_expiration(token: Token) -> Result<(), String> {
     return Ok(());
 }
 
-pub fn token_into_typed(token: &String, secret_key: &[u8]) -> Result<Token, String> {
-    let key: Hmac<Sha256> = Hmac::new_from_slice(secret_key).unwrap();
-    let claims_wrapped: Result<BTreeMap<String, String>, jwt::Error> = token.verify_with_key(&key);
-
-    let claims = match claims_wrapped {
-        Err(_) => return Err("Error validating user token".to_owned()),
+/// Verifies signature, issuer, audience and `nbf` of the token. The expiration is checked by `check_token_expiration`
+pub fn token_into_typed(token: &String, keyring: &Keyring) -> Result<Token, String> {
+    let mut parts = token.split('.');
+    let (header_part, claims_part, signature_part) =
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(header), Some(claims), Some(signature), None) => (header, claims, signature),
+            _ => return Err(String::from("Token is not a JWS")),
+        };
+
+    let header: Header = match serde_json::from_slice(&base64_decode(header_part)?) {
+        Err(err) => return Err(format!("Could not parse token header - {}", err)),
         Ok(res) => res,
     };
-    let uuid = claims.get("sub").unwrap();
-    let exp = match claims.get("exp").unwrap().to_owned().parse::<u64>() {
-        Err(err) => return Err(format!("Could not parse exp claim - {}", err)),
+
+    let key = match keyring.get_key(&header.kid) {
+        None => return Err(format!("Token was signed with unknown key {}", header.kid)),
+        Some(key) => key,
+    };
+
+    // The algorithm is bound to the key, a token must not be able to choose it
+    if header.alg != key.algorithm.name() {
+        return Err(String::from("Token algorithm does not match its key"));
+    }
+
+    let signing_input = format!("{}.{}", header_part, claims_part);
+    if !key.verify(signing_input.as_bytes(), &base64_decode(signature_part)?) {
+        return Err("Error validating user token".to_owned());
+    }
+
+    let claims: Claims = match serde_json::from_slice(&base64_decode(claims_part)?) {
+        Err(err) => return Err(format!("Could not parse token claims - {}", err)),
         Ok(res) => res,
     };
 
+    if claims.iss != keyring.issuer {
+        return Err(format!("Token was issued by {}", claims.iss));
+    }
+
+    if !claims.aud.contains(&keyring.audience) {
+        return Err(String::from("Token is not meant for this audience"));
+    }
+
+    if Duration::from_secs(claims.nbf) > get_time_since_epoch() + CLOCK_SKEW_LEEWAY {
+        return Err(String::from("Token is not valid yet"));
+    }
+
     return Ok(Token {
-        sub: uuid.to_owned(),
-        public_key: claims.get("public_key").unwrap().to_owned(),
-        exp: Duration::from_nanos(exp),
+        sub: claims.sub,
+        exp: Duration::from_secs(claims.exp),
+        iat: Duration::from_micros((claims.iat * 1_000_000.0).round() as u64),
+        jti: claims.jti,
+        sid: claims.sid,
+        cfp: claims.cfp,
     });
 }
 
@@ -1,11 +1,48 @@
-use std::time::Duration;
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-use tracing::info;
+use base64::Engine;
 
-use crate::{models::UserDTO, helper::jwt::{Token, generate_token_expiration, token_into_typed}};
+use crate::{models::UserDTO, helper::{jwt::{Token, generate_token_expiration, token_into_typed, validate_user_token}, fingerprint_test::test_fingerprint, keyring_test::test_keyring}, persistence::token_revocation_repository::ITokenRevocationRepository};
 
 use super::jwt::create_user_token;
 
+#[derive(Debug, Default)]
+pub struct TokenRevocationRepositoryMock {
+    pub revoked_tokens: Mutex<Vec<String>>,
+    pub revoked_users: Mutex<HashMap<String, (SystemTime, Option<String>)>>,
+}
+
+impl ITokenRevocationRepository for TokenRevocationRepositoryMock {
+    fn revoke_token(&self, token: &Token) -> Result<(), String> {
+        self.revoked_tokens.lock().unwrap().push(token.jti.clone());
+        Ok(())
+    }
+
+    fn revoke_user_tokens(&self, username: &String, revoked_before: SystemTime, _: SystemTime, kept_session_id: Option<&String>) -> Result<(), String> {
+        self.revoked_users.lock().unwrap().insert(username.clone(), (revoked_before, kept_session_id.cloned()));
+        Ok(())
+    }
+
+    fn is_revoked(&self, token: &Token) -> Result<bool, String> {
+        if self.revoked_tokens.lock().unwrap().contains(&token.jti) {
+            return Ok(true);
+        }
+        match self.revoked_users.lock().unwrap().get(&token.sub) {
+            None => Ok(false),
+            Some((_, Some(kept_session_id))) if kept_session_id == &token.sid => Ok(false),
+            Some((revoked_before, _)) => Ok(*revoked_before > UNIX_EPOCH + token.iat),
+        }
+    }
+
+    fn delete_expired_revocations(&self) -> Result<usize, String> {
+        Ok(0)
+    }
+}
+
 #[test]
 pub fn test_create_user_token() {
     let user = UserDTO {
@@ -13,25 +50,36 @@ pub fn test_create_user_token() {
         public_key: vec![69, 69],
         username: String::from("User1")
     };
-    let secret_key = String::from("abc");
-    let secret_key = secret_key.as_bytes();
+    let keyring = test_keyring();
 
     let (valid_for, _) = generate_token_expiration(Duration::new(15*60, 0));
-    let (token, _) = create_user_token(user, secret_key, valid_for);
+    let (token, token_str) = create_user_token(user, &keyring, valid_for, String::from("sid"), test_fingerprint());
 
     let token_expect = Token {
-        exp: valid_for,
-        public_key: String::from("EE"),
-        sub: String::from("User1")
+        exp: Duration::from_secs(valid_for.as_secs()),
+        iat: token.iat,
+        sub: String::from("User1"),
+        jti: token.jti.clone(),
+        sid: String::from("sid"),
+        cfp: Some(test_fingerprint()),
     };
 
     assert_eq!(token, token_expect);
+
+    let claims = token_str.split('.').nth(1).unwrap();
+    let claims = base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(claims).unwrap();
+    let claims: serde_json::Value = serde_json::from_slice(&claims).unwrap();
+    assert_eq!(claims["iss"], "sanctumchat-service");
+    assert_eq!(claims["aud"], "sanctumchat");
+    assert_eq!(claims["exp"], valid_for.as_secs());
+    assert_eq!(claims["iat"].as_f64(), Some(token.iat.as_secs_f64()));
+    assert_eq!(claims["nbf"], token.iat.as_secs());
+    assert_eq!(claims["jti"], token.jti);
 }
 
 #[test]
 pub fn test_token_into_typed() {
-    let secret_key = String::from("abc");
-    let secret_key = secret_key.as_bytes();
+    let keyring = test_keyring();
     let user = UserDTO {
         password: String::from(""),
         public_key: vec![69, 69],
@@ -39,9 +87,67 @@ pub fn test_token_into_typed() {
     };
     let (valid_for, _) = generate_token_expiration(Duration::new(15*60, 0));
 
-    let (token, token_str) = create_user_token(user, secret_key, valid_for);
+    let (token, token_str) = create_user_token(user, &keyring, valid_for, String::from("sid"), test_fingerprint());
 
-    let token_decoded = token_into_typed(&token_str, secret_key).expect("Error");
+    let token_decoded = token_into_typed(&token_str, &keyring).expect("Error");
 
     assert_eq!(token_decoded, token);
-}
\ No newline at end of file
+}
+
+#[test]
+pub fn test_validate_user_token_rejects_revoked_tokens() {
+    let keyring = test_keyring();
+    let revocations = TokenRevocationRepositoryMock::default();
+    let user = UserDTO {
+        password: String::from(""),
+        public_key: vec![69, 69],
+        username: String::from("User1")
+    };
+    let (valid_for, _) = generate_token_expiration(Duration::new(15*60, 0));
+
+    let (token, token_str) = create_user_token(user.clone(), &keyring, valid_for, String::from("sid"), test_fingerprint());
+    let (_, other_token_str) = create_user_token(user.clone(), &keyring, valid_for, String::from("other"), test_fingerprint());
+    assert_eq!(validate_user_token(&token_str, &keyring, &revocations), Ok(token.clone()));
+
+    // Logging out revokes only the token of that device session
+    revocations.revoke_token(&token).unwrap();
+    assert!(validate_user_token(&token_str, &keyring, &revocations).is_err());
+    assert!(validate_user_token(&other_token_str, &keyring, &revocations).is_ok());
+
+    // Logging out everywhere revokes every token issued so far, but not the ones of later logins
+    let now = SystemTime::now();
+    revocations.revoke_user_tokens(&user.username, now, now + Duration::new(15*60, 0), None).unwrap();
+    assert!(validate_user_token(&other_token_str, &keyring, &revocations).is_err());
+
+    // Usually within the same second as the revocation
+    let (_, later_login_str) = create_user_token(user.clone(), &keyring, valid_for, String::from("later"), test_fingerprint());
+    assert!(validate_user_token(&later_login_str, &keyring, &revocations).is_ok());
+
+    let later_login = Token {
+        iat: now.duration_since(UNIX_EPOCH).unwrap() + Duration::from_millis(1),
+        jti: String::from("later"),
+        ..token
+    };
+    assert_eq!(revocations.is_revoked(&later_login), Ok(false));
+}
+
+#[test]
+pub fn test_revoke_user_tokens_keeps_session() {
+    let keyring = test_keyring();
+    let revocations = TokenRevocationRepositoryMock::default();
+    let user = UserDTO {
+        password: String::from(""),
+        public_key: vec![69, 69],
+        username: String::from("User1")
+    };
+    let (valid_for, _) = generate_token_expiration(Duration::new(15*60, 0));
+
+    let (_, token_str) = create_user_token(user.clone(), &keyring, valid_for, String::from("sid"), test_fingerprint());
+    let (_, other_token_str) = create_user_token(user.clone(), &keyring, valid_for, String::from("other"), test_fingerprint());
+
+    // Changing the password logs out every other device session
+    let now = SystemTime::now();
+    revocations.revoke_user_tokens(&user.username, now, now + Duration::new(15*60, 0), Some(&String::from("sid"))).unwrap();
+    assert!(validate_user_token(&token_str, &keyring, &revocations).is_ok());
+    assert!(validate_user_token(&other_token_str, &keyring, &revocations).is_err());
+}
@@ -0,0 +1,274 @@
+use std::{collections::HashMap, fmt::Debug, fs};
+
+use hmac::{Hmac, Mac};
+use openssl::{
+    hash::MessageDigest,
+    pkey::{Id, PKey, Private, Public},
+    sign::{Signer, Verifier},
+};
+use sha2::Sha256;
+
+use crate::config::EnvConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SigningAlgorithm {
+    HS256,
+    RS256,
+    EdDSA,
+}
+
+impl SigningAlgorithm {
+    /// Name used in the `alg` header
+    pub fn name(&self) -> &'static str {
+        match self {
+            SigningAlgorithm::HS256 => "HS256",
+            SigningAlgorithm::RS256 => "RS256",
+            SigningAlgorithm::EdDSA => "EdDSA",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "HS256" => Ok(SigningAlgorithm::HS256),
+            "RS256" => Ok(SigningAlgorithm::RS256),
+            "EdDSA" => Ok(SigningAlgorithm::EdDSA),
+            _ => Err(format!("Unsupported signing algorithm {}", name)),
+        }
+    }
+}
+
+enum KeyMaterial {
+    Hmac(Vec<u8>),
+    /// Keys without a private part can only verify, which is how retired keys are kept around
+    Asymmetric {
+        private: Option<PKey<Private>>,
+        public: PKey<Public>,
+    },
+}
+
+/// A key tokens are signed or verified with, referenced by the `kid` header
+pub struct JwtKey {
+    pub kid: String,
+    pub algorithm: SigningAlgorithm,
+    material: KeyMaterial,
+}
+
+impl Debug for JwtKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JwtKey")
+            .field("kid", &self.kid)
+            .field("algorithm", &self.algorithm)
+            .finish()
+    }
+}
+
+impl JwtKey {
+    pub fn hmac(kid: &str, secret: &[u8]) -> Self {
+        return Self {
+            kid: kid.to_owned(),
+            algorithm: SigningAlgorithm::HS256,
+            material: KeyMaterial::Hmac(secret.to_vec()),
+        };
+    }
+
+    /// Reads a PEM encoded private key, or a public key for a key that can only verify
+    pub fn from_pem(kid: &str, algorithm: SigningAlgorithm, pem: &[u8]) -> Result<Self, String> {
+        let (private, public) = match PKey::private_key_from_pem(pem) {
+            Ok(private) => {
+                let public = private
+                    .public_key_to_der()
+                    .and_then(|der| PKey::public_key_from_der(&der))
+                    .map_err(|err| format!("Could not derive public key of {}: {}", kid, err))?;
+                (Some(private), public)
+            }
+            Err(_) => match PKey::public_key_from_pem(pem) {
+                Err(err) => return Err(format!("Could not read key {}: {}", kid, err)),
+                Ok(public) => (None, public),
+            },
+        };
+
+        let expected_type = match algorithm {
+            SigningAlgorithm::HS256 => {
+                return Err(format!("Key {} is a PEM key, HS256 needs a secret", kid))
+            }
+            SigningAlgorithm::RS256 => Id::RSA,
+            SigningAlgorithm::EdDSA => Id::ED25519,
+        };
+        if public.id() != expected_type {
+            return Err(format!(
+                "Key {} does not fit the algorithm {}",
+                kid,
+                algorithm.name()
+            ));
+        }
+
+        Ok(Self {
+            kid: kid.to_owned(),
+            algorithm,
+            material: KeyMaterial::Asymmetric { private, public },
+        })
+    }
+
+    pub fn can_sign(&self) -> bool {
+        match &self.material {
+            KeyMaterial::Hmac(_) => true,
+            KeyMaterial::Asymmetric { private, .. } => private.is_some(),
+        }
+    }
+
+    pub fn sign(&self, input: &[u8]) -> Result<Vec<u8>, String> {
+        match &self.material {
+            KeyMaterial::Hmac(secret) => {
+                let mut mac =
+                    Hmac::<Sha256>::new_from_slice(secret).expect("HMAC can take key of any size");
+                mac.update(input);
+                Ok(mac.finalize().into_bytes().to_vec())
+            }
+            KeyMaterial::Asymmetric { private: None, .. } => {
+                Err(format!("Key {} can only verify", self.kid))
+            }
+            KeyMaterial::Asymmetric {
+                private: Some(private),
+                ..
+            } => {
+                let signer = match self.algorithm {
+                    SigningAlgorithm::EdDSA => Signer::new_without_digest(private),
+                    _ => Signer::new(MessageDigest::sha256(), private),
+                };
+                signer
+                    .and_then(|mut signer| signer.sign_oneshot_to_vec(input))
+                    .map_err(|err| format!("Could not sign with key {}: {}", self.kid, err))
+            }
+        }
+    }
+
+    pub fn verify(&self, input: &[u8], signature: &[u8]) -> bool {
+        match &self.material {
+            KeyMaterial::Hmac(secret) => {
+                let mut mac =
+                    Hmac::<Sha256>::new_from_slice(secret).expect("HMAC can take key of any size");
+                mac.update(input);
+                mac.verify_slice(signature).is_ok()
+            }
+            KeyMaterial::Asymmetric { public, .. } => {
+                let verifier = match self.algorithm {
+                    SigningAlgorithm::EdDSA => Verifier::new_without_digest(public),
+                    _ => Verifier::new(MessageDigest::sha256(), public),
+                };
+                verifier
+                    .and_then(|mut verifier| verifier.verify_oneshot(signature, input))
+                    .unwrap_or(false)
+            }
+        }
+    }
+}
+
+/// All keys tokens are accepted from, plus the one new tokens are signed with.
+/// Rotating means adding a new key, making it the signing key and dropping the old one once its tokens expired
+#[derive(Debug)]
+pub struct Keyring {
+    pub issuer: String,
+    pub audience: String,
+    keys: HashMap<String, JwtKey>,
+    signing_kid: String,
+    /// Root of the secrets of the service which are not token keys, see `derive_secret`.
+    /// Independent of the token keys, so rotating them keeps the derived secrets
+    secret: Vec<u8>,
+}
+
+impl Keyring {
+    pub fn new(
+        issuer: &str,
+        audience: &str,
+        keys: Vec<JwtKey>,
+        signing_kid: &str,
+        secret: &[u8],
+    ) -> Result<Self, String> {
+        let keys: HashMap<String, JwtKey> =
+            keys.into_iter().map(|key| (key.kid.clone(), key)).collect();
+
+        match keys.get(signing_kid).map(|key| key.can_sign()) {
+            None => return Err(format!("Signing key {} is not in the keyring", signing_kid)),
+            Some(false) => return Err(format!("Signing key {} has no private key", signing_kid)),
+            Some(true) => {}
+        };
+        if secret.is_empty() {
+            return Err(String::from("The server secret must not be empty"));
+        }
+
+        Ok(Self {
+            issuer: issuer.to_owned(),
+            audience: audience.to_owned(),
+            keys,
+            signing_kid: signing_kid.to_owned(),
+            secret: secret.to_vec(),
+        })
+    }
+
+    /// Secret for a single purpose, e.g. `client-fingerprint`, so no two features share a key
+    pub fn derive_secret(&self, purpose: &str) -> Vec<u8> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC can take key of any size");
+        mac.update(purpose.as_bytes());
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    /// Parses `JWT_KEYS`, a comma separated list of `kid:algorithm:key`.
+    /// The key is the secret for HS256 and the path to a PEM file for RS256 and EdDSA.
+    /// `JWT_SIGNING_KEY_ID` selects the signing key, by default it is the first one
+    pub fn from_config(env: &EnvConfig) -> Result<Self, String> {
+        let mut keys = vec![];
+        for entry in env
+            .JWT_KEYS
+            .split(',')
+            .map(str::trim)
+            .filter(|e| !e.is_empty())
+        {
+            let mut parts = entry.splitn(3, ':');
+            let (kid, algorithm, value) = match (parts.next(), parts.next(), parts.next()) {
+                (Some(kid), Some(algorithm), Some(value)) => (kid, algorithm, value),
+                _ => {
+                    return Err(format!(
+                        "Invalid JWT_KEYS entry {}, expected kid:algorithm:key",
+                        entry
+                    ))
+                }
+            };
+
+            let algorithm = SigningAlgorithm::from_name(algorithm)?;
+            let key = match algorithm {
+                SigningAlgorithm::HS256 => JwtKey::hmac(kid, value.as_bytes()),
+                _ => {
+                    let pem = fs::read(value)
+                        .map_err(|err| format!("Could not read key file {}: {}", value, err))?;
+                    JwtKey::from_pem(kid, algorithm, &pem)?
+                }
+            };
+            keys.push(key);
+        }
+
+        let signing_kid = match (&env.JWT_SIGNING_KEY_ID, keys.first()) {
+            (Some(kid), _) => kid.clone(),
+            (None, Some(key)) => key.kid.clone(),
+            (None, None) => return Err(String::from("JWT_KEYS does not contain any key")),
+        };
+
+        Self::new(
+            &env.JWT_ISSUER,
+            &env.JWT_AUDIENCE,
+            keys,
+            &signing_kid,
+            env.SERVER_SECRET.as_bytes(),
+        )
+    }
+
+    pub fn signing_key(&self) -> &JwtKey {
+        self.keys
+            .get(&self.signing_kid)
+            .expect("Signing key is checked on creation")
+    }
+
+    pub fn get_key(&self, kid: &str) -> Option<&JwtKey> {
+        self.keys.get(kid)
+    }
+}
@@ -0,0 +1,225 @@
+use super::keyring::{JwtKey, Keyring};
+
+/// HS256 keyring with the issuer and audience the service uses by default
+pub fn test_keyring() -> Keyring {
+    Keyring::new(
+        "sanctumchat-service",
+        "sanctumchat",
+        vec![JwtKey::hmac("test", b"abc")],
+        "test",
+        b"server",
+    )
+    .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use base64::Engine;
+    use openssl::{pkey::PKey, rsa::Rsa};
+
+    use crate::{
+        helper::{
+            fingerprint_test::test_fingerprint,
+            jwt::{create_user_token, generate_token_expiration, token_into_typed},
+            keyring::{JwtKey, Keyring, SigningAlgorithm},
+        },
+        models::UserDTO,
+    };
+
+    fn user() -> UserDTO {
+        UserDTO {
+            password: String::from(""),
+            public_key: vec![69, 69],
+            username: String::from("User1"),
+        }
+    }
+
+    fn sign(keyring: &Keyring) -> String {
+        let (valid_for, _) = generate_token_expiration(Duration::new(15 * 60, 0));
+        create_user_token(
+            user(),
+            keyring,
+            valid_for,
+            String::from("sid"),
+            test_fingerprint(),
+        )
+        .1
+    }
+
+    fn header(token: &String) -> serde_json::Value {
+        let header = token.split('.').next().unwrap();
+        let header = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(header)
+            .unwrap();
+        serde_json::from_slice(&header).unwrap()
+    }
+
+    fn rsa_key(kid: &str) -> JwtKey {
+        let pem = Rsa::generate(2048).unwrap().private_key_to_pem().unwrap();
+        JwtKey::from_pem(kid, SigningAlgorithm::RS256, &pem).unwrap()
+    }
+
+    fn ed25519_pem() -> Vec<u8> {
+        PKey::generate_ed25519()
+            .unwrap()
+            .private_key_to_pem_pkcs8()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_asymmetric_keys_sign_and_verify() {
+        let keyring = Keyring::new("iss", "aud", vec![rsa_key("rsa")], "rsa", b"server").unwrap();
+        let token = sign(&keyring);
+        assert_eq!(header(&token)["alg"], "RS256");
+        assert_eq!(header(&token)["kid"], "rsa");
+        assert_eq!(token_into_typed(&token, &keyring).unwrap().sub, "User1");
+
+        let ed25519 = JwtKey::from_pem("ed", SigningAlgorithm::EdDSA, &ed25519_pem()).unwrap();
+        let keyring = Keyring::new("iss", "aud", vec![ed25519], "ed", b"server").unwrap();
+        let token = sign(&keyring);
+        assert_eq!(header(&token)["alg"], "EdDSA");
+        assert_eq!(token_into_typed(&token, &keyring).unwrap().sid, "sid");
+
+        // Keys have to fit their algorithm
+        assert!(JwtKey::from_pem("ed", SigningAlgorithm::RS256, &ed25519_pem()).is_err());
+    }
+
+    #[test]
+    fn test_public_keys_only_verify() {
+        let private_pem = ed25519_pem();
+        let public_pem = PKey::private_key_from_pem(&private_pem)
+            .unwrap()
+            .public_key_to_pem()
+            .unwrap();
+
+        let signing = JwtKey::from_pem("ed", SigningAlgorithm::EdDSA, &private_pem).unwrap();
+        let token = sign(&Keyring::new("iss", "aud", vec![signing], "ed", b"server").unwrap());
+
+        let public = JwtKey::from_pem("ed", SigningAlgorithm::EdDSA, &public_pem).unwrap();
+        assert!(!public.can_sign());
+        let other = JwtKey::hmac("hmac", b"abc");
+        let keyring = Keyring::new("iss", "aud", vec![public, other], "hmac", b"server").unwrap();
+        assert!(token_into_typed(&token, &keyring).is_ok());
+
+        let public = JwtKey::from_pem("ed", SigningAlgorithm::EdDSA, &public_pem).unwrap();
+        assert!(Keyring::new("iss", "aud", vec![public], "ed", b"server").is_err());
+    }
+
+    #[test]
+    fn test_rotation_keeps_old_tokens_valid() {
+        let old_keyring = Keyring::new(
+            "iss",
+            "aud",
+            vec![JwtKey::hmac("2024-01", b"old")],
+            "2024-01",
+            b"server",
+        )
+        .unwrap();
+        let old_token = sign(&old_keyring);
+
+        let rotated = Keyring::new(
+            "iss",
+            "aud",
+            vec![
+                JwtKey::hmac("2024-01", b"old"),
+                JwtKey::hmac("2024-02", b"new"),
+            ],
+            "2024-02",
+            b"server",
+        )
+        .unwrap();
+        let new_token = sign(&rotated);
+        assert_eq!(header(&new_token)["kid"], "2024-02");
+
+        assert!(token_into_typed(&old_token, &rotated).is_ok());
+        assert!(token_into_typed(&new_token, &rotated).is_ok());
+
+        // Once the old key is dropped its tokens are rejected
+        let retired = Keyring::new(
+            "iss",
+            "aud",
+            vec![JwtKey::hmac("2024-02", b"new")],
+            "2024-02",
+            b"server",
+        )
+        .unwrap();
+        assert!(token_into_typed(&old_token, &retired).is_err());
+        assert!(token_into_typed(&new_token, &retired).is_ok());
+    }
+
+    #[test]
+    fn test_tokens_are_bound_to_issuer_audience_and_algorithm() {
+        let keyring = Keyring::new(
+            "iss",
+            "aud",
+            vec![JwtKey::hmac("k", b"abc")],
+            "k",
+            b"server",
+        )
+        .unwrap();
+        let token = sign(&keyring);
+
+        let other_issuer = Keyring::new(
+            "other",
+            "aud",
+            vec![JwtKey::hmac("k", b"abc")],
+            "k",
+            b"server",
+        )
+        .unwrap();
+        assert!(token_into_typed(&token, &other_issuer).is_err());
+
+        let other_audience = Keyring::new(
+            "iss",
+            "other",
+            vec![JwtKey::hmac("k", b"abc")],
+            "k",
+            b"server",
+        )
+        .unwrap();
+        assert!(token_into_typed(&token, &other_audience).is_err());
+
+        // A token claiming another algorithm for the kid is rejected before verifying
+        let rsa_keyring = Keyring::new("iss", "aud", vec![rsa_key("k")], "k", b"server").unwrap();
+        assert!(token_into_typed(&token, &rsa_keyring).is_err());
+
+        let mut tampered = token.clone();
+        tampered.push('A');
+        assert!(token_into_typed(&tampered, &keyring).is_err());
+    }
+
+    #[test]
+    fn test_derive_secret() {
+        let keyring = Keyring::new(
+            "iss",
+            "aud",
+            vec![JwtKey::hmac("k", b"abc")],
+            "k",
+            b"server",
+        )
+        .unwrap();
+        assert_eq!(keyring.derive_secret("a"), keyring.derive_secret("a"));
+        assert_ne!(keyring.derive_secret("a"), keyring.derive_secret("b"));
+
+        // The derived secrets do not depend on the signing key, so they survive rotating it
+        let rotated = Keyring::new(
+            "iss",
+            "aud",
+            vec![JwtKey::hmac("k2", b"def")],
+            "k2",
+            b"server",
+        )
+        .unwrap();
+        assert_eq!(keyring.derive_secret("a"), rotated.derive_secret("a"));
+        let rsa_keyring =
+            Keyring::new("iss", "aud", vec![rsa_key("rsa")], "rsa", b"server").unwrap();
+        assert_eq!(keyring.derive_secret("a"), rsa_keyring.derive_secret("a"));
+
+        let other_secret =
+            Keyring::new("iss", "aud", vec![JwtKey::hmac("k", b"abc")], "k", b"other").unwrap();
+        assert_ne!(keyring.derive_secret("a"), other_secret.derive_secret("a"));
+        assert!(Keyring::new("iss", "aud", vec![JwtKey::hmac("k", b"abc")], "k", b"").is_err());
+    }
+}
@@ -0,0 +1,68 @@
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use openssl::{hash::MessageDigest, pkey::PKey, rsa::Rsa, sign::Signer};
+
+    use crate::helper::keys::{
+        generate_rsa_key_pair, issue_key_challenge, verify_key_challenge,
+        verify_proof_of_possession, KEY_CHALLENGE_LIFETIME,
+    };
+
+    const SECRET: &[u8] = b"secret";
+
+    fn at(seconds: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    fn sign(private_key: &Vec<u8>, data: &String) -> Vec<u8> {
+        let pkey = PKey::from_rsa(Rsa::private_key_from_pem(private_key).unwrap()).unwrap();
+        let mut signer = Signer::new(MessageDigest::sha256(), &pkey).unwrap();
+        signer.sign_oneshot_to_vec(data.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_verify_key_challenge() {
+        let username = String::from("user");
+        let now = at(1700000000);
+        let challenge = issue_key_challenge(SECRET, &username, now).unwrap();
+
+        assert_eq!(verify_key_challenge(SECRET, &username, &challenge, now), Ok(()));
+        assert!(verify_key_challenge(SECRET, &String::from("other"), &challenge, now).is_err());
+        assert!(verify_key_challenge(b"other", &username, &challenge, now).is_err());
+        assert!(verify_key_challenge(SECRET, &username, &challenge, now + KEY_CHALLENGE_LIFETIME).is_err());
+    }
+
+    #[test]
+    fn test_verify_key_challenge_rejects_tampered_challenges() {
+        let username = String::from("user");
+        let now = at(1700000000);
+        let challenge = issue_key_challenge(SECRET, &username, now).unwrap();
+
+        // Pushing the expiry out invalidates the MAC
+        let (expires_at, rest) = challenge.split_once('.').unwrap();
+        let extended = format!("{}.{}", expires_at.parse::<u64>().unwrap() + 3600, rest);
+        assert!(verify_key_challenge(SECRET, &username, &extended, now).is_err());
+
+        assert!(verify_key_challenge(SECRET, &username, &String::from("abc"), now).is_err());
+        assert!(verify_key_challenge(SECRET, &username, &String::from("1.2.3"), now).is_err());
+    }
+
+    #[test]
+    fn test_verify_proof_of_possession() {
+        let (private_key, public_key) = generate_rsa_key_pair().unwrap();
+        let (other_private_key, _) = generate_rsa_key_pair().unwrap();
+        let challenge = issue_key_challenge(SECRET, &String::from("user"), SystemTime::now()).unwrap();
+
+        let signature = sign(&private_key, &challenge);
+        assert_eq!(verify_proof_of_possession(&public_key, &challenge, &signature), Ok(()));
+
+        let other_signature = sign(&other_private_key, &challenge);
+        assert!(verify_proof_of_possession(&public_key, &challenge, &other_signature).is_err());
+
+        let other_challenge = issue_key_challenge(SECRET, &String::from("user"), SystemTime::now()).unwrap();
+        assert!(verify_proof_of_possession(&public_key, &other_challenge, &signature).is_err());
+
+        assert!(verify_proof_of_possession(&b"no key".to_vec(), &challenge, &signature).is_err());
+    }
+}
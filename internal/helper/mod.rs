@@ -1,8 +1,32 @@
+pub mod backplane;
+mod backplane_test;
+pub mod cookie;
+mod cookie_test;
 pub mod errors;
+pub mod fingerprint;
+#[cfg(test)]
+pub mod fingerprint_test;
 pub mod jwt;
+#[cfg(test)]
 pub mod jwt_test;
+pub mod keyring;
+#[cfg(test)]
+pub mod keyring_test;
 pub mod keys;
+mod keys_test;
 pub mod pagination;
+mod pagination_test;
+pub mod password;
+mod password_test;
+pub mod persistent_session;
+pub mod rate_limit;
+#[cfg(test)]
+pub mod rate_limit_test;
 pub mod session;
 mod session_test;
 pub mod sql;
+pub mod totp;
+mod totp_test;
+pub mod websocket_ticket;
+#[cfg(test)]
+pub mod websocket_ticket_test;
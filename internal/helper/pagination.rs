@@ -1,21 +1,95 @@
+use std::{
+    fmt::Display,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use uuid::Uuid;
+
+/// Position of a message in a conversation. Messages are ordered by (`sent_at`, `id`), the id breaks ties of equal timestamps.
+/// Serialized as `<microseconds since epoch>_<id>`, which is what clients pass back as `before`/`after`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MessageCursor {
+    pub sent_at: SystemTime,
+    pub id: Uuid,
+}
+
+impl MessageCursor {
+    pub fn new(sent_at: SystemTime, id: Uuid) -> Self {
+        return Self { sent_at, id };
+    }
+}
+
+impl Display for MessageCursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let micros = self
+            .sent_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros();
+        write!(f, "{}_{}", micros, self.id)
+    }
+}
+
+impl FromStr for MessageCursor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("{} is not a valid cursor", s);
+
+        let (micros, id) = s.split_once('_').ok_or_else(invalid)?;
+        let micros = micros.parse::<u64>().map_err(|_| invalid())?;
+        let id = Uuid::from_str(id).map_err(|_| invalid())?;
+
+        Ok(Self {
+            sent_at: UNIX_EPOCH + Duration::from_micros(micros),
+            id,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PageCursor {
+    /// The newest messages
+    Latest,
+    /// Messages older than the cursor
+    Before(MessageCursor),
+    /// Messages newer than the cursor
+    After(MessageCursor),
+    /// The given message with the messages surrounding it, used to jump to a message
+    Around(Uuid),
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Pagination {
     pub size: u8,
-    pub index: u8,
+    pub cursor: PageCursor,
 }
 
 impl Pagination {
-    pub fn new(size: Option<u8>, index: Option<u8>) -> Self {
-        let mut s: u8 = 10;
-        let mut i: u8 = 0;
-
-        if let Some(size) = size {
-            s = size
+    pub fn new(
+        size: Option<u8>,
+        before: Option<String>,
+        after: Option<String>,
+        around: Option<Uuid>,
+    ) -> Result<Self, String> {
+        let size = size.unwrap_or(10);
+        if size == 0 {
+            return Err(String::from("size has to be greater than 0"));
         }
 
-        if let Some(index) = index {
-            i = index
-        }
+        let cursor = match (before, after, around) {
+            (None, None, None) => PageCursor::Latest,
+            (Some(before), None, None) => PageCursor::Before(MessageCursor::from_str(&before)?),
+            (None, Some(after), None) => PageCursor::After(MessageCursor::from_str(&after)?),
+            (None, None, Some(around)) => PageCursor::Around(around),
+            _ => {
+                return Err(String::from(
+                    "Only one of before, after and around can be specified",
+                ))
+            }
+        };
 
-        return Self { size: s, index: i }
+        return Ok(Self { size, cursor });
     }
-}
\ No newline at end of file
+}
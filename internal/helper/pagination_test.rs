@@ -0,0 +1,49 @@
+#[cfg(test)]
+mod tests {
+    use std::{
+        str::FromStr,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use uuid::Uuid;
+
+    use crate::helper::pagination::{MessageCursor, PageCursor, Pagination};
+
+    #[test]
+    fn test_message_cursor_roundtrip() {
+        let id = Uuid::from_str("18cb8735-b226-49d5-a726-e6937bd6e841").unwrap();
+        let cursor = MessageCursor::new(UNIX_EPOCH + Duration::from_micros(1709980000123456), id);
+
+        let serialized = cursor.to_string();
+        assert_eq!(serialized, "1709980000123456_18cb8735-b226-49d5-a726-e6937bd6e841");
+        assert_eq!(MessageCursor::from_str(&serialized).unwrap(), cursor);
+
+        assert!(MessageCursor::from_str("1709980000123456").is_err());
+        assert!(MessageCursor::from_str("abc_18cb8735-b226-49d5-a726-e6937bd6e841").is_err());
+        assert!(MessageCursor::from_str("1709980000123456_abc").is_err());
+    }
+
+    #[test]
+    fn test_pagination_accepts_one_cursor() {
+        let id = Uuid::new_v4();
+        let cursor = MessageCursor::new(UNIX_EPOCH + Duration::from_secs(1), id).to_string();
+
+        let pagination = Pagination::new(None, None, None, None).unwrap();
+        assert_eq!(pagination.size, 10);
+        assert_eq!(pagination.cursor, PageCursor::Latest);
+
+        let pagination = Pagination::new(Some(50), Some(cursor.clone()), None, None).unwrap();
+        assert_eq!(pagination.size, 50);
+        assert!(matches!(pagination.cursor, PageCursor::Before(c) if c.id == id));
+
+        let pagination = Pagination::new(None, None, Some(cursor.clone()), None).unwrap();
+        assert!(matches!(pagination.cursor, PageCursor::After(c) if c.id == id));
+
+        let pagination = Pagination::new(None, None, None, Some(id)).unwrap();
+        assert_eq!(pagination.cursor, PageCursor::Around(id));
+
+        assert!(Pagination::new(Some(0), None, None, None).is_err());
+        assert!(Pagination::new(None, Some(cursor.clone()), Some(cursor), None).is_err());
+        assert!(Pagination::new(None, Some(String::from("invalid")), None, None).is_err());
+    }
+}
@@ -0,0 +1,121 @@
+use std::sync::OnceLock;
+
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Algorithm, Argon2, Params, Version,
+};
+
+use super::jwt::hash_string;
+
+#[derive(Debug, PartialEq)]
+pub enum PasswordVerification {
+    Invalid,
+    Valid,
+    /// The password matched a legacy hash or one with outdated parameters and should be hashed again
+    ValidNeedsRehash,
+}
+
+fn argon2() -> Argon2<'static> {
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, Params::default())
+}
+
+/// Hashes the password with Argon2id and a random salt.
+/// The result is a PHC string, so algorithm, parameters and salt are stored alongside the hash
+pub fn hash_password(password: &str) -> Result<String, String> {
+    let salt = SaltString::generate(&mut OsRng);
+    match argon2().hash_password(password.as_bytes(), &salt) {
+        Err(err) => Err(format!("Could not hash password: {}", err)),
+        Ok(hash) => Ok(hash.to_string()),
+    }
+}
+
+/// Checks the password against a stored hash.
+/// Hashes that are not PHC strings are legacy HMAC-SHA256 hashes keyed with `legacy_key`
+pub fn verify_password(
+    password: &str,
+    stored_hash: &str,
+    legacy_key: &[u8],
+) -> PasswordVerification {
+    let parsed_hash = match PasswordHash::new(stored_hash) {
+        Err(_) => return verify_legacy_password(password, stored_hash, legacy_key),
+        Ok(res) => res,
+    };
+
+    if !verify_argon2(password, &parsed_hash) {
+        return PasswordVerification::Invalid;
+    }
+
+    let current = Params::default();
+    let is_current = parsed_hash.algorithm == Algorithm::Argon2id.ident()
+        && parsed_hash.version == Some(Version::V0x13.into())
+        && match Params::try_from(&parsed_hash) {
+            Err(_) => false,
+            Ok(params) => {
+                params.m_cost() == current.m_cost()
+                    && params.t_cost() == current.t_cost()
+                    && params.p_cost() == current.p_cost()
+            }
+        };
+
+    match is_current {
+        true => PasswordVerification::Valid,
+        false => PasswordVerification::ValidNeedsRehash,
+    }
+}
+
+/// Argon2id hash of a random password, verified against whenever there is no real hash to verify,
+/// so that a login takes the same time whether the user exists or not
+pub fn dummy_password_hash() -> &'static str {
+    static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+    DUMMY_HASH.get_or_init(|| {
+        hash_password(&uuid::Uuid::new_v4().to_string()).expect("Could not hash dummy password")
+    })
+}
+
+/// Verifies against an unknown password, taking as long as verifying a real hash. Always fails
+pub fn verify_dummy_password(password: &str) -> PasswordVerification {
+    let dummy_hash = PasswordHash::new(dummy_password_hash()).expect("Dummy hash is a PHC string");
+    verify_argon2(password, &dummy_hash);
+    PasswordVerification::Invalid
+}
+
+#[cfg(test)]
+thread_local! {
+    static ARGON2_VERIFICATIONS: std::cell::Cell<usize> = std::cell::Cell::new(0);
+}
+
+/// Argon2id verifications done on the current thread, lets tests check that code paths cost the same
+#[cfg(test)]
+pub fn argon2_verifications() -> usize {
+    ARGON2_VERIFICATIONS.with(|count| count.get())
+}
+
+/// Every Argon2id verification goes through here, it is what makes up the time a login takes
+fn verify_argon2(password: &str, hash: &PasswordHash) -> bool {
+    #[cfg(test)]
+    ARGON2_VERIFICATIONS.with(|count| count.set(count.get() + 1));
+    argon2().verify_password(password.as_bytes(), hash).is_ok()
+}
+
+fn verify_legacy_password(
+    password: &str,
+    stored_hash: &str,
+    legacy_key: &[u8],
+) -> PasswordVerification {
+    // HMAC is much cheaper than Argon2id, users which were not migrated yet would stand out otherwise
+    verify_dummy_password(password);
+
+    let legacy_hash = hash_string(password, legacy_key);
+    match constant_time_eq(legacy_hash.as_bytes(), stored_hash.as_bytes()) {
+        false => PasswordVerification::Invalid,
+        true => PasswordVerification::ValidNeedsRehash,
+    }
+}
+
+/// Compares without returning early, so the time taken does not tell how much of the input matched
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b.iter()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -0,0 +1,73 @@
+#[cfg(test)]
+mod tests {
+    use argon2::{
+        password_hash::{rand_core::OsRng, PasswordHasher, SaltString},
+        Algorithm, Argon2, Params, Version,
+    };
+
+    use crate::helper::{
+        jwt::hash_string,
+        password::{hash_password, verify_password, PasswordVerification},
+    };
+
+    const LEGACY_KEY: &[u8] = b"abc";
+
+    #[test]
+    fn test_hash_and_verify_password() {
+        let hash = hash_password("Password1").unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+
+        // Every hash gets its own salt
+        assert_ne!(hash, hash_password("Password1").unwrap());
+
+        assert_eq!(
+            verify_password("Password1", &hash, LEGACY_KEY),
+            PasswordVerification::Valid
+        );
+        assert_eq!(
+            verify_password("Password2", &hash, LEGACY_KEY),
+            PasswordVerification::Invalid
+        );
+    }
+
+    #[test]
+    fn test_legacy_hashes_need_rehash() {
+        let legacy_hash = hash_string("Password1", LEGACY_KEY);
+
+        assert_eq!(
+            verify_password("Password1", &legacy_hash, LEGACY_KEY),
+            PasswordVerification::ValidNeedsRehash
+        );
+        assert_eq!(
+            verify_password("Password2", &legacy_hash, LEGACY_KEY),
+            PasswordVerification::Invalid
+        );
+        assert_eq!(
+            verify_password("Password1", &legacy_hash, b"other key"),
+            PasswordVerification::Invalid
+        );
+        assert_eq!(
+            verify_password("Password1", "not a hash", LEGACY_KEY),
+            PasswordVerification::Invalid
+        );
+    }
+
+    #[test]
+    fn test_outdated_parameters_need_rehash() {
+        let weak = Argon2::new(
+            Algorithm::Argon2id,
+            Version::V0x13,
+            Params::new(8 * 1024, 1, 1, None).unwrap(),
+        );
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = weak
+            .hash_password(b"Password1", &salt)
+            .unwrap()
+            .to_string();
+
+        assert_eq!(
+            verify_password("Password1", &hash, LEGACY_KEY),
+            PasswordVerification::ValidNeedsRehash
+        );
+    }
+}
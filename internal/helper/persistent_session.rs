@@ -0,0 +1,236 @@
+use super::{
+    backplane::IBackplane,
+    jwt::Token,
+    session::{ClientInfo, ISession, ISessionManager, SessionManager, UserSessions},
+};
+use crate::{
+    entities::friends::{repository::IFriendRepository, service::FriendDomain},
+    models::{SessionRecord, UserDTO},
+    persistence::session_repository::ISessionRepository,
+};
+use axum::async_trait;
+use futures::lock::Mutex;
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use uuid::Uuid;
+
+/// Session manager persisting every session record in postgres.
+/// Only the live socket handles are kept in memory, everything else can be rehydrated from the database.
+/// This allows restarting the service, or running multiple instances, without logging out every user.
+#[derive(Debug)]
+pub struct PersistentSessionManager<S: ISession<F>, F: IFriendRepository, R: ISessionRepository> {
+    live_sessions: SessionManager<S, F>,
+    session_repository: R,
+}
+
+impl<S: ISession<F>, F: IFriendRepository, R: ISessionRepository> PersistentSessionManager<S, F, R> {
+    pub fn new(friend_domain: FriendDomain<F>, session_repository: R) -> Self {
+        return Self {
+            live_sessions: SessionManager::new(friend_domain),
+            session_repository,
+        };
+    }
+
+    pub fn with_backplane(
+        friend_domain: FriendDomain<F>,
+        session_repository: R,
+        backplane: Arc<dyn IBackplane>,
+    ) -> Self {
+        return Self {
+            live_sessions: SessionManager::with_backplane(friend_domain, backplane),
+            session_repository,
+        };
+    }
+
+    /// Loads all sessions which are not expired yet into memory. Should be called once on startup
+    pub async fn rehydrate(&self) -> Result<usize, String> {
+        let records = self.session_repository.get_active_sessions()?;
+        let count = records.len();
+        for (record, user) in records {
+            self.live_sessions
+                .insert_into_current_user_connections(record_to_session::<S, F>(record, user))
+                .await;
+        }
+        tracing::debug!(target: "application", "[rehydrate] Rehydrated {} sessions", count);
+        Ok(count)
+    }
+}
+
+fn session_to_record<S: ISession<F>, F: IFriendRepository>(session: &S) -> SessionRecord {
+    let token = session.get_token();
+    let client_info = session.get_client_info();
+    SessionRecord {
+        id: Uuid::new_v4(),
+        username: session.get_user().username,
+        token_id: token.jti,
+        expires_at: UNIX_EPOCH + token.exp,
+        user_agent: client_info.user_agent,
+        ip_address: client_info.ip,
+        created_at: SystemTime::now(),
+        session_id: token.sid,
+    }
+}
+
+fn record_to_session<S: ISession<F>, F: IFriendRepository>(record: SessionRecord, user: UserDTO) -> S {
+    let token = Token {
+        sub: user.username.clone(),
+        exp: record
+            .expires_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default(),
+        // The record is saved when the token is issued
+        iat: record
+            .created_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default(),
+        jti: record.token_id,
+        sid: record.session_id,
+        cfp: None,
+    };
+    let mut session = <S as ISession<F>>::new(user, token);
+    session.set_client_info(ClientInfo {
+        user_agent: record.user_agent,
+        ip: record.ip_address,
+    });
+    session
+}
+
+#[async_trait]
+impl<S: ISession<F>, F: IFriendRepository, R: ISessionRepository> ISessionManager<S, F>
+    for PersistentSessionManager<S, F, R>
+{
+    fn get_current_user_connections(&self) -> &Arc<Mutex<HashMap<String, UserSessions<S>>>> {
+        return self.live_sessions.get_current_user_connections();
+    }
+
+    fn get_backplane(&self) -> Arc<dyn IBackplane> {
+        return self.live_sessions.get_backplane();
+    }
+
+    /// Falls back to the persisted session if the user is not connected to this instance
+    async fn get_session(&self, username: &String, session_id: &String) -> Option<Arc<Mutex<S>>> {
+        if let Some(session) = self.live_sessions.get_session(username, session_id).await {
+            return Some(session);
+        }
+
+        let (record, user) = match self.session_repository.get_session(session_id) {
+            Ok(Some(res)) => res,
+            Ok(None) => return None,
+            Err(err) => {
+                tracing::error!(target: "application", "[get_session] {}", err);
+                return None;
+            }
+        };
+
+        if &record.username != username {
+            return None;
+        }
+
+        self.live_sessions
+            .insert_into_current_user_connections(record_to_session::<S, F>(record, user))
+            .await;
+        self.live_sessions.get_session(username, session_id).await
+    }
+
+    /// Also considers devices connected to other instances
+    async fn is_user_connected(&self, username: &String) -> bool {
+        if self.live_sessions.is_user_connected(username).await {
+            return true;
+        }
+        match self.session_repository.get_user_sessions(username) {
+            Ok(sessions) => sessions.len() > 0,
+            Err(err) => {
+                tracing::error!(target: "application", "[is_user_connected] {}", err);
+                false
+            }
+        }
+    }
+
+    async fn insert_into_current_user_connections(&self, session: S) {
+        if let Err(err) = self
+            .session_repository
+            .save_session(&session_to_record::<S, F>(&session))
+        {
+            tracing::error!(target: "application", "[insert_into_current_user_connections] {}", err);
+        }
+        self.live_sessions
+            .insert_into_current_user_connections(session)
+            .await;
+    }
+
+    async fn remove_from_current_user_connections(
+        &self,
+        username: &String,
+        session_id: &String,
+    ) -> Result<Arc<Mutex<S>>, String> {
+        if let Err(err) = self.session_repository.delete_session(session_id) {
+            tracing::error!(target: "application", "[remove_from_current_user_connections] {}", err);
+        }
+        self.live_sessions
+            .remove_from_current_user_connections(username, session_id)
+            .await
+    }
+
+    async fn remove_expired_current_user_connections_sessions(&self) {
+        self.live_sessions
+            .remove_expired_current_user_connections_sessions()
+            .await;
+        match self.session_repository.delete_expired_sessions() {
+            Ok(count) => {
+                tracing::debug!(target: "application", "[remove_expired_current_user_connections_sessions] Deleted {} expired session records", count)
+            }
+            Err(err) => {
+                tracing::error!(target: "application", "[remove_expired_current_user_connections_sessions] {}", err)
+            }
+        }
+    }
+
+    /// Also contains friends whose session is only known from the database, e.g. because they are connected to another instance
+    async fn get_friends_in_current_user_connections<'a>(
+        &self,
+        username: &String,
+    ) -> HashMap<String, UserSessions<S>> {
+        let mut friends = self
+            .live_sessions
+            .get_friends_in_current_user_connections(username)
+            .await;
+
+        // Without the friends from the database only the ones connected to this instance are known
+        let friends_from_db = match self.live_sessions.get_friend_domain().get_friends(&username) {
+            Ok(res) => res,
+            Err(err) => {
+                tracing::error!(target: "application", "[get_friends_in_current_user_connections] {}", err);
+                return friends;
+            }
+        };
+
+        for friend in friends_from_db.iter() {
+            if friends.contains_key(&friend.username) {
+                continue;
+            }
+            match self.session_repository.get_user_sessions(&friend.username) {
+                Ok(records) if records.len() > 0 => {
+                    let user_sessions = records
+                        .into_iter()
+                        .map(|(record, user)| {
+                            (
+                                record.session_id.clone(),
+                                Arc::new(Mutex::new(record_to_session::<S, F>(record, user))),
+                            )
+                        })
+                        .collect::<UserSessions<S>>();
+                    friends.insert(friend.username.clone(), user_sessions);
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    tracing::error!(target: "application", "[get_friends_in_current_user_connections] {}", err)
+                }
+            }
+        }
+
+        friends
+    }
+}
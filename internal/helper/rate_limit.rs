@@ -0,0 +1,161 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::persistence::login_failure_repository::ILoginFailureRepository;
+
+/// Sliding window rate limiter, allowing `max_hits` per key within `window`
+#[derive(Debug)]
+pub struct RateLimiter {
+    max_hits: usize,
+    window: Duration,
+    hits: Mutex<HashMap<String, VecDeque<Instant>>>,
+}
+
+impl RateLimiter {
+    pub fn new(max_hits: usize, window: Duration) -> Self {
+        return Self {
+            max_hits,
+            window,
+            hits: Mutex::new(HashMap::new()),
+        };
+    }
+
+    /// Records a hit for the key. If the limit is exceeded the hit is not recorded and the time until the next hit is allowed is returned
+    pub fn check(&self, key: &String) -> Result<(), Duration> {
+        self.check_at(key, Instant::now())
+    }
+
+    pub fn check_at(&self, key: &String, now: Instant) -> Result<(), Duration> {
+        let mut hits = self.hits.lock().expect("Rate limiter lock poisoned");
+
+        // Keys are attacker controlled, so the ones without a hit in the window must not pile up
+        hits.retain(|_, key_hits| {
+            while let Some(oldest) = key_hits.front() {
+                if now.duration_since(*oldest) < self.window {
+                    break;
+                }
+                key_hits.pop_front();
+            }
+            !key_hits.is_empty()
+        });
+
+        let key_hits = hits.entry(key.clone()).or_default();
+
+        if key_hits.len() >= self.max_hits {
+            let oldest = key_hits.front().expect("max_hits has to be greater than 0");
+            return Err(self.window - now.duration_since(*oldest));
+        }
+
+        key_hits.push_back(now);
+        Ok(())
+    }
+
+    #[cfg(test)]
+    pub fn tracked_keys(&self) -> usize {
+        self.hits.lock().expect("Rate limiter lock poisoned").len()
+    }
+}
+
+/// Tracks failed attempts per key. After `free_attempts` failures every further failure locks the key,
+/// starting with `base_lockout` and doubling up to `max_lockout`. Failures are forgotten `reset_after` the last one.
+/// The failures are kept in the repository, so all instances share the lockouts of a `scope`
+#[derive(Debug)]
+pub struct LoginThrottle {
+    scope: &'static str,
+    free_attempts: u32,
+    base_lockout: Duration,
+    max_lockout: Duration,
+    reset_after: Duration,
+    failures: Arc<dyn ILoginFailureRepository>,
+}
+
+impl LoginThrottle {
+    pub fn new(
+        scope: &'static str,
+        failures: Arc<dyn ILoginFailureRepository>,
+        free_attempts: u32,
+        base_lockout: Duration,
+        max_lockout: Duration,
+        reset_after: Duration,
+    ) -> Self {
+        return Self {
+            scope,
+            free_attempts,
+            base_lockout,
+            max_lockout,
+            reset_after,
+            failures,
+        };
+    }
+
+    /// Returns the time until the key is unlocked again if it is locked
+    pub fn check(&self, key: &String) -> Result<(), Duration> {
+        self.check_at(key, SystemTime::now())
+    }
+
+    pub fn check_at(&self, key: &String, now: SystemTime) -> Result<(), Duration> {
+        // If the failures cannot be read the credentials cannot be checked either, so the attempt fails anyway
+        match self.failures.locked_until(self.scope, key) {
+            Err(err) => {
+                tracing::error!(target: "application", "[login_throttle] {}", err);
+                Ok(())
+            }
+            Ok(Some(locked_until)) if locked_until > now => {
+                Err(locked_until.duration_since(now).unwrap_or_default())
+            }
+            Ok(_) => Ok(()),
+        }
+    }
+
+    /// Returns the lockout if this failure locked the key
+    pub fn record_failure(&self, key: &String) -> Option<Duration> {
+        self.record_failure_at(key, SystemTime::now())
+    }
+
+    pub fn record_failure_at(&self, key: &String, now: SystemTime) -> Option<Duration> {
+        let forget_before = now.checked_sub(self.reset_after).unwrap_or(UNIX_EPOCH);
+        let count = match self
+            .failures
+            .record_failure(self.scope, key, now, forget_before)
+        {
+            Err(err) => {
+                tracing::error!(target: "application", "[login_throttle] {}", err);
+                return None;
+            }
+            Ok(count) => count,
+        };
+
+        if count <= self.free_attempts {
+            return None;
+        }
+
+        let exponent = (count - self.free_attempts - 1).min(16);
+        let lockout = self
+            .base_lockout
+            .saturating_mul(1 << exponent)
+            .min(self.max_lockout);
+        if let Err(err) = self.failures.lock(self.scope, key, now + lockout) {
+            tracing::error!(target: "application", "[login_throttle] {}", err);
+            return None;
+        }
+        Some(lockout)
+    }
+
+    pub fn reset(&self, key: &String) {
+        if let Err(err) = self.failures.reset(self.scope, key) {
+            tracing::error!(target: "application", "[login_throttle] {}", err);
+        }
+    }
+
+    /// Deletes the failures which are forgotten anyway, as keys are attacker controlled and must not pile up
+    pub fn delete_stale_failures(&self) -> Result<usize, String> {
+        let forget_before = SystemTime::now()
+            .checked_sub(self.reset_after)
+            .unwrap_or(UNIX_EPOCH);
+        self.failures
+            .delete_stale_failures(self.scope, forget_before)
+    }
+}
@@ -0,0 +1,220 @@
+use std::{collections::HashMap, sync::Mutex, time::SystemTime};
+
+use crate::{models::LoginFailure, persistence::login_failure_repository::ILoginFailureRepository};
+
+#[derive(Debug, Default)]
+pub struct LoginFailureRepositoryMock {
+    pub failures: Mutex<HashMap<(String, String), LoginFailure>>,
+}
+
+impl ILoginFailureRepository for LoginFailureRepositoryMock {
+    fn locked_until(&self, scope: &str, key: &String) -> Result<Option<SystemTime>, String> {
+        let failures = self.failures.lock().unwrap();
+        Ok(failures
+            .get(&(scope.to_string(), key.clone()))
+            .and_then(|failure| failure.locked_until))
+    }
+
+    fn record_failure(
+        &self,
+        scope: &str,
+        key: &String,
+        now: SystemTime,
+        forget_before: SystemTime,
+    ) -> Result<u32, String> {
+        let mut failures = self.failures.lock().unwrap();
+        let id = (scope.to_string(), key.clone());
+        if let Some(failure) = failures.get(&id) {
+            if failure.last_failure < forget_before {
+                failures.remove(&id);
+            }
+        }
+        let failure = failures.entry(id).or_insert(LoginFailure {
+            scope: scope.to_string(),
+            throttle_key: key.clone(),
+            failures: 0,
+            last_failure: now,
+            locked_until: None,
+        });
+        failure.failures += 1;
+        failure.last_failure = now;
+        Ok(failure.failures as u32)
+    }
+
+    fn lock(&self, scope: &str, key: &String, until: SystemTime) -> Result<(), String> {
+        let mut failures = self.failures.lock().unwrap();
+        if let Some(failure) = failures.get_mut(&(scope.to_string(), key.clone())) {
+            failure.locked_until = Some(until);
+        }
+        Ok(())
+    }
+
+    fn reset(&self, scope: &str, key: &String) -> Result<(), String> {
+        let mut failures = self.failures.lock().unwrap();
+        failures.remove(&(scope.to_string(), key.clone()));
+        Ok(())
+    }
+
+    fn delete_stale_failures(
+        &self,
+        scope: &str,
+        forget_before: SystemTime,
+    ) -> Result<usize, String> {
+        let mut failures = self.failures.lock().unwrap();
+        let before = failures.len();
+        failures.retain(|(failure_scope, _), failure| {
+            failure_scope != scope || failure.last_failure >= forget_before
+        });
+        Ok(before - failures.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::Arc,
+        time::{Duration, Instant, SystemTime},
+    };
+
+    use super::LoginFailureRepositoryMock;
+    use crate::helper::rate_limit::{LoginThrottle, RateLimiter};
+
+    #[test]
+    fn test_rate_limiter_blocks_until_window_passed() {
+        let limiter = RateLimiter::new(2, Duration::from_secs(10));
+        let key = String::from("User");
+        let start = Instant::now();
+
+        assert!(limiter.check_at(&key, start).is_ok());
+        assert!(limiter.check_at(&key, start + Duration::from_secs(1)).is_ok());
+
+        let retry_after = limiter
+            .check_at(&key, start + Duration::from_secs(2))
+            .unwrap_err();
+        assert_eq!(retry_after, Duration::from_secs(8));
+
+        // Other keys are not affected
+        assert!(limiter.check_at(&String::from("Other"), start).is_ok());
+
+        assert!(limiter.check_at(&key, start + Duration::from_secs(10)).is_ok());
+    }
+
+    #[test]
+    fn test_rate_limiter_evicts_stale_keys() {
+        let limiter = RateLimiter::new(2, Duration::from_secs(10));
+        let start = Instant::now();
+
+        for index in 0..100 {
+            assert!(limiter.check_at(&format!("User{}", index), start).is_ok());
+        }
+        assert_eq!(limiter.tracked_keys(), 100);
+
+        // Only the key hit within the window is kept
+        let later = start + Duration::from_secs(10);
+        assert!(limiter.check_at(&String::from("User0"), later).is_ok());
+        assert_eq!(limiter.tracked_keys(), 1);
+    }
+
+    #[test]
+    fn test_login_throttle_backs_off_exponentially() {
+        let throttle = LoginThrottle::new(
+            "username",
+            Arc::new(LoginFailureRepositoryMock::default()),
+            2,
+            Duration::from_secs(30),
+            Duration::from_secs(100),
+            Duration::from_secs(3600),
+        );
+        let key = String::from("User");
+        let start = SystemTime::now();
+
+        assert_eq!(throttle.record_failure_at(&key, start), None);
+        assert_eq!(throttle.record_failure_at(&key, start), None);
+        assert!(throttle.check_at(&key, start).is_ok());
+
+        assert_eq!(
+            throttle.record_failure_at(&key, start),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            throttle.check_at(&key, start + Duration::from_secs(10)),
+            Err(Duration::from_secs(20))
+        );
+        assert!(throttle
+            .check_at(&key, start + Duration::from_secs(30))
+            .is_ok());
+
+        let later = start + Duration::from_secs(30);
+        assert_eq!(
+            throttle.record_failure_at(&key, later),
+            Some(Duration::from_secs(60))
+        );
+        // Capped at the maximum lockout
+        assert_eq!(
+            throttle.record_failure_at(&key, later),
+            Some(Duration::from_secs(100))
+        );
+
+        // Other keys are not affected
+        assert!(throttle.check_at(&String::from("Other"), later).is_ok());
+
+        throttle.reset(&key);
+        assert!(throttle.check_at(&key, later).is_ok());
+        assert_eq!(throttle.record_failure_at(&key, later), None);
+    }
+
+    #[test]
+    fn test_login_throttle_forgets_old_failures() {
+        let throttle = LoginThrottle::new(
+            "username",
+            Arc::new(LoginFailureRepositoryMock::default()),
+            1,
+            Duration::from_secs(30),
+            Duration::from_secs(100),
+            Duration::from_secs(3600),
+        );
+        let key = String::from("User");
+        let start = SystemTime::now();
+
+        assert_eq!(throttle.record_failure_at(&key, start), None);
+        assert_eq!(
+            throttle.record_failure_at(&key, start + Duration::from_secs(3601)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_login_throttle_lockout_is_shared() {
+        // Two instances with the same repository, as the failures are stored in the database
+        let failures = Arc::new(LoginFailureRepositoryMock::default());
+        let instance = |scope| {
+            LoginThrottle::new(
+                scope,
+                failures.clone(),
+                1,
+                Duration::from_secs(30),
+                Duration::from_secs(100),
+                Duration::from_secs(3600),
+            )
+        };
+        let (first, second, by_ip) = (instance("username"), instance("username"), instance("ip"));
+        let key = String::from("User");
+        let start = SystemTime::now();
+
+        assert_eq!(first.record_failure_at(&key, start), None);
+        assert_eq!(
+            second.record_failure_at(&key, start),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            first.check_at(&key, start + Duration::from_secs(10)),
+            Err(Duration::from_secs(20))
+        );
+
+        // Other scopes are not affected
+        assert!(by_ip.check_at(&key, start).is_ok());
+
+        second.reset(&key);
+        assert!(first.check_at(&key, start).is_ok());
+    }
+}
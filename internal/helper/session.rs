@@ -1,4 +1,7 @@
-use super::jwt::{check_token_expiration, Token};
+use super::{
+    backplane::{BackplaneMessage, IBackplane, InProcessBackplane},
+    jwt::{check_token_expiration, Token},
+};
 use crate::{
     entities::friends::{repository::IFriendRepository, service::FriendDomain},
     interfaces::websockets::socket_messages::{
@@ -6,12 +9,15 @@ use crate::{
     },
     models::UserDTO,
 };
-use axum::async_trait;
+use axum::{async_trait, http::HeaderMap};
 use futures::lock::Mutex;
-use std::{collections::HashMap, fmt::Debug, sync::Arc};
+use std::{collections::HashMap, fmt::Debug, net::SocketAddr, sync::Arc};
 use tokio::sync::broadcast;
 use tracing::error;
 
+/// All sessions of a single user, keyed by the session id of the device
+pub type UserSessions<S> = HashMap<String, Arc<Mutex<S>>>;
+
 #[async_trait]
 pub trait ISessionManager<S: ISession<F>, F: IFriendRepository>:
     Debug + Send + Sync + 'static
@@ -20,82 +26,163 @@ pub trait ISessionManager<S: ISession<F>, F: IFriendRepository>:
     async fn remove_from_current_user_connections(
         &self,
         username: &String,
+        session_id: &String,
     ) -> Result<Arc<Mutex<S>>, String>;
 
     async fn remove_expired_current_user_connections_sessions(&self);
     async fn get_friends_in_current_user_connections<'a>(
         &self,
         client_uuid: &String,
-    ) -> HashMap<String, Arc<Mutex<S>>>;
-    fn get_current_user_connections(&self) -> &Arc<Mutex<HashMap<String, Arc<Mutex<S>>>>>;
+    ) -> HashMap<String, UserSessions<S>>;
+    fn get_current_user_connections(&self) -> &Arc<Mutex<HashMap<String, UserSessions<S>>>>;
+    async fn get_session(&self, username: &String, session_id: &String) -> Option<Arc<Mutex<S>>>;
+    /// Whether the user still has a session on any device
+    async fn is_user_connected(&self, username: &String) -> bool;
+    fn get_backplane(&self) -> Arc<dyn IBackplane>;
+
+    /// Sends the message to all devices of the user, regardless of the instance they are connected to
+    async fn send_to_user(&self, username: &String, message: SocketMessage) {
+        if let Err(err) = self
+            .get_backplane()
+            .publish(BackplaneMessage::new(username.clone(), message))
+            .await
+        {
+            error!("{}", err);
+        }
+    }
+
+    /// Closes the sessions of the user on every instance, except `kept_session_id`. `notice` is the last message they receive
+    async fn close_user_sessions(
+        &self,
+        username: &String,
+        kept_session_id: Option<&String>,
+        notice: SocketMessage,
+    ) {
+        if let Err(err) = self
+            .get_backplane()
+            .publish(BackplaneMessage::close_sessions(
+                username.clone(),
+                kept_session_id.cloned(),
+                notice,
+            ))
+            .await
+        {
+            error!("{}", err);
+        }
+    }
 }
 #[derive(Debug)]
 pub struct SessionManager<S: ISession<F>, F: IFriendRepository> {
-    sessions: Arc<Mutex<HashMap<String, Arc<Mutex<S>>>>>,
+    sessions: Arc<Mutex<HashMap<String, UserSessions<S>>>>,
     friend_domain: FriendDomain<F>,
+    backplane: Arc<dyn IBackplane>,
 }
 
 impl<S: ISession<F>, F: IFriendRepository> SessionManager<S, F> {
     pub fn new(friend_domain: FriendDomain<F>) -> Self {
+        return Self::with_backplane(friend_domain, Arc::new(InProcessBackplane::new()));
+    }
+
+    pub fn with_backplane(friend_domain: FriendDomain<F>, backplane: Arc<dyn IBackplane>) -> Self {
         return Self {
             friend_domain: friend_domain,
             sessions: Arc::new(Mutex::new(HashMap::new())),
+            backplane,
         };
     }
+
+    pub fn get_friend_domain(&self) -> &FriendDomain<F> {
+        return &self.friend_domain;
+    }
 }
 
 #[async_trait]
 impl<S: ISession<F>, F: IFriendRepository> ISessionManager<S, F> for SessionManager<S, F> {
-    fn get_current_user_connections(&self) -> &Arc<Mutex<HashMap<String, Arc<Mutex<S>>>>> {
+    fn get_current_user_connections(&self) -> &Arc<Mutex<HashMap<String, UserSessions<S>>>> {
         return &self.sessions;
     }
-    async fn insert_into_current_user_connections(&self, session: S) {
+    async fn get_session(&self, username: &String, session_id: &String) -> Option<Arc<Mutex<S>>> {
+        return self
+            .sessions
+            .lock()
+            .await
+            .get(username)
+            .and_then(|user_sessions| user_sessions.get(session_id))
+            .cloned();
+    }
+    async fn is_user_connected(&self, username: &String) -> bool {
+        return self.sessions.lock().await.contains_key(username);
+    }
+    fn get_backplane(&self) -> Arc<dyn IBackplane> {
+        return self.backplane.clone();
+    }
+    async fn insert_into_current_user_connections(&self, mut session: S) {
+        session.set_backplane(self.backplane.clone());
         let mut current_user_connections = self.sessions.lock().await;
-        let username = &session.clone().get_user().username;
+        let username = session.get_user().username;
+        let session_id = session.get_token().sid;
         let session_manager = Arc::new(Mutex::new(session));
-        current_user_connections.insert(username.to_owned(), session_manager);
-        tracing::debug!(target: "application", "[insert_into_current_user_connections] Inserted {} session", &username);
+        current_user_connections
+            .entry(username.clone())
+            .or_default()
+            .insert(session_id.clone(), session_manager);
+        tracing::debug!(target: "application", "[insert_into_current_user_connections] Inserted {} session {}", &username, &session_id);
     }
 
     async fn remove_from_current_user_connections(
         &self,
         username: &String,
+        session_id: &String,
     ) -> Result<Arc<Mutex<S>>, String> {
         let mut current_user_connections = self.sessions.lock().await;
-        let session_manager = match current_user_connections.remove_entry(username) {
+        let user_sessions = match current_user_connections.get_mut(username) {
             None => {
                 return Err(format!(
                     "user not current_user_connections pool: {}",
                     username
                 ))
             }
-            Some(user) => user.1,
+            Some(user_sessions) => user_sessions,
+        };
+        let session_manager = match user_sessions.remove(session_id) {
+            None => {
+                return Err(format!(
+                    "session {} of {} not in current_user_connections pool",
+                    session_id, username
+                ))
+            }
+            Some(session) => session,
         };
-        tracing::debug!(target: "application", "[remove_from_current_user_connections] Removed {} session", &username);
+        // Only drop the user once the last device is gone
+        if user_sessions.is_empty() {
+            current_user_connections.remove(username);
+        }
+        tracing::debug!(target: "application", "[remove_from_current_user_connections] Removed {} session {}", &username, &session_id);
         Ok(session_manager)
     }
 
     async fn remove_expired_current_user_connections_sessions(&self) {
         let current_user_connections = self.sessions.lock().await.clone();
-        let current_user_connections = current_user_connections.iter();
-        let mut to_be_removed: Vec<&String> = Vec::new();
-        for (user_id, sm) in current_user_connections {
-            let sm = sm.lock().await;
-            let token_is_expired = match check_token_expiration(sm.get_token().clone()) {
-                Err(_) => true,
-                Ok(_) => false,
-            };
+        let mut to_be_removed: Vec<(String, String)> = Vec::new();
+        for (user_id, user_sessions) in current_user_connections.iter() {
+            for (session_id, sm) in user_sessions.iter() {
+                let sm = sm.lock().await;
+                let token_is_expired = match check_token_expiration(sm.get_token().clone()) {
+                    Err(_) => true,
+                    Ok(_) => false,
+                };
 
-            if !token_is_expired {
-                continue;
+                if !token_is_expired {
+                    continue;
+                }
+                // Token is expired
+                tracing::debug!(target: "application", "[remove_expired_current_user_connections_sessions] User: {} session {} token is expired, removing", &user_id, &session_id);
+                to_be_removed.push((user_id.clone(), session_id.clone()));
             }
-            // Token is expired
-            tracing::debug!(target: "application", "[remove_expired_current_user_connections_sessions] User: {} token is expired, removing", &user_id);
-            to_be_removed.push(user_id);
         }
-        for user_id in to_be_removed {
+        for (user_id, session_id) in to_be_removed {
             let session_manager = self
-                .remove_from_current_user_connections(&user_id)
+                .remove_from_current_user_connections(&user_id, &session_id)
                 .await
                 .expect("Could not remove from current_user_connections");
             tracing::debug!(
@@ -105,48 +192,73 @@ impl<S: ISession<F>, F: IFriendRepository> ISessionManager<S, F> for SessionMana
 
             let session_manager = session_manager.lock().await;
             session_manager.notify_offline(self).await;
-            session_manager
-                .send_direct_message(SocketMessage::SocketMessageNotification(
-                    SocketMessageNotification::new(
-                        String::from("error"),
-                        String::from("Important"),
-                        String::from("Your session expired"),
-                    ),
-                ))
-                .await;
+            // The session is not part of current_user_connections anymore, so it cannot be reached via the backplane
+            session_manager.deliver(SocketMessage::SocketMessageNotification(
+                SocketMessageNotification::new(
+                    String::from("error"),
+                    String::from("Important"),
+                    String::from("Your session expired"),
+                ),
+            ));
         }
     }
 
     async fn get_friends_in_current_user_connections<'a>(
         &self,
         username: &String,
-    ) -> HashMap<String, Arc<Mutex<S>>> {
-        let friends_from_db = self
-            .friend_domain
-            .get_friends(&username)
-            .expect("Could not get friends");
-        let mut friends: HashMap<String, Arc<Mutex<S>>> = HashMap::new();
+    ) -> HashMap<String, UserSessions<S>> {
+        let mut friends: HashMap<String, UserSessions<S>> = HashMap::new();
+        let friends_from_db = match self.friend_domain.get_friends(&username) {
+            Ok(res) => res,
+            Err(err) => {
+                error!(target: "application", "[get_friends_in_current_user_connections] {}", err);
+                return friends;
+            }
+        };
         let current_user_connections = self.get_current_user_connections().lock().await;
 
         // Currently just iterating over entire current_user_connections_state
         for user in friends_from_db.iter() {
-            // Get sessionmanager from current_user_connections pool
-            let session_manager = match current_user_connections.get(&user.username) {
-                Some(sm) => sm,
+            // Get sessions from current_user_connections pool
+            let user_sessions = match current_user_connections.get(&user.username) {
+                Some(sessions) => sessions,
                 None => continue,
             };
-            friends.insert(user.username.clone(), session_manager.to_owned());
+            friends.insert(user.username.clone(), user_sessions.to_owned());
         }
 
         friends
     }
 }
 
+/// Details about the client a session was created from
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClientInfo {
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+}
+
+impl ClientInfo {
+    pub fn new(headers: &HeaderMap, addr: &SocketAddr) -> Self {
+        let user_agent = headers
+            .get(axum::http::header::USER_AGENT)
+            .and_then(|ua| ua.to_str().ok())
+            .map(|ua| ua.chars().take(512).collect::<String>());
+        return Self {
+            user_agent,
+            ip: Some(addr.ip().to_string()),
+        };
+    }
+}
+
 #[derive(Debug)]
 pub struct Session {
     pub user_socket: broadcast::Sender<SocketMessage>,
     pub user: UserDTO,
     pub token: Token,
+    pub client_info: ClientInfo,
+    // Set when the session is inserted into a session manager
+    pub backplane: Option<Arc<dyn IBackplane>>,
 }
 
 unsafe impl Send for Session {}
@@ -157,6 +269,21 @@ pub trait ISession<F: IFriendRepository>: Clone + Debug + Send + 'static {
     fn get_user_socket(&self) -> broadcast::Sender<SocketMessage>;
     fn get_user(&self) -> UserDTO;
     fn get_token(&self) -> Token;
+    /// Replaces the token once it was refreshed, the session expires with its token
+    fn set_token(&mut self, token: Token);
+    fn get_client_info(&self) -> ClientInfo {
+        ClientInfo::default()
+    }
+    fn set_client_info(&mut self, _client_info: ClientInfo) {}
+    fn set_backplane(&mut self, _backplane: Arc<dyn IBackplane>) {}
+    /// Hands the message to the socket of this session, bypassing the backplane
+    fn deliver(&self, message: SocketMessage) {
+        message.debug_trace();
+        match self.get_user_socket().send(message) {
+            Err(err) => error!("Error, probably no listeners; {}", err),
+            Ok(_) => {}
+        };
+    }
     async fn send_direct_message(&self, message: SocketMessage);
     async fn notify_online(&self, session_manager: &impl ISessionManager<Self, F>);
     async fn notify_offline(&self, session_manager: &impl ISessionManager<Self, F>);
@@ -169,6 +296,8 @@ impl Clone for Session {
             user_socket: self.user_socket.clone(),
             user: self.user.clone(),
             token: self.token.clone(),
+            client_info: self.client_info.clone(),
+            backplane: self.backplane.clone(),
         }
     }
 }
@@ -180,56 +309,82 @@ impl<F: IFriendRepository> ISession<F> for Session {
             user_socket: broadcast::channel(20).0,
             user,
             token,
+            client_info: ClientInfo::default(),
+            backplane: None,
         }
     }
     fn get_token(&self) -> Token {
         self.token.clone()
     }
+    fn set_token(&mut self, token: Token) {
+        self.token = token;
+    }
+    fn get_client_info(&self) -> ClientInfo {
+        self.client_info.clone()
+    }
+    fn set_client_info(&mut self, client_info: ClientInfo) {
+        self.client_info = client_info;
+    }
+    fn set_backplane(&mut self, backplane: Arc<dyn IBackplane>) {
+        self.backplane = Some(backplane);
+    }
     fn get_user(&self) -> UserDTO {
         self.user.clone()
     }
     fn get_user_socket(&self) -> broadcast::Sender<SocketMessage> {
         self.user_socket.clone()
     }
+    /// Publishes through the backplane, so the message also reaches the user on other instances
     async fn send_direct_message(&self, message: SocketMessage) {
-        message.debug_trace();
-        match self.user_socket.send(message) {
-            Err(err) => error!("Error, probably no listeners; {}", err),
-            Ok(_) => {}
+        let backplane = match &self.backplane {
+            None => return <Self as ISession<F>>::deliver(self, message),
+            Some(backplane) => backplane,
         };
+        if let Err(err) = backplane
+            .publish(BackplaneMessage::new(self.user.username.clone(), message))
+            .await
+        {
+            error!("{}", err);
+        }
     }
     async fn notify_online(&self, session_manager: &impl ISessionManager<Self, F>) {
         let friends_in_current_user_connections = session_manager
             .get_friends_in_current_user_connections(&self.user.username)
             .await;
-        for (_, friend_session) in friends_in_current_user_connections {
-            let friend_session = friend_session.lock().await;
-            // This is necessary to satisfy all trait bounds. Because in this context friend_session is a Session, but it could be anything implementing ISession
-            <Self as ISession<F>>::send_direct_message(
-                &friend_session,
-                SocketMessage::SocketMessageStatusChange(SocketMessageStatusChange::new(
-                    EEvent::ONLINE,
-                    self.user.username.clone(),
-                )),
-            )
-            .await;
+        for (friend_username, _) in friends_in_current_user_connections {
+            session_manager
+                .send_to_user(
+                    &friend_username,
+                    SocketMessage::SocketMessageStatusChange(SocketMessageStatusChange::new(
+                        EEvent::ONLINE,
+                        self.user.username.clone(),
+                    )),
+                )
+                .await;
         }
     }
 
+    /// Only notifies once the user disconnected from the last device
     async fn notify_offline(&self, session_manager: &impl ISessionManager<Self, F>) {
+        if session_manager
+            .is_user_connected(&self.user.username)
+            .await
+        {
+            return;
+        }
         let friends_in_current_user_connections = session_manager
             .get_friends_in_current_user_connections(&self.user.username)
             .await;
-        for (_, friend_session) in friends_in_current_user_connections {
-            let friend_session = friend_session.lock().await;
-            <Self as ISession<F>>::send_direct_message(
-                &friend_session,
-                SocketMessage::SocketMessageStatusChange(SocketMessageStatusChange::new(
-                    EEvent::OFFLINE,
-                    self.user.username.clone(),
-                )),
-            )
-            .await;
+        for (friend_username, _) in friends_in_current_user_connections {
+            session_manager
+                .send_to_user(
+                    &friend_username,
+                    SocketMessage::SocketMessageStatusChange(SocketMessageStatusChange::new(
+                        EEvent::OFFLINE,
+                        self.user.username.clone(),
+                    )),
+                )
+                .await;
         }
     }
 }
@@ -2,6 +2,8 @@ use std::{marker::PhantomData, sync::Arc, time::Duration};
 
 use crate::{
     appstate::IAppState,
+    models::SessionRecord,
+    persistence::session_repository::ISessionRepository,
     entities::friends::repository::{FriendDTO, IFriendRepository},
     helper::session::ISessionManager,
     interfaces::websockets::socket_messages::SocketMessage,
@@ -32,6 +34,9 @@ impl IFriendRepository for MockFriendRepository {
         &self,
         username: &String,
     ) -> Result<Vec<crate::entities::friends::repository::FriendDTO>, String> {
+        if username == "Unreachable" {
+            return Err(String::from("Could not get friends"));
+        }
         let mut friends = Vec::<crate::entities::friends::repository::FriendDTO>::new();
         let friend1 = FriendDTO {
             username: String::from("Friend1"),
@@ -61,6 +66,9 @@ impl<F: IFriendRepository + Clone> ISession<F> for MockSession<F> {
     fn get_token(&self) -> crate::helper::jwt::Token {
         return self.token.clone();
     }
+    fn set_token(&mut self, token: crate::helper::jwt::Token) {
+        self.token = token;
+    }
     fn get_user(&self) -> crate::models::UserDTO {
         return self.user.clone();
     }
@@ -97,6 +105,59 @@ impl IConnectionManager for MockConnectionManager {
     }
 }
 
+#[derive(Debug, Default)]
+pub struct MockSessionRepository {
+    pub sessions: std::sync::Mutex<Vec<(SessionRecord, UserDTO)>>,
+}
+
+impl ISessionRepository for MockSessionRepository {
+    fn save_session(&self, session: &SessionRecord) -> Result<(), String> {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|(s, _)| s.session_id != session.session_id);
+        sessions.push((
+            session.clone(),
+            UserDTO {
+                username: session.username.clone(),
+                password: String::from("Pass"),
+                public_key: Vec::<u8>::new(),
+            },
+        ));
+        Ok(())
+    }
+    fn delete_session(&self, session_id: &String) -> Result<(), String> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .retain(|(s, _)| &s.session_id != session_id);
+        Ok(())
+    }
+    fn get_session(&self, session_id: &String) -> Result<Option<(SessionRecord, UserDTO)>, String> {
+        Ok(self
+            .sessions
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(s, _)| &s.session_id == session_id)
+            .cloned())
+    }
+    fn get_user_sessions(&self, username: &String) -> Result<Vec<(SessionRecord, UserDTO)>, String> {
+        Ok(self
+            .sessions
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(s, _)| &s.username == username)
+            .cloned()
+            .collect())
+    }
+    fn get_active_sessions(&self) -> Result<Vec<(SessionRecord, UserDTO)>, String> {
+        Ok(self.sessions.lock().unwrap().clone())
+    }
+    fn delete_expired_sessions(&self) -> Result<usize, String> {
+        Ok(0)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::{
@@ -113,8 +174,12 @@ mod tests {
     use crate::{
         config::ConfigManager,
         entities::friends::service::FriendDomain,
+        interfaces::websockets::socket_messages::SocketMessageNotification,
+        scheduler::backplane_listener::deliver_to_local_session,
         helper::{
+            backplane::BackplaneMessage,
             jwt::{get_time_since_epoch, Token},
+            persistent_session::PersistentSessionManager,
             session::SessionManager,
         },
     };
@@ -143,8 +208,11 @@ mod tests {
             },
             Token {
                 exp: Duration::from_micros(10000),
-                public_key: String::from("abc"),
+                iat: Duration::from_secs(0),
                 sub: String::from("Sub"),
+                jti: String::from("jti"),
+                sid: String::from("sid"),
+                cfp: None,
             },
         );
         session_manager
@@ -177,8 +245,11 @@ mod tests {
             },
             Token {
                 exp: Duration::from_micros(10000),
-                public_key: String::from("abc"),
+                iat: Duration::from_secs(0),
                 sub: String::from("Sub"),
+                jti: String::from("jti"),
+                sid: String::from("sid"),
+                cfp: None,
             },
         );
         let mock_session2 = MockSession::new(
@@ -189,8 +260,11 @@ mod tests {
             },
             Token {
                 exp: Duration::from_micros(10000),
-                public_key: String::from("abc"),
+                iat: Duration::from_secs(0),
                 sub: String::from("Sub"),
+                jti: String::from("jti"),
+                sid: String::from("sid"),
+                cfp: None,
             },
         );
 
@@ -212,7 +286,7 @@ mod tests {
         );
 
         session_manager
-            .remove_from_current_user_connections(&String::from("Delete-Me"))
+            .remove_from_current_user_connections(&String::from("Delete-Me"), &String::from("sid"))
             .await
             .expect("");
 
@@ -251,8 +325,11 @@ mod tests {
             },
             Token {
                 exp: get_time_since_epoch().sub(Duration::from_secs(300)), // Invalid token, expired 5 min ago
-                public_key: String::from("abc"),
+                iat: Duration::from_secs(0),
                 sub: String::from("Should expire"),
+                jti: String::from("jti"),
+                sid: String::from("sid"),
+                cfp: None,
             },
         );
         let mock_session2 = MockSession::new(
@@ -263,8 +340,11 @@ mod tests {
             },
             Token {
                 exp: get_time_since_epoch().add(Duration::from_secs(300)), // Valid token, expires in 5 min
-                public_key: String::from("abc"),
+                iat: Duration::from_secs(0),
                 sub: String::from("Should stay"),
+                jti: String::from("jti"),
+                sid: String::from("sid"),
+                cfp: None,
             },
         );
 
@@ -324,8 +404,11 @@ mod tests {
             },
             Token {
                 exp: Duration::from_micros(10000),
-                public_key: String::from("abc"),
+                iat: Duration::from_secs(0),
                 sub: String::from("Sub"),
+                jti: String::from("jti"),
+                sid: String::from("sid"),
+                cfp: None,
             },
         );
         let mock_session2 = MockSession::new(
@@ -336,8 +419,11 @@ mod tests {
             },
             Token {
                 exp: Duration::from_micros(10000),
-                public_key: String::from("abc"),
+                iat: Duration::from_secs(0),
                 sub: String::from("Sub"),
+                jti: String::from("jti"),
+                sid: String::from("sid"),
+                cfp: None,
             },
         );
 
@@ -349,8 +435,11 @@ mod tests {
             },
             Token {
                 exp: Duration::from_micros(10000),
-                public_key: String::from("abc"),
+                iat: Duration::from_secs(0),
                 sub: String::from("Sub"),
+                jti: String::from("jti"),
+                sid: String::from("sid"),
+                cfp: None,
             },
         );
 
@@ -362,8 +451,11 @@ mod tests {
             },
             Token {
                 exp: Duration::from_micros(10000),
-                public_key: String::from("abc"),
+                iat: Duration::from_secs(0),
                 sub: String::from("Sub"),
+                jti: String::from("jti"),
+                sid: String::from("sid"),
+                cfp: None,
             },
         );
 
@@ -388,4 +480,304 @@ mod tests {
         assert!(friends.contains_key(&String::from("Friend1")));
         assert!(friends.contains_key(&String::from("Friend2")));
     }
+
+    #[tokio::test]
+    async fn test_that_session_manager_keeps_sessions_of_other_devices() {
+        initialize_testing_environment();
+        let session_manager: SessionManager<
+            MockSession<MockFriendRepository>,
+            MockFriendRepository,
+        > = SessionManager::new(FriendDomain::new(MockFriendRepository {}));
+
+        for device in ["laptop", "phone"] {
+            let mock_session = MockSession::new(
+                UserDTO {
+                    username: String::from("Test"),
+                    password: String::from("Pass"),
+                    public_key: Vec::<u8>::new(),
+                },
+                Token {
+                    exp: get_time_since_epoch().add(Duration::from_secs(300)),
+                    iat: Duration::from_secs(0),
+                    sub: String::from("Test"),
+                    jti: String::from("jti"),
+                    sid: String::from(device),
+                    cfp: None,
+                },
+            );
+            session_manager
+                .insert_into_current_user_connections(mock_session)
+                .await;
+        }
+
+        let username = String::from("Test");
+        assert!(
+            session_manager
+                .get_current_user_connections()
+                .lock()
+                .await
+                .get(&username)
+                .expect("User should be connected")
+                .len()
+                == 2
+        );
+
+        session_manager
+            .remove_from_current_user_connections(&username, &String::from("laptop"))
+            .await
+            .expect("");
+        assert!(session_manager.is_user_connected(&username).await);
+        assert!(session_manager
+            .get_session(&username, &String::from("phone"))
+            .await
+            .is_some());
+
+        session_manager
+            .remove_from_current_user_connections(&username, &String::from("phone"))
+            .await
+            .expect("");
+        assert!(session_manager.is_user_connected(&username).await == false);
+    }
+
+    #[tokio::test]
+    async fn test_that_close_events_close_sessions_except_the_kept_one() {
+        initialize_testing_environment();
+        let session_manager: SessionManager<
+            MockSession<MockFriendRepository>,
+            MockFriendRepository,
+        > = SessionManager::new(FriendDomain::new(MockFriendRepository {}));
+
+        for device in ["laptop", "phone", "tablet"] {
+            let mock_session = MockSession::new(
+                UserDTO {
+                    username: String::from("Test"),
+                    password: String::from("Pass"),
+                    public_key: Vec::<u8>::new(),
+                },
+                Token {
+                    exp: get_time_since_epoch().add(Duration::from_secs(300)),
+                    iat: Duration::from_secs(0),
+                    sub: String::from("Test"),
+                    jti: String::from("jti"),
+                    sid: String::from(device),
+                    cfp: None,
+                },
+            );
+            session_manager
+                .insert_into_current_user_connections(mock_session)
+                .await;
+        }
+
+        // What another instance publishes on logout-all
+        let username = String::from("Test");
+        deliver_to_local_session(
+            &session_manager,
+            BackplaneMessage::close_sessions(
+                username.clone(),
+                Some(String::from("phone")),
+                SocketMessage::SocketMessageNotification(SocketMessageNotification::new(
+                    String::from("error"),
+                    String::from("Important"),
+                    String::from("Logged out"),
+                )),
+            ),
+        )
+        .await;
+
+        let sessions = session_manager.get_current_user_connections().lock().await;
+        let user_sessions = sessions.get(&username).expect("User should be connected");
+        assert_eq!(user_sessions.len(), 1);
+        assert!(user_sessions.contains_key("phone"));
+    }
+
+    #[tokio::test]
+    async fn test_that_close_events_without_kept_session_disconnect_the_user() {
+        initialize_testing_environment();
+        let session_manager: SessionManager<
+            MockSession<MockFriendRepository>,
+            MockFriendRepository,
+        > = SessionManager::new(FriendDomain::new(MockFriendRepository {}));
+
+        for device in ["laptop", "phone"] {
+            let mock_session = MockSession::new(
+                UserDTO {
+                    username: String::from("Test"),
+                    password: String::from("Pass"),
+                    public_key: Vec::<u8>::new(),
+                },
+                Token {
+                    exp: get_time_since_epoch().add(Duration::from_secs(300)),
+                    iat: Duration::from_secs(0),
+                    sub: String::from("Test"),
+                    jti: String::from("jti"),
+                    sid: String::from(device),
+                    cfp: None,
+                },
+            );
+            session_manager
+                .insert_into_current_user_connections(mock_session)
+                .await;
+        }
+
+        // What another instance publishes when the account is deleted
+        let username = String::from("Test");
+        deliver_to_local_session(
+            &session_manager,
+            BackplaneMessage::close_sessions(
+                username.clone(),
+                None,
+                SocketMessage::SocketMessageNotification(SocketMessageNotification::new(
+                    String::from("error"),
+                    String::from("Important"),
+                    String::from("Your account was deleted"),
+                )),
+            ),
+        )
+        .await;
+
+        assert!(session_manager.is_user_connected(&username).await == false);
+    }
+
+    fn persisted_session_record(username: &str) -> SessionRecord {
+        SessionRecord {
+            id: uuid::Uuid::new_v4(),
+            username: String::from(username),
+            token_id: String::from("jti"),
+            expires_at: std::time::SystemTime::now().add(Duration::from_secs(300)),
+            user_agent: Some(String::from("Agent")),
+            ip_address: Some(String::from("127.0.0.1")),
+            created_at: std::time::SystemTime::now(),
+            session_id: format!("{}-sid", username),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_that_persistent_session_manager_persists_sessions() {
+        initialize_testing_environment();
+        let session_manager: PersistentSessionManager<
+            MockSession<MockFriendRepository>,
+            MockFriendRepository,
+            MockSessionRepository,
+        > = PersistentSessionManager::new(
+            FriendDomain::new(MockFriendRepository {}),
+            MockSessionRepository::default(),
+        );
+
+        let mock_session1 = MockSession::new(
+            UserDTO {
+                username: String::from("Test"),
+                password: String::from("Pass"),
+                public_key: Vec::<u8>::new(),
+            },
+            Token {
+                exp: get_time_since_epoch().add(Duration::from_secs(300)),
+                iat: Duration::from_secs(0),
+                sub: String::from("Sub"),
+                jti: String::from("jti"),
+                sid: String::from("sid"),
+                cfp: None,
+            },
+        );
+        session_manager
+            .insert_into_current_user_connections(mock_session1)
+            .await;
+
+        assert!(session_manager
+            .get_session(&String::from("Test"), &String::from("sid"))
+            .await
+            .is_some());
+        assert!(
+            session_manager
+                .get_current_user_connections()
+                .lock()
+                .await
+                .len()
+                == 1
+        );
+
+        session_manager
+            .remove_from_current_user_connections(&String::from("Test"), &String::from("sid"))
+            .await
+            .expect("");
+
+        assert!(session_manager
+            .get_session(&String::from("Test"), &String::from("sid"))
+            .await
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_that_persistent_session_manager_rehydrates_sessions() {
+        initialize_testing_environment();
+        let session_repository = MockSessionRepository::default();
+        session_repository
+            .save_session(&persisted_session_record("Rehydrated"))
+            .unwrap();
+
+        let session_manager: PersistentSessionManager<
+            MockSession<MockFriendRepository>,
+            MockFriendRepository,
+            MockSessionRepository,
+        > = PersistentSessionManager::new(
+            FriendDomain::new(MockFriendRepository {}),
+            session_repository,
+        );
+
+        let count = session_manager.rehydrate().await.expect("");
+        assert!(count == 1);
+
+        let session = session_manager
+            .get_session(&String::from("Rehydrated"), &String::from("Rehydrated-sid"))
+            .await
+            .expect("Session should have been rehydrated");
+        let token = session.lock().await.get_token();
+        assert_eq!(token.sub, String::from("Rehydrated"));
+        assert_eq!(token.jti, String::from("jti"));
+        assert_eq!(token.sid, String::from("Rehydrated-sid"));
+    }
+
+    #[tokio::test]
+    async fn test_that_persistent_session_manager_finds_friends_of_other_instances() {
+        initialize_testing_environment();
+        let session_repository = MockSessionRepository::default();
+        // Friend1 logged in on another instance, so only the record is known
+        session_repository
+            .save_session(&persisted_session_record("Friend1"))
+            .unwrap();
+
+        let session_manager: PersistentSessionManager<
+            MockSession<MockFriendRepository>,
+            MockFriendRepository,
+            MockSessionRepository,
+        > = PersistentSessionManager::new(
+            FriendDomain::new(MockFriendRepository {}),
+            session_repository,
+        );
+
+        let friends = session_manager
+            .get_friends_in_current_user_connections(&String::from("User"))
+            .await;
+
+        assert!(friends.len() == 1);
+        assert!(friends.contains_key(&String::from("Friend1")));
+    }
+
+    #[tokio::test]
+    async fn test_that_persistent_session_manager_survives_failing_friend_lookups() {
+        initialize_testing_environment();
+        let session_manager: PersistentSessionManager<
+            MockSession<MockFriendRepository>,
+            MockFriendRepository,
+            MockSessionRepository,
+        > = PersistentSessionManager::new(
+            FriendDomain::new(MockFriendRepository {}),
+            MockSessionRepository::default(),
+        );
+
+        let friends = session_manager
+            .get_friends_in_current_user_connections(&String::from("Unreachable"))
+            .await;
+
+        assert!(friends.len() == 0);
+    }
 }
@@ -0,0 +1,93 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+
+use super::password::constant_time_eq;
+
+/// Name authenticator apps list the account under
+pub const TOTP_ISSUER: &str = "SanctumChat";
+/// Seconds a code is valid for. Authenticator apps only support the defaults of RFC 6238 reliably
+pub const TOTP_STEP: u64 = 30;
+const TOTP_DIGITS: u32 = 6;
+/// Codes of the previous and the next step are accepted as well, clocks of phones drift
+const TOTP_ALLOWED_DRIFT: u64 = 1;
+/// 160 bits, the output size of SHA-1 as recommended by RFC 4226
+const TOTP_SECRET_LENGTH: usize = 20;
+
+pub fn generate_totp_secret() -> Result<Vec<u8>, String> {
+    let mut secret = vec![0u8; TOTP_SECRET_LENGTH];
+    openssl::rand::rand_bytes(&mut secret)
+        .map_err(|err| format!("Could not generate TOTP secret: {}", err))?;
+    Ok(secret)
+}
+
+pub fn totp_step(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs()
+        / TOTP_STEP
+}
+
+/// HOTP of RFC 4226, TOTP uses the time step as counter
+pub fn hotp(secret: &[u8], counter: u64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC takes keys of any size");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    format!(
+        "{:0width$}",
+        binary % 10u32.pow(TOTP_DIGITS),
+        width = TOTP_DIGITS as usize
+    )
+}
+
+/// Returns the time step the code belongs to. Steps up to `last_used_step` are rejected,
+/// so a code cannot be used twice, e.g. by someone looking over the user's shoulder
+pub fn verify_totp(
+    secret: &[u8],
+    code: &str,
+    time: SystemTime,
+    last_used_step: Option<u64>,
+) -> Option<u64> {
+    let current_step = totp_step(time);
+    (current_step.saturating_sub(TOTP_ALLOWED_DRIFT)..=current_step + TOTP_ALLOWED_DRIFT)
+        .filter(|step| last_used_step.map_or(true, |last_used_step| *step > last_used_step))
+        .find(|step| constant_time_eq(hotp(secret, *step).as_bytes(), code.as_bytes()))
+}
+
+/// Authenticator apps expect secrets as unpadded base32
+pub fn encode_totp_secret(secret: &[u8]) -> String {
+    base32::encode(base32::Alphabet::RFC4648 { padding: false }, secret)
+}
+
+/// Key URI of the secret, usually shown as QR code to be scanned by an authenticator app
+pub fn otpauth_uri(username: &str, secret: &[u8]) -> String {
+    format!(
+        "otpauth://totp/{issuer}:{username}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={digits}&period={period}",
+        issuer = percent_encode(TOTP_ISSUER),
+        username = percent_encode(username),
+        secret = encode_totp_secret(secret),
+        digits = TOTP_DIGITS,
+        period = TOTP_STEP,
+    )
+}
+
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
@@ -0,0 +1,82 @@
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use crate::helper::totp::{
+        encode_totp_secret, hotp, otpauth_uri, totp_step, verify_totp, TOTP_STEP,
+    };
+
+    // Secret of the test vectors in RFC 6238, appendix B
+    const SECRET: &[u8] = b"12345678901234567890";
+
+    fn at(seconds: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn test_hotp_matches_rfc_test_vectors() {
+        // Last six digits of the SHA-1 vectors
+        assert_eq!(hotp(SECRET, totp_step(at(59))), "287082");
+        assert_eq!(hotp(SECRET, totp_step(at(1111111109))), "081804");
+        assert_eq!(hotp(SECRET, totp_step(at(1111111111))), "050471");
+        assert_eq!(hotp(SECRET, totp_step(at(1234567890))), "005924");
+        assert_eq!(hotp(SECRET, totp_step(at(2000000000))), "279037");
+    }
+
+    #[test]
+    fn test_verify_totp() {
+        let now = at(1234567890);
+        let step = totp_step(now);
+
+        assert_eq!(verify_totp(SECRET, "005924", now, None), Some(step));
+        assert_eq!(verify_totp(SECRET, "005925", now, None), None);
+        assert_eq!(verify_totp(SECRET, "", now, None), None);
+        assert_eq!(verify_totp(b"another secret", "005924", now, None), None);
+    }
+
+    #[test]
+    fn test_verify_totp_allows_clock_drift_of_one_step() {
+        let now = at(1234567890);
+        let step = totp_step(now);
+
+        let previous = hotp(SECRET, step - 1);
+        let next = hotp(SECRET, step + 1);
+        let too_old = hotp(SECRET, step - 2);
+
+        assert_eq!(verify_totp(SECRET, &previous, now, None), Some(step - 1));
+        assert_eq!(verify_totp(SECRET, &next, now, None), Some(step + 1));
+        assert_eq!(verify_totp(SECRET, &too_old, now, None), None);
+        assert_eq!(
+            verify_totp(SECRET, &too_old, now - Duration::from_secs(TOTP_STEP), None),
+            Some(step - 2)
+        );
+    }
+
+    #[test]
+    fn test_verify_totp_rejects_used_steps() {
+        let now = at(1234567890);
+        let step = totp_step(now);
+
+        assert_eq!(verify_totp(SECRET, "005924", now, Some(step)), None);
+        assert_eq!(
+            verify_totp(SECRET, "005924", now, Some(step - 1)),
+            Some(step)
+        );
+
+        // A code of an earlier step is not accepted once a later one was used
+        let previous = hotp(SECRET, step - 1);
+        assert_eq!(verify_totp(SECRET, &previous, now, Some(step)), None);
+    }
+
+    #[test]
+    fn test_otpauth_uri() {
+        assert_eq!(
+            encode_totp_secret(SECRET),
+            "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ"
+        );
+        assert_eq!(
+            otpauth_uri("alice smith", SECRET),
+            "otpauth://totp/SanctumChat:alice%20smith?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&issuer=SanctumChat&algorithm=SHA1&digits=6&period=30"
+        );
+    }
+}
@@ -0,0 +1,69 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::Engine;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    models::WebsocketTicket,
+    persistence::{
+        token_revocation_repository::ITokenRevocationRepository,
+        websocket_ticket_repository::IWebsocketTicketRepository,
+    },
+};
+
+use super::jwt::{check_token_expiration, Token};
+
+/// Only long enough for the client to open the socket right after requesting the ticket
+pub const WEBSOCKET_TICKET_LIFETIME: Duration = Duration::from_secs(30);
+
+/// Issues a single use ticket standing in for the access token on the websocket handshake,
+/// so the token does not end up in access logs of the service and proxies
+pub fn issue_websocket_ticket(
+    tickets: &dyn IWebsocketTicketRepository,
+    token: &Token,
+) -> Result<String, String> {
+    let mut secret = [0u8; 32];
+    openssl::rand::rand_bytes(&mut secret)
+        .map_err(|err| format!("Could not generate websocket ticket: {}", err))?;
+    let ticket = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(secret);
+
+    let now = SystemTime::now();
+    let claims = serde_json::to_string(token)
+        .map_err(|err| format!("Could not serialize token claims: {}", err))?;
+    tickets.save_ticket(&WebsocketTicket {
+        ticket_hash: hash_websocket_ticket(&ticket),
+        username: token.sub.clone(),
+        token: claims,
+        // A ticket does not outlive its token
+        expires_at: (now + WEBSOCKET_TICKET_LIFETIME).min(UNIX_EPOCH + token.exp),
+        created_at: now,
+    })?;
+
+    Ok(ticket)
+}
+
+/// Consumes the ticket and returns the token it was issued for, which has to be valid still
+pub fn redeem_websocket_ticket(
+    tickets: &dyn IWebsocketTicketRepository,
+    revocations: &dyn ITokenRevocationRepository,
+    ticket: &String,
+) -> Result<Token, String> {
+    let record = match tickets.consume_ticket(&hash_websocket_ticket(ticket))? {
+        None => return Err(String::from("Websocket ticket is invalid or expired")),
+        Some(record) => record,
+    };
+
+    let token: Token = serde_json::from_str(&record.token)
+        .map_err(|err| format!("Could not deserialize token claims: {}", err))?;
+    check_token_expiration(token.clone())?;
+
+    match revocations.is_revoked(&token) {
+        Err(err) => Err(err),
+        Ok(true) => Err(String::from("Token was revoked")),
+        Ok(false) => Ok(token),
+    }
+}
+
+pub fn hash_websocket_ticket(ticket: &String) -> String {
+    hex::encode(Sha256::digest(ticket.as_bytes()))
+}
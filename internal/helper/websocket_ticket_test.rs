@@ -0,0 +1,127 @@
+use std::{sync::Mutex, time::SystemTime};
+
+use crate::{
+    models::WebsocketTicket, persistence::websocket_ticket_repository::IWebsocketTicketRepository,
+};
+
+#[derive(Debug, Default)]
+pub struct WebsocketTicketRepositoryMock {
+    pub tickets: Mutex<Vec<WebsocketTicket>>,
+}
+
+impl IWebsocketTicketRepository for WebsocketTicketRepositoryMock {
+    fn save_ticket(&self, ticket: &WebsocketTicket) -> Result<(), String> {
+        self.tickets.lock().unwrap().push(ticket.clone());
+        Ok(())
+    }
+
+    fn consume_ticket(&self, ticket_hash: &String) -> Result<Option<WebsocketTicket>, String> {
+        let mut tickets = self.tickets.lock().unwrap();
+        let position = tickets.iter().position(|ticket| {
+            &ticket.ticket_hash == ticket_hash && ticket.expires_at > SystemTime::now()
+        });
+        Ok(position.map(|position| tickets.remove(position)))
+    }
+
+    fn delete_expired_tickets(&self) -> Result<usize, String> {
+        let mut tickets = self.tickets.lock().unwrap();
+        let count = tickets.len();
+        tickets.retain(|ticket| ticket.expires_at > SystemTime::now());
+        Ok(count - tickets.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use crate::{
+        helper::{
+            fingerprint_test::test_fingerprint,
+            jwt::{get_time_since_epoch, Token},
+            jwt_test::TokenRevocationRepositoryMock,
+            websocket_ticket::{
+                hash_websocket_ticket, issue_websocket_ticket, redeem_websocket_ticket,
+                WEBSOCKET_TICKET_LIFETIME,
+            },
+        },
+        persistence::token_revocation_repository::ITokenRevocationRepository,
+    };
+
+    use super::WebsocketTicketRepositoryMock;
+
+    fn token(valid_for: Duration) -> Token {
+        let now = get_time_since_epoch();
+        Token {
+            sub: String::from("User1"),
+            exp: Duration::from_secs((now + valid_for).as_secs()),
+            iat: Duration::from_secs(now.as_secs()),
+            jti: String::from("jti"),
+            sid: String::from("sid"),
+            cfp: Some(test_fingerprint()),
+        }
+    }
+
+    #[test]
+    fn test_redeem_websocket_ticket() {
+        let tickets = WebsocketTicketRepositoryMock::default();
+        let revocations = TokenRevocationRepositoryMock::default();
+        let token = token(Duration::from_secs(15 * 60));
+
+        let ticket = issue_websocket_ticket(&tickets, &token).unwrap();
+
+        // Only the hash is stored
+        let stored = tickets.tickets.lock().unwrap()[0].clone();
+        assert_eq!(stored.ticket_hash, hash_websocket_ticket(&ticket));
+        assert!(!stored.token.contains(&ticket));
+
+        assert_eq!(
+            redeem_websocket_ticket(&tickets, &revocations, &ticket),
+            Ok(token)
+        );
+    }
+
+    #[test]
+    fn test_websocket_ticket_is_single_use() {
+        let tickets = WebsocketTicketRepositoryMock::default();
+        let revocations = TokenRevocationRepositoryMock::default();
+        let ticket =
+            issue_websocket_ticket(&tickets, &token(Duration::from_secs(15 * 60))).unwrap();
+
+        assert!(redeem_websocket_ticket(&tickets, &revocations, &ticket).is_ok());
+        assert!(redeem_websocket_ticket(&tickets, &revocations, &ticket).is_err());
+    }
+
+    #[test]
+    fn test_unknown_websocket_ticket_is_rejected() {
+        let tickets = WebsocketTicketRepositoryMock::default();
+        let revocations = TokenRevocationRepositoryMock::default();
+        issue_websocket_ticket(&tickets, &token(Duration::from_secs(15 * 60))).unwrap();
+
+        assert!(redeem_websocket_ticket(&tickets, &revocations, &String::from("guess")).is_err());
+    }
+
+    #[test]
+    fn test_websocket_ticket_does_not_outlive_token() {
+        let tickets = WebsocketTicketRepositoryMock::default();
+        let token = token(Duration::from_secs(10));
+
+        issue_websocket_ticket(&tickets, &token).unwrap();
+
+        let stored = tickets.tickets.lock().unwrap()[0].clone();
+        assert_eq!(stored.expires_at, UNIX_EPOCH + token.exp);
+        assert!(stored.expires_at < SystemTime::now() + WEBSOCKET_TICKET_LIFETIME);
+    }
+
+    #[test]
+    fn test_websocket_ticket_of_revoked_token_is_rejected() {
+        let tickets = WebsocketTicketRepositoryMock::default();
+        let revocations = TokenRevocationRepositoryMock::default();
+        let token = token(Duration::from_secs(15 * 60));
+        let ticket = issue_websocket_ticket(&tickets, &token).unwrap();
+
+        revocations.revoke_token(&token).unwrap();
+
+        assert!(redeem_websocket_ticket(&tickets, &revocations, &ticket).is_err());
+    }
+}
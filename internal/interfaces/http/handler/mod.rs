@@ -1,2 +1,3 @@
 pub mod version_handler;
 pub mod ws_handler;
+pub mod ws_ticket_handler;
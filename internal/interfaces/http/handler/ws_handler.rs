@@ -1,14 +1,26 @@
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc};
 
 use crate::{
     appstate::{AppState, IAppState},
-    entities::friends::repository::IFriendRepository,
+    entities::{
+        friends::repository::IFriendRepository,
+        messages::{messages::MessageDomain, repository::MessageRepository},
+    },
     helper::{
-        jwt::{token_into_typed, validate_user_token},
+        cookie::{get_cookie, SESSION_COOKIE},
+        errors::HTTPResponse,
+        fingerprint::{
+            check_client_binding, ClientFingerprint, CLIENT_FINGERPRINT_SECRET, DEVICE_KEY_HEADER,
+        },
+        jwt::{validate_user_token, Token},
         session::{ISession, ISessionManager},
+        websocket_ticket::redeem_websocket_ticket,
     },
     interfaces::websockets::{
-        socket_messages::{SocketMessage, SocketMessageError, SocketMessageOnlineUsers},
+        socket_messages::{
+            SocketMessage, SocketMessageError, SocketMessageOnlineUsers,
+            SocketMessageUndeliveredMessages,
+        },
         ws_receive_handler::ws_receive_handler,
     },
     persistence::connection_manager::IConnectionManager,
@@ -16,9 +28,13 @@ use crate::{
 use axum::{
     extract::{
         ws::{Message, WebSocket, WebSocketUpgrade},
-        Query, State,
+        ConnectInfo, Query, State,
+    },
+    http::{
+        header::{HOST, ORIGIN, USER_AGENT},
+        HeaderMap, StatusCode,
     },
-    response::Response,
+    response::{IntoResponse, Response},
 };
 use futures::{sink::SinkExt, stream::StreamExt};
 use serde_json::{from_str, to_string};
@@ -27,7 +43,10 @@ use tracing::info;
 
 #[derive(serde::Deserialize)]
 pub struct WsQuery {
-    token: String,
+    /// Single use ticket issued by `POST /ws/ticket`
+    ticket: Option<String>,
+    /// Access token, unless disabled by WS_QUERY_TOKEN. Cookie authenticated clients connect without either
+    token: Option<String>,
 }
 
 pub async fn ws_handler<
@@ -38,9 +57,95 @@ pub async fn ws_handler<
 >(
     ws: WebSocketUpgrade,
     State(app_state): State<Arc<AppState<SM, S, C, F>>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Query(query): Query<WsQuery>,
 ) -> Response {
-    ws.on_upgrade(move |socket| handle_socket(socket, app_state.to_owned(), query))
+    let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+    let credential = match (query.ticket, query.token) {
+        (Some(ticket), _) => WsCredential::Ticket(ticket),
+        (None, Some(token)) => {
+            if !app_state.get_config().env.WS_QUERY_TOKEN {
+                return HTTPResponse::<()> {
+                    message: Some(String::from(
+                        "Tokens in the query string are disabled, use a websocket ticket",
+                    )),
+                    data: None,
+                    status: StatusCode::UNAUTHORIZED,
+                }
+                .into_response();
+            }
+            WsCredential::AccessToken(token)
+        }
+        (None, None) => match get_cookie(&headers, SESSION_COOKIE) {
+            None => return StatusCode::UNAUTHORIZED.into_response(),
+            Some(token) => {
+                // Browsers send cookies along with handshakes started by any site, so only the frontend may use them.
+                // Without CORS_ORIGIN that is a frontend served from the same host as the service
+                let config_origin = app_state.get_config().env.CORS_ORIGIN;
+                let allowed_origin = config_origin.as_deref().or(header(HOST.as_str()));
+                let is_allowed = match (header(ORIGIN.as_str()), allowed_origin) {
+                    (Some(origin), Some(allowed_origin)) => same_origin(origin, allowed_origin),
+                    _ => false,
+                };
+                if !is_allowed {
+                    tracing::warn!(target: "audit", "[ws_handler] rejected cookie authenticated handshake from origin {:?}", header(ORIGIN.as_str()));
+                    return StatusCode::FORBIDDEN.into_response();
+                }
+                WsCredential::AccessToken(token)
+            }
+        },
+    };
+    // The device key is only accepted as header, query strings end up in access logs
+    let fingerprint = ClientFingerprint::new(
+        &app_state.get_keyring().derive_secret(CLIENT_FINGERPRINT_SECRET),
+        header(USER_AGENT.as_str()),
+        &addr.ip(),
+        header(DEVICE_KEY_HEADER),
+    );
+    ws.on_upgrade(move |socket| {
+        handle_socket(socket, app_state.to_owned(), credential, fingerprint)
+    })
+}
+
+/// What the handshake is authenticated with
+enum WsCredential {
+    Ticket(String),
+    AccessToken(String),
+}
+
+fn authenticate<
+    SM: ISessionManager<S, F>,
+    S: ISession<F>,
+    F: IFriendRepository,
+    C: IConnectionManager,
+>(
+    app_state: &AppState<SM, S, C, F>,
+    credential: &WsCredential,
+) -> Result<Token, String> {
+    match credential {
+        WsCredential::Ticket(ticket) => redeem_websocket_ticket(
+            app_state.get_websocket_tickets(),
+            app_state.get_token_revocations(),
+            ticket,
+        ),
+        WsCredential::AccessToken(token) => validate_user_token(
+            token,
+            app_state.get_keyring(),
+            app_state.get_token_revocations(),
+        ),
+    }
+}
+
+/// CORS_ORIGIN may be configured without scheme, the Host header has none
+fn same_origin(origin: &str, allowed_origin: &str) -> bool {
+    let host = |origin: &str| {
+        origin
+            .split_once("://")
+            .map_or(origin, |(_, host)| host)
+            .to_owned()
+    };
+    host(origin) == host(allowed_origin)
 }
 
 async fn handle_socket<
@@ -51,17 +156,14 @@ async fn handle_socket<
 >(
     stream: WebSocket,
     app_state: Arc<AppState<SM, S, C, F>>,
-    query: WsQuery,
+    credential: WsCredential,
+    mut fingerprint: ClientFingerprint,
 ) {
     let (sender, mut receiver) = stream.split();
     let sender = Arc::new(Mutex::new(sender));
 
     let app_state_orig = app_state.clone();
-    let is_validated_result = validate_user_token(
-        query.token.clone(),
-        &app_state_orig.get_config().env.HASHING_KEY.as_bytes(),
-    );
-    match is_validated_result {
+    let token = match authenticate(&app_state_orig, &credential) {
         Err(_) => {
             let message = SocketMessageError::new(String::from("You are not authenticated"));
             match sender
@@ -77,26 +179,64 @@ async fn handle_socket<
             };
             return;
         }
-        Ok(_) => {}
+        Ok(token) => token,
+    };
+
+    // Browsers cannot set headers on handshakes. The device key of a ticket was already checked when it was
+    // issued on POST /ws/ticket, so it carries over to the handshake
+    if let (WsCredential::Ticket(_), None) = (&credential, &fingerprint.dk) {
+        fingerprint.dk = token.cfp.as_ref().and_then(|cfp| cfp.dk.clone());
+    }
+
+    if let Err(err) = check_client_binding(
+        &token.cfp,
+        &fingerprint,
+        app_state_orig.get_client_binding(),
+    ) {
+        tracing::warn!(target: "audit", "[handle_socket] token of {} session {} presented by another client: {}", token.sub, token.sid, err);
+        let message = SocketMessageError::new(String::from("The client changed, log in again"));
+        if let Err(err) = sender
+            .lock()
+            .await
+            .send(Message::Text(
+                to_string(&message).expect("Could not serialize message"),
+            ))
+            .await
+        {
+            info!("{}", err);
+        }
+        return;
     }
 
-    let token = token_into_typed(
-        &query.token,
-        app_state_orig.get_config().env.HASHING_KEY.as_bytes(),
-    )
-    .unwrap();
     let token2 = token.clone();
 
-    let current_user_connections_connection = app_state_orig
+    let client_session = match app_state_orig
         .get_session_manager()
-        .get_current_user_connections()
-        .lock()
-        .await;
-    let client_session = current_user_connections_connection.get(&token.sub).expect("Error getting client session. This should not appear because a session in create on login/token validations").lock().await;
+        .get_session(&token.sub, &token.sid)
+        .await
+    {
+        Some(session) => session,
+        None => {
+            let message = SocketMessageError::new(String::from(
+                "No session found, please login again",
+            ));
+            match sender
+                .lock()
+                .await
+                .send(Message::Text(
+                    to_string(&message).expect("Could not serialize message"),
+                ))
+                .await
+            {
+                Err(err) => info!("{}", err),
+                Ok(_) => {}
+            };
+            return;
+        }
+    };
 
-    let mut client_session_receiver = client_session.get_user_socket().subscribe();
+    let mut client_session_receiver = client_session.lock().await.get_user_socket().subscribe();
     drop(client_session);
-    drop(current_user_connections_connection);
 
     // get online friends at client start/initialization
     let friends = app_state_orig
@@ -120,6 +260,28 @@ async fn handle_socket<
         .await
         .expect("Failed sending online_friends message");
 
+    // Replay everything which was sent while the user was offline. The client acknowledges what it received
+    let mut message_domain = MessageDomain::new(MessageRepository {
+        pg_pool: app_state_orig.get_db_pool(),
+    });
+    match message_domain.get_undelivered_messages(&token.sub) {
+        Err(err) => tracing::error!(target: "application", "[handle_socket] {}", err),
+        Ok(messages) if messages.len() > 0 => {
+            let mess = SocketMessage::SocketMessageUndeliveredMessages(
+                SocketMessageUndeliveredMessages::new(messages),
+            );
+            if let Err(err) = sender
+                .lock()
+                .await
+                .send(Message::Text(to_string(&mess).unwrap()))
+                .await
+            {
+                info!("{}", err);
+            }
+        }
+        Ok(_) => {}
+    }
+
     let sender_clone = sender.clone();
 
     // Handle whenever someone sends a message to the internally saved session_receiver user_socket
@@ -182,7 +344,7 @@ async fn handle_socket<
     tokio::select! {
         _ = (&mut handle_receive_task) => {
             handle_client_session_receive_task.abort();
-            let session = match app_state_clone2.get_session_manager().remove_from_current_user_connections(&token2.sub).await {
+            let session = match app_state_clone2.get_session_manager().remove_from_current_user_connections(&token2.sub, &token2.sid).await {
                 Ok(s) => s,
                 Err(err) => return info!("Error ocurred removing user from current_user_connections: {}; Maybe the user session expired or the user already logged out", err)
             };
@@ -192,7 +354,7 @@ async fn handle_socket<
         },
         _ = (&mut handle_client_session_receive_task) => {
             handle_receive_task.abort();
-            let session = match app_state_clone2.get_session_manager().remove_from_current_user_connections(&token2.sub).await {
+            let session = match app_state_clone2.get_session_manager().remove_from_current_user_connections(&token2.sub, &token2.sid).await {
                 Ok(s) => s,
                 Err(err) => return info!("Error ocurred removing user from current_user_connections: {}; Maybe the user session expired or the user already logged out", err)
             };
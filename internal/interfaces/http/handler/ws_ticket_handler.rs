@@ -0,0 +1,44 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Extension};
+use std::sync::Arc;
+
+use crate::{
+    appstate::{AppState, IAppState},
+    entities::friends::repository::IFriendRepository,
+    helper::{
+        errors::HTTPResponse,
+        jwt::Token,
+        session::{ISession, ISessionManager},
+        websocket_ticket::{issue_websocket_ticket, WEBSOCKET_TICKET_LIFETIME},
+    },
+    persistence::connection_manager::IConnectionManager,
+};
+
+#[derive(serde::Serialize)]
+pub struct WebsocketTicketDTO {
+    /// Passed as `ticket` query parameter of the websocket handshake
+    pub ticket: String,
+    /// Seconds until the ticket expires
+    pub expires_in: u64,
+}
+
+pub async fn ws_ticket_handler<
+    SM: ISessionManager<S, F>,
+    S: ISession<F>,
+    F: IFriendRepository,
+    C: IConnectionManager,
+>(
+    State(app_state): State<Arc<AppState<SM, S, C, F>>>,
+    token: Extension<Token>,
+) -> impl IntoResponse {
+    match issue_websocket_ticket(app_state.get_websocket_tickets(), &token) {
+        Err(err) => HTTPResponse::<WebsocketTicketDTO>::new_internal_error(err),
+        Ok(ticket) => HTTPResponse::<WebsocketTicketDTO> {
+            data: Some(WebsocketTicketDTO {
+                ticket,
+                expires_in: WEBSOCKET_TICKET_LIFETIME.as_secs(),
+            }),
+            message: None,
+            status: StatusCode::OK,
+        },
+    }
+}
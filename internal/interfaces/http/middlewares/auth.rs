@@ -2,6 +2,8 @@ use crate::{
     appstate::{AppState, IAppState},
     entities::friends::repository::IFriendRepository,
     helper::{
+        cookie::{get_cookie, SESSION_COOKIE},
+        fingerprint::{check_client_binding, ClientFingerprint, CLIENT_FINGERPRINT_SECRET},
         jwt::validate_user_token,
         session::{ISession, ISessionManager},
     },
@@ -9,12 +11,15 @@ use crate::{
 };
 use axum::{
     body::Body,
-    extract::State,
-    http::{HeaderMap, Request, StatusCode},
+    extract::{ConnectInfo, State},
+    http::{
+        header::{AUTHORIZATION, WWW_AUTHENTICATE},
+        HeaderMap, HeaderValue, Request, StatusCode,
+    },
     middleware::Next,
-    response::Response,
+    response::{IntoResponse, Response},
 };
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc};
 
 pub async fn bearer_token_validation<
     'a,
@@ -24,27 +29,72 @@ pub async fn bearer_token_validation<
     C: IConnectionManager,
 >(
     State(app_state): State<Arc<AppState<SM, S, C, F>>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
     headers: HeaderMap,
     request: Request<Body>,
     next: Next,
-) -> Result<Response, StatusCode> {
-    let auth_header = match headers.get("authorization") {
-        None => return Err(StatusCode::UNAUTHORIZED),
-        Some(header) => {
-            let owned = header.to_owned();
-            let bearer_string = owned.to_str().unwrap().to_owned();
-            bearer_string.replace("Bearer ", "")
-        }
+) -> Result<Response, Response> {
+    let auth_token = match request_token(&headers) {
+        None => return Err(StatusCode::UNAUTHORIZED.into_response()),
+        Some(auth_token) => auth_token.value,
     };
 
-    match validate_user_token(
-        auth_header,
-        app_state.get_config().env.HASHING_KEY.as_bytes(),
+    let token = match validate_user_token(
+        &auth_token,
+        app_state.get_keyring(),
+        app_state.get_token_revocations(),
     ) {
-        Err(_) => return Err(StatusCode::UNAUTHORIZED),
-        Ok(_) => {}
+        Err(_) => return Err(StatusCode::UNAUTHORIZED.into_response()),
+        Ok(token) => token,
     };
 
+    if let Err(err) = check_client_binding(
+        &token.cfp,
+        &ClientFingerprint::from_request(
+            &app_state.get_keyring().derive_secret(CLIENT_FINGERPRINT_SECRET),
+            &headers,
+            &addr,
+        ),
+        app_state.get_client_binding(),
+    ) {
+        tracing::warn!(target: "audit", "[bearer_token_validation] token of {} session {} presented by another client from {}: {}", token.sub, token.sid, addr.ip(), err);
+        return Err(step_up_challenge());
+    }
+
     let response: Response = next.run(request).await;
     Ok(response)
 }
+
+/// Token a request is authenticated with
+pub struct RequestToken {
+    pub value: String,
+    /// Cookies are sent by the browser on its own, so these requests have to be checked for CSRF
+    pub from_cookie: bool,
+}
+
+/// The `Authorization` header takes precedence over the session cookie
+pub fn request_token(headers: &HeaderMap) -> Option<RequestToken> {
+    if let Some(header) = headers.get(AUTHORIZATION) {
+        let value = header.to_str().ok()?;
+        return Some(RequestToken {
+            value: value.trim_start_matches("Bearer ").to_owned(),
+            from_cookie: false,
+        });
+    }
+    get_cookie(headers, SESSION_COOKIE).map(|value| RequestToken {
+        value,
+        from_cookie: true,
+    })
+}
+
+/// Step-up challenge of RFC 9470, the client has to authenticate with its password again
+pub fn step_up_challenge() -> Response {
+    let mut response = StatusCode::UNAUTHORIZED.into_response();
+    response.headers_mut().insert(
+        WWW_AUTHENTICATE,
+        HeaderValue::from_static(
+            "Bearer error=\"insufficient_user_authentication\", error_description=\"The client changed, log in again\"",
+        ),
+    );
+    response
+}
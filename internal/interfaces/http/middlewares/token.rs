@@ -12,10 +12,12 @@ use crate::{
     appstate::{AppState, IAppState},
     entities::friends::repository::IFriendRepository,
     helper::{
+        cookie::{check_csrf, requires_csrf_check},
         errors::HTTPResponse,
         jwt::token_into_typed,
         session::{ISession, ISessionManager},
     },
+    interfaces::http::middlewares::auth::request_token,
     persistence::connection_manager::IConnectionManager,
 };
 
@@ -30,7 +32,7 @@ pub async fn token_mw<
     next: Next,
 ) -> Result<Response, HTTPResponse<()>> {
     let headers = request.headers();
-    let auth_token = match headers.get("authorization") {
+    let request_token = match request_token(headers) {
         None => {
             return Err(HTTPResponse {
                 message: Some(String::from("Authentication token not provided")),
@@ -38,13 +40,20 @@ pub async fn token_mw<
                 status: StatusCode::UNAUTHORIZED,
             })
         }
-        Some(token) => token.to_str().unwrap().replace("Bearer ", ""),
+        Some(request_token) => request_token,
     };
 
-    let auth_token = match token_into_typed(
-        &auth_token,
-        app_state.get_config().env.HASHING_KEY.as_bytes(),
-    ) {
+    if request_token.from_cookie && requires_csrf_check(request.method()) {
+        if let Err(err) = check_csrf(headers) {
+            return Err(HTTPResponse {
+                message: Some(err),
+                data: None,
+                status: StatusCode::FORBIDDEN,
+            });
+        }
+    }
+
+    let auth_token = match token_into_typed(&request_token.value, app_state.get_keyring()) {
         Err(_) => {
             return Err(HTTPResponse {
                 message: Some(String::from("Could not deserialize token")),
@@ -55,6 +64,19 @@ pub async fn token_mw<
         Ok(token) => token,
     };
 
+    // Expired tokens are accepted here, e.g. to log out, revoked ones are not
+    match app_state.get_token_revocations().is_revoked(&auth_token) {
+        Err(err) => return Err(HTTPResponse::new_internal_error(err)),
+        Ok(true) => {
+            return Err(HTTPResponse {
+                message: Some(String::from("Token was revoked")),
+                data: None,
+                status: StatusCode::UNAUTHORIZED,
+            })
+        }
+        Ok(false) => {}
+    }
+
     request.extensions_mut().insert(auth_token);
     let response: Response = next.run(request).await;
     Ok(response)
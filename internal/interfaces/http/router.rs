@@ -3,7 +3,7 @@ use std::sync::Arc;
 use axum::{
     http::{HeaderValue, Method},
     middleware,
-    routing::{get, patch, post},
+    routing::{delete, get, patch, post},
     Router,
 };
 use tower_http::{
@@ -15,8 +15,9 @@ use crate::{
     appstate::AppState,
     config::ConfigManager,
     entities::{
+        conversations,
         friends::{self, repository::IFriendRepository},
-        messages, users,
+        keys, messages, mfa, rooms, users,
     },
     helper::session::{ISession, ISessionManager},
     interfaces::http::middlewares,
@@ -24,7 +25,7 @@ use crate::{
     persistence::connection_manager::IConnectionManager,
 };
 
-use super::handler::{version_handler, ws_handler};
+use super::handler::{version_handler, ws_handler, ws_ticket_handler};
 
 pub fn get_main_router<
     SM: ISessionManager<S, F>,
@@ -42,6 +43,18 @@ pub fn get_main_router<
             patch(messages::controller::set_messages_read),
         )
         .route("/messages", get(messages::controller::get_messages))
+        .route(
+            "/messages/:uuid",
+            patch(messages::controller::edit_message).delete(messages::controller::delete_message),
+        )
+        .route(
+            "/messages/:uuid/edits",
+            get(messages::controller::get_message_edits),
+        )
+        .route(
+            "/conversations",
+            get(conversations::controller::get_conversations),
+        )
         .route(
             "/friends/active",
             get(friends::controller::get_active_friends),
@@ -56,7 +69,37 @@ pub fn get_main_router<
             "/friend-requests/:uuid",
             patch(friends::controller::patch_friend_request),
         )
-        .route("/token", post(users::controller::token))
+        .route(
+            "/rooms",
+            get(rooms::controller::get_rooms).post(rooms::controller::create_room),
+        )
+        .route(
+            "/rooms/:uuid/members",
+            post(rooms::controller::invite_to_room),
+        )
+        .route("/rooms/:uuid/leave", post(rooms::controller::leave_room))
+        .route("/logout/all", post(users::controller::logout_all))
+        .route("/users/me", delete(users::controller::delete_user))
+        .route(
+            "/users/me/keys",
+            get(keys::controller::get_own_keys).post(keys::controller::rotate_key),
+        )
+        .route("/users/:username/keys", get(keys::controller::get_keys))
+        .route(
+            "/users/me/password",
+            patch(users::controller::change_password),
+        )
+        .route("/users/me/mfa", delete(mfa::controller::disable_mfa))
+        .route("/users/me/mfa/totp", post(mfa::controller::enroll_totp))
+        .route(
+            "/users/me/mfa/totp/confirm",
+            post(mfa::controller::confirm_totp),
+        )
+        .route(
+            "/users/me/mfa/recovery-codes",
+            post(mfa::controller::regenerate_recovery_codes),
+        )
+        .route("/ws/ticket", post(ws_ticket_handler::ws_ticket_handler))
         .route_layer(middleware::from_fn_with_state(
             app_state.clone(),
             middlewares::auth::bearer_token_validation,
@@ -67,8 +110,11 @@ pub fn get_main_router<
             middlewares::token::token_mw,
         ))
         .route("/users", post(users::controller::create_user))
+        .route("/users/key-challenge", post(users::controller::key_challenge))
         .route("/ws", get(ws_handler::ws_handler))
         .route("/login", post(users::controller::login))
+        .route("/login/mfa", post(users::controller::login_mfa))
+        .route("/token", post(users::controller::token))
         .route("/version", get(version_handler::version_handler))
         .route_layer(middleware::from_fn(middlewares::cookies::cookie_mw))
         .layer(cors)
@@ -92,8 +138,16 @@ pub fn initialize_http_server<
     };
 
     let cors = CorsLayer::new()
-        .allow_methods([Method::GET, Method::POST, Method::OPTIONS, Method::PATCH])
-        .allow_headers(AllowHeaders::any())
+        .allow_methods([
+            Method::GET,
+            Method::POST,
+            Method::OPTIONS,
+            Method::PATCH,
+            Method::DELETE,
+        ])
+        // Credentials cannot be combined with wildcards, so cookies only work across origins if CORS_ORIGIN is set
+        .allow_headers(AllowHeaders::mirror_request())
+        .allow_credentials(config.env.CORS_ORIGIN.is_some())
         .allow_origin(origin);
 
     let trace_layer: TraceLayer<
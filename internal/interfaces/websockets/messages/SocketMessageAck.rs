@@ -0,0 +1,50 @@
+use crate::appstate::{AppState, IAppState};
+use crate::entities::friends::repository::IFriendRepository;
+use crate::entities::messages::{messages::MessageDomain, repository::MessageRepository};
+use crate::helper::session::ISessionManager;
+use crate::helper::{jwt::Token, session::ISession};
+use crate::interfaces::websockets::socket_messages::{Receivable, SocketMessageError};
+use crate::persistence::connection_manager::IConnectionManager;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Sent by the client once messages reached the device
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+pub struct SocketMessageAck {
+    pub message_ids: Vec<Uuid>,
+    pub TYPE: Option<String>,
+}
+
+impl SocketMessageAck {
+    pub fn new(message_ids: Vec<Uuid>) -> SocketMessageAck {
+        SocketMessageAck {
+            message_ids,
+            TYPE: Some(String::from("SOCKET_MESSAGE_ACK")),
+        }
+    }
+}
+
+impl<SM: ISessionManager<S, F>, S: ISession<F>, F: IFriendRepository, C: IConnectionManager>
+    Receivable<SM, S, F, C> for SocketMessageAck
+{
+    async fn handle_receive(
+        &self,
+        app_state: Arc<AppState<SM, S, C, F>>,
+        token: Token,
+    ) -> Result<(), SocketMessageError> {
+        let message_repo = MessageRepository {
+            pg_pool: app_state.get_db_pool(),
+        };
+        let mut message_domain = MessageDomain::new(message_repo);
+
+        match message_domain.acknowledge_messages(&self.message_ids, &token.sub) {
+            Err(err) => {
+                tracing::error!("{}", &err);
+                Err(SocketMessageError::new(String::from(
+                    "Could not acknowledge messages",
+                )))
+            }
+            Ok(_) => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use crate::appstate::{AppState, IAppState};
+use crate::entities::friends::repository::IFriendRepository;
+use crate::entities::messages::{messages::MessageDomain, repository::MessageRepository};
+use crate::helper::session::ISessionManager;
+use crate::helper::{jwt::Token, session::ISession};
+use crate::interfaces::websockets::socket_messages::{
+    Receivable, SocketMessage, SocketMessageError,
+};
+use crate::persistence::connection_manager::IConnectionManager;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Deletion of a message, sent by its sender and pushed to everyone who had a copy of it
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+pub struct SocketMessageDelete {
+    pub message_id: Uuid,
+    pub sender: Option<String>,
+    pub TYPE: Option<String>,
+}
+
+impl SocketMessageDelete {
+    pub fn new(message_id: Uuid, sender: Option<String>) -> SocketMessageDelete {
+        SocketMessageDelete {
+            message_id,
+            sender,
+            TYPE: Some(String::from("SOCKET_MESSAGE_DELETE")),
+        }
+    }
+}
+
+pub async fn send_message_delete<SM: ISessionManager<S, F>, S: ISession<F>, F: IFriendRepository>(
+    session_manager: &SM,
+    delete: SocketMessageDelete,
+    recipients: Vec<String>,
+) {
+    for recipient in recipients.iter() {
+        session_manager
+            .send_to_user(recipient, SocketMessage::SocketMessageDelete(delete.clone()))
+            .await;
+    }
+}
+
+impl<SM: ISessionManager<S, F>, S: ISession<F>, F: IFriendRepository, C: IConnectionManager>
+    Receivable<SM, S, F, C> for SocketMessageDelete
+{
+    async fn handle_receive(
+        &self,
+        app_state: Arc<AppState<SM, S, C, F>>,
+        token: Token,
+    ) -> Result<(), SocketMessageError> {
+        let message_repo = MessageRepository {
+            pg_pool: app_state.get_db_pool(),
+        };
+        let mut message_domain = MessageDomain::new(message_repo);
+
+        let recipients = match message_domain.delete_message(&token.sub, &self.message_id) {
+            Err(err) => {
+                return Err(SocketMessageError::new(
+                    err.message
+                        .unwrap_or_else(|| String::from("Uuups, something went wrong..")),
+                ))
+            }
+            Ok(res) => res,
+        };
+
+        send_message_delete(
+            app_state.get_session_manager(),
+            SocketMessageDelete::new(self.message_id, Some(token.sub)),
+            recipients,
+        )
+        .await;
+        Ok(())
+    }
+}
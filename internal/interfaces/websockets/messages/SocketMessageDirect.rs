@@ -12,6 +12,7 @@ use crate::{
     },
     helper::{jwt::Token, session::ISession},
 };
+use axum::http::StatusCode;
 use std::sync::Arc;
 use uuid::Uuid;
 
@@ -23,6 +24,9 @@ pub struct SocketMessageDirect {
     pub message_signature: String,
     pub message_self_encrypted: String,
     pub message_self_encrypted_signature: String,
+    /// Key of the recipient `message` is encrypted for and key of the sender both copies are signed with
+    pub key_id: Option<Uuid>,
+    pub sender_key_id: Option<Uuid>,
     pub id: Option<Uuid>,
     pub TYPE: Option<String>,
 }
@@ -35,12 +39,16 @@ impl SocketMessageDirect {
         message_signature: String,
         message_self_encrypted: String,
         message_self_encrypted_signature: String,
+        key_id: Option<Uuid>,
+        sender_key_id: Option<Uuid>,
     ) -> SocketMessageDirect {
         SocketMessageDirect {
             message,
             message_signature,
             message_self_encrypted,
             message_self_encrypted_signature,
+            key_id,
+            sender_key_id,
             id: Some(Uuid::new_v4()),
             recipient,
             sender,
@@ -66,7 +74,8 @@ impl<SM: ISessionManager<S, F>, S: ISession<F>, F: IFriendRepository, C: IConnec
         };
 
         let friend_domain = FriendDomain::new(friend_repo);
-        let mut message_domain = MessageDomain::new(message_repo);
+        let mut message_domain = MessageDomain::new(message_repo)
+            .with_signature_verification(app_state.get_config().env.VERIFY_MESSAGE_SIGNATURES);
         let recipient = match &self.recipient {
             None => {
                 return Err(SocketMessageError::new(String::from(
@@ -93,7 +102,14 @@ impl<SM: ISessionManager<S, F>, S: ISession<F>, F: IFriendRepository, C: IConnec
         }
 
         // Get fresh connection to get latest state
-        let client_session = app_state.get_session_manager().get_current_user_connections().lock().await.get(&token.sub).expect("Error getting client session. This should not appear because a session in create on login/token validations").lock().await.clone();
+        let client_session = match app_state.get_session_manager().get_session(&token.sub, &token.sid).await {
+            Some(session) => session.lock().await.clone(),
+            None => {
+                return Err(SocketMessageError::new(String::from(
+                    "No session found, please login again",
+                )))
+            }
+        };
 
         let direct_message = SocketMessageDirect::new(
             Some(token.sub),
@@ -102,21 +118,24 @@ impl<SM: ISessionManager<S, F>, S: ISession<F>, F: IFriendRepository, C: IConnec
             self.message_signature.clone(),
             self.message_self_encrypted.clone(),
             self.message_self_encrypted_signature.clone(),
+            self.key_id,
+            self.sender_key_id,
         );
 
         let message = message_domain.direct_message_to_message_entity(&direct_message);
-        let message = match message {
+        let (message, payloads) = match message {
             Ok(m) => m,
             Err(err) => {
                 return Err(SocketMessageError::new(err))
             }
         };
 
-        match message_domain.save_message(&message) {
-            Err(err) => {
-                tracing::error!("{}", &err);
+        match message_domain.save_message(&message, &payloads) {
+            Err(err) if err.status == StatusCode::INTERNAL_SERVER_ERROR => {
+                tracing::error!("{}", err.message.unwrap_or_default());
                 return Err(SocketMessageError::new(String::from("An error ocurred while saving the message ...")))
             },
+            Err(err) => return Err(SocketMessageError::new(err.message.unwrap_or_default())),
             Ok(_) => {}
         };
 
@@ -124,22 +143,14 @@ impl<SM: ISessionManager<S, F>, S: ISession<F>, F: IFriendRepository, C: IConnec
             .send_direct_message(SocketMessage::SocketMessageDirect(direct_message.clone()))
             .await;
 
-        let current_user_connections = app_state
-            .current_user_connections
-            .get_current_user_connections()
-            .lock()
-            .await
-            .clone();
-        let recipient_session_manager = current_user_connections.get(recipient).clone();
-        match recipient_session_manager {
-            None => {}
-            Some(sm) => {
-                sm.lock()
-                    .await
-                    .send_direct_message(SocketMessage::SocketMessageDirect(direct_message.clone()))
-                    .await;
-            }
-        }
+        // The recipient might be connected to another instance, so always go through the backplane
+        app_state
+            .get_session_manager()
+            .send_to_user(
+                recipient,
+                SocketMessage::SocketMessageDirect(direct_message.clone()),
+            )
+            .await;
         return Ok(());
     }
 }
@@ -0,0 +1,99 @@
+use crate::appstate::{AppState, IAppState};
+use crate::entities::friends::repository::IFriendRepository;
+use crate::entities::messages::{messages::MessageDomain, repository::MessageRepository};
+use crate::helper::session::ISessionManager;
+use crate::helper::{jwt::Token, session::ISession};
+use crate::interfaces::websockets::socket_messages::{
+    Receivable, SocketMessage, SocketMessageError,
+};
+use crate::persistence::connection_manager::IConnectionManager;
+use std::sync::Arc;
+use std::time::SystemTime;
+use uuid::Uuid;
+
+/// New content of an existing direct message, sent by its sender and pushed to both parties
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+pub struct SocketMessageEdit {
+    pub message_id: Uuid,
+    pub sender: Option<String>,
+    pub recipient: Option<String>,
+    pub message: String,
+    pub message_signature: String,
+    pub message_self_encrypted: String,
+    pub message_self_encrypted_signature: String,
+    pub key_id: Option<Uuid>,
+    pub sender_key_id: Option<Uuid>,
+    pub edited_at: Option<SystemTime>,
+    pub TYPE: Option<String>,
+}
+
+impl SocketMessageEdit {
+    pub fn new(
+        message_id: Uuid,
+        sender: Option<String>,
+        recipient: Option<String>,
+        message: String,
+        message_signature: String,
+        message_self_encrypted: String,
+        message_self_encrypted_signature: String,
+        key_id: Option<Uuid>,
+        sender_key_id: Option<Uuid>,
+        edited_at: Option<SystemTime>,
+    ) -> SocketMessageEdit {
+        SocketMessageEdit {
+            message_id,
+            sender,
+            recipient,
+            message,
+            message_signature,
+            message_self_encrypted,
+            message_self_encrypted_signature,
+            key_id,
+            sender_key_id,
+            edited_at,
+            TYPE: Some(String::from("SOCKET_MESSAGE_EDIT")),
+        }
+    }
+}
+
+/// Pushes the edit to every device of sender and recipient
+pub async fn send_message_edit<SM: ISessionManager<S, F>, S: ISession<F>, F: IFriendRepository>(
+    session_manager: &SM,
+    edit: SocketMessageEdit,
+) {
+    let parties = [edit.sender.clone(), edit.recipient.clone()];
+    for party in parties.iter().flatten() {
+        session_manager
+            .send_to_user(party, SocketMessage::SocketMessageEdit(edit.clone()))
+            .await;
+    }
+}
+
+impl<SM: ISessionManager<S, F>, S: ISession<F>, F: IFriendRepository, C: IConnectionManager>
+    Receivable<SM, S, F, C> for SocketMessageEdit
+{
+    async fn handle_receive(
+        &self,
+        app_state: Arc<AppState<SM, S, C, F>>,
+        token: Token,
+    ) -> Result<(), SocketMessageError> {
+        let message_repo = MessageRepository {
+            pg_pool: app_state.get_db_pool(),
+        };
+        let mut message_domain = MessageDomain::new(message_repo)
+            .with_signature_verification(app_state.get_config().env.VERIFY_MESSAGE_SIGNATURES);
+
+        let (_, edit) = match message_domain.edit_message(&token.sub, self) {
+            Err(err) => {
+                return Err(SocketMessageError::new(
+                    err.message
+                        .unwrap_or_else(|| String::from("Uuups, something went wrong..")),
+                ))
+            }
+            Ok(res) => res,
+        };
+
+        send_message_edit(app_state.get_session_manager(), edit).await;
+        Ok(())
+    }
+}
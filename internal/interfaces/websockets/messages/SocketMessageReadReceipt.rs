@@ -0,0 +1,79 @@
+use crate::appstate::{AppState, IAppState};
+use crate::entities::friends::repository::IFriendRepository;
+use crate::entities::messages::{messages::MessageDomain, repository::MessageRepository};
+use crate::helper::session::ISessionManager;
+use crate::helper::{jwt::Token, session::ISession};
+use crate::interfaces::websockets::socket_messages::{
+    Receivable, SocketMessage, SocketMessageError,
+};
+use crate::persistence::connection_manager::IConnectionManager;
+use std::collections::HashMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Sent by the recipient to mark messages as read, and pushed to the senders of those messages.
+/// The ids are not called `message_ids` so the untagged deserialization does not mistake it for a SocketMessageAck
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+pub struct SocketMessageReadReceipt {
+    pub read_message_ids: Vec<Uuid>,
+    pub reader: Option<String>,
+    pub TYPE: Option<String>,
+}
+
+impl SocketMessageReadReceipt {
+    pub fn new(read_message_ids: Vec<Uuid>, reader: Option<String>) -> SocketMessageReadReceipt {
+        SocketMessageReadReceipt {
+            read_message_ids,
+            reader,
+            TYPE: Some(String::from("SOCKET_MESSAGE_READ_RECEIPT")),
+        }
+    }
+}
+
+/// Tells every sender which of their messages were read by the reader
+pub async fn send_read_receipts<SM: ISessionManager<S, F>, S: ISession<F>, F: IFriendRepository>(
+    session_manager: &SM,
+    reader: &String,
+    read_by_sender: HashMap<String, Vec<Uuid>>,
+) {
+    for (sender, message_ids) in read_by_sender {
+        session_manager
+            .send_to_user(
+                &sender,
+                SocketMessage::SocketMessageReadReceipt(SocketMessageReadReceipt::new(
+                    message_ids,
+                    Some(reader.clone()),
+                )),
+            )
+            .await;
+    }
+}
+
+impl<SM: ISessionManager<S, F>, S: ISession<F>, F: IFriendRepository, C: IConnectionManager>
+    Receivable<SM, S, F, C> for SocketMessageReadReceipt
+{
+    async fn handle_receive(
+        &self,
+        app_state: Arc<AppState<SM, S, C, F>>,
+        token: Token,
+    ) -> Result<(), SocketMessageError> {
+        let message_repo = MessageRepository {
+            pg_pool: app_state.get_db_pool(),
+        };
+        let mut message_domain = MessageDomain::new(message_repo);
+
+        let read_by_sender =
+            match message_domain.set_message_read(&self.read_message_ids, &true, &token.sub) {
+                Err(err) => {
+                    tracing::error!("{}", &err);
+                    return Err(SocketMessageError::new(String::from(
+                        "Could not mark messages as read",
+                    )));
+                }
+                Ok(res) => res,
+            };
+
+        send_read_receipts(app_state.get_session_manager(), &token.sub, read_by_sender).await;
+        Ok(())
+    }
+}
@@ -0,0 +1,118 @@
+use crate::appstate::{AppState, IAppState};
+use crate::entities::friends::repository::IFriendRepository;
+use crate::entities::messages::{messages::MessageDomain, repository::MessageRepository};
+use crate::entities::rooms::{repository::RoomRepository, rooms::RoomDomain};
+use crate::helper::session::ISessionManager;
+use crate::helper::{jwt::Token, session::ISession};
+use crate::interfaces::websockets::socket_messages::{
+    Receivable, SocketMessage, SocketMessageError, SocketMessagePayload,
+};
+use crate::persistence::connection_manager::IConnectionManager;
+use axum::http::StatusCode;
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+pub struct SocketMessageRoom {
+    pub room_id: Uuid,
+    pub sender: Option<String>,
+    // One encrypted copy per room member
+    pub payloads: Vec<SocketMessagePayload>,
+    /// Key of the sender the copies are signed with
+    pub sender_key_id: Option<Uuid>,
+    pub id: Option<Uuid>,
+    pub TYPE: Option<String>,
+}
+
+impl SocketMessageRoom {
+    pub fn new(
+        room_id: Uuid,
+        sender: Option<String>,
+        payloads: Vec<SocketMessagePayload>,
+        sender_key_id: Option<Uuid>,
+    ) -> SocketMessageRoom {
+        SocketMessageRoom {
+            room_id,
+            sender,
+            payloads,
+            sender_key_id,
+            id: Some(Uuid::new_v4()),
+            TYPE: Some(String::from("SOCKET_MESSAGE_ROOM")),
+        }
+    }
+
+    /// Returns a copy of this message only containing the payload addressed to the recipient
+    pub fn for_recipient(&self, recipient: &String) -> SocketMessageRoom {
+        let mut message = self.clone();
+        message.payloads.retain(|p| &p.recipient == recipient);
+        message
+    }
+}
+
+impl<SM: ISessionManager<S, F>, S: ISession<F>, F: IFriendRepository, C: IConnectionManager>
+    Receivable<SM, S, F, C> for SocketMessageRoom
+{
+    async fn handle_receive(
+        &self,
+        app_state: Arc<AppState<SM, S, C, F>>,
+        token: Token,
+    ) -> Result<(), SocketMessageError> {
+        let room_repo = RoomRepository {
+            pg_pool: app_state.get_db_pool(),
+        };
+        let message_repo = MessageRepository {
+            pg_pool: app_state.get_db_pool(),
+        };
+        let mut room_domain = RoomDomain::new(room_repo);
+        let mut message_domain = MessageDomain::new(message_repo)
+            .with_signature_verification(app_state.get_config().env.VERIFY_MESSAGE_SIGNATURES);
+
+        // Also checks whether the sender is part of the room
+        let members = match room_domain.get_room_members(&self.room_id, &token.sub) {
+            Ok(members) => members,
+            Err(err) => {
+                return Err(SocketMessageError::new(
+                    err.message
+                        .unwrap_or_else(|| String::from("Uuups, something went wrong..")),
+                ))
+            }
+        };
+
+        let room_message =
+            SocketMessageRoom::new(
+            self.room_id,
+            Some(token.sub),
+            self.payloads.clone(),
+            self.sender_key_id,
+        );
+
+        let (message, payloads) =
+            match message_domain.room_message_to_message_entity(&room_message, &members) {
+                Ok(m) => m,
+                Err(err) => return Err(SocketMessageError::new(err)),
+            };
+
+        match message_domain.save_message(&message, &payloads) {
+            Err(err) if err.status == StatusCode::INTERNAL_SERVER_ERROR => {
+                tracing::error!("{}", err.message.unwrap_or_default());
+                return Err(SocketMessageError::new(String::from(
+                    "An error ocurred while saving the message ...",
+                )));
+            }
+            Err(err) => return Err(SocketMessageError::new(err.message.unwrap_or_default())),
+            Ok(_) => {}
+        };
+
+        // Fan out to every member, including the sender. Each member only receives the copy addressed to them
+        for member in members.iter() {
+            app_state
+                .get_session_manager()
+                .send_to_user(
+                    member,
+                    SocketMessage::SocketMessageRoom(room_message.for_recipient(member)),
+                )
+                .await;
+        }
+        return Ok(());
+    }
+}
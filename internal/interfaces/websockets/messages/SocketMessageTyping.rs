@@ -0,0 +1,80 @@
+use crate::appstate::{AppState, IAppState};
+use crate::entities::friends::repository::IFriendRepository;
+use crate::entities::friends::{repository::FriendRepository, service::FriendDomain};
+use crate::helper::session::ISessionManager;
+use crate::helper::{jwt::Token, session::ISession};
+use crate::interfaces::websockets::socket_messages::{
+    Receivable, SocketMessage, SocketMessageError,
+};
+use crate::persistence::connection_manager::IConnectionManager;
+use std::sync::Arc;
+
+/// Ephemeral "is typing" indicator. Only forwarded to live sessions and never persisted
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+pub struct SocketMessageTyping {
+    pub recipient: String,
+    pub sender: Option<String>,
+    pub is_typing: bool,
+    pub TYPE: Option<String>,
+}
+
+impl SocketMessageTyping {
+    pub fn new(sender: Option<String>, recipient: String, is_typing: bool) -> SocketMessageTyping {
+        SocketMessageTyping {
+            recipient,
+            sender,
+            is_typing,
+            TYPE: Some(String::from("SOCKET_MESSAGE_TYPING")),
+        }
+    }
+}
+
+impl<SM: ISessionManager<S, F>, S: ISession<F>, F: IFriendRepository, C: IConnectionManager>
+    Receivable<SM, S, F, C> for SocketMessageTyping
+{
+    async fn handle_receive(
+        &self,
+        app_state: Arc<AppState<SM, S, C, F>>,
+        token: Token,
+    ) -> Result<(), SocketMessageError> {
+        // Clients send these on every keystroke, excess indicators are just dropped
+        if let Err(_) = app_state.typing_rate_limiter.check(&token.sub) {
+            tracing::trace!(target: "websocket::message", "Dropped typing indicator of {}", &token.sub);
+            return Ok(());
+        }
+
+        let friend_repo = FriendRepository {
+            pg_pool: C::new(app_state.get_config().env),
+        };
+        let friend_domain = FriendDomain::new(friend_repo);
+
+        match friend_domain.check_if_user_has_friend(&token.sub, &self.recipient) {
+            Err(_) => {
+                return Err(SocketMessageError::new(String::from(
+                    "Uuups, something went wrong..",
+                )))
+            }
+            Ok(false) => {
+                return Err(SocketMessageError::new(format!(
+                    "You are not befriended with {}",
+                    self.recipient
+                )))
+            }
+            Ok(true) => {}
+        };
+
+        // The backplane only reaches sessions which are connected right now
+        app_state
+            .get_session_manager()
+            .send_to_user(
+                &self.recipient,
+                SocketMessage::SocketMessageTyping(SocketMessageTyping::new(
+                    Some(token.sub),
+                    self.recipient.clone(),
+                    self.is_typing,
+                )),
+            )
+            .await;
+        Ok(())
+    }
+}
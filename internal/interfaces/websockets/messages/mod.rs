@@ -1 +1,7 @@
 pub mod SocketMessageDirect;
+pub mod SocketMessageRoom;
+pub mod SocketMessageAck;
+pub mod SocketMessageReadReceipt;
+pub mod SocketMessageTyping;
+pub mod SocketMessageEdit;
+pub mod SocketMessageDelete;
@@ -4,7 +4,7 @@ use uuid::Uuid;
 
 use crate::{
     appstate::AppState,
-    entities::friends::repository::IFriendRepository,
+    entities::{friends::repository::IFriendRepository, messages::repository::MessageDTO},
     helper::{
         jwt::Token,
         session::{ISession, ISessionManager},
@@ -12,7 +12,12 @@ use crate::{
     persistence::connection_manager::IConnectionManager,
 };
 
-use super::messages::SocketMessageDirect::SocketMessageDirect;
+use super::messages::{
+    SocketMessageAck::SocketMessageAck, SocketMessageDelete::SocketMessageDelete,
+    SocketMessageDirect::SocketMessageDirect, SocketMessageEdit::SocketMessageEdit,
+    SocketMessageReadReceipt::SocketMessageReadReceipt, SocketMessageRoom::SocketMessageRoom,
+    SocketMessageTyping::SocketMessageTyping,
+};
 
 #[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
 pub struct SocketMessageNotification {
@@ -41,6 +46,8 @@ impl SocketMessageNotification {
 pub enum EEvent {
     ONLINE,
     OFFLINE,
+    /// The user deleted their account, clients drop the conversation with them
+    DELETED,
 }
 
 #[derive(Clone, serde::Deserialize, serde::Serialize, Debug)]
@@ -107,6 +114,52 @@ impl SocketMessageFriendRequest {
     }
 }
 
+/// Pushed to the friends of a user who replaced their public key. New messages have to be encrypted for `key_id`
+#[derive(Clone, serde::Deserialize, serde::Serialize, Debug)]
+pub struct SocketMessageKeyChange {
+    pub username: String,
+    pub key_id: Uuid,
+    pub public_key: String,
+    pub TYPE: String,
+}
+
+impl SocketMessageKeyChange {
+    pub fn new(username: String, key_id: Uuid, public_key: String) -> SocketMessageKeyChange {
+        SocketMessageKeyChange {
+            username,
+            key_id,
+            public_key,
+            TYPE: String::from("SOCKET_MESSAGE_KEY_CHANGE"),
+        }
+    }
+}
+
+/// Pushed on connect with every message which did not reach any of the user's devices yet, oldest first
+#[derive(Clone, serde::Deserialize, serde::Serialize, Debug)]
+pub struct SocketMessageUndeliveredMessages {
+    pub messages: Vec<MessageDTO>,
+    pub TYPE: String,
+}
+
+impl SocketMessageUndeliveredMessages {
+    pub fn new(messages: Vec<MessageDTO>) -> SocketMessageUndeliveredMessages {
+        SocketMessageUndeliveredMessages {
+            messages,
+            TYPE: String::from("SOCKET_MESSAGE_UNDELIVERED_MESSAGES"),
+        }
+    }
+}
+
+/// A single encrypted copy of a message, addressed to one recipient's public key
+#[derive(Clone, serde::Deserialize, serde::Serialize, Debug, PartialEq)]
+pub struct SocketMessagePayload {
+    pub recipient: String,
+    pub message: String,
+    pub message_signature: String,
+    /// Key of the recipient the copy is encrypted for
+    pub key_id: Option<Uuid>,
+}
+
 #[derive(Clone, serde::Deserialize, serde::Serialize, Debug)]
 pub struct SocketMessageError {
     pub message: String,
@@ -140,11 +193,21 @@ pub trait Receivable<
 #[serde(untagged)]
 
 pub enum SocketMessage {
+    // Has to be tried before SocketMessageDirect, an edit contains every field of a direct message
+    SocketMessageEdit(SocketMessageEdit),
     SocketMessageDirect(SocketMessageDirect),
     SocketMessageNotification(SocketMessageNotification),
     SocketMessageStatusChange(SocketMessageStatusChange),
     SocketMessageOnlineUsers(SocketMessageOnlineUsers),
     SocketMessageFriendRequest(SocketMessageFriendRequest),
+    SocketMessageRoom(SocketMessageRoom),
+    SocketMessageAck(SocketMessageAck),
+    SocketMessageUndeliveredMessages(SocketMessageUndeliveredMessages),
+    SocketMessageReadReceipt(SocketMessageReadReceipt),
+    SocketMessageTyping(SocketMessageTyping),
+    SocketMessageKeyChange(SocketMessageKeyChange),
+    // Has to be tried after SocketMessageEdit, which also carries a message_id
+    SocketMessageDelete(SocketMessageDelete),
 }
 
 impl SocketMessage {
@@ -155,6 +218,14 @@ impl SocketMessage {
             SocketMessage::SocketMessageNotification(m) => tracing::trace!(target: "websocket::message", "{}: {} ", m.TYPE, m.debug()),
             SocketMessage::SocketMessageOnlineUsers(m) => tracing::trace!(target: "websocket::message", "{}", m.TYPE),
             SocketMessage::SocketMessageStatusChange(m) => tracing::trace!(target: "websocket::message", "{}: user: {} status: {:?}", m.TYPE, m.user_id, m.status),
+            SocketMessage::SocketMessageRoom(m) => tracing::trace!(target: "websocket::message", "SocketMessageRoom: {} -> {}", m.sender.clone().unwrap_or_else(||String::from("_")), m.room_id),
+            SocketMessage::SocketMessageAck(m) => tracing::trace!(target: "websocket::message", "SocketMessageAck: {} messages", m.message_ids.len()),
+            SocketMessage::SocketMessageReadReceipt(m) => tracing::trace!(target: "websocket::message", "SocketMessageReadReceipt: {} read {} messages", m.reader.clone().unwrap_or_else(||String::from("_")), m.read_message_ids.len()),
+            SocketMessage::SocketMessageTyping(m) => tracing::trace!(target: "websocket::message", "SocketMessageTyping: {} -> {}: {}", m.sender.clone().unwrap_or_else(||String::from("_")), m.recipient, m.is_typing),
+            SocketMessage::SocketMessageEdit(m) => tracing::trace!(target: "websocket::message", "SocketMessageEdit: {} edited {}", m.sender.clone().unwrap_or_else(||String::from("_")), m.message_id),
+            SocketMessage::SocketMessageDelete(m) => tracing::trace!(target: "websocket::message", "SocketMessageDelete: {} deleted {}", m.sender.clone().unwrap_or_else(||String::from("_")), m.message_id),
+            SocketMessage::SocketMessageKeyChange(m) => tracing::trace!(target: "websocket::message", "{}: {} now uses key {}", m.TYPE, m.username, m.key_id),
+            SocketMessage::SocketMessageUndeliveredMessages(m) => tracing::trace!(target: "websocket::message", "{}: {} messages", m.TYPE, m.messages.len()),
         };
     }
 }
\ No newline at end of file
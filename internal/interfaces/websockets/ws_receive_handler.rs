@@ -23,6 +23,14 @@ pub async fn ws_receive_handler<
 ) -> Result<(), SocketMessageError> {
     match message {
         SocketMessage::SocketMessageDirect(m) => return m.handle_receive(app_state, token).await,
+        SocketMessage::SocketMessageRoom(m) => return m.handle_receive(app_state, token).await,
+        SocketMessage::SocketMessageAck(m) => return m.handle_receive(app_state, token).await,
+        SocketMessage::SocketMessageEdit(m) => return m.handle_receive(app_state, token).await,
+        SocketMessage::SocketMessageDelete(m) => return m.handle_receive(app_state, token).await,
+        SocketMessage::SocketMessageTyping(m) => return m.handle_receive(app_state, token).await,
+        SocketMessage::SocketMessageReadReceipt(m) => {
+            return m.handle_receive(app_state, token).await
+        }
         _ => return Ok(()),
     };
 }
@@ -3,7 +3,14 @@ use std::{
     time::{self, SystemTime},
 };
 
-use axum::body::Body;
+use axum::{
+    body::Body,
+    http::{
+        header::{AUTHORIZATION, COOKIE},
+        Uri,
+    },
+};
+use crate::helper::fingerprint::DEVICE_KEY_HEADER;
 use cookie::time::{format_description::well_known::Rfc3339, OffsetDateTime};
 use serde_json::json;
 use tower_http::trace::{OnRequest, OnResponse};
@@ -54,8 +61,13 @@ impl OnErrorLogger {
 impl<B> OnRequest<B> for OnRequestLogger {
     fn on_request(&mut self, request: &axum::http::Request<B>, span: &tracing::Span) {
         let method = request.method();
-        let uri = request.uri();
-        let headers = request.headers();
+        let uri = redact_query(request.uri());
+        let mut headers = request.headers().clone();
+        for (name, value) in headers.iter_mut() {
+            if name == AUTHORIZATION || name == COOKIE || name == DEVICE_KEY_HEADER {
+                value.set_sensitive(true);
+            }
+        }
         let offset_dt: OffsetDateTime = SystemTime::now().into();
         let offset_dt = offset_dt
             .format(&Rfc3339)
@@ -66,7 +78,24 @@ impl<B> OnRequest<B> for OnRequestLogger {
     }
 }
 
-pub fn initialize_logger() -> (WorkerGuard, WorkerGuard, WorkerGuard) {
+/// Credentials in the query string, e.g. of websocket handshakes, are not written to the logs
+fn redact_query(uri: &Uri) -> String {
+    let query = match uri.query() {
+        None => return uri.to_string(),
+        Some(query) => query,
+    };
+    let query = query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((name @ ("token" | "ticket" | "device_key"), _)) => format!("{}=[redacted]", name),
+            _ => pair.to_owned(),
+        })
+        .collect::<Vec<String>>()
+        .join("&");
+    format!("{}?{}", uri.path(), query)
+}
+
+pub fn initialize_logger() -> (WorkerGuard, WorkerGuard, WorkerGuard, WorkerGuard) {
     let stdout_log = tracing_subscriber::fmt::layer()
         .with_writer(io::stdout)
         .with_target(true)
@@ -103,6 +132,15 @@ pub fn initialize_logger() -> (WorkerGuard, WorkerGuard, WorkerGuard) {
     let (application_log_handle, _application_guard) =
         tracing_appender::non_blocking(application_log_writer);
 
+    let audit_log_writer = tracing_appender::rolling::Builder::new()
+        .filename_prefix("audit")
+        .filename_suffix("log")
+        .rotation(Rotation::DAILY)
+        .build("./logs")
+        .expect("Could not initiate audit_log");
+
+    let (audit_log_handle, _audit_guard) = tracing_appender::non_blocking(audit_log_writer);
+
     let access_log = tracing_subscriber::fmt::layer()
         .with_writer(access_log_handle)
         .with_line_number(false)
@@ -121,12 +159,19 @@ pub fn initialize_logger() -> (WorkerGuard, WorkerGuard, WorkerGuard) {
         .with_target(false)
         .json();
 
+    let audit_log = tracing_subscriber::fmt::layer()
+        .with_writer(audit_log_handle)
+        .with_line_number(false)
+        .with_target(false)
+        .json();
+
     tracing_subscriber::registry()
         .with(stdout_log.with_filter(filter::Targets::new()
                     .with_targets(vec![
                         ("websocket", LevelFilter::from_level(Level::TRACE)),
                         ("http", LevelFilter::from_level(Level::TRACE)),
-                        ("application", LevelFilter::from_level(Level::TRACE))
+                        ("application", LevelFilter::from_level(Level::TRACE)),
+                        ("audit", LevelFilter::from_level(Level::TRACE))
                         ])))
         .with(
             access_log.with_filter(
@@ -141,7 +186,13 @@ pub fn initialize_logger() -> (WorkerGuard, WorkerGuard, WorkerGuard) {
                     .with_targets(vec![("application", LevelFilter::from_level(Level::TRACE))]),
             ),
         )
+        .with(
+            audit_log.with_filter(
+                filter::Targets::new()
+                    .with_targets(vec![("audit", LevelFilter::from_level(Level::TRACE))]),
+            ),
+        )
         .with(tracing_subscriber::filter::EnvFilter::from_default_env())
         .init();
-    return (_access_guard, _error_guard, _application_guard);
+    return (_access_guard, _error_guard, _application_guard, _audit_guard);
 }
@@ -1,9 +1,17 @@
 use appstate::AppState;
+use config::ConfigManager;
 use entities::friends::repository::FriendRepository;
 use entities::friends::service::FriendDomain;
-use helper::session::{Session, SessionManager};
+use helper::backplane::{IBackplane, InProcessBackplane, PostgresBackplane};
+use helper::persistent_session::PersistentSessionManager;
+use helper::session::{ISessionManager, Session, SessionManager};
 use interfaces::http::router::initialize_http_server;
 use persistence::connection_manager::{ConnectionManager, IConnectionManager};
+use persistence::login_failure_repository::LoginFailureRepository;
+use persistence::session_repository::SessionRepository;
+use persistence::token_revocation_repository::TokenRevocationRepository;
+use persistence::websocket_ticket_repository::WebsocketTicketRepository;
+use scheduler::backplane_listener::initialize_backplane_listener;
 use scheduler::session_cleanup::initialize_session_cleanup_schedule;
 use std::net::SocketAddr;
 use std::sync::Arc;
@@ -27,23 +35,76 @@ async fn main() {
     let config = config::ConfigManager::new();
 
     // This is needed. If the guards are _, the variables are deallocated and the logging does not work anymore
-    let (_access_guard, _error_guard, _application_guard) = initialize_logger();
+    let (_access_guard, _error_guard, _application_guard, _audit_guard) = initialize_logger();
     let connection_manager = ConnectionManager::new(config.env.clone());
 
     let friend_domain = FriendDomain::new(FriendRepository {
         pg_pool: connection_manager.clone(),
     });
-    let session_manager = SessionManager::new(friend_domain);
+
+    let backplane: Arc<dyn IBackplane> = match config.env.BACKPLANE.as_str() {
+        "in-process" => Arc::new(InProcessBackplane::new()),
+        "postgres" => Arc::new(
+            PostgresBackplane::connect(&config.env.DATABASE_URL)
+                .await
+                .expect("Could not initialize backplane"),
+        ),
+        other => panic!("Unknown BACKPLANE {}, use in-process or postgres", other),
+    };
+
+    match config.env.SESSION_STORE.as_str() {
+        "memory" => {
+            let session_manager = SessionManager::with_backplane(friend_domain, backplane);
+            serve(connection_manager, config, session_manager).await
+        }
+        "postgres" => {
+            let session_manager = PersistentSessionManager::with_backplane(
+                friend_domain,
+                SessionRepository {
+                    pg_pool: connection_manager.clone(),
+                },
+                backplane,
+            );
+            session_manager
+                .rehydrate()
+                .await
+                .expect("Could not rehydrate sessions");
+            serve(connection_manager, config, session_manager).await
+        }
+        other => panic!("Unknown SESSION_STORE {}, use memory or postgres", other),
+    }
+}
+
+async fn serve<SM: ISessionManager<Session, FriendRepository<ConnectionManager>>>(
+    connection_manager: ConnectionManager,
+    config: ConfigManager,
+    session_manager: SM,
+) {
+    let token_revocations = Arc::new(TokenRevocationRepository {
+        pg_pool: connection_manager.clone(),
+    });
+    let websocket_tickets = Arc::new(WebsocketTicketRepository {
+        pg_pool: connection_manager.clone(),
+    });
+    let login_failures = Arc::new(LoginFailureRepository {
+        pg_pool: connection_manager.clone(),
+    });
     let app_state = Arc::new(AppState::<
-        SessionManager<Session, FriendRepository<ConnectionManager>>,
+        SM,
         Session,
         ConnectionManager,
         FriendRepository<ConnectionManager>,
     >::new(
-        connection_manager, config.clone(), session_manager
+        connection_manager,
+        config.clone(),
+        session_manager,
+        token_revocations,
+        websocket_tickets,
+        login_failures,
     ));
 
     initialize_session_cleanup_schedule(app_state.clone());
+    initialize_backplane_listener(app_state.clone());
 
     let app = initialize_http_server(&app_state, config);
     let addr = SocketAddr::from(([0, 0, 0, 0], 3000));
@@ -41,13 +41,52 @@ impl UserDTO {
 pub struct Message {
     pub id: Uuid,
     pub sender: String,
-    pub recipient: String,
+    pub recipient: Option<String>,
     pub sent_at: SystemTime,
+    pub is_read: bool,
+    pub room_id: Option<Uuid>,
+    pub delivered_at: Option<SystemTime>,
+    pub edited_at: Option<SystemTime>,
+    // Set once the sender deleted the message, the encrypted copies are wiped then
+    pub deleted_at: Option<SystemTime>,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize, diesel::Queryable, diesel::Selectable, diesel::Insertable, Clone, PartialEq)]
+#[diesel(table_name = crate::schema::message_payloads)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct MessagePayload {
+    pub message_id: Uuid,
+    pub recipient: String,
     pub content: String,
-    pub content_self_encrypted: String,
     pub content_signature: String,
-    pub content_self_encrypted_signature: String,
-    pub is_read: bool,
+    pub delivered_at: Option<SystemTime>,
+    // Keys of the recipient and the sender the client used, checked against their key history before saving
+    pub key_id: Option<Uuid>,
+    pub sender_key_id: Option<Uuid>,
+}
+
+/// Previous version of an encrypted copy, kept when the sender edits the message
+#[derive(Debug, serde::Deserialize, serde::Serialize, diesel::Queryable, diesel::Selectable, diesel::Insertable, Clone, PartialEq)]
+#[diesel(table_name = crate::schema::message_edits)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct MessageEdit {
+    pub id: Uuid,
+    pub message_id: Uuid,
+    pub recipient: String,
+    pub content: String,
+    pub content_signature: String,
+    pub edited_at: SystemTime,
+    pub key_id: Option<Uuid>,
+    pub sender_key_id: Option<Uuid>,
+}
+
+/// Message id together with its sender, returned when messages are marked as read
+#[derive(Debug, Clone, PartialEq, QueryableByName)]
+pub struct MessageSenderDTO {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    pub id: Uuid,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub sender: String,
 }
 
 #[derive(Debug, serde::Deserialize, serde::Serialize, diesel::Queryable, diesel::Selectable, diesel::Insertable, Clone)]
@@ -69,4 +108,147 @@ pub struct FriendRequest {
     pub accepted: Option<bool>
 }
 
+#[derive(Debug, serde::Deserialize, serde::Serialize, diesel::Queryable, diesel::Selectable, diesel::Insertable, Clone)]
+#[diesel(table_name = crate::schema::rooms)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Room {
+    pub id: Uuid,
+    pub name: String,
+    pub owner: String,
+    pub created_at: SystemTime,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize, diesel::Queryable, diesel::Selectable, diesel::Insertable, Clone)]
+#[diesel(table_name = crate::schema::room_members)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct RoomMember {
+    pub id: Uuid,
+    pub room_id: Uuid,
+    pub user_id: String,
+    pub joined_at: SystemTime,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize, diesel::Queryable, diesel::Selectable, diesel::Insertable, Clone, PartialEq)]
+#[diesel(table_name = crate::schema::sessions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct SessionRecord {
+    pub id: Uuid,
+    pub username: String,
+    pub token_id: String,
+    pub expires_at: SystemTime,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub created_at: SystemTime,
+    pub session_id: String,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize, diesel::Queryable, diesel::Selectable, diesel::Insertable, Clone, PartialEq)]
+#[diesel(table_name = crate::schema::refresh_tokens)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct RefreshToken {
+    pub id: Uuid,
+    pub family_id: Uuid,
+    pub session_id: String,
+    pub username: String,
+    pub token_hash: String,
+    pub expires_at: SystemTime,
+    pub used_at: Option<SystemTime>,
+    pub revoked_at: Option<SystemTime>,
+    pub created_at: SystemTime,
+    // JSON of the ClientFingerprint, missing for tokens issued before clients were bound
+    pub client_fingerprint: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize, diesel::Queryable, diesel::Selectable, diesel::Insertable, Clone, PartialEq)]
+#[diesel(table_name = crate::schema::revoked_tokens)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct RevokedToken {
+    pub token_id: String,
+    pub username: String,
+    pub expires_at: SystemTime,
+    pub revoked_at: SystemTime,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize, diesel::Queryable, diesel::Selectable, diesel::Insertable, Clone, PartialEq)]
+#[diesel(table_name = crate::schema::user_token_revocations)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct UserTokenRevocation {
+    pub username: String,
+    pub revoked_before: SystemTime,
+    pub expires_at: SystemTime,
+    pub kept_session_id: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize, diesel::Queryable, diesel::Selectable, diesel::Insertable, Clone, PartialEq)]
+#[diesel(table_name = crate::schema::websocket_tickets)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct WebsocketTicket {
+    pub ticket_hash: String,
+    pub username: String,
+    // JSON of the claims of the access token the ticket was issued for
+    pub token: String,
+    pub expires_at: SystemTime,
+    pub created_at: SystemTime,
+}
+
+#[derive(Debug, diesel::Queryable, diesel::Selectable, diesel::Insertable, Clone, PartialEq)]
+#[diesel(table_name = crate::schema::login_failures)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct LoginFailure {
+    // Which throttle the key belongs to, e.g. username or ip
+    pub scope: String,
+    pub throttle_key: String,
+    pub failures: i32,
+    pub last_failure: SystemTime,
+    pub locked_until: Option<SystemTime>,
+}
 
+#[derive(Debug, serde::Deserialize, serde::Serialize, diesel::Queryable, diesel::Selectable, diesel::Insertable, Clone, PartialEq)]
+#[diesel(table_name = crate::schema::user_totp)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct UserTotp {
+    pub username: String,
+    #[serde(skip_serializing)]
+    pub secret: Vec<u8>,
+    // Missing until the user entered a first code, the second factor is not required before
+    pub confirmed_at: Option<SystemTime>,
+    pub last_used_step: Option<i64>,
+    pub created_at: SystemTime,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize, diesel::Queryable, diesel::Selectable, diesel::Insertable, Clone, PartialEq)]
+#[diesel(table_name = crate::schema::recovery_codes)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct RecoveryCode {
+    pub id: Uuid,
+    pub username: String,
+    pub code_hash: String,
+    pub used_at: Option<SystemTime>,
+    pub created_at: SystemTime,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize, diesel::Queryable, diesel::Selectable, diesel::Insertable, Clone, PartialEq)]
+#[diesel(table_name = crate::schema::mfa_challenges)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct MfaChallenge {
+    pub challenge_hash: String,
+    pub username: String,
+    // Device session the login continues with
+    pub session_id: String,
+    // JSON of the ClientFingerprint of the client which passed the password check
+    pub client_fingerprint: Option<String>,
+    pub expires_at: SystemTime,
+    pub created_at: SystemTime,
+}
+
+/// Public key of a user. Keys are never deleted, so messages encrypted for or signed with an older key stay readable
+#[derive(Debug, serde::Deserialize, serde::Serialize, diesel::Queryable, diesel::Selectable, diesel::Insertable, Clone, PartialEq)]
+#[diesel(table_name = crate::schema::user_keys)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct UserKey {
+    pub id: Uuid,
+    pub username: String,
+    pub public_key: Vec<u8>,
+    pub created_at: SystemTime,
+    pub replaced_at: Option<SystemTime>,
+}
@@ -0,0 +1,155 @@
+use std::{fmt::Debug, time::SystemTime};
+
+use diesel::prelude::*;
+
+use crate::{
+    models::LoginFailure, persistence::connection_manager::IConnectionManager,
+    schema::login_failures,
+};
+
+/// Failed logins are stored in the database, so a lockout holds on every instance and not only on the one which counted the failures
+pub trait ILoginFailureRepository: Debug + Send + Sync + 'static {
+    fn locked_until(&self, scope: &str, key: &String) -> Result<Option<SystemTime>, String>;
+    /// Counts a failure and returns the failures of the key so far. Failures which happened before `forget_before` are forgotten first
+    fn record_failure(
+        &self,
+        scope: &str,
+        key: &String,
+        now: SystemTime,
+        forget_before: SystemTime,
+    ) -> Result<u32, String>;
+    fn lock(&self, scope: &str, key: &String, until: SystemTime) -> Result<(), String>;
+    fn reset(&self, scope: &str, key: &String) -> Result<(), String>;
+    fn delete_stale_failures(
+        &self,
+        scope: &str,
+        forget_before: SystemTime,
+    ) -> Result<usize, String>;
+}
+
+unsafe impl<C: IConnectionManager> Send for LoginFailureRepository<C> {}
+unsafe impl<C: IConnectionManager> Sync for LoginFailureRepository<C> {}
+
+pub struct LoginFailureRepository<C: IConnectionManager> {
+    pub pg_pool: C,
+}
+
+impl<C: IConnectionManager> Debug for LoginFailureRepository<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LoginFailureRepository").finish()
+    }
+}
+
+impl<C: IConnectionManager> ILoginFailureRepository for LoginFailureRepository<C> {
+    fn locked_until(&self, scope: &str, key: &String) -> Result<Option<SystemTime>, String> {
+        let mut connection = self.pg_pool.get()?;
+        let result = login_failures::table
+            .filter(login_failures::scope.eq(scope))
+            .filter(login_failures::throttle_key.eq(key))
+            .select(login_failures::locked_until)
+            .first::<Option<SystemTime>>(&mut connection)
+            .optional();
+
+        match result {
+            Err(err) => Err(format!("Could not get login lockout of {}: {}", key, err)),
+            Ok(res) => Ok(res.flatten()),
+        }
+    }
+
+    fn record_failure(
+        &self,
+        scope: &str,
+        key: &String,
+        now: SystemTime,
+        forget_before: SystemTime,
+    ) -> Result<u32, String> {
+        let mut connection = self.pg_pool.get()?;
+        let result = connection.transaction::<i32, diesel::result::Error, _>(|connection| {
+            diesel::delete(
+                login_failures::table
+                    .filter(login_failures::scope.eq(scope))
+                    .filter(login_failures::throttle_key.eq(key))
+                    .filter(login_failures::last_failure.lt(forget_before)),
+            )
+            .execute(connection)?;
+
+            // A single upsert, so concurrent failures on different instances are all counted
+            diesel::insert_into(login_failures::table)
+                .values(&LoginFailure {
+                    scope: scope.to_string(),
+                    throttle_key: key.clone(),
+                    failures: 1,
+                    last_failure: now,
+                    locked_until: None,
+                })
+                .on_conflict((login_failures::scope, login_failures::throttle_key))
+                .do_update()
+                .set((
+                    login_failures::failures.eq(login_failures::failures + 1),
+                    login_failures::last_failure.eq(now),
+                ))
+                .returning(login_failures::failures)
+                .get_result(connection)
+        });
+
+        match result {
+            Err(err) => Err(format!("Could not record failed login of {}: {}", key, err)),
+            Ok(res) => Ok(res as u32),
+        }
+    }
+
+    fn lock(&self, scope: &str, key: &String, until: SystemTime) -> Result<(), String> {
+        let mut connection = self.pg_pool.get()?;
+        let result = diesel::update(
+            login_failures::table
+                .filter(login_failures::scope.eq(scope))
+                .filter(login_failures::throttle_key.eq(key)),
+        )
+        .set(login_failures::locked_until.eq(until))
+        .execute(&mut connection);
+
+        match result {
+            Err(err) => Err(format!("Could not lock login of {}: {}", key, err)),
+            Ok(_) => Ok(()),
+        }
+    }
+
+    fn reset(&self, scope: &str, key: &String) -> Result<(), String> {
+        let mut connection = self.pg_pool.get()?;
+        let result = diesel::delete(
+            login_failures::table
+                .filter(login_failures::scope.eq(scope))
+                .filter(login_failures::throttle_key.eq(key)),
+        )
+        .execute(&mut connection);
+
+        match result {
+            Err(err) => Err(format!("Could not reset failed logins of {}: {}", key, err)),
+            Ok(_) => Ok(()),
+        }
+    }
+
+    fn delete_stale_failures(
+        &self,
+        scope: &str,
+        forget_before: SystemTime,
+    ) -> Result<usize, String> {
+        let mut connection = self.pg_pool.get()?;
+        let result = diesel::delete(
+            login_failures::table
+                .filter(login_failures::scope.eq(scope))
+                .filter(login_failures::last_failure.lt(forget_before))
+                .filter(
+                    login_failures::locked_until
+                        .is_null()
+                        .or(login_failures::locked_until.lt(SystemTime::now())),
+                ),
+        )
+        .execute(&mut connection);
+
+        match result {
+            Err(err) => Err(format!("Could not delete stale failed logins: {}", err)),
+            Ok(res) => Ok(res),
+        }
+    }
+}
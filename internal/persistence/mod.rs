@@ -1 +1,5 @@
 pub mod connection_manager;
+pub mod login_failure_repository;
+pub mod session_repository;
+pub mod token_revocation_repository;
+pub mod websocket_ticket_repository;
@@ -0,0 +1,124 @@
+use std::{fmt::Debug, time::SystemTime};
+
+use diesel::prelude::*;
+
+use crate::{
+    models::{SessionRecord, UserDTO},
+    persistence::connection_manager::IConnectionManager,
+    schema::{sessions, users},
+};
+
+pub trait ISessionRepository: Debug + Send + Sync + 'static {
+    /// Inserts the session record or replaces the existing record of the same device session
+    fn save_session(&self, session: &SessionRecord) -> Result<(), String>;
+    fn delete_session(&self, session_id: &String) -> Result<(), String>;
+    fn get_session(&self, session_id: &String) -> Result<Option<(SessionRecord, UserDTO)>, String>;
+    /// All active sessions of the user, one per device
+    fn get_user_sessions(&self, username: &String) -> Result<Vec<(SessionRecord, UserDTO)>, String>;
+    fn get_active_sessions(&self) -> Result<Vec<(SessionRecord, UserDTO)>, String>;
+    fn delete_expired_sessions(&self) -> Result<usize, String>;
+}
+
+unsafe impl<C: IConnectionManager> Send for SessionRepository<C> {}
+unsafe impl<C: IConnectionManager> Sync for SessionRepository<C> {}
+
+pub struct SessionRepository<C: IConnectionManager> {
+    pub pg_pool: C,
+}
+
+impl<C: IConnectionManager> Debug for SessionRepository<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SessionRepository").finish()
+    }
+}
+
+impl<C: IConnectionManager> ISessionRepository for SessionRepository<C> {
+    fn save_session(&self, session: &SessionRecord) -> Result<(), String> {
+        let mut connection = self.pg_pool.get()?;
+        let result = diesel::insert_into(sessions::table)
+            .values(session)
+            .on_conflict(sessions::session_id)
+            .do_update()
+            .set((
+                sessions::token_id.eq(&session.token_id),
+                sessions::expires_at.eq(&session.expires_at),
+                sessions::user_agent.eq(&session.user_agent),
+                sessions::ip_address.eq(&session.ip_address),
+                sessions::created_at.eq(&session.created_at),
+            ))
+            .execute(&mut connection);
+
+        match result {
+            Err(err) => Err(format!("Could not save session {:?}: {}", session, err)),
+            Ok(_) => Ok(()),
+        }
+    }
+
+    fn delete_session(&self, session_id: &String) -> Result<(), String> {
+        let mut connection = self.pg_pool.get()?;
+        let result = diesel::delete(sessions::table.filter(sessions::session_id.eq(session_id)))
+            .execute(&mut connection);
+
+        match result {
+            Err(err) => Err(format!("Could not delete session {}: {}", session_id, err)),
+            Ok(_) => Ok(()),
+        }
+    }
+
+    fn get_session(&self, session_id: &String) -> Result<Option<(SessionRecord, UserDTO)>, String> {
+        let mut connection = self.pg_pool.get()?;
+        let result = sessions::table
+            .inner_join(users::table)
+            .select((SessionRecord::as_select(), UserDTO::as_select()))
+            .filter(sessions::session_id.eq(session_id))
+            .filter(sessions::expires_at.gt(SystemTime::now()))
+            .first::<(SessionRecord, UserDTO)>(&mut connection)
+            .optional();
+
+        match result {
+            Err(err) => Err(format!("Could not get session {}: {}", session_id, err)),
+            Ok(res) => Ok(res),
+        }
+    }
+
+    fn get_user_sessions(&self, username: &String) -> Result<Vec<(SessionRecord, UserDTO)>, String> {
+        let mut connection = self.pg_pool.get()?;
+        let result = sessions::table
+            .inner_join(users::table)
+            .select((SessionRecord::as_select(), UserDTO::as_select()))
+            .filter(sessions::username.eq(username))
+            .filter(sessions::expires_at.gt(SystemTime::now()))
+            .load::<(SessionRecord, UserDTO)>(&mut connection);
+
+        match result {
+            Err(err) => Err(format!("Could not get sessions of {}: {}", username, err)),
+            Ok(res) => Ok(res),
+        }
+    }
+
+    fn get_active_sessions(&self) -> Result<Vec<(SessionRecord, UserDTO)>, String> {
+        let mut connection = self.pg_pool.get()?;
+        let result = sessions::table
+            .inner_join(users::table)
+            .select((SessionRecord::as_select(), UserDTO::as_select()))
+            .filter(sessions::expires_at.gt(SystemTime::now()))
+            .load::<(SessionRecord, UserDTO)>(&mut connection);
+
+        match result {
+            Err(err) => Err(format!("Could not get active sessions: {}", err)),
+            Ok(res) => Ok(res),
+        }
+    }
+
+    fn delete_expired_sessions(&self) -> Result<usize, String> {
+        let mut connection = self.pg_pool.get()?;
+        let result =
+            diesel::delete(sessions::table.filter(sessions::expires_at.le(SystemTime::now())))
+                .execute(&mut connection);
+
+        match result {
+            Err(err) => Err(format!("Could not delete expired sessions: {}", err)),
+            Ok(res) => Ok(res),
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use std::{
+    fmt::Debug,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use diesel::{dsl::exists, prelude::*, select};
+
+use crate::{
+    helper::jwt::Token,
+    models::{RevokedToken, UserTokenRevocation},
+    persistence::connection_manager::IConnectionManager,
+    schema::{revoked_tokens, user_token_revocations},
+};
+
+/// Denylist of access tokens which are not expired yet but must not be accepted anymore.
+/// Entries are only needed until the tokens they revoke expire
+pub trait ITokenRevocationRepository: Debug + Send + Sync + 'static {
+    fn revoke_token(&self, token: &Token) -> Result<(), String>;
+    /// Revokes every token of the user issued up to `revoked_before`, except the ones of `kept_session_id`.
+    /// `expires_at` is when the last of those tokens expires
+    fn revoke_user_tokens(
+        &self,
+        username: &String,
+        revoked_before: SystemTime,
+        expires_at: SystemTime,
+        kept_session_id: Option<&String>,
+    ) -> Result<(), String>;
+    fn is_revoked(&self, token: &Token) -> Result<bool, String>;
+    fn delete_expired_revocations(&self) -> Result<usize, String>;
+}
+
+unsafe impl<C: IConnectionManager> Send for TokenRevocationRepository<C> {}
+unsafe impl<C: IConnectionManager> Sync for TokenRevocationRepository<C> {}
+
+pub struct TokenRevocationRepository<C: IConnectionManager> {
+    pub pg_pool: C,
+}
+
+impl<C: IConnectionManager> Debug for TokenRevocationRepository<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TokenRevocationRepository").finish()
+    }
+}
+
+impl<C: IConnectionManager> ITokenRevocationRepository for TokenRevocationRepository<C> {
+    fn revoke_token(&self, token: &Token) -> Result<(), String> {
+        let mut connection = self.pg_pool.get()?;
+        let revoked_token = RevokedToken {
+            token_id: token.jti.clone(),
+            username: token.sub.clone(),
+            expires_at: UNIX_EPOCH + token.exp,
+            revoked_at: SystemTime::now(),
+        };
+        let result = diesel::insert_into(revoked_tokens::table)
+            .values(&revoked_token)
+            .on_conflict_do_nothing()
+            .execute(&mut connection);
+
+        match result {
+            Err(err) => Err(format!("Could not revoke token {}: {}", token.jti, err)),
+            Ok(_) => Ok(()),
+        }
+    }
+
+    fn revoke_user_tokens(
+        &self,
+        username: &String,
+        revoked_before: SystemTime,
+        expires_at: SystemTime,
+        kept_session_id: Option<&String>,
+    ) -> Result<(), String> {
+        let mut connection = self.pg_pool.get()?;
+        let revocation = UserTokenRevocation {
+            username: username.clone(),
+            revoked_before,
+            expires_at,
+            kept_session_id: kept_session_id.cloned(),
+        };
+        let result = diesel::insert_into(user_token_revocations::table)
+            .values(&revocation)
+            .on_conflict(user_token_revocations::username)
+            .do_update()
+            .set((
+                user_token_revocations::revoked_before.eq(&revocation.revoked_before),
+                user_token_revocations::expires_at.eq(&revocation.expires_at),
+                user_token_revocations::kept_session_id.eq(&revocation.kept_session_id),
+            ))
+            .execute(&mut connection);
+
+        match result {
+            Err(err) => Err(format!("Could not revoke tokens of {}: {}", username, err)),
+            Ok(_) => Ok(()),
+        }
+    }
+
+    fn is_revoked(&self, token: &Token) -> Result<bool, String> {
+        let mut connection = self.pg_pool.get()?;
+        // Both have microseconds, so a login right after the revocation is not caught by it
+        let result = select(
+            exists(revoked_tokens::table.filter(revoked_tokens::token_id.eq(&token.jti))).or(
+                exists(
+                    user_token_revocations::table
+                        .filter(user_token_revocations::username.eq(&token.sub))
+                        .filter(user_token_revocations::revoked_before.gt(UNIX_EPOCH + token.iat))
+                        .filter(
+                            user_token_revocations::kept_session_id
+                                .is_null()
+                                .or(user_token_revocations::kept_session_id.ne(&token.sid)),
+                        ),
+                ),
+            ),
+        )
+        .get_result::<bool>(&mut connection);
+
+        match result {
+            Err(err) => Err(format!(
+                "Could not check revocation of token {}: {}",
+                token.jti, err
+            )),
+            Ok(res) => Ok(res),
+        }
+    }
+
+    fn delete_expired_revocations(&self) -> Result<usize, String> {
+        let mut connection = self.pg_pool.get()?;
+        let now = SystemTime::now();
+        let result = connection.transaction::<_, diesel::result::Error, _>(|conn| {
+            let tokens =
+                diesel::delete(revoked_tokens::table.filter(revoked_tokens::expires_at.le(now)))
+                    .execute(conn)?;
+            let users = diesel::delete(
+                user_token_revocations::table.filter(user_token_revocations::expires_at.le(now)),
+            )
+            .execute(conn)?;
+            Ok(tokens + users)
+        });
+
+        match result {
+            Err(err) => Err(format!("Could not delete expired revocations: {}", err)),
+            Ok(res) => Ok(res),
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use std::{fmt::Debug, time::SystemTime};
+
+use diesel::prelude::*;
+
+use crate::{
+    models::WebsocketTicket, persistence::connection_manager::IConnectionManager,
+    schema::websocket_tickets,
+};
+
+/// Tickets are stored in the database, so the handshake may reach another instance than the one which issued the ticket
+pub trait IWebsocketTicketRepository: Debug + Send + Sync + 'static {
+    fn save_ticket(&self, ticket: &WebsocketTicket) -> Result<(), String>;
+    /// Deletes the ticket, so it can only be used once. Expired tickets are not returned
+    fn consume_ticket(&self, ticket_hash: &String) -> Result<Option<WebsocketTicket>, String>;
+    fn delete_expired_tickets(&self) -> Result<usize, String>;
+}
+
+unsafe impl<C: IConnectionManager> Send for WebsocketTicketRepository<C> {}
+unsafe impl<C: IConnectionManager> Sync for WebsocketTicketRepository<C> {}
+
+pub struct WebsocketTicketRepository<C: IConnectionManager> {
+    pub pg_pool: C,
+}
+
+impl<C: IConnectionManager> Debug for WebsocketTicketRepository<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WebsocketTicketRepository").finish()
+    }
+}
+
+impl<C: IConnectionManager> IWebsocketTicketRepository for WebsocketTicketRepository<C> {
+    fn save_ticket(&self, ticket: &WebsocketTicket) -> Result<(), String> {
+        let mut connection = self.pg_pool.get()?;
+        let result = diesel::insert_into(websocket_tickets::table)
+            .values(ticket)
+            .execute(&mut connection);
+
+        match result {
+            Err(err) => Err(format!(
+                "Could not save websocket ticket of {}: {}",
+                ticket.username, err
+            )),
+            Ok(_) => Ok(()),
+        }
+    }
+
+    fn consume_ticket(&self, ticket_hash: &String) -> Result<Option<WebsocketTicket>, String> {
+        let mut connection = self.pg_pool.get()?;
+        // A single delete, so concurrent handshakes with the same ticket cannot both get it
+        let result = diesel::delete(
+            websocket_tickets::table
+                .filter(websocket_tickets::ticket_hash.eq(ticket_hash))
+                .filter(websocket_tickets::expires_at.gt(SystemTime::now())),
+        )
+        .returning(WebsocketTicket::as_returning())
+        .get_result(&mut connection)
+        .optional();
+
+        match result {
+            Err(err) => Err(format!("Could not consume websocket ticket: {}", err)),
+            Ok(res) => Ok(res),
+        }
+    }
+
+    fn delete_expired_tickets(&self) -> Result<usize, String> {
+        let mut connection = self.pg_pool.get()?;
+        let result = diesel::delete(
+            websocket_tickets::table.filter(websocket_tickets::expires_at.le(SystemTime::now())),
+        )
+        .execute(&mut connection);
+
+        match result {
+            Err(err) => Err(format!(
+                "Could not delete expired websocket tickets: {}",
+                err
+            )),
+            Ok(res) => Ok(res),
+        }
+    }
+}
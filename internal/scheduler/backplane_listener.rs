@@ -0,0 +1,115 @@
+use crate::{
+    appstate::{AppState, IAppState},
+    interfaces::websockets::socket_messages::SocketMessage,
+    entities::friends::repository::IFriendRepository,
+    helper::backplane::BackplaneMessage,
+    helper::session::ISessionManager,
+    persistence::connection_manager::IConnectionManager,
+};
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::helper::session::ISession;
+
+/// Delivers every message published on the backplane to the recipient, if the recipient is connected to this instance
+pub fn initialize_backplane_listener<
+    SM: ISessionManager<S, F>,
+    S: ISession<F>,
+    F: IFriendRepository,
+    C: IConnectionManager,
+>(
+    app_state: Arc<AppState<SM, S, C, F>>,
+) {
+    let mut receiver = app_state.get_session_manager().get_backplane().subscribe();
+    tokio::spawn(async move {
+        loop {
+            match receiver.recv().await {
+                Ok(message) => {
+                    deliver_to_local_session(app_state.get_session_manager(), message).await
+                }
+                Err(RecvError::Lagged(count)) => {
+                    tracing::error!(target: "application", "[initialize_backplane_listener] Skipped {} messages", count)
+                }
+                Err(RecvError::Closed) => {
+                    tracing::error!(target: "application", "[initialize_backplane_listener] Backplane closed");
+                    break;
+                }
+            }
+        }
+    });
+}
+
+pub async fn deliver_to_local_session<
+    SM: ISessionManager<S, F>,
+    S: ISession<F>,
+    F: IFriendRepository,
+>(
+    session_manager: &SM,
+    message: BackplaneMessage,
+) {
+    if let Some(close_sessions) = &message.close_sessions {
+        return close_local_sessions(
+            session_manager,
+            &message.recipient,
+            close_sessions.kept_session_id.as_ref(),
+            message.message,
+        )
+        .await;
+    }
+
+    // Only look at live sessions, persisted ones of other instances are delivered by their own listener
+    let user_sessions = match session_manager
+        .get_current_user_connections()
+        .lock()
+        .await
+        .get(&message.recipient)
+    {
+        None => return,
+        Some(user_sessions) => user_sessions.clone(),
+    };
+    // Every device of the user gets its own copy
+    for (_, session) in user_sessions {
+        session.lock().await.deliver(message.message.clone());
+    }
+}
+
+/// Closes the sessions of the user connected to this instance, except `kept_session_id`
+pub async fn close_local_sessions<
+    SM: ISessionManager<S, F>,
+    S: ISession<F>,
+    F: IFriendRepository,
+>(
+    session_manager: &SM,
+    username: &String,
+    kept_session_id: Option<&String>,
+    notice: SocketMessage,
+) {
+    let session_ids: Vec<String> = match session_manager
+        .get_current_user_connections()
+        .lock()
+        .await
+        .get(username)
+    {
+        None => return,
+        Some(user_sessions) => user_sessions
+            .keys()
+            .filter(|session_id| Some(*session_id) != kept_session_id)
+            .cloned()
+            .collect(),
+    };
+
+    for session_id in session_ids {
+        let session = match session_manager
+            .remove_from_current_user_connections(username, &session_id)
+            .await
+        {
+            Ok(session) => session,
+            // Closed concurrently, e.g. by the socket disconnecting
+            Err(_) => continue,
+        };
+        let session = session.lock().await;
+        session.notify_offline(session_manager).await;
+        // Dropping the session closes its socket
+        session.deliver(notice.clone());
+    }
+}
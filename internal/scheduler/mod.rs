@@ -1 +1,2 @@
 pub mod session_cleanup;
+pub mod backplane_listener;
@@ -17,15 +17,49 @@ pub fn initialize_session_cleanup_schedule<
 >(
     app_state: Arc<AppState<SM, S, C, F>>,
 ) {
-    let app_state = app_state.clone();
+    let token_revocations = app_state.token_revocations.clone();
+    let websocket_tickets = app_state.websocket_tickets.clone();
+    let session_state = app_state.clone();
     tokio::spawn(async move {
         let mut interval = tokio::time::interval(time::Duration::from_secs(15));
         loop {
             interval.tick().await;
-            app_state
+            session_state
                 .current_user_connections
                 .remove_expired_current_user_connections_sessions()
                 .await
         }
     });
+
+    // Revocations are only needed until the revoked tokens expire, unused tickets are dropped along with them.
+    // Failed logins are forgotten once they no longer count towards a lockout
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(time::Duration::from_secs(60 * 60));
+        loop {
+            interval.tick().await;
+            match token_revocations.delete_expired_revocations() {
+                Err(err) => tracing::error!(target: "application", "[session_cleanup] {}", err),
+                Ok(deleted) => {
+                    tracing::debug!(target: "application", "[session_cleanup] Deleted {} expired token revocations", deleted)
+                }
+            }
+            match websocket_tickets.delete_expired_tickets() {
+                Err(err) => tracing::error!(target: "application", "[session_cleanup] {}", err),
+                Ok(deleted) => {
+                    tracing::debug!(target: "application", "[session_cleanup] Deleted {} expired websocket tickets", deleted)
+                }
+            }
+            for throttle in [
+                &app_state.login_throttle_by_username,
+                &app_state.login_throttle_by_ip,
+            ] {
+                match throttle.delete_stale_failures() {
+                    Err(err) => tracing::error!(target: "application", "[session_cleanup] {}", err),
+                    Ok(deleted) => {
+                        tracing::debug!(target: "application", "[session_cleanup] Deleted {} stale failed logins", deleted)
+                    }
+                }
+            }
+        }
+    });
 }
@@ -22,38 +22,246 @@ diesel::table! {
 }
 
 diesel::table! {
-    messages (id) {
+    login_failures (scope, throttle_key) {
+        #[max_length = 16]
+        scope -> Varchar,
+        throttle_key -> Text,
+        failures -> Int4,
+        last_failure -> Timestamp,
+        locked_until -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    message_edits (id) {
         id -> Uuid,
-        #[max_length = 30]
-        sender -> Varchar,
+        message_id -> Uuid,
         #[max_length = 30]
         recipient -> Varchar,
-        sent_at -> Timestamp,
         #[max_length = 1024]
         content -> Varchar,
         #[max_length = 1024]
-        content_self_encrypted -> Varchar,
-        #[max_length = 1024]
         content_signature -> Varchar,
+        edited_at -> Timestamp,
+        key_id -> Nullable<Uuid>,
+        sender_key_id -> Nullable<Uuid>,
+    }
+}
+
+diesel::table! {
+    message_payloads (message_id, recipient) {
+        message_id -> Uuid,
+        #[max_length = 30]
+        recipient -> Varchar,
         #[max_length = 1024]
-        content_self_encrypted_signature -> Varchar,
+        content -> Varchar,
+        #[max_length = 1024]
+        content_signature -> Varchar,
+        delivered_at -> Nullable<Timestamp>,
+        key_id -> Nullable<Uuid>,
+        sender_key_id -> Nullable<Uuid>,
+    }
+}
+
+diesel::table! {
+    messages (id) {
+        id -> Uuid,
+        #[max_length = 30]
+        sender -> Varchar,
+        #[max_length = 30]
+        recipient -> Nullable<Varchar>,
+        sent_at -> Timestamp,
         is_read -> Bool,
+        room_id -> Nullable<Uuid>,
+        delivered_at -> Nullable<Timestamp>,
+        edited_at -> Nullable<Timestamp>,
+        deleted_at -> Nullable<Timestamp>,
     }
 }
 
 diesel::table! {
-    users (username) {
+    mfa_challenges (challenge_hash) {
+        #[max_length = 64]
+        challenge_hash -> Varchar,
+        #[max_length = 30]
+        username -> Varchar,
+        #[max_length = 64]
+        session_id -> Varchar,
+        #[max_length = 255]
+        client_fingerprint -> Nullable<Varchar>,
+        expires_at -> Timestamp,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    recovery_codes (id) {
+        id -> Uuid,
+        #[max_length = 30]
+        username -> Varchar,
+        #[max_length = 64]
+        code_hash -> Varchar,
+        used_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    refresh_tokens (id) {
+        id -> Uuid,
+        family_id -> Uuid,
+        #[max_length = 64]
+        session_id -> Varchar,
         #[max_length = 30]
         username -> Varchar,
         #[max_length = 64]
+        token_hash -> Varchar,
+        expires_at -> Timestamp,
+        used_at -> Nullable<Timestamp>,
+        revoked_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        #[max_length = 255]
+        client_fingerprint -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    revoked_tokens (token_id) {
+        #[max_length = 64]
+        token_id -> Varchar,
+        #[max_length = 30]
+        username -> Varchar,
+        expires_at -> Timestamp,
+        revoked_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    room_members (id) {
+        id -> Uuid,
+        room_id -> Uuid,
+        #[max_length = 30]
+        user_id -> Varchar,
+        joined_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    rooms (id) {
+        id -> Uuid,
+        #[max_length = 80]
+        name -> Varchar,
+        #[max_length = 30]
+        owner -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    sessions (id) {
+        id -> Uuid,
+        #[max_length = 30]
+        username -> Varchar,
+        #[max_length = 64]
+        token_id -> Varchar,
+        expires_at -> Timestamp,
+        #[max_length = 512]
+        user_agent -> Nullable<Varchar>,
+        #[max_length = 64]
+        ip_address -> Nullable<Varchar>,
+        created_at -> Timestamp,
+        #[max_length = 64]
+        session_id -> Varchar,
+    }
+}
+
+diesel::table! {
+    user_keys (id) {
+        id -> Uuid,
+        #[max_length = 30]
+        username -> Varchar,
+        public_key -> Bytea,
+        created_at -> Timestamp,
+        replaced_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    user_token_revocations (username) {
+        #[max_length = 30]
+        username -> Varchar,
+        revoked_before -> Timestamp,
+        expires_at -> Timestamp,
+        #[max_length = 64]
+        kept_session_id -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    user_totp (username) {
+        #[max_length = 30]
+        username -> Varchar,
+        secret -> Bytea,
+        confirmed_at -> Nullable<Timestamp>,
+        last_used_step -> Nullable<Int8>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    users (username) {
+        #[max_length = 30]
+        username -> Varchar,
+        #[max_length = 255]
         password -> Varchar,
         public_key -> Bytea,
     }
 }
 
+diesel::table! {
+    websocket_tickets (ticket_hash) {
+        #[max_length = 64]
+        ticket_hash -> Varchar,
+        #[max_length = 30]
+        username -> Varchar,
+        token -> Text,
+        expires_at -> Timestamp,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::joinable!(message_payloads -> messages (message_id));
+diesel::joinable!(message_payloads -> users (recipient));
+diesel::joinable!(messages -> rooms (room_id));
+diesel::joinable!(mfa_challenges -> users (username));
+diesel::joinable!(recovery_codes -> users (username));
+diesel::joinable!(refresh_tokens -> users (username));
+diesel::joinable!(revoked_tokens -> users (username));
+diesel::joinable!(room_members -> rooms (room_id));
+diesel::joinable!(room_members -> users (user_id));
+diesel::joinable!(rooms -> users (owner));
+diesel::joinable!(sessions -> users (username));
+diesel::joinable!(user_keys -> users (username));
+diesel::joinable!(user_totp -> users (username));
+diesel::joinable!(websocket_tickets -> users (username));
+
 diesel::allow_tables_to_appear_in_same_query!(
     friend_requests,
     friends,
+    login_failures,
+    message_edits,
+    message_payloads,
     messages,
+    mfa_challenges,
+    recovery_codes,
+    refresh_tokens,
+    revoked_tokens,
+    room_members,
+    rooms,
+    sessions,
+    user_keys,
+    user_token_revocations,
+    user_totp,
     users,
+    websocket_tickets,
 );
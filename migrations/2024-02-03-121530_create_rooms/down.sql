@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS room_members;
+DROP TABLE IF EXISTS rooms;
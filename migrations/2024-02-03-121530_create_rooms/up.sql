@@ -0,0 +1,20 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS rooms (
+  id UUID NOT NULL DEFAULT uuid_generate_v4(),
+  name varchar(80) NOT NULL,
+  owner varchar(30) NOT NULL,
+  created_at timestamp NOT NULL DEFAULT now(),
+  PRIMARY KEY(id),
+  CONSTRAINT fk_owner FOREIGN KEY(owner) REFERENCES users(username) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS room_members (
+  id UUID NOT NULL DEFAULT uuid_generate_v4(),
+  room_id UUID NOT NULL,
+  user_id varchar(30) NOT NULL,
+  joined_at timestamp NOT NULL DEFAULT now(),
+  PRIMARY KEY(id),
+  CONSTRAINT fk_room FOREIGN KEY(room_id) REFERENCES rooms(id) ON DELETE CASCADE,
+  CONSTRAINT fk_user FOREIGN KEY(user_id) REFERENCES users(username) ON DELETE CASCADE,
+  CONSTRAINT unique_room_member UNIQUE(room_id, user_id)
+);
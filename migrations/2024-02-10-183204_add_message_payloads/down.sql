@@ -0,0 +1,25 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM messages WHERE room_id IS NOT NULL;
+
+ALTER TABLE messages
+  DROP CONSTRAINT recipient_or_room,
+  DROP COLUMN room_id,
+  ADD COLUMN content varchar(1024),
+  ADD COLUMN content_self_encrypted varchar(1024),
+  ADD COLUMN content_signature varchar(1024),
+  ADD COLUMN content_self_encrypted_signature varchar(1024);
+
+UPDATE messages SET content = p.content, content_signature = p.content_signature
+  FROM message_payloads as p WHERE p.message_id = messages.id AND p.recipient = messages.recipient;
+
+UPDATE messages SET content_self_encrypted = p.content, content_self_encrypted_signature = p.content_signature
+  FROM message_payloads as p WHERE p.message_id = messages.id AND p.recipient = messages.sender;
+
+ALTER TABLE messages
+  ALTER COLUMN recipient SET NOT NULL,
+  ALTER COLUMN content SET NOT NULL,
+  ALTER COLUMN content_self_encrypted SET NOT NULL,
+  ALTER COLUMN content_signature SET NOT NULL,
+  ALTER COLUMN content_self_encrypted_signature SET NOT NULL;
+
+DROP TABLE IF EXISTS message_payloads;
@@ -0,0 +1,28 @@
+-- Your SQL goes here
+
+-- Every message is stored once as an envelope in `messages` and once per addressed key in `message_payloads`
+CREATE TABLE IF NOT EXISTS message_payloads (
+  message_id UUID NOT NULL,
+  recipient varchar(30) NOT NULL,
+  content varchar(1024) NOT NULL,
+  content_signature varchar(1024) NOT NULL,
+  PRIMARY KEY(message_id, recipient),
+  CONSTRAINT fk_message FOREIGN KEY(message_id) REFERENCES messages(id) ON DELETE CASCADE,
+  CONSTRAINT fk_recipient FOREIGN KEY(recipient) REFERENCES users(username) ON DELETE CASCADE
+);
+
+INSERT INTO message_payloads(message_id, recipient, content, content_signature)
+  SELECT id, recipient, content, content_signature FROM messages;
+
+INSERT INTO message_payloads(message_id, recipient, content, content_signature)
+  SELECT id, sender, content_self_encrypted, content_self_encrypted_signature FROM messages;
+
+ALTER TABLE messages
+  DROP COLUMN content,
+  DROP COLUMN content_self_encrypted,
+  DROP COLUMN content_signature,
+  DROP COLUMN content_self_encrypted_signature,
+  ALTER COLUMN recipient DROP NOT NULL,
+  ADD COLUMN room_id UUID,
+  ADD CONSTRAINT fk_room FOREIGN KEY(room_id) REFERENCES rooms(id) ON DELETE CASCADE,
+  ADD CONSTRAINT recipient_or_room CHECK ((recipient IS NULL) <> (room_id IS NULL));
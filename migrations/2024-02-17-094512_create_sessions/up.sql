@@ -0,0 +1,15 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS sessions (
+  id UUID NOT NULL DEFAULT uuid_generate_v4(),
+  username varchar(30) NOT NULL,
+  token_id varchar(64) NOT NULL,
+  expires_at timestamp NOT NULL,
+  user_agent varchar(512),
+  ip_address varchar(64),
+  created_at timestamp NOT NULL DEFAULT now(),
+  PRIMARY KEY(id),
+  CONSTRAINT unique_session_username UNIQUE(username),
+  CONSTRAINT fk_user FOREIGN KEY(username) REFERENCES users(username) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS sessions_expires_at_idx ON sessions(expires_at);
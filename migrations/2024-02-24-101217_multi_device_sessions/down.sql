@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM sessions;
+
+DROP INDEX IF EXISTS sessions_username_idx;
+ALTER TABLE sessions DROP CONSTRAINT IF EXISTS unique_session_session_id;
+ALTER TABLE sessions DROP COLUMN IF EXISTS session_id;
+ALTER TABLE sessions ADD CONSTRAINT unique_session_username UNIQUE(username);
@@ -0,0 +1,9 @@
+-- Your SQL goes here
+-- Sessions created before can not be mapped to a device, their users have to log in again
+DELETE FROM sessions;
+
+ALTER TABLE sessions DROP CONSTRAINT IF EXISTS unique_session_username;
+ALTER TABLE sessions ADD COLUMN session_id varchar(64) NOT NULL;
+ALTER TABLE sessions ADD CONSTRAINT unique_session_session_id UNIQUE(session_id);
+
+CREATE INDEX IF NOT EXISTS sessions_username_idx ON sessions(username);
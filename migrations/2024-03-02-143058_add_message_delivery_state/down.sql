@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS message_payloads_undelivered_idx;
+ALTER TABLE messages DROP COLUMN IF EXISTS delivered_at;
+ALTER TABLE message_payloads DROP COLUMN IF EXISTS delivered_at;
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+
+-- Per recipient: when the copy reached one of the recipient's devices
+ALTER TABLE message_payloads ADD COLUMN delivered_at timestamp;
+-- Per message: when every copy was delivered
+ALTER TABLE messages ADD COLUMN delivered_at timestamp;
+
+-- Everything sent before has been fetched by the clients already
+UPDATE message_payloads SET delivered_at = messages.sent_at
+  FROM messages WHERE messages.id = message_payloads.message_id;
+UPDATE messages SET delivered_at = sent_at;
+
+CREATE INDEX IF NOT EXISTS message_payloads_undelivered_idx
+  ON message_payloads(recipient) WHERE delivered_at IS NULL;
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS message_edits;
+ALTER TABLE messages
+  DROP COLUMN IF EXISTS edited_at,
+  DROP COLUMN IF EXISTS deleted_at;
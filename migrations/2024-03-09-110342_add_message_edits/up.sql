@@ -0,0 +1,18 @@
+-- Your SQL goes here
+ALTER TABLE messages
+  ADD COLUMN edited_at timestamp,
+  ADD COLUMN deleted_at timestamp;
+
+-- Previous versions of every encrypted copy. Removed again once the message is deleted
+CREATE TABLE IF NOT EXISTS message_edits (
+  id UUID NOT NULL DEFAULT uuid_generate_v4(),
+  message_id UUID NOT NULL,
+  recipient varchar(30) NOT NULL,
+  content varchar(1024) NOT NULL,
+  content_signature varchar(1024) NOT NULL,
+  edited_at timestamp NOT NULL DEFAULT now(),
+  PRIMARY KEY(id),
+  CONSTRAINT fk_message_payload FOREIGN KEY(message_id, recipient) REFERENCES message_payloads(message_id, recipient) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS message_edits_message_id_idx ON message_edits(message_id);
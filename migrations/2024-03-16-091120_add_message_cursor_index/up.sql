@@ -0,0 +1,3 @@
+-- Your SQL goes here
+-- Keyset pagination orders conversations by (sent_at, id)
+CREATE INDEX IF NOT EXISTS messages_sent_at_id_idx ON messages(sent_at, id);
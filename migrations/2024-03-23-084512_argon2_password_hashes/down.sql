@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+-- Argon2id hashes do not fit anymore, those users have to reset their password
+UPDATE users SET password = '' WHERE length(password) > 64;
+ALTER TABLE users ALTER COLUMN password TYPE varchar(64);
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+-- Argon2id PHC strings are longer than the legacy hex encoded HMAC hashes
+ALTER TABLE users ALTER COLUMN password TYPE varchar(255);
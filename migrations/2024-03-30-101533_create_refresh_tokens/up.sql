@@ -0,0 +1,19 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS refresh_tokens (
+  id UUID NOT NULL DEFAULT uuid_generate_v4(),
+  family_id UUID NOT NULL,
+  session_id varchar(64) NOT NULL,
+  username varchar(30) NOT NULL,
+  token_hash varchar(64) NOT NULL,
+  expires_at timestamp NOT NULL,
+  used_at timestamp,
+  revoked_at timestamp,
+  created_at timestamp NOT NULL DEFAULT now(),
+  PRIMARY KEY(id),
+  CONSTRAINT unique_refresh_token_hash UNIQUE(token_hash),
+  CONSTRAINT fk_user FOREIGN KEY(username) REFERENCES users(username) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS refresh_tokens_family_id_idx ON refresh_tokens(family_id);
+CREATE INDEX IF NOT EXISTS refresh_tokens_session_id_idx ON refresh_tokens(session_id);
+CREATE INDEX IF NOT EXISTS refresh_tokens_expires_at_idx ON refresh_tokens(expires_at);
@@ -0,0 +1,22 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS revoked_tokens (
+  token_id varchar(64) NOT NULL,
+  username varchar(30) NOT NULL,
+  expires_at timestamp NOT NULL,
+  revoked_at timestamp NOT NULL DEFAULT now(),
+  PRIMARY KEY(token_id),
+  CONSTRAINT fk_user FOREIGN KEY(username) REFERENCES users(username) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS revoked_tokens_expires_at_idx ON revoked_tokens(expires_at);
+
+-- Every token of the user issued up to revoked_before is revoked
+CREATE TABLE IF NOT EXISTS user_token_revocations (
+  username varchar(30) NOT NULL,
+  revoked_before timestamp NOT NULL,
+  expires_at timestamp NOT NULL,
+  PRIMARY KEY(username),
+  CONSTRAINT fk_user FOREIGN KEY(username) REFERENCES users(username) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS user_token_revocations_expires_at_idx ON user_token_revocations(expires_at);
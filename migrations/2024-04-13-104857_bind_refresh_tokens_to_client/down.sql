@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE refresh_tokens DROP COLUMN IF EXISTS client_fingerprint;
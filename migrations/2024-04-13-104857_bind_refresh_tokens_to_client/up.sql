@@ -0,0 +1,3 @@
+-- Your SQL goes here
+-- Fingerprint of the client the token family was issued to, see ClientFingerprint
+ALTER TABLE refresh_tokens ADD COLUMN client_fingerprint varchar(255);
@@ -0,0 +1,13 @@
+-- Your SQL goes here
+-- Single use tickets websocket handshakes are authenticated with instead of access tokens in the query string
+CREATE TABLE IF NOT EXISTS websocket_tickets (
+  ticket_hash varchar(64) NOT NULL,
+  username varchar(30) NOT NULL,
+  token text NOT NULL,
+  expires_at timestamp NOT NULL,
+  created_at timestamp NOT NULL DEFAULT now(),
+  PRIMARY KEY(ticket_hash),
+  CONSTRAINT fk_user FOREIGN KEY(username) REFERENCES users(username) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS websocket_tickets_expires_at_idx ON websocket_tickets(expires_at);
@@ -0,0 +1,38 @@
+-- Your SQL goes here
+-- TOTP secret of the user, the second factor is only required once the enrollment was confirmed
+CREATE TABLE IF NOT EXISTS user_totp (
+  username varchar(30) NOT NULL,
+  secret bytea NOT NULL,
+  confirmed_at timestamp NULL,
+  -- Time step of the last accepted code, codes cannot be used twice
+  last_used_step bigint NULL,
+  created_at timestamp NOT NULL DEFAULT now(),
+  PRIMARY KEY(username),
+  CONSTRAINT fk_user FOREIGN KEY(username) REFERENCES users(username) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS recovery_codes (
+  id uuid NOT NULL,
+  username varchar(30) NOT NULL,
+  code_hash varchar(64) NOT NULL,
+  used_at timestamp NULL,
+  created_at timestamp NOT NULL DEFAULT now(),
+  PRIMARY KEY(id),
+  CONSTRAINT fk_user FOREIGN KEY(username) REFERENCES users(username) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS recovery_codes_username_idx ON recovery_codes(username);
+
+-- Logins which passed the password check and wait for the second factor
+CREATE TABLE IF NOT EXISTS mfa_challenges (
+  challenge_hash varchar(64) NOT NULL,
+  username varchar(30) NOT NULL,
+  session_id varchar(64) NOT NULL,
+  client_fingerprint varchar(255) NULL,
+  expires_at timestamp NOT NULL,
+  created_at timestamp NOT NULL DEFAULT now(),
+  PRIMARY KEY(challenge_hash),
+  CONSTRAINT fk_user FOREIGN KEY(username) REFERENCES users(username) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS mfa_challenges_username_idx ON mfa_challenges(username);
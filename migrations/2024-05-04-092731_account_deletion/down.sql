@@ -0,0 +1,18 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE user_token_revocations DROP COLUMN IF EXISTS kept_session_id;
+
+DELETE FROM user_token_revocations WHERE username NOT IN (SELECT username FROM users);
+ALTER TABLE user_token_revocations
+  ADD CONSTRAINT fk_user FOREIGN KEY(username) REFERENCES users(username) ON DELETE CASCADE;
+
+ALTER TABLE friends
+  DROP CONSTRAINT fk_user,
+  DROP CONSTRAINT fk_befriended_user_id,
+  ADD CONSTRAINT fk_user FOREIGN KEY(user_id) REFERENCES users(username),
+  ADD CONSTRAINT fk_befriended_user_id FOREIGN KEY(befriended_user_id) REFERENCES users(username);
+
+ALTER TABLE friend_requests
+  DROP CONSTRAINT fk_sender,
+  DROP CONSTRAINT fk_recipient,
+  ADD CONSTRAINT fk_sender FOREIGN KEY(sender) REFERENCES users(username),
+  ADD CONSTRAINT fk_recipient FOREIGN KEY(recipient) REFERENCES users(username);
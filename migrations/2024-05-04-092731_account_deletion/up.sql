@@ -0,0 +1,19 @@
+-- Your SQL goes here
+-- Deleting a user removes their friendships and friend requests
+ALTER TABLE friend_requests
+  DROP CONSTRAINT fk_sender,
+  DROP CONSTRAINT fk_recipient,
+  ADD CONSTRAINT fk_sender FOREIGN KEY(sender) REFERENCES users(username) ON DELETE CASCADE,
+  ADD CONSTRAINT fk_recipient FOREIGN KEY(recipient) REFERENCES users(username) ON DELETE CASCADE;
+
+ALTER TABLE friends
+  DROP CONSTRAINT fk_user,
+  DROP CONSTRAINT fk_befriended_user_id,
+  ADD CONSTRAINT fk_user FOREIGN KEY(user_id) REFERENCES users(username) ON DELETE CASCADE,
+  ADD CONSTRAINT fk_befriended_user_id FOREIGN KEY(befriended_user_id) REFERENCES users(username) ON DELETE CASCADE;
+
+-- Revocations have to outlive deleted users, their access tokens stay valid until they expire otherwise
+ALTER TABLE user_token_revocations DROP CONSTRAINT fk_user;
+
+-- Session of the user which is not revoked, e.g. the one the password was changed with
+ALTER TABLE user_token_revocations ADD COLUMN kept_session_id varchar(64);
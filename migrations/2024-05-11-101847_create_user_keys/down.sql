@@ -0,0 +1,10 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE message_edits
+  DROP COLUMN IF EXISTS key_id,
+  DROP COLUMN IF EXISTS sender_key_id;
+
+ALTER TABLE message_payloads
+  DROP COLUMN IF EXISTS key_id,
+  DROP COLUMN IF EXISTS sender_key_id;
+
+DROP TABLE IF EXISTS user_keys;
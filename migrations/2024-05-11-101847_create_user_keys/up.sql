@@ -0,0 +1,49 @@
+-- Your SQL goes here
+-- Every public key a user ever had. users.public_key stays a copy of the current key
+CREATE TABLE IF NOT EXISTS user_keys (
+  id UUID NOT NULL DEFAULT uuid_generate_v4(),
+  username varchar(30) NOT NULL,
+  public_key BYTEA NOT NULL,
+  created_at timestamp NOT NULL DEFAULT now(),
+  -- Set once the user uploaded a newer key
+  replaced_at timestamp,
+  PRIMARY KEY(id),
+  CONSTRAINT fk_user FOREIGN KEY(username) REFERENCES users(username) ON DELETE CASCADE
+);
+
+-- A user has exactly one current key
+CREATE UNIQUE INDEX IF NOT EXISTS user_keys_current_idx ON user_keys(username) WHERE replaced_at IS NULL;
+
+INSERT INTO user_keys(username, public_key, created_at)
+  SELECT username, public_key, now() FROM users;
+
+-- Key the copy was encrypted for and key of the sender the signature was made with
+ALTER TABLE message_payloads
+  ADD COLUMN key_id UUID,
+  ADD COLUMN sender_key_id UUID,
+  ADD CONSTRAINT fk_key FOREIGN KEY(key_id) REFERENCES user_keys(id) ON DELETE SET NULL,
+  ADD CONSTRAINT fk_sender_key FOREIGN KEY(sender_key_id) REFERENCES user_keys(id) ON DELETE SET NULL;
+
+ALTER TABLE message_edits
+  ADD COLUMN key_id UUID,
+  ADD COLUMN sender_key_id UUID,
+  ADD CONSTRAINT fk_key FOREIGN KEY(key_id) REFERENCES user_keys(id) ON DELETE SET NULL,
+  ADD CONSTRAINT fk_sender_key FOREIGN KEY(sender_key_id) REFERENCES user_keys(id) ON DELETE SET NULL;
+
+-- Up to now every user only had a single key
+UPDATE message_payloads
+  SET key_id = user_keys.id
+  FROM user_keys
+  WHERE user_keys.username = message_payloads.recipient;
+
+UPDATE message_payloads
+  SET sender_key_id = user_keys.id
+  FROM messages, user_keys
+  WHERE messages.id = message_payloads.message_id
+  AND user_keys.username = messages.sender;
+
+UPDATE message_edits
+  SET key_id = message_payloads.key_id, sender_key_id = message_payloads.sender_key_id
+  FROM message_payloads
+  WHERE message_payloads.message_id = message_edits.message_id
+  AND message_payloads.recipient = message_edits.recipient;
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+-- Failed logins per username and per ip address, shared so a lockout holds on every instance
+CREATE TABLE IF NOT EXISTS login_failures (
+  scope varchar(16) NOT NULL,
+  throttle_key text NOT NULL,
+  failures integer NOT NULL,
+  last_failure timestamp NOT NULL,
+  locked_until timestamp,
+  PRIMARY KEY(scope, throttle_key)
+);
+
+CREATE INDEX IF NOT EXISTS login_failures_last_failure_idx ON login_failures(last_failure);
@@ -26,7 +26,8 @@ user2 = {
 
 
 def setup_users():
-    
+    credentials = os.path.dirname(os.path.abspath(__file__)) + "/../credentials"
+
     user1_public_key_raw = open(os.path.dirname(os.path.abspath(__file__)) + "/../credentials/test-user-1.pub.pem", "r").read()
     user1_public_key_base64 = base64.b64encode(user1_public_key_raw.encode("ascii")).decode("ascii")
     
@@ -34,8 +35,8 @@ def setup_users():
     user2_public_key_base64 = base64.b64encode(user2_public_key_raw.encode("ascii")).decode("ascii")
 
     print(user1_public_key_base64)
-    utils.register(user1, user1_public_key_base64)
-    utils.register(user2, user2_public_key_base64)
+    utils.register(user1, user1_public_key_base64, credentials + "/test-user-1.pem")
+    utils.register(user2, user2_public_key_base64, credentials + "/test-user-2.pem")
 
     token1 = utils.login(user1)
     token2 = utils.login(user2)
@@ -2,6 +2,8 @@ import websocket
 import requests
 import string
 import random
+import subprocess
+import base64
 backend_base_url = "localhost:3000/api"
 
 
@@ -16,7 +18,9 @@ def debug_version():
 
 
 def establish_websocket_connection(token: str):
-    connection = websocket.create_connection(build_path("/ws?token="+token, "ws"))
+    response = requests.post(build_path("/ws/ticket"), headers={"Authorization": "Bearer "+token})
+    ticket = response.json().get("data").get("ticket")
+    connection = websocket.create_connection(build_path("/ws?ticket="+ticket, "ws"))
     return connection
 
 def compare(info: str, result: str, expect: str):
@@ -46,14 +50,24 @@ def login(user):
     }
     response = requests.post(build_path(path="/login"), json=params)
     response = response.json()
-    return response.get("data")
+    return response.get("data").get("access_token")
+
+def register(user, public_key, private_key_path):
+    response = requests.post(build_path("/users/key-challenge"), json={"username": user.get("username")})
+    challenge = response.json().get("data").get("challenge")
+    # Proves that we hold the private key of the registered public key
+    signature = subprocess.run(
+        ["openssl", "dgst", "-sha256", "-sign", private_key_path],
+        input=challenge.encode("ascii"), capture_output=True, check=True
+    ).stdout
 
-def register(user, public_key):
     response = requests.post(build_path("/users"), json={
         "username": user.get("username"),
         "password": user.get("password"),
         "generate_key": False,
-        "public_key": public_key
+        "public_key": public_key,
+        "challenge": challenge,
+        "signature": base64.b64encode(signature).decode("ascii")
     })
     print(response)
 